@@ -1,34 +1,254 @@
 use colored::Color;
 use colored::Colorize;
-use reqwest::blocking::Client;
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
 use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-pub fn alpha_vantage_query(function: &str, symbol: &str) -> Result<String, String> {
-    let api_key =
-        env::var("ALPHA_VANTAGE_API_KEY").expect("ALPHA_VANTAGE_API_KEY not found in ~/.gemini");
-    let client = Client::new();
+use crate::http;
+use crate::runtime;
+use crate::ui;
 
-    let url = format!(
-        "https://www.alphavantage.co/query?function={}&symbol={}&apikey={}",
-        function, symbol, api_key
-    );
+const ALPHA_VANTAGE_ENDPOINT: &str = "https://www.alphavantage.co/query";
+// Rows of a time series or list returned to the model, newest first
+const DEFAULT_ROWS: usize = 10;
+// Longest cell of a table; longer text (news summaries, descriptions) is cut
+const MAX_CELL_CHARS: usize = 200;
+// Requests per minute the free tier allows
+const DEFAULT_CALLS_PER_MINUTE: usize = 5;
+// How long a response is reused for the same query
+const DEFAULT_CACHE_SECONDS: usize = 300;
 
-    println!(
+// When the recent requests were (or are about to be) sent, oldest first
+static RECENT_CALLS: Lazy<Mutex<VecDeque<Instant>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+// Responses by query (without the key), with the time they arrived
+static CACHE: Lazy<Mutex<HashMap<String, (Instant, String)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Calls an Alpha Vantage function for a stock symbol. `extra` holds optional parameters
+/// such as interval, outputsize and month.
+pub fn alpha_vantage_query(function: &str, symbol: &str, extra: &[(&str, &str)]) -> Result<String, String> {
+    let mut params = vec![("function", function)];
+    if !symbol.is_empty() {
+        params.push(("symbol", symbol));
+    }
+    params.extend(extra.iter().filter(|(_, value)| !value.is_empty()));
+    query(&params, symbol)
+}
+
+/// Sends a query (the function and its parameters) and returns a compact summary of the
+/// response: scalar fields as `name: value` lines and time series or lists as a Markdown
+/// table of the latest ALPHA_VANTAGE_ROWS entries. `subject` is shown in the status line.
+///
+/// The same query within ALPHA_VANTAGE_CACHE_SECONDS is answered from memory, and requests
+/// are spaced to stay within ALPHA_VANTAGE_CALLS_PER_MINUTE, so the free tier's quota lasts.
+pub fn query(params: &[(&str, &str)], subject: &str) -> Result<String, String> {
+    let api_key = env::var("ALPHA_VANTAGE_API_KEY")
+        .ok()
+        .filter(|key| !key.trim().is_empty())
+        .ok_or_else(|| "ALPHA_VANTAGE_API_KEY is not set in ~/.gemini.conf".to_string())?;
+    let endpoint = env::var("ALPHA_VANTAGE_ENDPOINT").unwrap_or_else(|_| ALPHA_VANTAGE_ENDPOINT.to_string());
+
+    ui::status(&format!(
         "{} {}",
         "Gemini is querying alpha vantage for:"
             .color(Color::Cyan)
             .bold(),
-        symbol
-    );
+        subject
+    ));
 
-    let response = client
-        .get(&url)
-        .send()
-        .map_err(|e| format!("Alpha Vantage API request failed: {}", e))?;
+    let mut query = params.to_vec();
+    let cache_key: String = query.iter().map(|(name, value)| format!("{}={}&", name, value.to_uppercase())).collect();
+
+    let response_text = match cached(&cache_key) {
+        Some(text) => text,
+        None => {
+            query.push(("apikey", api_key.as_str()));
+            runtime::block_on(fetch(&endpoint, &query))
+                .map_err(|e| format!("Alpha Vantage API request failed: {}", e))??
+        }
+    };
+    let json: Value = match serde_json::from_str(&response_text) {
+        Ok(json) => json,
+        // CSV and other plain answers are passed on as they are
+        Err(_) => return Ok(response_text),
+    };
 
-    let response_text = response
+    // Alpha Vantage answers 200 either way and explains problems in the body
+    if let Some(message) = json["Error Message"].as_str() {
+        return Err(format!("Alpha Vantage error: {}", message));
+    }
+    if let Some(message) = json["Information"].as_str().or(json["Note"].as_str()) {
+        if json.as_object().is_some_and(|fields| fields.len() == 1) {
+            return Err(quota_message(message));
+        }
+    }
+    CACHE.lock().unwrap().insert(cache_key, (Instant::now(), response_text.clone()));
+    let rows = setting("ALPHA_VANTAGE_ROWS", DEFAULT_ROWS).max(1);
+    Ok(summarize(&json, rows))
+}
+
+/// The stored response to the same query, while it is fresh.
+fn cached(key: &str) -> Option<String> {
+    let ttl = Duration::from_secs(setting("ALPHA_VANTAGE_CACHE_SECONDS", DEFAULT_CACHE_SECONDS) as u64);
+    let mut cache = CACHE.lock().unwrap();
+    cache.retain(|_, (stored, _)| stored.elapsed() < ttl);
+    cache.get(key).map(|(_, text)| text.clone())
+}
+
+/// Sends a query once the rate limit allows it and returns the response body.
+async fn fetch(endpoint: &str, query: &[(&str, &str)]) -> Result<String, String> {
+    wait_for_quota().await;
+    let response = http::async_client()
+        .get(endpoint)
+        .query(query)
+        .send()
+        .await
+        .map_err(|e| format!("Alpha Vantage API request failed: {}", e.without_url()))?;
+    response
         .text()
-        .map_err(|e| format!("Failed to parse Alpha Vantage response: {}", e))?;
+        .await
+        .map_err(|e| format!("Failed to parse Alpha Vantage response: {}", e))
+}
+
+/// Sleeps until another request fits into the per-minute limit. The request is counted
+/// before the wait, so calls waiting at the same time line up behind each other.
+async fn wait_for_quota() {
+    let per_minute = setting("ALPHA_VANTAGE_CALLS_PER_MINUTE", DEFAULT_CALLS_PER_MINUTE);
+    if per_minute == 0 {
+        return;
+    }
+    let minute = Duration::from_secs(60);
+    let now = Instant::now();
+    let send_at = {
+        let mut recent = RECENT_CALLS.lock().unwrap();
+        while recent.front().is_some_and(|sent| now.saturating_duration_since(*sent) >= minute) {
+            recent.pop_front();
+        }
+        let send_at = match recent.len().checked_sub(per_minute) {
+            Some(index) => (recent[index] + minute).max(now),
+            None => now,
+        };
+        recent.push_back(send_at);
+        send_at
+    };
+    let wait = send_at - now;
+    if !wait.is_zero() {
+        let message = format!("Waiting {}s for the Alpha Vantage rate limit", wait.as_secs() + 1);
+        ui::status(&message.color(Color::Yellow).to_string());
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// A short explanation in place of the notice Alpha Vantage sends when the quota is used up.
+fn quota_message(message: &str) -> String {
+    let lower = message.to_lowercase();
+    if lower.contains("rate limit") || lower.contains("call frequency") || lower.contains("requests per") {
+        "Alpha Vantage's request limit was reached (the free tier allows a few requests per minute and 25 per day). \
+            Reuse the data already fetched, or try again later."
+            .to_string()
+    } else {
+        format!("Alpha Vantage: {}", message)
+    }
+}
+
+fn setting(key: &str, default: usize) -> usize {
+    env::var(key).ok().and_then(|v| v.trim().parse().ok()).unwrap_or(default)
+}
+
+/// Turns a response into text: scalars as lines, objects of scalars as sections, objects of
+/// records (time series keyed by date) and arrays of records as tables of their first rows.
+fn summarize(json: &Value, rows: usize) -> String {
+    let fields = match json.as_object() {
+        Some(fields) if !fields.is_empty() => fields,
+        _ => return "Alpha Vantage returned no data for this query.".to_string(),
+    };
+    let mut lines = Vec::new();
+    let mut sections = Vec::new();
+    for (name, value) in fields {
+        match value {
+            Value::Object(entries) if !entries.is_empty() && entries.values().all(Value::is_object) => {
+                // Time series are keyed by date or time, which sort newest last
+                let mut keys: Vec<&String> = entries.keys().collect();
+                keys.sort_by(|a, b| b.cmp(a));
+                let records: Vec<(Option<&str>, &Map<String, Value>)> = keys
+                    .iter()
+                    .filter_map(|key| entries[key.as_str()].as_object().map(|record| (Some(key.as_str()), record)))
+                    .collect();
+                sections.push(table(name, &records, rows));
+            }
+            Value::Object(entries) => {
+                let mut section = format!("## {}", label(name));
+                for (key, value) in entries {
+                    section.push_str(&format!("\n{}: {}", label(key), cell(value)));
+                }
+                sections.push(section);
+            }
+            Value::Array(items) if items.iter().any(Value::is_object) => {
+                let records: Vec<(Option<&str>, &Map<String, Value>)> =
+                    items.iter().filter_map(Value::as_object).map(|record| (None, record)).collect();
+                sections.push(table(name, &records, rows));
+            }
+            other => lines.push(format!("{}: {}", label(name), cell(other))),
+        }
+    }
+    lines.extend(sections);
+    lines.join("\n\n")
+}
+
+/// A Markdown table of the first `rows` records, with their key (a date) as the first column.
+fn table(name: &str, records: &[(Option<&str>, &Map<String, Value>)], rows: usize) -> String {
+    let mut columns: Vec<&String> = Vec::new();
+    for (_, record) in records.iter().take(rows) {
+        for key in record.keys() {
+            if !columns.contains(&key) {
+                columns.push(key);
+            }
+        }
+    }
+    let keyed = records.first().is_some_and(|(key, _)| key.is_some());
+    let mut header: Vec<String> = columns.iter().map(|column| label(column)).collect();
+    if keyed {
+        header.insert(0, "date".to_string());
+    }
+
+    let mut text = format!("## {}", label(name));
+    if records.len() > rows {
+        text.push_str(&format!(" (latest {} of {})", rows, records.len()));
+    }
+    text.push_str(&format!("\n| {} |\n|{}", header.join(" | "), " --- |".repeat(header.len())));
+    for (key, record) in records.iter().take(rows) {
+        let mut cells: Vec<String> = columns
+            .iter()
+            .map(|column| record.get(column.as_str()).map(cell).unwrap_or_default())
+            .collect();
+        if let Some(key) = key {
+            cells.insert(0, key.to_string());
+        }
+        text.push_str(&format!("\n| {} |", cells.join(" | ")));
+    }
+    text
+}
+
+/// A field name without Alpha Vantage's numbering ("1. open" becomes "open").
+fn label(name: &str) -> String {
+    match name.split_once(". ") {
+        Some((number, rest)) if number.chars().all(|c| c.is_ascii_alphanumeric()) && number.len() <= 3 => rest.to_string(),
+        _ => name.to_string(),
+    }
+}
 
-    Ok(response_text)
+fn cell(value: &Value) -> String {
+    let text = match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    };
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ").replace('|', "\\|");
+    if text.chars().count() > MAX_CELL_CHARS {
+        format!("{}…", text.chars().take(MAX_CELL_CHARS).collect::<String>())
+    } else {
+        text
+    }
 }
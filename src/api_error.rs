@@ -0,0 +1,219 @@
+use serde_json::Value;
+use std::fmt;
+use std::time::Duration;
+
+use crate::providers;
+use crate::safety;
+
+// Longest server-suggested wait honored before retrying
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// An error response from the Gemini API, e.g.
+/// `{"error": {"code": 429, "status": "RESOURCE_EXHAUSTED", "message": "...", "details": [...]}}`.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: u16,
+    /// The RPC status name, e.g. RESOURCE_EXHAUSTED
+    pub code: Option<String>,
+    pub message: String,
+    /// The ErrorInfo reason from the details, e.g. API_KEY_INVALID
+    pub reason: Option<String>,
+    /// How long the server asked us to wait, from RetryInfo
+    pub retry_delay: Option<Duration>,
+    /// The quota that ran out, from QuotaFailure, e.g. GenerateRequestsPerDayPerProjectPerModel-FreeTier
+    pub quota: Option<String>,
+    /// The first link from the Help details, usually the relevant documentation page
+    pub help_url: Option<String>,
+}
+
+impl ApiError {
+    /// Builds the error from an HTTP status and the response body, which may not be JSON at all.
+    pub fn parse(status: u16, body: &str) -> ApiError {
+        let json: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+        let error = &json["error"];
+        let details = error["details"].as_array().cloned().unwrap_or_default();
+        let detail = |type_suffix: &str| {
+            details
+                .iter()
+                .find(|d| d["@type"].as_str().is_some_and(|t| t.ends_with(type_suffix)))
+                .cloned()
+                .unwrap_or(Value::Null)
+        };
+
+        let message = match error["message"].as_str() {
+            Some(message) => message.to_string(),
+            None if body.trim().is_empty() => "no details in the response".to_string(),
+            None => body.trim().chars().take(300).collect(),
+        };
+        let text = |value: &Value| value.as_str().map(|s| s.to_string());
+        ApiError {
+            status,
+            code: text(&error["status"]),
+            message,
+            reason: text(&detail("ErrorInfo")["reason"]),
+            retry_delay: text(&detail("RetryInfo")["retryDelay"])
+                .and_then(|d| d.trim_end_matches('s').parse::<f64>().ok())
+                .map(|secs| Duration::from_secs_f64(secs).min(MAX_RETRY_DELAY)),
+            quota: text(&detail("QuotaFailure")["violations"][0]["quotaId"]),
+            help_url: text(&detail("Help")["links"][0]["url"]),
+        }
+    }
+
+    /// Rate limits and server-side failures may succeed on a later attempt; anything else
+    /// (bad key, bad request, no access) fails the same way every time.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.status, 429 | 500 | 502 | 503 | 504 | 529)
+    }
+
+    /// Whether a daily quota ran out, which no retry today gets around; another model may
+    /// still have quota left.
+    pub fn is_daily_quota(&self) -> bool {
+        self.quota.as_deref().is_some_and(|quota| quota.contains("PerDay"))
+    }
+
+    /// Short form for notices, e.g. "429 RESOURCE_EXHAUSTED".
+    pub fn summary(&self) -> String {
+        match &self.code {
+            Some(code) => format!("{} {}", self.status, code),
+            None => self.status.to_string(),
+        }
+    }
+
+    /// The error without the hint, e.g. "HTTP 429 RESOURCE_EXHAUSTED: You exceeded your current quota".
+    pub fn headline(&self) -> String {
+        format!("HTTP {}: {}", self.summary(), self.message)
+    }
+
+    /// What the user can do about it, with a link to the documentation when the API sent one.
+    pub fn hint(&self) -> String {
+        match &self.help_url {
+            Some(url) => format!("{} See {}", self.advice(), url),
+            None => self.advice().to_string(),
+        }
+    }
+
+    fn advice(&self) -> &'static str {
+        let reason = self.reason.as_deref().unwrap_or("");
+        let code = self.code.as_deref().unwrap_or("");
+        let quota = self.quota.as_deref().unwrap_or("");
+        let message = self.message.to_lowercase();
+        match (self.status, code, reason) {
+            _ if message.contains("api key expired") => {
+                "The API key has expired. Create a new one at https://aistudio.google.com/apikey and set it with \
+                 gemini-cli-rs config set GEMINI_API_KEY <key>."
+            }
+            (_, _, "API_KEY_INVALID") | (401, _, _) => {
+                "Check GEMINI_API_KEY in ~/.gemini.conf (gemini-cli-rs config set GEMINI_API_KEY <key>)."
+            }
+            (_, _, "API_KEY_SERVICE_BLOCKED") | (403, _, _) => {
+                "The key has no access to this model, or the Generative Language API is not enabled for its project."
+            }
+            (404, _, _) => "The model name may be wrong, or the model is not available to this key.",
+            (429, _, _) if self.is_daily_quota() => {
+                "Today's quota for this model is used up; it resets at midnight Pacific time. Switch models with \
+                 /model, set GEMINI_FALLBACK_MODEL, or enable billing for the key's project."
+            }
+            (429, _, _) if quota.contains("InputTokens") => {
+                "Too many input tokens per minute. Wait a minute, or shrink the conversation with /compact."
+            }
+            (429, _, _) => {
+                "Quota or rate limit exceeded. Wait a moment, set GEMINI_FALLBACK_MODEL, or check your plan's limits."
+            }
+            (400, "FAILED_PRECONDITION", _) => {
+                "The free tier is not available in your region; enable billing for the key's project."
+            }
+            (400, _, _) if message.contains("token") && (message.contains("exceed") || message.contains("too long")) => {
+                "The conversation is too long for the model. Shrink it with /compact, or start over with 'clear'."
+            }
+            (400, _, _) => {
+                "The request was rejected. The conversation may be too long or contain unsupported content; try 'clear'."
+            }
+            (500..=599, _, _) => "The service is failing or overloaded. Try again shortly.",
+            _ => "See the message above for details.",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\nHint: {}", self.headline(), self.hint())
+    }
+}
+
+/// Why a successful response has no answer: the prompt or the reply was blocked, or the
+/// model stopped before writing anything.
+pub fn blocked(response: &Value) -> Option<String> {
+    let feedback = &response["promptFeedback"];
+    if let Some(reason) = feedback["blockReason"].as_str() {
+        return Some(format!(
+            "The prompt was blocked ({}). Rephrase the request or remove the flagged content.{}",
+            with_categories(reason, &feedback["safetyRatings"]),
+            safety_hint(reason)
+        ));
+    }
+    let candidate = response["candidates"].as_array()?.first()?;
+    let finish = candidate["finishReason"].as_str()?;
+    let has_content = candidate["content"]["parts"].as_array().is_some_and(|p| !p.is_empty());
+    if has_content {
+        return None;
+    }
+    match finish {
+        "STOP" | "FINISH_REASON_UNSPECIFIED" => None,
+        "SAFETY" | "PROHIBITED_CONTENT" | "BLOCKLIST" | "SPII" | "RECITATION" | "IMAGE_SAFETY" => Some(format!(
+            "The answer was blocked ({}). Rephrase the request.{}",
+            with_categories(finish, &candidate["safetyRatings"]),
+            safety_hint(finish)
+        )),
+        "MAX_TOKENS" => Some(
+            "The model reached the output token limit before writing an answer (MAX_TOKENS). Raise it with \
+             /gen max_tokens or GEMINI_MAX_OUTPUT_TOKENS, or lower the thinking budget."
+                .to_string(),
+        ),
+        "MALFORMED_FUNCTION_CALL" => {
+            Some("The model wrote a tool call that could not be read (MALFORMED_FUNCTION_CALL). Try again.".to_string())
+        }
+        other => Some(format!("The model stopped without an answer ({}). Try again or rephrase the request.", other)),
+    }
+}
+
+/// Why an answer ends early, e.g. at the token limit or when the rest was blocked; shown
+/// after the text that did arrive.
+pub fn cut_short(response: &Value) -> Option<String> {
+    let candidate = response["candidates"].as_array()?.first()?;
+    let finish = candidate["finishReason"].as_str()?;
+    candidate["content"]["parts"].as_array().filter(|parts| !parts.is_empty())?;
+    match finish {
+        "MAX_TOKENS" => Some(
+            "The answer was cut off at the output token limit (MAX_TOKENS). Ask the model to continue, or raise \
+             the limit with /gen max_tokens."
+                .to_string(),
+        ),
+        "SAFETY" | "PROHIBITED_CONTENT" | "BLOCKLIST" | "SPII" | "RECITATION" => Some(format!(
+            "The rest of the answer was blocked ({}).{}",
+            with_categories(finish, &candidate["safetyRatings"]),
+            safety_hint(finish)
+        )),
+        _ => None,
+    }
+}
+
+/// The reason followed by the categories the safety ratings flagged, e.g.
+/// "SAFETY: dangerous content (HIGH)".
+fn with_categories(reason: &str, ratings: &Value) -> String {
+    let flagged = safety::flagged(ratings);
+    if flagged.is_empty() {
+        reason.to_string()
+    } else {
+        format!("{}: {}", reason, flagged.join(", "))
+    }
+}
+
+/// For blocks by the adjustable safety filters on Gemini, how to loosen them.
+fn safety_hint(reason: &str) -> &'static str {
+    if reason == "SAFETY" && providers::current().name() == "gemini" {
+        " If the topic is legitimate (e.g. security research), GEMINI_SAFETY can lower the thresholds, \
+         e.g. GEMINI_SAFETY=dangerous=high."
+    } else {
+        ""
+    }
+}
@@ -0,0 +1,140 @@
+use chrono::{DateTime, Local};
+use colored::{Color, Colorize};
+use serde_json::{json, Value};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::redact;
+use crate::ui;
+use crate::SANDBOX_ROOT;
+
+// Tools that only read the sandbox; their calls are not logged
+const READ_ONLY: &[&str] = &["list_files", "search_codebase", "list_jobs"];
+const READ_ONLY_EDITOR: &[&str] = &["read", "search"];
+// Longer argument strings (file contents, email bodies) keep their beginning
+const MAX_ARG_CHARS: usize = 500;
+// Characters of a tool's result kept as its summary
+const MAX_RESULT_CHARS: usize = 300;
+// Entries /audit shows when no number is given
+const DEFAULT_ENTRIES: usize = 20;
+
+/// The audit log: `~/.gemini_cli_audit.jsonl`, another file with GEMINI_AUDIT_LOG, or None
+/// with GEMINI_AUDIT_LOG=0.
+fn log_path() -> Option<PathBuf> {
+    let configured = env::var("GEMINI_AUDIT_LOG").unwrap_or_default();
+    let configured = configured.trim();
+    match configured {
+        "0" | "false" | "off" => None,
+        "" => dirs::home_dir().map(|home| home.join(".gemini_cli_audit.jsonl")),
+        path => match path.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir().map(|home| home.join(rest)),
+            None => Some(PathBuf::from(path)),
+        },
+    }
+}
+
+/// Appends a tool call to the audit log: when and where it ran, its arguments (with
+/// credentials redacted and long texts cut), whether it succeeded and the start of its
+/// result. `origin` tells a chat from an MCP client. Calls that only read the sandbox are
+/// left out.
+pub fn record(origin: &str, tool: &str, args: &Value, result: &Result<String, String>, duration: Duration) {
+    let read_only = READ_ONLY.contains(&tool)
+        || (tool == "file_editor" && READ_ONLY_EDITOR.contains(&args["subcommand"].as_str().unwrap_or("")));
+    let Some(path) = log_path().filter(|_| !read_only) else { return };
+
+    let mut args = args.clone();
+    shorten(&mut args);
+    redact::redact_json(&mut args);
+    let (ok, output) = match result {
+        Ok(output) => (!ui::looks_like_failure(output), output),
+        Err(e) => (false, e),
+    };
+    let entry = json!({
+        "timestamp": Local::now().to_rfc3339(),
+        "origin": origin,
+        "sandbox": &*SANDBOX_ROOT,
+        "tool": tool,
+        "args": args,
+        "ok": ok,
+        "duration_ms": duration.as_millis() as u64,
+        "result": redact::redact_secrets(&summary(output, MAX_RESULT_CHARS)),
+    });
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", entry));
+    if let Err(e) = written {
+        ui::status(&format!("Could not write to the audit log {}: {}", path.display(), e).color(Color::Yellow).to_string());
+    }
+}
+
+/// The latest `count` entries of the audit log for /audit, oldest first: time, tool, key
+/// arguments and outcome.
+pub fn report(count: Option<usize>) -> String {
+    let Some(path) = log_path() else {
+        return "The audit log is off (GEMINI_AUDIT_LOG=0).".to_string();
+    };
+    let text = fs::read_to_string(&path).unwrap_or_default();
+    let entries: Vec<Value> = text.lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+    if entries.is_empty() {
+        return format!("No tool calls recorded yet in {}.", path.display());
+    }
+    let count = count.unwrap_or(DEFAULT_ENTRIES).max(1);
+    let mut lines = vec![format!(
+        "Last {} of {} tool calls in {}:",
+        count.min(entries.len()),
+        entries.len(),
+        path.display()
+    )];
+    for entry in &entries[entries.len().saturating_sub(count)..] {
+        let time = entry["timestamp"]
+            .as_str()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let tool = entry["tool"].as_str().unwrap_or("?");
+        let status = if entry["ok"].as_bool().unwrap_or(false) {
+            "✓".color(Color::Green)
+        } else {
+            "✗".color(Color::Red)
+        };
+        let details = summary(&ui::summarize_args(tool, &entry["args"]), 100);
+        lines.push(format!("{} {} {} {}", time.dimmed(), status, tool.bold(), details));
+        let result = entry["result"].as_str().unwrap_or("");
+        if !result.is_empty() {
+            lines.push(format!("    {}", summary(result, 120).dimmed()));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Cuts long strings in the arguments, noting their full length.
+fn shorten(value: &mut Value) {
+    match value {
+        Value::String(text) if text.chars().count() > MAX_ARG_CHARS => {
+            let length = text.chars().count();
+            *text = format!("{} [... {} chars in total]", text.chars().take(MAX_ARG_CHARS).collect::<String>(), length);
+        }
+        Value::Array(items) => items.iter_mut().for_each(shorten),
+        Value::Object(fields) => fields.values_mut().for_each(shorten),
+        _ => {}
+    }
+}
+
+/// The text on one line, cut to `max_chars`.
+fn summary(text: &str, max_chars: usize) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() > max_chars {
+        format!("{}…", line.chars().take(max_chars).collect::<String>())
+    } else {
+        line
+    }
+}
@@ -0,0 +1,243 @@
+use clap::Subcommand;
+use colored::{Color, Colorize};
+use serde_json::Value;
+use std::env;
+use std::time::Duration;
+
+use crate::api_error::ApiError;
+use crate::error::CliError;
+use crate::github;
+use crate::http;
+use crate::keys;
+use crate::models::{self, Task};
+use crate::providers;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Subcommand)]
+pub enum AuthAction {
+    /// Check every configured API key with a minimal request
+    Test,
+}
+
+/// Result of checking one provider's credentials.
+enum Check {
+    Valid(String),
+    /// The key works but something is off, e.g. no access to the configured model
+    Limited(String),
+    Invalid(String),
+    NotConfigured,
+    /// The service couldn't be reached, so the key is neither confirmed nor rejected
+    Unreachable(String),
+}
+
+/// Handles `gemini-cli-rs auth ...`.
+pub fn run(action: Option<AuthAction>) -> Result<(), String> {
+    match action.unwrap_or(AuthAction::Test) {
+        AuthAction::Test => {
+            let gemini_keys = keys::configured();
+            let mut checks: Vec<(String, Check)> = match gemini_keys.len() {
+                0 => vec![("Gemini".to_string(), Check::NotConfigured)],
+                1 => vec![("Gemini".to_string(), check_gemini(&gemini_keys[0]))],
+                _ => gemini_keys
+                    .iter()
+                    .enumerate()
+                    .map(|(i, key)| (format!("Gemini key {}", i + 1), check_gemini(key)))
+                    .collect(),
+            };
+            checks.extend([
+                ("Google Search".to_string(), check_search()),
+                ("Brave Search".to_string(), check_brave()),
+                ("Alpha Vantage".to_string(), check_alpha_vantage()),
+                ("GitHub".to_string(), check_github()),
+            ]);
+            let mut failed = false;
+            for (provider, check) in checks {
+                let (label, detail) = match check {
+                    Check::Valid(detail) => ("valid".color(Color::Green).bold(), detail),
+                    Check::Limited(detail) => ("limited".color(Color::Yellow).bold(), detail),
+                    Check::Invalid(detail) => {
+                        failed = true;
+                        ("invalid".color(Color::Red).bold(), detail)
+                    }
+                    Check::NotConfigured => ("not configured".dimmed(), String::new()),
+                    Check::Unreachable(detail) => ("unreachable".color(Color::Yellow).bold(), detail),
+                };
+                println!("{:<15} {:<16} {}", provider, label, detail);
+            }
+            if failed {
+                Err("Some keys were rejected".to_string())
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Quick check of the Gemini key before an interactive session, so a bad key fails with an
+/// explanation instead of on the first prompt. GEMINI_STARTUP_CHECK=0 skips it.
+pub fn startup_check() -> Result<(), CliError> {
+    let disabled = env::var("GEMINI_STARTUP_CHECK")
+        .map(|v| v == "0" || v == "false")
+        .unwrap_or(false);
+    // The check only knows the Gemini API
+    if disabled || providers::current().name() != "gemini" {
+        return Ok(());
+    }
+    match check_gemini(&keys::current()) {
+        Check::Invalid(detail) => Err(CliError::Config(format!(
+            "The Gemini API key was rejected: {}\nFix GEMINI_API_KEY in ~/.gemini.conf, then run 'gemini-cli-rs auth test'.",
+            detail
+        ))),
+        Check::Limited(detail) => {
+            println!("{}", format!("Warning: the Gemini API key has {}", detail).color(Color::Yellow));
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn configured(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.trim().is_empty())
+}
+
+/// Lists the models the key can use and checks that the configured ones are among them.
+fn check_gemini(api_key: &str) -> Check {
+    if api_key.is_empty() {
+        return Check::NotConfigured;
+    }
+    let response = match http::client()
+        .get("https://generativelanguage.googleapis.com/v1beta/models")
+        .timeout(CHECK_TIMEOUT)
+        .query(&[("key", api_key), ("pageSize", "1000")])
+        .send()
+    {
+        Ok(response) => response,
+        Err(e) => return Check::Unreachable(e.without_url().to_string()),
+    };
+
+    let status = response.status();
+    let body = response.text().unwrap_or_default();
+    if !status.is_success() {
+        let error = ApiError::parse(status.as_u16(), &body);
+        return if error.is_retryable() {
+            Check::Unreachable(error.summary())
+        } else if error.message.to_lowercase().contains("expired") {
+            Check::Invalid(format!("the key has expired ({})", error.summary()))
+        } else {
+            Check::Invalid(format!("{}: {}", error.summary(), error.message))
+        };
+    }
+
+    let available: Vec<String> = serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|json| json["models"].as_array().cloned())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|m| m["name"].as_str())
+        .map(|name| name.trim_start_matches("models/").to_string())
+        .collect();
+    let mut wanted = vec![models::model_for(Task::Main), models::model_for(Task::Condense)];
+    wanted.extend(models::fallback_model());
+    wanted.dedup();
+    let missing: Vec<&String> = wanted.iter().filter(|m| !available.contains(m)).collect();
+    if missing.is_empty() {
+        Check::Valid(format!("access to {}", wanted.join(", ")))
+    } else {
+        Check::Limited(format!(
+            "no access to {}",
+            missing.iter().map(|m| m.as_str()).collect::<Vec<_>>().join(", ")
+        ))
+    }
+}
+
+fn check_search() -> Check {
+    let (api_key, cx) = match (configured("GOOGLE_SEARCH_API_KEY"), configured("GOOGLE_SEARCH_ENGINE_ID")) {
+        (Some(key), Some(cx)) => (key, cx),
+        (None, _) => return Check::NotConfigured,
+        (Some(_), None) => return Check::Invalid("GOOGLE_SEARCH_ENGINE_ID is not set".to_string()),
+    };
+    let response = match http::client()
+        .get("https://www.googleapis.com/customsearch/v1")
+        .timeout(CHECK_TIMEOUT)
+        .query(&[("key", api_key.as_str()), ("cx", cx.as_str()), ("q", "test"), ("num", "1")])
+        .send()
+    {
+        Ok(response) => response,
+        Err(e) => return Check::Unreachable(e.without_url().to_string()),
+    };
+    let status = response.status();
+    if status.is_success() {
+        return Check::Valid(String::new());
+    }
+    let error = ApiError::parse(status.as_u16(), &response.text().unwrap_or_default());
+    if error.status == 429 {
+        Check::Limited("daily query quota exhausted".to_string())
+    } else {
+        Check::Invalid(format!("{}: {}", error.summary(), error.message))
+    }
+}
+
+fn check_brave() -> Check {
+    let api_key = match configured("BRAVE_SEARCH_API_KEY") {
+        Some(key) => key,
+        None => return Check::NotConfigured,
+    };
+    let response = match http::client()
+        .get("https://api.search.brave.com/res/v1/web/search")
+        .timeout(CHECK_TIMEOUT)
+        .header("X-Subscription-Token", &api_key)
+        .header("Accept", "application/json")
+        .query(&[("q", "test"), ("count", "1")])
+        .send()
+    {
+        Ok(response) => response,
+        Err(e) => return Check::Unreachable(e.without_url().to_string()),
+    };
+    let status = response.status();
+    if status.is_success() {
+        return Check::Valid(String::new());
+    }
+    let error = ApiError::parse(status.as_u16(), &response.text().unwrap_or_default());
+    if error.status == 429 {
+        Check::Limited("query quota exhausted".to_string())
+    } else {
+        Check::Invalid(format!("{}: {}", error.summary(), error.message))
+    }
+}
+
+fn check_alpha_vantage() -> Check {
+    let api_key = match configured("ALPHA_VANTAGE_API_KEY") {
+        Some(key) => key,
+        None => return Check::NotConfigured,
+    };
+    let json: Value = match http::client()
+        .get("https://www.alphavantage.co/query")
+        .timeout(CHECK_TIMEOUT)
+        .query(&[("function", "GLOBAL_QUOTE"), ("symbol", "IBM"), ("apikey", api_key.as_str())])
+        .send()
+        .and_then(|r| r.json())
+    {
+        Ok(json) => json,
+        Err(e) => return Check::Unreachable(e.without_url().to_string()),
+    };
+    // Alpha Vantage answers 200 either way and explains problems in the body
+    if let Some(message) = json["Error Message"].as_str() {
+        Check::Invalid(message.to_string())
+    } else if let Some(message) = json["Information"].as_str().or(json["Note"].as_str()) {
+        Check::Limited(message.to_string())
+    } else {
+        Check::Valid(String::new())
+    }
+}
+
+fn check_github() -> Check {
+    if configured("GITHUB_TOKEN").is_none() {
+        return Check::NotConfigured;
+    }
+    match github::authenticated_user() {
+        Ok(login) => Check::Valid(format!("signed in as {}", login)),
+        Err(e) if e.starts_with("GitHub request failed") => Check::Unreachable(e),
+        Err(e) => Check::Invalid(e),
+    }
+}
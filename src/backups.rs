@@ -0,0 +1,100 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use crate::locale;
+use crate::SANDBOX_ROOT;
+
+// Backups kept per sandbox; older ones are removed
+const MAX_BACKUPS: usize = 100;
+
+/// A file as it was before one edit.
+#[derive(Serialize, Deserialize)]
+struct Backup {
+    file: String,
+    time: DateTime<Local>,
+    /// None when the edit created the file
+    content: Option<String>,
+}
+
+/// Keeps the current content of a file before file_editor changes it, so the edit can be
+/// undone with /undo-edit or the undo_last_edit tool.
+pub fn save(filename: &str, path: &Path) -> Result<(), String> {
+    let dir = backup_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Error creating '{}': {}", dir.display(), e))?;
+    let backup = Backup {
+        file: filename.to_string(),
+        time: Local::now(),
+        content: fs::read_to_string(path).ok(),
+    };
+    let number = numbers().last().map(|n| n + 1).unwrap_or(1);
+    let json = serde_json::to_string(&backup).map_err(|e| e.to_string())?;
+    fs::write(dir.join(format!("{:06}.json", number)), json).map_err(|e| format!("Error writing backup: {}", e))?;
+
+    let numbers = numbers();
+    for old in &numbers[..numbers.len().saturating_sub(MAX_BACKUPS)] {
+        fs::remove_file(dir.join(format!("{:06}.json", old))).ok();
+    }
+    Ok(())
+}
+
+/// Restores the file changed by the most recent edit and drops its backup. Earlier edits can
+/// be undone by calling this again.
+pub fn undo_last_edit() -> Result<String, String> {
+    let number = *numbers().last().ok_or("No file edits to undo")?;
+    let path = backup_dir().join(format!("{:06}.json", number));
+    let backup: Backup = fs::read_to_string(&path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .ok_or_else(|| format!("The backup '{}' can't be read", path.display()))?;
+
+    let target = Path::new(&*SANDBOX_ROOT).join(&backup.file);
+    let result = match &backup.content {
+        Some(content) => fs::write(&target, content),
+        None => fs::remove_file(&target),
+    };
+    result.map_err(|e| format!("Error restoring '{}': {}", backup.file, e))?;
+    fs::remove_file(&path).ok();
+
+    let when = format!("{} {}", locale::format_date(&backup.time), locale::format_time(&backup.time));
+    Ok(match backup.content {
+        Some(_) => format!("Restored '{}' as it was before the edit of {}", backup.file, when),
+        None => format!("Removed '{}', which was created by the edit of {}", backup.file, when),
+    })
+}
+
+/// Deletes the backups of this sandbox, for sandboxes that are themselves temporary.
+pub fn discard() -> Result<(), String> {
+    let dir = backup_dir();
+    if !dir.exists() {
+        return Ok(());
+    }
+    fs::remove_dir_all(&dir).map_err(|e| format!("Error removing '{}': {}", dir.display(), e))
+}
+
+/// The numbers of the backups of this sandbox, oldest first.
+fn numbers() -> Vec<usize> {
+    let mut numbers: Vec<usize> = fs::read_dir(backup_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json")?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    numbers.sort_unstable();
+    numbers
+}
+
+fn backup_dir() -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    SANDBOX_ROOT.hash(&mut hasher);
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("gemini-cli")
+        .join("backups")
+        .join(format!("{:016x}", hasher.finish()))
+}
@@ -1,10 +1,21 @@
+use chardetng::EncodingDetector;
+use chrono::Local;
+use colored::{Color, Colorize};
 use once_cell::sync::Lazy;
+use std::env;
+use std::fs;
 use std::io::{self, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::str;
+use std::sync::Mutex;
 use std::thread;
 
+use crate::config;
+use crate::stats;
+use crate::tui;
+use crate::ui;
+
 static SANDBOX_ROOT: Lazy<String> = Lazy::new(|| {
     let path = std::env::current_dir()
         .unwrap_or_else(|_| PathBuf::from("."))
@@ -28,29 +39,181 @@ static SANDBOX_ROOT: Lazy<String> = Lazy::new(|| {
     }
 });
 
+// Bytes of output a command passes on to the model unless GEMINI_COMMAND_OUTPUT_LIMIT says otherwise
+const DEFAULT_OUTPUT_LIMIT: usize = 50_000;
+// Share of the kept output taken from the beginning; the end, where builds and tests report
+// errors, gets the rest
+const HEAD_SHARE: usize = 4;
+// Full outputs kept in the temporary directory; older ones are removed
+const MAX_SAVED_OUTPUTS: usize = 20;
+
+// Where the working directory and exported variables of commands are kept between commands:
+// a directory of the user's own that nobody else can write to, since every command sources
+// the variables recorded there. Files left by sessions that ended more than a day ago are
+// removed. None when the directory can't be made private; commands then start afresh.
+static SHELL_DIR: Lazy<Option<PathBuf>> = Lazy::new(|| {
+    let dir = env::temp_dir().join(shell_dir_name());
+    if let Err(e) = private_dir(&dir) {
+        ui::status(
+            &format!("Warning: {}, so commands don't keep their working directory and variables", e)
+                .color(Color::Yellow)
+                .to_string(),
+        );
+        return None;
+    }
+    let own = format!("{}.", std::process::id());
+    for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age.as_secs() > 24 * 60 * 60);
+        // Files of an earlier session with the same process id would be taken for ours
+        if stale || entry.file_name().to_string_lossy().starts_with(&own) {
+            fs::remove_file(entry.path()).ok();
+        }
+    }
+    Some(dir)
+});
+// Working directory of the next command when an earlier one changed it
+static WORKING_DIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+// Where macOS keeps sandbox-exec
+const SANDBOX_EXEC: &str = "/usr/bin/sandbox-exec";
+
+// Seatbelt profile for sandbox-exec: like the bwrap setup, everything can be read and only
+// the sandbox (plus the temporary directories and devices) can be written
+const SEATBELT_PROFILE: &str = r#"(version 1)
+(allow default)
+(deny file-write*)
+(allow file-write*
+    (subpath (param "SANDBOX_ROOT"))
+    (subpath "/private/tmp")
+    (subpath "/private/var/folders")
+    (subpath "/dev"))"#;
+
+/// How commands are isolated from the rest of the system, chosen with GEMINI_SANDBOX.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// bubblewrap on Linux: the file system is read-only except for the sandbox
+    Bwrap,
+    /// sandbox-exec on macOS, with the same rules
+    Seatbelt,
+    /// Commands run directly: on Windows, with GEMINI_SANDBOX=none, or when no sandbox was
+    /// found (after a warning)
+    Direct,
+}
+
+impl Backend {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Backend::Bwrap => "bwrap",
+            Backend::Seatbelt => "sandbox-exec",
+            Backend::Direct => "none",
+        }
+    }
+}
+
+static BACKEND: Lazy<Result<Backend, String>> = Lazy::new(choose_backend);
+
+/// The sandbox backend commands run with, or why the configured one can't be used.
+pub fn backend() -> Result<Backend, String> {
+    BACKEND.clone()
+}
+
+/// Resolves GEMINI_SANDBOX: `auto` (the default) uses bwrap on Linux and sandbox-exec on
+/// macOS and refuses to run commands when they're missing, rather than run them unprotected;
+/// `bwrap` and `seatbelt` insist on their backend; `none` runs commands directly, which is
+/// also what `auto` does on Windows, where there is no sandbox to use.
+fn choose_backend() -> Result<Backend, String> {
+    let setting = env::var("GEMINI_SANDBOX").unwrap_or_default().trim().to_lowercase();
+    match setting.as_str() {
+        "" | "auto" => auto_backend(),
+        "bwrap" if cfg!(target_os = "linux") && in_path("bwrap") => Ok(Backend::Bwrap),
+        "bwrap" => Err("GEMINI_SANDBOX is bwrap, but bwrap is not installed (install bubblewrap)".to_string()),
+        "seatbelt" | "sandbox-exec" if cfg!(target_os = "macos") && Path::new(SANDBOX_EXEC).exists() => {
+            Ok(Backend::Seatbelt)
+        }
+        "seatbelt" | "sandbox-exec" => Err("GEMINI_SANDBOX is seatbelt, but sandbox-exec is only available on macOS".to_string()),
+        "none" | "off" => Ok(Backend::Direct),
+        other => Err(format!("Unknown GEMINI_SANDBOX '{}' (use auto, bwrap, seatbelt or none)", other)),
+    }
+}
+
+fn auto_backend() -> Result<Backend, String> {
+    let missing = if cfg!(target_os = "linux") {
+        if in_path("bwrap") {
+            return Ok(Backend::Bwrap);
+        }
+        "bwrap is not installed (install bubblewrap)"
+    } else if cfg!(target_os = "macos") {
+        if Path::new(SANDBOX_EXEC).exists() {
+            return Ok(Backend::Seatbelt);
+        }
+        "sandbox-exec was not found"
+    } else {
+        return Ok(Backend::Direct);
+    };
+    Err(format!(
+        "no command sandbox is available: {}. Commands are not run without one unless \
+         GEMINI_SANDBOX=none is set in ~/.gemini.conf",
+        missing
+    ))
+}
+
+fn in_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+/// Credentials commands may see anyway, from GEMINI_ENV_PASSTHROUGH (comma-separated names,
+/// or `*` for all of them).
+fn passed_through(name: &str) -> bool {
+    env::var("GEMINI_ENV_PASSTHROUGH")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(name))
+}
+
 pub fn execute_command(command: &str) -> String {
     if command.trim().is_empty() {
         return "Error: No command provided".to_string();
     }
+    stats::record_command(command);
 
-    let (program, args) = get_command_parts(command);
+    let backend = match backend() {
+        Ok(backend) => backend,
+        Err(e) => return format!("Error: {}", e),
+    };
+    let persistent = persistent() && create_session_files();
+    let script = if persistent { with_session(command) } else { command.to_string() };
+    let (program, args) = get_command_parts(&script, backend, persistent);
 
-    let child = Command::new(&program)
+    // When stdout is reserved for protocol traffic, the child gets no stdin and its output
+    // is echoed to stderr instead; in the TUI stdin is kept for the CLI's own questions
+    let interactive = !ui::stdout_reserved() && !tui::active();
+
+    let mut process = Command::new(&program);
+    process
         .args(&args)
-        .current_dir(&*SANDBOX_ROOT)
-        .stdin(Stdio::piped())
+        .current_dir(working_dir())
+        .stdin(if interactive { Stdio::piped() } else { Stdio::null() })
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn();
+        .stderr(Stdio::piped());
+    scrub_environment(&mut process);
+    let child = process.spawn();
 
     match child {
         Ok(mut child_proc) => {
             let stdout = child_proc.stdout.take().unwrap();
             let stderr = child_proc.stderr.take().unwrap();
 
-            if let Some(child_stdin) = child_proc.stdin.take() {
-                // Start a thread to forward input from parent stdin to child stdin
-                let input_handle = thread::spawn(move || {
+            // Start a thread to forward input from parent stdin to child stdin
+            let input_handle = child_proc.stdin.take().map(|child_stdin| {
+                thread::spawn(move || {
                     let mut child_stdin = child_stdin;
                     let mut buffer = [0u8; 1024];
                     loop {
@@ -64,125 +227,433 @@ pub fn execute_command(command: &str) -> String {
                             Err(_) => break,
                         }
                     }
-                });
-
-                // Start threads to read stdout and stderr, print to terminal, and collect
-                let stdout_handle = {
-                    let mut stdout = stdout;
-                    thread::spawn(move || {
-                        let mut buf = Vec::new();
-                        let mut temp = [0u8; 1024];
-                        loop {
-                            match stdout.read(&mut temp) {
-                                Ok(0) => break,
-                                Ok(n) => {
-                                    io::stdout().write_all(&temp[..n]).ok();
-                                    io::stdout().flush().ok();
-                                    buf.extend_from_slice(&temp[..n]);
-                                }
-                                Err(_) => break,
-                            }
-                        }
-                        buf
-                    })
-                };
-
-                let stderr_handle = {
-                    let mut stderr = stderr;
-                    thread::spawn(move || {
-                        let mut buf = Vec::new();
-                        let mut temp = [0u8; 1024];
-                        loop {
-                            match stderr.read(&mut temp) {
-                                Ok(0) => break,
-                                Ok(n) => {
-                                    io::stderr().write_all(&temp[..n]).ok();
-                                    io::stderr().flush().ok();
-                                    buf.extend_from_slice(&temp[..n]);
-                                }
-                                Err(_) => break,
-                            }
-                        }
-                        buf
-                    })
-                };
-
-                let status = child_proc.wait();
-                input_handle.join().ok();
-
-                let stdout_buf = stdout_handle.join().unwrap_or_default();
-                let stderr_buf = stderr_handle.join().unwrap_or_default();
-
-                match status {
-                    Ok(_) => {
-                        let stdout_str = String::from_utf8_lossy(&stdout_buf);
-                        let stderr_str = String::from_utf8_lossy(&stderr_buf);
-
-                        let output = if stdout_str.is_empty() && stderr_str.is_empty() {
-                            "Command executed (no output)".to_string()
-                        } else {
-                            format!("{}{}", stdout_str, stderr_str)
-                        };
-                        output
-                    }
-                    Err(e) => format!("Error waiting for command '{}': {:?}", command, e),
-                }
-            } else {
-                // No stdin pipe, just wait
-                let status = child_proc.wait();
-                match status {
-                    Ok(_) => {
-                        // Read stdout and stderr
-                        let mut stdout_buf = Vec::new();
-                        let mut stderr_buf = Vec::new();
-                        let mut stdout = stdout;
-                        let mut stderr = stderr;
-                        stdout.read_to_end(&mut stdout_buf).ok();
-                        stderr.read_to_end(&mut stderr_buf).ok();
-
-                        let stdout_str = String::from_utf8_lossy(&stdout_buf);
-                        let stderr_str = String::from_utf8_lossy(&stderr_buf);
-
-                        let output = if stdout_str.is_empty() && stderr_str.is_empty() {
-                            "Command executed (no output)".to_string()
-                        } else {
-                            format!("{}{}", stdout_str, stderr_str)
-                        };
-                        output
+                })
+            });
+
+            // Start threads to read stdout and stderr, print to terminal, and collect
+            let stdout_handle = tee_output(stdout, !interactive);
+            let stderr_handle = tee_output(stderr, true);
+
+            let status = child_proc.wait();
+            if let Some(handle) = input_handle {
+                handle.join().ok();
+            }
+
+            let stdout_buf = stdout_handle.join().unwrap_or_default();
+            let stderr_buf = stderr_handle.join().unwrap_or_default();
+
+            match status {
+                Ok(_) => {
+                    let stdout_str = decode_output(&stdout_buf);
+                    let stderr_str = decode_output(&stderr_buf);
+
+                    let output = if stdout_str.is_empty() && stderr_str.is_empty() {
+                        "Command executed (no output)".to_string()
+                    } else {
+                        truncate_output(format!("{}{}", stdout_str, stderr_str))
+                    };
+                    match persistent.then(update_working_dir).flatten() {
+                        Some(note) => format!("{}\n{}", output.trim_end(), note),
+                        None => output,
                     }
-                    Err(e) => format!("Error waiting for command '{}': {:?}", command, e),
                 }
+                Err(e) => format!("Error waiting for command '{}': {:?}", command, e),
             }
         }
         Err(e) => format!("Error spawning command '{}': {:?}", command, e),
     }
 }
 
-fn get_command_parts(command: &str) -> (String, Vec<String>) {
-    #[cfg(target_os = "linux")]
+/// A command set up to run in the sandbox like those of execute_command, in the current
+/// working directory but outside the shell session, with no input and its output piped. It
+/// gets a process group of its own, so Ctrl-C at the terminal doesn't reach it and it can be
+/// stopped together with its children.
+pub fn background_command(command: &str) -> Result<Command, String> {
+    let backend = backend()?;
+    let (program, args) = get_command_parts(command, backend, false);
+    let mut process = Command::new(&program);
+    process
+        .args(&args)
+        .current_dir(working_dir())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    scrub_environment(&mut process);
+    #[cfg(unix)]
     {
-        // Use bwrap
-        let args = vec![
-            "--ro-bind".to_string(), "/".to_string(), "/".to_string(),
-            "--bind".to_string(), SANDBOX_ROOT.clone(), SANDBOX_ROOT.clone(),
-            "--dev".to_string(), "/dev".to_string(),
-            "--proc".to_string(), "/proc".to_string(),
-            "/bin/sh".to_string(), "-c".to_string(), command.to_string(),
-        ];
-        ("bwrap".to_string(), args)
+        use std::os::unix::process::CommandExt;
+        process.process_group(0);
     }
-
-    #[cfg(target_os = "windows")]
+    #[cfg(windows)]
     {
-        // Use cmd
-        ("cmd".to_string(), vec!["/C".to_string(), command.to_string()])
+        use std::os::windows::process::CommandExt;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        process.creation_flags(CREATE_NEW_PROCESS_GROUP);
     }
+    Ok(process)
+}
 
-    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
-    {
-        // Use shell
+/// Keeps API keys, tokens and passwords out of the command's environment, except those
+/// GEMINI_ENV_PASSTHROUGH lets through. The sandboxes limit what commands can write, not what
+/// they can send elsewhere.
+pub fn scrub_environment(process: &mut Command) {
+    for (name, _) in env::vars_os() {
+        let name_text = name.to_string_lossy();
+        if config::is_secret(&name_text) && !passed_through(&name_text) {
+            process.env_remove(name);
+        }
+    }
+}
+
+/// Whether the working directory and exported variables carry over from one command to the
+/// next, as in a terminal; GEMINI_PERSISTENT_SHELL=0 starts every command afresh in the sandbox.
+fn persistent() -> bool {
+    !env::var("GEMINI_PERSISTENT_SHELL")
+        .map(|v| v == "0" || v == "false")
+        .unwrap_or(false)
+}
+
+/// Forgets the working directory and variables left by earlier commands, so the next one
+/// starts in the sandbox root again (used when the conversation is cleared).
+pub fn reset_session() {
+    *WORKING_DIR.lock().unwrap() = None;
+    if SHELL_DIR.is_some() {
+        fs::remove_file(session_file("cwd")).ok();
+        fs::remove_file(session_file("env")).ok();
+    }
+}
+
+/// A file of this session in SHELL_DIR; only used once SHELL_DIR is known to exist.
+fn session_file(kind: &str) -> PathBuf {
+    let dir = SHELL_DIR.as_ref().expect("the shell directory is private");
+    dir.join(format!("{}.{}", std::process::id(), kind))
+}
+
+/// Creates the session files that don't exist yet, readable by the user only, so the shell
+/// writes into files of ours. Returns false when there is no private directory for them.
+fn create_session_files() -> bool {
+    if SHELL_DIR.is_none() {
+        return false;
+    }
+    ["cwd", "env"].iter().all(|kind| {
+        let path = session_file(kind);
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&path) {
+            Ok(_) => true,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => fs::symlink_metadata(&path).is_ok_and(|m| m.is_file()),
+            Err(_) => false,
+        }
+    })
+}
+
+#[cfg(unix)]
+fn shell_dir_name() -> String {
+    // SAFETY: geteuid has no preconditions and can't fail
+    format!("gemini-cli-shell-{}", unsafe { libc::geteuid() })
+}
+
+// The temporary directory is the user's own on Windows
+#[cfg(not(unix))]
+fn shell_dir_name() -> String {
+    "gemini-cli-shell".to_string()
+}
+
+/// Creates `dir` with access for the user only, or checks that the existing one is a
+/// directory (not a link) that belongs to the user and that no one else can open.
+#[cfg(unix)]
+fn private_dir(dir: &Path) -> Result<(), String> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(format!("{} can't be created: {}", dir.display(), e)),
+    }
+    let metadata = fs::symlink_metadata(dir).map_err(|e| format!("{} can't be read: {}", dir.display(), e))?;
+    // SAFETY: geteuid has no preconditions and can't fail
+    let uid = unsafe { libc::geteuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(format!("{} is not a private directory of this user", dir.display()));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn private_dir(dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("{} can't be created: {}", dir.display(), e))
+}
+
+fn working_dir() -> PathBuf {
+    WORKING_DIR
+        .lock()
+        .unwrap()
+        .clone()
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| PathBuf::from(&*SANDBOX_ROOT))
+}
+
+/// The command between lines that restore the variables exported by earlier commands and
+/// record the directory and variables it leaves behind. Keys and passwords are never
+/// recorded. cmd only records the directory.
+fn with_session(command: &str) -> String {
+    let cwd = session_file("cwd");
+    if cfg!(target_os = "windows") {
+        return format!("{} & cd > \"{}\"", command, cwd.display());
+    }
+    let vars = sh_quote(&session_file("env").to_string_lossy());
+    let secrets: Vec<String> = env::vars_os()
+        .map(|(name, _)| name.to_string_lossy().to_string())
+        .filter(|name| config::is_secret(name))
+        .collect();
+    let unset = if secrets.is_empty() { String::new() } else { format!("unset {}\n", secrets.join(" ")) };
+    format!(
+        "[ -f {vars} ] && . {vars} 2>/dev/null\n{command}\n__gemini_status=$?\n{unset}export -p > {vars}\npwd > {cwd}\nexit $__gemini_status",
+        vars = vars,
+        command = command,
+        unset = unset,
+        cwd = sh_quote(&cwd.to_string_lossy()),
+    )
+}
+
+fn sh_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Takes over the directory the last command ended in. Returns a note for the model when it
+/// changed; a directory outside the sandbox is not kept.
+fn update_working_dir() -> Option<String> {
+    let recorded = fs::read(session_file("cwd")).ok()?;
+    fs::remove_file(session_file("cwd")).ok();
+    let dir = PathBuf::from(decode_output(&recorded).trim());
+    let root = Path::new(&*SANDBOX_ROOT);
+    let canonical_root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let canonical = dir.canonicalize().unwrap_or(dir);
+
+    let mut working_dir = WORKING_DIR.lock().unwrap();
+    let (next, note) = match canonical.strip_prefix(&canonical_root) {
+        Ok(relative) if relative.as_os_str().is_empty() => (None, "[Working directory is now the sandbox root]".to_string()),
+        Ok(relative) => (Some(canonical.clone()), format!("[Working directory is now {}]", relative.display())),
+        Err(_) => {
+            *working_dir = None;
+            return Some("[The command left the sandbox; the next command starts in the sandbox root]".to_string());
+        }
+    };
+    let changed = next != *working_dir;
+    *working_dir = next;
+    changed.then_some(note)
+}
+
+/// Most bytes of output sent back for one command; GEMINI_COMMAND_OUTPUT_LIMIT=0 sends all of it.
+fn output_limit() -> usize {
+    env::var("GEMINI_COMMAND_OUTPUT_LIMIT")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_OUTPUT_LIMIT)
+}
+
+/// Shortens long output to whole lines from its beginning and its end, with a note on how much
+/// was left out. With GEMINI_COMMAND_OUTPUT_FILE=1 the full output is saved to a temporary file
+/// the model can read in parts.
+fn truncate_output(output: String) -> String {
+    let limit = output_limit();
+    if limit == 0 || output.len() <= limit {
+        return output;
+    }
+
+    let head_end = floor_boundary(&output, limit / HEAD_SHARE);
+    let head_end = output[..head_end].rfind('\n').map(|i| i + 1).unwrap_or(head_end);
+    let tail_start = ceil_boundary(&output, output.len() - (limit - limit / HEAD_SHARE));
+    let tail_start = output[tail_start..]
+        .find('\n')
+        .map(|i| tail_start + i + 1)
+        .filter(|&i| i < output.len())
+        .unwrap_or(tail_start);
+
+    let omitted = &output[head_end..tail_start];
+    let first = output[..head_end].matches('\n').count() + 1;
+    let last = first + omitted.matches('\n').count() - usize::from(omitted.ends_with('\n'));
+    let lines = if first == last { format!("line {}", first) } else { format!("lines {}-{}", first, last) };
+    let mut note = format!("[... {} bytes of output omitted ({}) ...]", omitted.len(), lines);
+    if save_full_output() {
+        match save_output(&output) {
+            Ok(path) => note.push_str(&format!(
+                "\n[The full output is in {}; read the omitted lines in parts with execute_command, e.g. {}]",
+                path,
+                if cfg!(target_os = "windows") {
+                    format!("powershell \"Get-Content '{}' | Select-Object -Skip {} -First 100\"", path, first - 1)
+                } else {
+                    format!("sed -n '{},{}p' '{}' or grep -n error '{}'", first, (first + 99).min(last), path, path)
+                }
+            )),
+            Err(e) => note.push_str(&format!("\n[The full output could not be saved: {}]", e)),
+        }
+    }
+
+    let mut head = output[..head_end].to_string();
+    if !head.is_empty() && !head.ends_with('\n') {
+        head.push('\n');
+    }
+    format!("{}{}\n{}", head, note, &output[tail_start..])
+}
+
+/// Whether long outputs are saved in full before they're shortened (GEMINI_COMMAND_OUTPUT_FILE=1).
+fn save_full_output() -> bool {
+    env::var("GEMINI_COMMAND_OUTPUT_FILE")
+        .map(|v| v == "1" || v == "true")
+        .unwrap_or(false)
+}
+
+/// Writes an output to the gemini-cli-output folder of the temporary directory, which commands
+/// can read from inside the sandbox, and returns its path.
+fn save_output(output: &str) -> Result<String, String> {
+    let dir = env::temp_dir().join("gemini-cli-output");
+    fs::create_dir_all(&dir).map_err(|e| format!("Error creating '{}': {}", dir.display(), e))?;
+    let path = dir.join(format!("command-{}.log", Local::now().format("%Y%m%d-%H%M%S-%3f")));
+    fs::write(&path, output).map_err(|e| format!("Error writing '{}': {}", path.display(), e))?;
+
+    let mut saved: Vec<PathBuf> = fs::read_dir(&dir)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    saved.sort();
+    for old in &saved[..saved.len().saturating_sub(MAX_SAVED_OUTPUTS)] {
+        fs::remove_file(old).ok();
+    }
+    Ok(path.to_string_lossy().to_string())
+}
+
+fn floor_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+/// Decodes command output that isn't necessarily UTF-8. Valid UTF-8 lines are kept as they
+/// are; the others are decoded with the console code page on Windows (OEM code pages such as
+/// 437 or 850), or else with the encoding detected from those lines.
+pub fn decode_output(bytes: &[u8]) -> String {
+    if let Ok(text) = str::from_utf8(bytes) {
+        return text.to_string();
+    }
+
+    // Tools can mix encodings, e.g. UTF-8 file names in a legacy-encoded listing
+    let lines: Vec<&[u8]> = bytes.split_inclusive(|&b| b == b'\n').collect();
+    let mut detector = EncodingDetector::new();
+    for line in lines.iter().filter(|line| str::from_utf8(line).is_err()) {
+        detector.feed(line, false);
+    }
+    detector.feed(&[], true);
+    let encoding = detector.guess(None, false);
+
+    lines
+        .iter()
+        .map(|line| match str::from_utf8(line) {
+            Ok(text) => text.to_string(),
+            Err(_) => decode_console_codepage(line).unwrap_or_else(|| encoding.decode(line).0.into_owned()),
+        })
+        .collect()
+}
+
+#[cfg(target_os = "windows")]
+fn decode_console_codepage(bytes: &[u8]) -> Option<String> {
+    use windows_sys::Win32::Globalization::MultiByteToWideChar;
+    use windows_sys::Win32::System::Console::GetConsoleOutputCP;
+
+    const CP_UTF8: u32 = 65001;
+    // SAFETY: plain Win32 calls; the output buffer is sized by the first call
+    unsafe {
+        let codepage = GetConsoleOutputCP();
+        if codepage == 0 || codepage == CP_UTF8 {
+            return None;
+        }
+        let len = MultiByteToWideChar(codepage, 0, bytes.as_ptr(), bytes.len() as i32, std::ptr::null_mut(), 0);
+        if len <= 0 {
+            return None;
+        }
+        let mut wide = vec![0u16; len as usize];
+        let written = MultiByteToWideChar(codepage, 0, bytes.as_ptr(), bytes.len() as i32, wide.as_mut_ptr(), len);
+        if written <= 0 {
+            return None;
+        }
+        Some(String::from_utf16_lossy(&wide[..written as usize]))
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn decode_console_codepage(_bytes: &[u8]) -> Option<String> {
+    None
+}
+
+/// Copies a child's output stream to our stdout or stderr as it arrives, and collects it.
+fn tee_output<R: Read + Send + 'static>(mut source: R, to_stderr: bool) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let mut temp = [0u8; 1024];
+        loop {
+            match source.read(&mut temp) {
+                Ok(0) => break,
+                Ok(n) => {
+                    if to_stderr {
+                        io::stderr().write_all(&temp[..n]).ok();
+                        io::stderr().flush().ok();
+                    } else {
+                        io::stdout().write_all(&temp[..n]).ok();
+                        io::stdout().flush().ok();
+                    }
+                    buf.extend_from_slice(&temp[..n]);
+                }
+                Err(_) => break,
+            }
+        }
+        buf
+    })
+}
+
+fn get_command_parts(command: &str, backend: Backend, persistent: bool) -> (String, Vec<String>) {
+    match backend {
+        Backend::Bwrap => {
+            let mut args = vec![
+                "--ro-bind".to_string(), "/".to_string(), "/".to_string(),
+                "--bind".to_string(), SANDBOX_ROOT.clone(), SANDBOX_ROOT.clone(),
+            ];
+            if let Some(shell_dir) = SHELL_DIR.as_ref().filter(|_| persistent) {
+                let shell_dir = shell_dir.to_string_lossy().to_string();
+                args.extend(["--bind".to_string(), shell_dir.clone(), shell_dir]);
+            }
+            args.extend([
+                "--dev".to_string(), "/dev".to_string(),
+                "--proc".to_string(), "/proc".to_string(),
+                "/bin/sh".to_string(), "-c".to_string(), command.to_string(),
+            ]);
+            ("bwrap".to_string(), args)
+        }
+        Backend::Seatbelt => {
+            let args = vec![
+                "-p".to_string(), SEATBELT_PROFILE.to_string(),
+                "-D".to_string(), format!("SANDBOX_ROOT={}", *SANDBOX_ROOT),
+                "/bin/zsh".to_string(), "-c".to_string(), command.to_string(),
+            ];
+            (SANDBOX_EXEC.to_string(), args)
+        }
+        Backend::Direct => shell_parts(command),
+    }
+}
+
+fn shell_parts(command: &str) -> (String, Vec<String>) {
+    if cfg!(target_os = "windows") {
+        ("cmd".to_string(), vec!["/C".to_string(), command.to_string()])
+    } else {
         let shell = if cfg!(target_os = "macos") { "/bin/zsh" } else { "/bin/sh" };
         (shell.to_string(), vec!["-c".to_string(), command.to_string()])
     }
 }
-
@@ -0,0 +1,166 @@
+use colored::{Color, Colorize};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use crate::diff_render::colorize_patch;
+use crate::models::Task;
+use crate::ChatManager;
+
+// Large diffs are cut to keep the request reasonable; the --stat summary is always sent in full
+const MAX_DIFF_CHARS: usize = 60_000;
+
+const INSTRUCTION: &str = "You write git commit messages in the Conventional Commits format. \
+Reply with the commit message only: no code fences, no commentary. \
+The first line is `type(optional scope): summary` in the imperative mood, at most 72 characters, \
+where type is one of feat, fix, docs, style, refactor, perf, test, build, ci, chore or revert. \
+If the change needs explanation, add a blank line and a short body wrapped at 72 columns that says what changed and why. \
+Mark breaking changes with `!` after the type and a `BREAKING CHANGE:` footer.";
+
+/// Handles `gemini-cli-rs commit`: drafts a message for the staged changes, lets the
+/// user accept, edit or regenerate it, and then runs `git commit`.
+pub fn run(chat_manager: &Arc<Mutex<ChatManager>>, yes: bool, hint: Option<&str>) -> Result<(), String> {
+    let stat = git(&["diff", "--staged", "--stat"])?;
+    if stat.trim().is_empty() {
+        return Err("Nothing is staged. Stage your changes with `git add` first.".to_string());
+    }
+    let diff = git(&["diff", "--staged"])?;
+
+    println!("{}", "Staged changes:".color(Color::Cyan).bold());
+    println!("{}", stat.trim_end());
+    println!();
+
+    let prompt = build_prompt(&stat, &diff, hint);
+    let mut message = draft(chat_manager, &prompt)?;
+
+    loop {
+        println!("{}", "Proposed commit message:".color(Color::Cyan).bold());
+        println!("{}", message.color(Color::Yellow));
+        println!();
+
+        if yes {
+            return commit(&message);
+        }
+
+        print!("{}", "[c]ommit, [e]dit, [r]egenerate, [d]iff, or [q]uit? ".color(Color::Green).bold());
+        io::stdout().flush().ok();
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).map_err(|e| format!("Input error: {}", e))?;
+
+        match input.trim().to_lowercase().as_str() {
+            "c" | "commit" | "y" | "yes" => return commit(&message),
+            "e" | "edit" => message = edit(&message)?,
+            "r" | "regenerate" => message = draft(chat_manager, &prompt)?,
+            "d" | "diff" => println!("{}\n", colorize_patch(&diff)),
+            "q" | "quit" | "n" | "no" => {
+                println!("{}", "Commit cancelled.".color(Color::Cyan));
+                return Ok(());
+            }
+            _ => println!("{}", "Please answer c, e, r, d or q.".color(Color::Red)),
+        }
+    }
+}
+
+fn build_prompt(stat: &str, diff: &str, hint: Option<&str>) -> String {
+    let diff = if diff.len() > MAX_DIFF_CHARS {
+        let mut end = MAX_DIFF_CHARS;
+        while !diff.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}\n[diff truncated]", &diff[..end])
+    } else {
+        diff.to_string()
+    };
+    let mut prompt = format!("Write a commit message for these staged changes.\n\nSummary:\n{}\nDiff:\n{}", stat, diff);
+    if let Some(hint) = hint {
+        prompt.push_str(&format!("\n\nContext from the author: {}", hint));
+    }
+    prompt
+}
+
+fn draft(chat_manager: &Arc<Mutex<ChatManager>>, prompt: &str) -> Result<String, String> {
+    let reply = chat_manager
+        .lock()
+        .unwrap()
+        .complete(Task::Main, INSTRUCTION, prompt, "drafting commit message")?;
+    Ok(strip_fences(&reply))
+}
+
+/// Models sometimes wrap the message in a code fence despite being asked not to.
+fn strip_fences(reply: &str) -> String {
+    reply
+        .trim()
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Opens the message in $VISUAL / $EDITOR, like `git commit` does.
+fn edit(message: &str) -> Result<String, String> {
+    let path = env::temp_dir().join(format!("gemini-commit-{}.txt", std::process::id()));
+    let template = format!(
+        "{}\n\n# Edit the commit message above. Lines starting with '#' are ignored,\n# and an empty message keeps the previous one.\n",
+        message
+    );
+    fs::write(&path, template).map_err(|e| format!("Error writing to '{}': {}", path.display(), e))?;
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(target_os = "windows") { "notepad".to_string() } else { "vi".to_string() });
+    // The editor setting may include arguments, e.g. "code --wait"
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(parts)
+        .arg(&path)
+        .status()
+        .map_err(|e| format!("Failed to launch editor '{}': {}", editor, e))?;
+
+    let edited = fs::read_to_string(&path).unwrap_or_default();
+    fs::remove_file(&path).ok();
+    if !status.success() {
+        return Err(format!("Editor '{}' exited with {}", editor, status));
+    }
+
+    let edited = edited
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string();
+    Ok(if edited.is_empty() { message.to_string() } else { edited })
+}
+
+fn commit(message: &str) -> Result<(), String> {
+    let status = Command::new("git")
+        .args(["commit", "-m", message])
+        .status()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("git commit failed ({})", status))
+    }
+}
+
+/// Runs git and returns its stdout, or its stderr as the error.
+pub fn git(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
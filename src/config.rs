@@ -0,0 +1,240 @@
+use clap::Subcommand;
+use colored::{Color, Colorize};
+use std::fs;
+use std::path::PathBuf;
+
+// Words of a name that make it a credential
+const SECRET_WORDS: &[&str] = &[
+    "KEY", "KEYS", "APIKEY", "TOKEN", "TOKENS", "PASSWORD", "PASSWD", "PASSPHRASE", "SECRET", "SECRETS", "CREDENTIAL",
+    "CREDENTIALS",
+];
+// Parts that make a name a credential wherever they appear, as in PGPASSWORD or MYSQL_PWD
+const SECRET_PARTS: &[&str] = &["PASSWORD", "PASSWD", "SECRET", "PWD"];
+// Words that make a TOKEN name a number of model tokens
+const TOKEN_COUNT_WORDS: &[&str] = &["MAX", "BUDGET", "LIMIT"];
+// The shell's working directories, which end in PWD
+const NOT_SECRET: &[&str] = &["PWD", "OLDPWD"];
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Show all settings (secrets are masked)
+    Show,
+    /// Print the value of a setting
+    Get { key: String },
+    /// Set a setting, creating ~/.gemini.conf if needed
+    Set { key: String, value: String },
+    /// Remove a setting
+    Unset { key: String },
+    /// Print the path of the configuration file
+    Path,
+}
+
+/// Location of the configuration file loaded at startup.
+pub fn config_path() -> PathBuf {
+    dirs::home_dir()
+        .expect("Could not determine home directory")
+        .join(".gemini.conf")
+}
+
+/// Handles `gemini-cli-rs config ...`.
+pub fn run(action: Option<ConfigAction>) -> Result<(), String> {
+    match action.unwrap_or(ConfigAction::Show) {
+        ConfigAction::Show => {
+            let entries = read_entries();
+            if entries.is_empty() {
+                println!("{}", format!("No settings in {}", config_path().display()).color(Color::Cyan));
+            }
+            for (key, value) in entries {
+                let shown = if is_secret(&key) { mask(&value) } else { value };
+                println!("{}={}", key.color(Color::Cyan), shown);
+            }
+            Ok(())
+        }
+        ConfigAction::Get { key } => {
+            let value = get(&key).ok_or_else(|| format!("{} is not set", key))?;
+            println!("{}", value);
+            Ok(())
+        }
+        ConfigAction::Set { key, value } => {
+            set(&key, &value)?;
+            println!("{}", format!("{} updated in {}", key, config_path().display()).color(Color::Cyan));
+            Ok(())
+        }
+        ConfigAction::Unset { key } => {
+            if unset(&key)? {
+                println!("{}", format!("{} removed", key).color(Color::Cyan));
+            } else {
+                println!("{}", format!("{} was not set", key).color(Color::Cyan));
+            }
+            Ok(())
+        }
+        ConfigAction::Path => {
+            println!("{}", config_path().display());
+            Ok(())
+        }
+    }
+}
+
+/// Reads the KEY=VALUE pairs from the configuration file, in file order.
+pub fn read_entries() -> Vec<(String, String)> {
+    fs::read_to_string(config_path())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(parse_line)
+        .collect()
+}
+
+pub fn get(key: &str) -> Option<String> {
+    read_entries()
+        .into_iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v)
+}
+
+/// Sets a key in the file, keeping comments and the order of other settings.
+pub fn set(key: &str, value: &str) -> Result<(), String> {
+    validate_key(key)?;
+    let mut lines = read_lines();
+    let new_line = format!("{}={}", key, value);
+    match lines
+        .iter()
+        .position(|line| parse_line(line).map(|(k, _)| k == key).unwrap_or(false))
+    {
+        Some(index) => lines[index] = new_line,
+        None => lines.push(new_line),
+    }
+    write_lines(&lines)
+}
+
+/// Removes a key from the file. Returns whether it was present.
+pub fn unset(key: &str) -> Result<bool, String> {
+    let lines = read_lines();
+    let kept: Vec<String> = lines
+        .iter()
+        .filter(|line| parse_line(line).map(|(k, _)| k != key).unwrap_or(true))
+        .cloned()
+        .collect();
+    if kept.len() == lines.len() {
+        return Ok(false);
+    }
+    write_lines(&kept)?;
+    Ok(true)
+}
+
+fn read_lines() -> Vec<String> {
+    fs::read_to_string(config_path())
+        .unwrap_or_default()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+fn write_lines(lines: &[String]) -> Result<(), String> {
+    let path = config_path();
+    let mut content = lines.join("\n");
+    content.push('\n');
+    fs::write(&path, content).map_err(|e| format!("Error writing to '{}': {}", path.display(), e))
+}
+
+/// Parses a KEY=VALUE line, skipping blanks and comments.
+pub fn parse_line(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (key, value) = line.split_once('=')?;
+    // Drop trailing comments on unquoted values, as dotenv does
+    let value = value.trim();
+    let value = if value.starts_with('"') || value.starts_with('\'') {
+        value.trim_matches(|c| c == '"' || c == '\'')
+    } else {
+        value.split(" #").next().unwrap_or("").trim()
+    };
+    Some((key.trim().to_string(), value.to_string()))
+}
+
+fn validate_key(key: &str) -> Result<(), String> {
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("Invalid setting name '{}' (use letters, digits and underscores)", key));
+    }
+    Ok(())
+}
+
+/// Whether a setting or environment variable holds a credential, judging by the words of its
+/// name: keys, tokens, passwords and secrets along with the IDs, files and paths that go with
+/// them (AWS_ACCESS_KEY_ID, GITHUB_TOKEN_FILE, SSH_KEY_PATH), and account user names such as
+/// SMTP_USERNAME. Passwords and secrets count inside a word too (PGPASSWORD, AWSSECRETKEY).
+/// Numbers of model tokens (GEMINI_MAX_OUTPUT_TOKENS, GEMINI_TOOL_TOKEN_BUDGET) and the bare
+/// USERNAME of the login are not credentials.
+pub fn is_secret(key: &str) -> bool {
+    let key = key.to_uppercase();
+    if NOT_SECRET.contains(&key.as_str()) {
+        return false;
+    }
+    if SECRET_PARTS.iter().any(|part| key.contains(part)) {
+        return true;
+    }
+    let words: Vec<&str> = key.split(|c: char| !c.is_ascii_alphanumeric()).filter(|w| !w.is_empty()).collect();
+    let counts_tokens = words.iter().any(|word| TOKEN_COUNT_WORDS.contains(word));
+    words
+        .iter()
+        .any(|word| SECRET_WORDS.contains(word) && !(counts_tokens && word.starts_with("TOKEN")))
+        || (words.len() > 1 && words.last() == Some(&"USERNAME"))
+}
+
+fn mask(value: &str) -> String {
+    let visible: String = value.chars().rev().take(4).collect::<Vec<_>>().into_iter().rev().collect();
+    if value.chars().count() <= 8 {
+        "***masked***".to_string()
+    } else {
+        format!("***{}", visible)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_are_secret() {
+        for key in [
+            "GEMINI_API_KEY",
+            "GEMINI_API_KEYS",
+            "GITHUB_TOKEN",
+            "GITHUB_TOKEN_FILE",
+            "AWS_ACCESS_KEY_ID",
+            "AWS_SECRET_ACCESS_KEY",
+            "SSH_KEY_PATH",
+            "SMTP_USERNAME",
+            "SMTP_PASSWORD",
+            "GOOGLE_APPLICATION_CREDENTIALS",
+            "openai_apikey",
+            "PGPASSWORD",
+            "DBPASSWORD",
+            "MYSQL_PWD",
+            "AWSSECRETKEY",
+            "LDAP_PASSWD",
+        ] {
+            assert!(is_secret(key), "{} is not treated as a secret", key);
+        }
+    }
+
+    #[test]
+    fn other_settings_are_not() {
+        for key in [
+            "GEMINI_MAX_OUTPUT_TOKENS",
+            "GEMINI_SESSION_TOKEN_BUDGET",
+            "GEMINI_ENV_PASSTHROUGH",
+            "GEMINI_USER_AGENT",
+            "USERNAME",
+            "USER",
+            "KEYBOARD_LAYOUT",
+            "SSH_AUTH_SOCK",
+            "SMTP_SERVER_IP",
+            "PWD",
+            "OLDPWD",
+        ] {
+            assert!(!is_secret(key), "{} is treated as a secret", key);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::env;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::i18n;
+
+// Tools that only read, and so skip confirmation in safe mode unless GEMINI_CONFIRM_SKIP says otherwise
+const DEFAULT_SKIP: &[&str] = &["search_online", "scrape_url", "alpha_vantage_query", "fx_rate", "crypto_price", "company_overview", "company_earnings", "list_files", "search_codebase", "list_jobs"];
+
+// Operations of the multi-purpose tools that don't change anything
+const READ_ONLY_OPERATIONS: &[(&str, &str, &str)] = &[
+    ("file_editor", "subcommand", "read"),
+    ("file_editor", "subcommand", "search"),
+    ("github", "operation", "list_issues"),
+    ("github", "operation", "view_issue"),
+    ("github", "operation", "view_pr_diff"),
+    ("tracker", "operation", "get_issue"),
+    ("tracker", "operation", "my_issues"),
+];
+
+// HTTP methods that don't change anything on the server; http_request defaults to GET
+const SAFE_METHODS: &[&str] = &["GET", "HEAD", "OPTIONS"];
+
+// file_editor subcommands that are approved after their diff is shown, not before the call
+const EDIT_SUBCOMMANDS: &[&str] = &["write", "search_and_replace", "apply_diff"];
+
+static SAFE_MODE: AtomicBool = AtomicBool::new(false);
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+// Tools the user answered "always" for in this session
+static ALWAYS: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Turns on safe mode (--confirm or GEMINI_CONFIRM=1): every tool call that can change
+/// something is shown and has to be approved.
+pub fn set_safe_mode(enabled: bool) {
+    SAFE_MODE.store(enabled, Ordering::SeqCst);
+}
+
+pub fn safe_mode() -> bool {
+    SAFE_MODE.load(Ordering::SeqCst)
+        || env::var("GEMINI_CONFIRM")
+            .map(|v| v == "1" || v == "true")
+            .unwrap_or(false)
+}
+
+/// Turns on dry-run mode (--dry-run): tool calls that would change something are simulated.
+pub fn set_dry_run(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::SeqCst);
+}
+
+pub fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::SeqCst)
+}
+
+/// Whether a tool call is simulated instead of run: in dry-run mode, every call that can
+/// change something. File edits are not caught here; they compute and show their diff and
+/// stop before writing.
+pub fn simulated(tool: &str, args: &Value) -> bool {
+    let file_edit = tool == "apply_changeset"
+        || (tool == "file_editor" && args["subcommand"].as_str().is_some_and(|s| EDIT_SUBCOMMANDS.contains(&s)));
+    dry_run() && !DEFAULT_SKIP.contains(&tool) && !is_read_only(tool, args) && !file_edit
+}
+
+/// Whether a tool call has to be approved before it runs. Shell commands always are, in the
+/// foreground or the background, and so are HTTP requests other than GET, HEAD and OPTIONS; in
+/// safe mode so is everything else except read-only operations and the tools in
+/// GEMINI_CONFIRM_SKIP. An "always" answer approves a tool for the rest of the session.
+pub fn required(tool: &str, args: &Value) -> bool {
+    if ALWAYS.lock().unwrap().contains(tool) || skipped().iter().any(|t| t == tool) {
+        return false;
+    }
+    if tool == "execute_command" || tool == "run_in_background" {
+        return true;
+    }
+    if tool == "http_request" && !is_read_only(tool, args) {
+        return true;
+    }
+    if tool == "file_editor" && args["subcommand"].as_str().map(|s| EDIT_SUBCOMMANDS.contains(&s)).unwrap_or(false) {
+        return false;
+    }
+    safe_mode() && !is_read_only(tool, args)
+}
+
+/// Whether a file edit has to be approved once its diff has been shown: in safe mode, or
+/// with GEMINI_CONFIRM_EDITS=1, unless file_editor is skipped or was approved for good.
+pub fn edit_required() -> bool {
+    let confirm_edits = env::var("GEMINI_CONFIRM_EDITS")
+        .map(|v| v == "1" || v == "true")
+        .unwrap_or(false);
+    (safe_mode() || confirm_edits)
+        && !ALWAYS.lock().unwrap().contains("file_editor")
+        && !skipped().iter().any(|t| t == "file_editor")
+}
+
+/// Asks whether to apply the edit whose diff was just shown. "always" approves every further
+/// edit of the session.
+pub fn ask_edit() -> bool {
+    print!("{} ", i18n::t("policy.confirm_edit"));
+    io::stdout().flush().ok();
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input).is_err() {
+        return false;
+    }
+    if i18n::is_always(&input) {
+        ALWAYS.lock().unwrap().insert("file_editor".to_string());
+        return true;
+    }
+    i18n::is_yes(&input)
+}
+
+/// Shows the call with its exact arguments and asks for y/n/always. Returns whether the
+/// call may run.
+pub fn ask(tool: &str, args: &Value) -> bool {
+    match (tool, args["command"].as_str()) {
+        ("execute_command" | "run_in_background", Some(command)) => {
+            println!("{}", i18n::tf("policy.confirm_command", &[("command", &command.color(Color::Magenta))]));
+        }
+        _ => {
+            println!("{}", i18n::tf("policy.confirm_tool", &[("tool", &tool.color(Color::Magenta))]));
+            for (name, value) in args.as_object().into_iter().flatten() {
+                let value = match value {
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                };
+                let mut lines = value.lines();
+                println!("  {}: {}", name.bold(), lines.next().unwrap_or(""));
+                for line in lines {
+                    println!("  {}  {}", " ".repeat(name.chars().count()), line);
+                }
+            }
+            print!("{} ", i18n::t("policy.confirm_prompt"));
+            io::stdout().flush().ok();
+        }
+    }
+
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input).is_err() {
+        return false;
+    }
+    if i18n::is_always(&input) {
+        ALWAYS.lock().unwrap().insert(tool.to_string());
+        return true;
+    }
+    i18n::is_yes(&input)
+}
+
+/// Tools that never need confirmation, from GEMINI_CONFIRM_SKIP (read-only tools by default).
+fn skipped() -> Vec<String> {
+    match env::var("GEMINI_CONFIRM_SKIP") {
+        Ok(list) => list
+            .split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect(),
+        Err(_) => DEFAULT_SKIP.iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+fn is_read_only(tool: &str, args: &Value) -> bool {
+    if tool == "http_request" {
+        let method = args["method"].as_str().unwrap_or("GET").trim().to_uppercase();
+        return SAFE_METHODS.contains(&method.as_str());
+    }
+    READ_ONLY_OPERATIONS
+        .iter()
+        .any(|(name, key, operation)| *name == tool && args[*key] == *operation)
+}
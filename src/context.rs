@@ -0,0 +1,208 @@
+use colored::{Color, Colorize};
+use serde_json::{json, Value};
+use std::env;
+
+use crate::api_error::ApiError;
+use crate::http;
+use crate::images;
+use crate::keys;
+use crate::models::{self, Task};
+use crate::providers;
+use crate::ui;
+
+// Context window of the Gemini 2.5 models, in tokens
+const DEFAULT_CONTEXT_WINDOW: usize = 1_048_576;
+// Most tokens a single tool result may add, whatever room is left
+const DEFAULT_MAX_RESULT_TOKENS: usize = 50_000;
+// Share of the remaining context a single tool result may use
+const MAX_SHARE_OF_REMAINING: usize = 4;
+// Percentage of the context window at which the oldest turns are dropped
+const DEFAULT_TRIM_PERCENT: usize = 90;
+// Percentage of the context window left in use after dropping turns
+const TRIM_TARGET_PERCENT: usize = 70;
+
+/// Rough token count: about four characters per token for English text and code.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Rough token count of a history entry. Images count as a fixed number of tokens, not by
+/// the length of their base64 data.
+pub fn estimate_content_tokens(content: &Value) -> usize {
+    let mut content = content.clone();
+    let mut image_tokens = 0;
+    if let Some(parts) = content["parts"].as_array_mut() {
+        parts.retain(|part| {
+            let is_image = images::inline_data(part).is_some();
+            if is_image {
+                image_tokens += images::IMAGE_TOKENS;
+            }
+            !is_image
+        });
+    }
+    estimate_tokens(&content.to_string()) + image_tokens
+}
+
+/// Context window of the main model, overridable with GEMINI_CONTEXT_WINDOW.
+pub fn context_window() -> usize {
+    env::var("GEMINI_CONTEXT_WINDOW")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or_else(|| match models::model_for(Task::Main) {
+            m if m.starts_with("gemini-2") || m.starts_with("gemini-1.5") => DEFAULT_CONTEXT_WINDOW,
+            _ => DEFAULT_CONTEXT_WINDOW / 8,
+        })
+}
+
+/// Tokens at which the oldest turns are dropped before the next request; GEMINI_TRIM_PERCENT
+/// sets the share of the context window (default 90).
+pub fn trim_threshold() -> usize {
+    let percent = env::var("GEMINI_TRIM_PERCENT")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|p| (1..=100).contains(p))
+        .unwrap_or(DEFAULT_TRIM_PERCENT);
+    context_window() / 100 * percent
+}
+
+/// Tokens the conversation is cut down to once it reached the threshold.
+pub fn trim_target() -> usize {
+    (context_window() / 100 * TRIM_TARGET_PERCENT).min(trim_threshold())
+}
+
+/// Whether earlier turns are summarized, rather than dropped, when the conversation reaches
+/// the trim threshold; GEMINI_AUTO_COMPACT=0 turns it off.
+pub fn auto_compact() -> bool {
+    env::var("GEMINI_AUTO_COMPACT")
+        .map(|v| v != "0" && v != "false")
+        .unwrap_or(true)
+}
+
+/// Exact size of a generateContent request body for the main model, from the countTokens
+/// endpoint. Only Gemini has one; other providers rely on the estimate.
+pub fn count_tokens(body: &Value) -> Result<usize, String> {
+    if providers::current().name() != "gemini" {
+        return Err(format!("{} has no token counting endpoint", providers::current().title()));
+    }
+    let model = models::model_for(Task::Main);
+    let mut request = body.clone();
+    request["model"] = json!(format!("models/{}", model));
+    let response = http::client()
+        .post(format!(
+            "https://generativelanguage.googleapis.com/v1beta/models/{}:countTokens",
+            model
+        ))
+        .query(&[("key", keys::current())])
+        .json(&json!({"generateContentRequest": request}))
+        .send()
+        .map_err(|e| format!("Token count failed: {}", e.without_url()))?;
+
+    let status = response.status();
+    let text = response.text().unwrap_or_default();
+    if !status.is_success() {
+        return Err(format!("Token count failed: {}", ApiError::parse(status.as_u16(), &text)));
+    }
+    serde_json::from_str::<Value>(&text)
+        .ok()
+        .and_then(|json| json["totalTokens"].as_u64())
+        .map(|tokens| tokens as usize)
+        .ok_or_else(|| "Token count failed: no totalTokens in the response".to_string())
+}
+
+/// Cuts a tool result down when it would take too much of the remaining context: file reads
+/// keep their first lines, other output keeps its beginning and end. The result says what
+/// was left out, so the model can ask for a narrower range.
+pub fn fit(tool: &str, output: String, used_tokens: usize) -> String {
+    let remaining = context_window().saturating_sub(used_tokens);
+    let max_result = env::var("GEMINI_MAX_RESULT_TOKENS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_RESULT_TOKENS);
+    let budget = max_result.min(remaining / MAX_SHARE_OF_REMAINING);
+    let tokens = estimate_tokens(&output);
+    if tokens <= budget {
+        return output;
+    }
+
+    ui::status(
+        &format!(
+            "The {} result is about {} tokens, more than the {} it may use; sending part of it",
+            tool, tokens, budget
+        )
+        .color(Color::Yellow)
+        .to_string(),
+    );
+    let max_chars = budget * 4;
+
+    if tool == "file_editor" {
+        let mut kept = String::new();
+        let mut lines = 0;
+        for line in output.lines() {
+            if kept.len() + line.len() + 1 > max_chars {
+                break;
+            }
+            kept.push_str(line);
+            kept.push('\n');
+            lines += 1;
+        }
+        let last = last_file_line(&kept, lines);
+        return format!(
+            "{}\n[Context limit: the file is about {} tokens, more than the {} this result may use. \
+             Showing it up to line {}. Use the search subcommand to find the relevant part, or read \
+             other ranges with the read subcommand and start_line and end_line (e.g. start_line={}, \
+             end_line={}).]",
+            kept,
+            tokens,
+            budget,
+            last,
+            last + 1,
+            last + 200
+        );
+    }
+
+    let chars: Vec<char> = output.chars().collect();
+    let head = max_chars * 2 / 3;
+    let tail = max_chars - head;
+    let omitted = chars.len() - head - tail;
+    format!(
+        "{}\n[Context limit: the output was about {} tokens, more than the {} this result may use. \
+         {} characters were left out here.]\n{}",
+        chars[..head].iter().collect::<String>(),
+        tokens,
+        budget,
+        omitted,
+        chars[chars.len() - tail..].iter().collect::<String>()
+    )
+}
+
+/// The line of the file the kept part of a read ends with. A page or range of a file comes
+/// as numbered lines ("12 | text"); a whole file follows a "File contents:" line.
+fn last_file_line(kept: &str, kept_lines: usize) -> usize {
+    if kept.starts_with("File contents:") {
+        return kept_lines.saturating_sub(1);
+    }
+    kept.lines()
+        .last()
+        .and_then(|line| line.split_once(" | "))
+        .and_then(|(number, _)| number.trim().parse().ok())
+        // Only the "Lines 40-90 of 500" header was kept
+        .or_else(|| {
+            kept.strip_prefix("Lines ")
+                .and_then(|range| range.split('-').next())
+                .and_then(|start| start.parse::<usize>().ok())
+                .map(|start| start - 1)
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_file_line_skips_the_header() {
+        assert_eq!(last_file_line("File contents:\nfirst\nsecond | 3\n", 3), 2);
+        assert_eq!(last_file_line("Lines 40-90 of 500 in 'a.rs':\n40 | x\n41 | y\n", 3), 41);
+        assert_eq!(last_file_line("Lines 40-90 of 500 in 'a.rs':\n", 1), 39);
+    }
+}
@@ -0,0 +1,109 @@
+use colored::{Color, Colorize};
+use difference::{Changeset, Difference};
+
+use crate::ui;
+
+// Number of unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Builds a unified diff between two versions of a file. Returns an empty string when
+/// the contents are identical.
+pub fn unified_diff(old: &str, new: &str, filename: &str) -> String {
+    let changeset;
+    let mut ops: Vec<(char, &str)> = Vec::new();
+
+    // Ignore a single trailing newline so it doesn't show up as an extra context line
+    let old_trimmed = old.strip_suffix('\n').unwrap_or(old);
+    let new_trimmed = new.strip_suffix('\n').unwrap_or(new);
+
+    if old_trimmed.is_empty() || new_trimmed.is_empty() {
+        if !old_trimmed.is_empty() {
+            ops.extend(old_trimmed.split('\n').map(|l| ('-', l)));
+        }
+        if !new_trimmed.is_empty() {
+            ops.extend(new_trimmed.split('\n').map(|l| ('+', l)));
+        }
+    } else {
+        changeset = Changeset::new(old_trimmed, new_trimmed, "\n");
+        for diff in &changeset.diffs {
+            match diff {
+                Difference::Same(s) => ops.extend(s.split('\n').map(|l| (' ', l))),
+                Difference::Rem(s) => ops.extend(s.split('\n').map(|l| ('-', l))),
+                Difference::Add(s) => ops.extend(s.split('\n').map(|l| ('+', l))),
+            }
+        }
+    }
+
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (tag, _))| *tag != ' ')
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Group nearby changes into hunks, merging ones whose context would overlap
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &idx in &changes {
+        let start = idx.saturating_sub(CONTEXT_LINES);
+        let end = (idx + CONTEXT_LINES + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut output = format!("--- a/{}\n+++ b/{}\n", filename, filename);
+    for (start, end) in hunks {
+        let old_before = ops[..start].iter().filter(|(t, _)| *t != '+').count();
+        let new_before = ops[..start].iter().filter(|(t, _)| *t != '-').count();
+        let old_len = ops[start..end].iter().filter(|(t, _)| *t != '+').count();
+        let new_len = ops[start..end].iter().filter(|(t, _)| *t != '-').count();
+
+        // Unified diffs are 1-based, except that an empty range points at the line before it
+        let old_start = if old_len == 0 { old_before } else { old_before + 1 };
+        let new_start = if new_len == 0 { new_before } else { new_before + 1 };
+
+        output.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_len, new_start, new_len
+        ));
+        for (tag, line) in &ops[start..end] {
+            output.push(*tag);
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+
+    output
+}
+
+/// Colors a unified diff for terminal display: additions green, removals red, hunk headers cyan.
+pub fn colorize_patch(patch: &str) -> String {
+    patch
+        .lines()
+        .map(|line| {
+            if line.starts_with("+++") || line.starts_with("---") {
+                line.bold().to_string()
+            } else if line.starts_with("@@") {
+                line.color(Color::Cyan).to_string()
+            } else if line.starts_with('+') {
+                line.color(Color::Green).to_string()
+            } else if line.starts_with('-') {
+                line.color(Color::Red).to_string()
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prints a unified diff to the terminal with colors, skipping empty diffs.
+pub fn print_patch(patch: &str) {
+    if !patch.trim().is_empty() {
+        ui::status(&colorize_patch(patch));
+    }
+}
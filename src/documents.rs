@@ -0,0 +1,153 @@
+use encoding_rs::Encoding;
+use serde_json::Value;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::command;
+use crate::readability;
+
+/// What a response to scrape_url contains, from its Content-Type, its first bytes and the URL.
+pub enum Kind {
+    Html,
+    Pdf,
+    Json,
+    Text,
+    /// Images, archives and other binary files, with their media type
+    Unsupported(String),
+}
+
+/// Reads the media type of a response. Servers often send documents as
+/// application/octet-stream, so the file extension and the content decide then.
+pub fn kind(content_type: &str, url: &str, bytes: &[u8]) -> Kind {
+    let media_type = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    if bytes.starts_with(b"%PDF-") {
+        return Kind::Pdf;
+    }
+    match media_type.as_str() {
+        "text/html" | "application/xhtml+xml" => Kind::Html,
+        "application/pdf" => Kind::Pdf,
+        t if t == "application/json" || t.ends_with("+json") => Kind::Json,
+        t if t.starts_with("text/") || t.ends_with("+xml") || TEXT_TYPES.contains(&t) => Kind::Text,
+        "" | "application/octet-stream" | "binary/octet-stream" => by_extension(url, bytes),
+        other => Kind::Unsupported(other.to_string()),
+    }
+}
+
+// Media types outside text/ whose content is readable text
+const TEXT_TYPES: &[&str] = &[
+    "application/xml",
+    "application/javascript",
+    "application/x-yaml",
+    "application/yaml",
+    "application/toml",
+    "application/x-sh",
+    "application/sql",
+];
+
+fn by_extension(url: &str, bytes: &[u8]) -> Kind {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+    if path.ends_with(".json") {
+        Kind::Json
+    } else if path.ends_with(".html") || path.ends_with(".htm") {
+        Kind::Html
+    } else if std::str::from_utf8(&bytes[..bytes.len().min(4096)]).is_ok() || bytes.is_empty() {
+        // A cut in the middle of a character still counts as text
+        Kind::Text
+    } else {
+        Kind::Unsupported("application/octet-stream".to_string())
+    }
+}
+
+/// Decodes a text response with the charset from its Content-Type, or the detected one.
+pub fn decode(content_type: &str, bytes: &[u8]) -> String {
+    let charset = content_type
+        .split(';')
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .and_then(|(_, label)| Encoding::for_label(label.trim().trim_matches('"').as_bytes()));
+    match charset {
+        Some(encoding) => encoding.decode(bytes).0.into_owned(),
+        None => command::decode_output(bytes),
+    }
+}
+
+/// The readable text of a response, as Markdown for HTML pages and with page markers for PDFs.
+pub fn extract(kind: Kind, content_type: &str, url: &str, bytes: &[u8]) -> Result<String, String> {
+    match kind {
+        Kind::Html => {
+            let article = readability::extract(&decode(content_type, bytes), url);
+            Ok(if article.markdown.is_empty() || article.title.is_empty() {
+                article.markdown
+            } else {
+                format!("# {}\n\n{}", article.title, article.markdown)
+            })
+        }
+        Kind::Pdf => pdf_text(bytes),
+        Kind::Json => {
+            let text = decode(content_type, bytes);
+            // Pretty-printed JSON is easier to read and to cut into chunks
+            Ok(match serde_json::from_str::<Value>(&text) {
+                Ok(json) => serde_json::to_string_pretty(&json).unwrap_or(text),
+                Err(_) => text,
+            })
+        }
+        Kind::Text => Ok(decode(content_type, bytes)),
+        Kind::Unsupported(media_type) => Err(format!("Skipped: {} content can't be read as text", media_type)),
+    }
+}
+
+/// The text of each page of a PDF, headed by its page number.
+fn pdf_text(bytes: &[u8]) -> Result<String, String> {
+    // The PDF parser panics on some malformed files
+    let pages = panic::catch_unwind(AssertUnwindSafe(|| pdf_extract::extract_text_from_mem_by_pages(bytes)))
+        .map_err(|_| "Error reading PDF: the file is damaged or uses unsupported features".to_string())?
+        .map_err(|e| format!("Error reading PDF: {}", e))?;
+    let count = pages.len();
+    let text: Vec<String> = pages
+        .iter()
+        .enumerate()
+        .filter(|(_, page)| !page.trim().is_empty())
+        .map(|(index, page)| format!("[Page {} of {}]\n{}", index + 1, count, tidy(page)))
+        .collect();
+    if text.is_empty() {
+        return Err("Skipped: the PDF has no text layer (it may be scanned images)".to_string());
+    }
+    Ok(text.join("\n\n"))
+}
+
+/// Trims the trailing spaces and runs of blank lines PDF text extraction leaves behind.
+fn tidy(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().map(str::trim_end) {
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n").trim_end().to_string()
+}
+
+/// Cuts a long text into pieces of at most `size` bytes, at a blank line or else a line break
+/// when there is one in the second half of the piece.
+pub fn chunks(text: &str, size: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while rest.len() > size {
+        let mut end = size;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let window = &rest[..end];
+        let cut = window
+            .rfind("\n\n")
+            .or_else(|| window.rfind('\n'))
+            .filter(|&cut| cut >= end / 2)
+            .map(|cut| cut + 1)
+            .unwrap_or(end);
+        pieces.push(&rest[..cut]);
+        rest = &rest[cut..];
+    }
+    if !rest.trim().is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
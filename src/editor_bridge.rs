@@ -0,0 +1,190 @@
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::ui;
+
+// Protocol identifier returned in the handshake
+const PROTOCOL: &str = "gemini-edit/1";
+
+/// An editor connected over the local socket, able to review proposed edits.
+struct EditorConnection {
+    name: String,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+static CONNECTION: Lazy<Mutex<Option<EditorConnection>>> = Lazy::new(|| Mutex::new(None));
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The outcome of asking the editor to review an edit.
+pub enum Review {
+    /// The editor accepted the edit, possibly after modifying the content.
+    Accepted(String),
+    /// The user rejected the edit in the editor.
+    Rejected(String),
+    /// No editor is attached (or it went away); fall back to the terminal.
+    Unavailable,
+}
+
+/// Starts listening for an editor on 127.0.0.1:<port> in the background.
+pub fn listen(port: u16) -> Result<(), String> {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .map_err(|e| format!("Failed to listen for editors on port {}: {}", port, e))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            match handshake(stream) {
+                Ok(connection) => {
+                    ui::status(&format!(
+                        "{} {}",
+                        "Editor attached:".color(Color::Cyan).bold(),
+                        connection.name
+                    ));
+                    // A newly attached editor replaces the previous one
+                    *CONNECTION.lock().unwrap() = Some(connection);
+                }
+                Err(e) => ui::status(&format!("Editor connection refused: {}", e).color(Color::Red).to_string()),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// The editor must open with {"method": "hello", "params": {"name": ..., "token": ...}}.
+/// The token is only checked when GEMINI_EDITOR_TOKEN is set.
+fn handshake(stream: TcpStream) -> Result<EditorConnection, String> {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(|e| e.to_string())?;
+    let writer = stream.try_clone().map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| e.to_string())?;
+    let hello: Value = serde_json::from_str(&line).map_err(|e| format!("Invalid hello: {}", e))?;
+    if hello.get("method").and_then(|m| m.as_str()) != Some("hello") {
+        return Err("Expected a hello message".to_string());
+    }
+    let params = hello.get("params").cloned().unwrap_or(json!({}));
+    if let Ok(expected) = env::var("GEMINI_EDITOR_TOKEN") {
+        if params.get("token").and_then(|t| t.as_str()) != Some(expected.as_str()) {
+            return Err("Invalid token".to_string());
+        }
+    }
+    let name = params
+        .get("name")
+        .and_then(|n| n.as_str())
+        .unwrap_or("editor")
+        .to_string();
+
+    let mut connection = EditorConnection {
+        name,
+        reader,
+        writer,
+    };
+    if let Some(id) = hello.get("id") {
+        send(
+            &mut connection.writer,
+            &json!({"jsonrpc": "2.0", "id": id, "result": {"protocol": PROTOCOL}}),
+        )?;
+    }
+    // Reviews can take as long as the user needs
+    connection
+        .writer
+        .set_read_timeout(None)
+        .map_err(|e| e.to_string())?;
+    Ok(connection)
+}
+
+/// Sends a proposed edit to the attached editor and waits for the user's decision.
+pub fn propose_edit(filename: &str, absolute_path: &str, original: &str, proposed: &str, diff: &str) -> Review {
+    let mut guard = CONNECTION.lock().unwrap();
+    let connection = match guard.as_mut() {
+        Some(c) => c,
+        None => return Review::Unavailable,
+    };
+
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": "proposeEdit",
+        "params": {
+            "path": filename,
+            "absolutePath": absolute_path,
+            "original": original,
+            "proposed": proposed,
+            "diff": diff
+        }
+    });
+
+    ui::status(&format!(
+        "{} {} {}",
+        "Waiting for review in".color(Color::Cyan).bold(),
+        connection.name,
+        format!("({})", filename).dimmed()
+    ));
+
+    match exchange(connection, &request, id) {
+        Ok(result) => {
+            let decision = result.get("decision").and_then(|d| d.as_str()).unwrap_or("reject");
+            if decision == "accept" {
+                let content = result
+                    .get("content")
+                    .and_then(|c| c.as_str())
+                    .unwrap_or(proposed)
+                    .to_string();
+                Review::Accepted(content)
+            } else {
+                let reason = result
+                    .get("reason")
+                    .and_then(|r| r.as_str())
+                    .unwrap_or("no reason given")
+                    .to_string();
+                Review::Rejected(reason)
+            }
+        }
+        Err(e) => {
+            ui::status(&format!("Editor disconnected ({}), falling back to terminal preview", e).color(Color::Red).to_string());
+            *guard = None;
+            Review::Unavailable
+        }
+    }
+}
+
+/// Writes a request and reads lines until the response with the matching id arrives.
+fn exchange(connection: &mut EditorConnection, request: &Value, id: u64) -> Result<Value, String> {
+    send(&mut connection.writer, request)?;
+    loop {
+        let mut line = String::new();
+        let read = connection.reader.read_line(&mut line).map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Err("connection closed".to_string());
+        }
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+        if message.get("id").and_then(|i| i.as_u64()) != Some(id) {
+            continue; // Notifications or stale responses
+        }
+        if let Some(error) = message.get("error") {
+            return Err(error.get("message").and_then(|m| m.as_str()).unwrap_or("error").to_string());
+        }
+        return Ok(message.get("result").cloned().unwrap_or(json!({})));
+    }
+}
+
+fn send(writer: &mut TcpStream, message: &Value) -> Result<(), String> {
+    writeln!(writer, "{}", message)
+        .and_then(|_| writer.flush())
+        .map_err(|e| e.to_string())
+}
@@ -1,41 +1,123 @@
 use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
 use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::SmtpTransportBuilder;
 use lettre::{Message, SmtpTransport, Transport};
 use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
 
-pub fn send_email(subject: &str, body: &str, smtp_server: &str, debug: bool) -> String {
+use crate::file_edit::sandbox_path;
+use crate::stats;
+
+// Most bytes all attachments of one email may have together; mail servers commonly refuse
+// messages over 25 MB, and encoding adds a third
+const MAX_ATTACHMENT_BYTES: u64 = 15_000_000;
+// Seconds to wait for the SMTP server before giving up
+const DEFAULT_TIMEOUT: u64 = 30;
+
+/// An email the model asked to send.
+#[derive(Default)]
+pub struct Email {
+    pub subject: String,
+    /// Plain text body
+    pub body: String,
+    /// HTML version of the body, sent as multipart/alternative next to the plain text
+    pub html: Option<String>,
+    /// Paths in the sandbox
+    pub attachments: Vec<String>,
+    /// Recipients; DESTINATION_EMAIL when empty
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+}
+
+/// How the connection to the SMTP server is secured, from SMTP_TLS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TlsMode {
+    /// Plain SMTP, for local relays
+    None,
+    /// Plain connection upgraded with STARTTLS, usually on port 587
+    StartTls,
+    /// TLS from the start, usually on port 465
+    Tls,
+}
+
+/// Sends an email through the SMTP server. Recipients must be on the allowlist
+/// (EMAIL_ALLOWED_RECIPIENTS, or only DESTINATION_EMAIL when it isn't set).
+pub fn send_email(email: &Email, smtp_server: &str, debug: bool) -> String {
     if debug {
         println!("=== Email Debug Info ===");
         println!("SMTP Server: {}", smtp_server);
-        println!("Subject: {}", subject);
-        println!("Body length: {} characters", body.len());
+        println!("Subject: {}", email.subject);
+        println!("Body length: {} characters", email.body.len());
+        println!("Attachments: {}", email.attachments.join(", "));
     }
 
-    let recipient = match env::var("DESTINATION_EMAIL") {
-        Ok(val) => {
-            if debug {
-                println!("Recipient: {}", val);
-            }
-            val
-        },
-        Err(_) => return "DESTINATION_EMAIL environment variable not set. Please set it to the recipient's email address.".to_string(),
+    let destination = env::var("DESTINATION_EMAIL").ok().filter(|d| !d.trim().is_empty());
+    let to = if email.to.is_empty() {
+        match &destination {
+            Some(destination) => vec![destination.clone()],
+            None => return "DESTINATION_EMAIL environment variable not set. Please set it to the recipient's email address.".to_string(),
+        }
+    } else {
+        email.to.clone()
     };
+    let allowed = allowed_recipients(destination.as_deref());
+    let mut mailboxes = Vec::new();
+    for (kind, addresses) in [("To", &to), ("Cc", &email.cc), ("Bcc", &email.bcc)] {
+        for address in addresses {
+            let mailbox: Mailbox = match address.trim().parse() {
+                Ok(mailbox) => mailbox,
+                Err(e) => return format!("Invalid recipient '{}': {}", address, e),
+            };
+            if !is_allowed(mailbox.email.as_ref(), &allowed) {
+                return format!(
+                    "Recipient '{}' is not allowed; add it to EMAIL_ALLOWED_RECIPIENTS in ~/.gemini.conf to send there",
+                    address
+                );
+            }
+            mailboxes.push((kind, mailbox));
+        }
+    }
+    let recipients: Vec<String> = mailboxes.iter().map(|(_, mailbox)| mailbox.email.to_string()).collect();
+    if debug {
+        println!("Recipients: {}", recipients.join(", "));
+    }
 
     // For simplicity, assume sender is the same as recipient or a default
-    let sender = env::var("SENDER_EMAIL").unwrap_or_else(|_| recipient.clone());
+    let sender = env::var("SENDER_EMAIL")
+        .ok()
+        .or(destination)
+        .unwrap_or_else(|| to[0].clone());
     if debug {
         println!("Sender: {}", sender);
     }
+    let sender: Mailbox = match sender.parse() {
+        Ok(sender) => sender,
+        Err(e) => return format!("Invalid sender '{}': {}", sender, e),
+    };
 
     // Build the email message
-    let email = match Message::builder()
-        .from(sender.parse().unwrap())
-        .to(recipient.parse().unwrap())
-        .subject(subject)
-        .header(ContentType::TEXT_PLAIN)
-        .body(body.to_string())
-    {
-        Ok(email) => email,
+    let mut message = Message::builder().from(sender).subject(&email.subject);
+    for (kind, mailbox) in mailboxes {
+        message = match kind {
+            "To" => message.to(mailbox),
+            "Cc" => message.cc(mailbox),
+            _ => message.bcc(mailbox),
+        };
+    }
+    let message = if email.html.is_none() && email.attachments.is_empty() {
+        message.header(ContentType::TEXT_PLAIN).body(email.body.clone())
+    } else {
+        match content(&email.body, email.html.as_deref(), &email.attachments) {
+            Ok(content) => message.multipart(content),
+            Err(e) => return e,
+        }
+    };
+    let message = match message {
+        Ok(message) => message,
         Err(e) => return format!("Failed to build email: {}", e),
     };
 
@@ -43,69 +125,55 @@ pub fn send_email(subject: &str, body: &str, smtp_server: &str, debug: bool) ->
     if debug {
         println!("Creating SMTP transport...");
     }
-    let mailer = if smtp_server == "localhost" {
-        if debug {
-            println!("Using localhost configuration (no auth)");
-        }
-        // For localhost, try without auth
-        SmtpTransport::builder_dangerous(smtp_server)
-            .port(25)
-            .build()
-    } else {
-        if debug {
-            println!("Using remote server configuration");
-        }
-        // For other servers, check for credentials
-        let creds = if let (Ok(username), Ok(password)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+    let credentials = match (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+        (Ok(username), Ok(password)) => {
             if debug {
                 println!("Found SMTP credentials for user: {}", username);
             }
             Some(Credentials::new(username, password))
-        } else {
+        }
+        _ => {
             if debug {
                 println!("No SMTP credentials found, trying without authentication");
             }
             None
-        };
-
-        if let Some(creds) = creds {
-            if debug {
-                println!("Building SMTP transport with authentication...");
-            }
-            match SmtpTransport::relay(smtp_server) {
-                Ok(relay) => {
-                    if debug {
-                        println!("SMTP relay created successfully, adding credentials...");
-                    }
-                    // Try port 25 first (plain SMTP), then fall back to 587 if needed
-                    let mailer = relay.port(25).credentials(creds).build();
-                    if debug {
-                        println!("SMTP transport created on port 25");
-                    }
-                    mailer
-                },
-                Err(e) => {
-                    if debug {
-                        println!("Failed to create SMTP relay: {}", e);
-                    }
-                    return format!("Failed to create SMTP relay: {}", e);
-                }
-            }
-        } else {
-            if debug {
-                println!("No SMTP credentials found, trying without authentication...");
-            }
-            // Try without authentication for local/trusted servers
-            match SmtpTransport::builder_dangerous(smtp_server).port(25).build() {
-                mailer => {
-                    if debug {
-                        println!("SMTP transport created without authentication");
-                    }
-                    mailer
-                }
-            }
         }
     };
+    let tls = match tls_mode(smtp_server, credentials.is_some()) {
+        Ok(tls) => tls,
+        Err(e) => return e,
+    };
+    let port: u16 = env::var("SMTP_PORT")
+        .ok()
+        .and_then(|p| p.trim().parse().ok())
+        .unwrap_or(match tls {
+            TlsMode::None => 25,
+            TlsMode::StartTls => 587,
+            TlsMode::Tls => 465,
+        });
+    let timeout = env::var("SMTP_TIMEOUT")
+        .ok()
+        .and_then(|t| t.trim().parse().ok())
+        .unwrap_or(DEFAULT_TIMEOUT);
+    if debug {
+        println!("Connecting to {}:{} with TLS mode {:?}, timeout {}s", smtp_server, port, tls, timeout);
+    }
+    let builder: SmtpTransportBuilder = match tls {
+        TlsMode::None => SmtpTransport::builder_dangerous(smtp_server),
+        TlsMode::StartTls => match SmtpTransport::starttls_relay(smtp_server) {
+            Ok(builder) => builder,
+            Err(e) => return format!("Failed to create SMTP relay: {}", e),
+        },
+        TlsMode::Tls => match SmtpTransport::relay(smtp_server) {
+            Ok(builder) => builder,
+            Err(e) => return format!("Failed to create SMTP relay: {}", e),
+        },
+    };
+    let mut builder = builder.port(port).timeout(Some(Duration::from_secs(timeout)));
+    if let Some(credentials) = credentials {
+        builder = builder.credentials(credentials);
+    }
+    let mailer = builder.build();
     if debug {
         println!("SMTP transport created successfully");
     }
@@ -114,12 +182,17 @@ pub fn send_email(subject: &str, body: &str, smtp_server: &str, debug: bool) ->
     if debug {
         println!("Attempting to send email...");
     }
-    match mailer.send(&email) {
+    match mailer.send(&message) {
         Ok(_) => {
             if debug {
                 println!("Email sent successfully!");
             }
-            format!("Email sent successfully to {} via {}", recipient, smtp_server)
+            let recipients = recipients.join(", ");
+            stats::record_email(&format!("\"{}\" to {}", email.subject, recipients));
+            match email.attachments.len() {
+                0 => format!("Email sent successfully to {} via {}", recipients, smtp_server),
+                count => format!("Email sent successfully to {} via {} with {} attachment(s)", recipients, smtp_server, count),
+            }
         },
         Err(e) => {
             if debug {
@@ -129,3 +202,102 @@ pub fn send_email(subject: &str, body: &str, smtp_server: &str, debug: bool) ->
         }
     }
 }
+
+/// SMTP_TLS: none, starttls or tls. Without it, local servers get plain SMTP, SMTP_PORT 465
+/// TLS and 587 STARTTLS; other servers use TLS when there are credentials, as before.
+fn tls_mode(smtp_server: &str, authenticated: bool) -> Result<TlsMode, String> {
+    match env::var("SMTP_TLS").map(|v| v.trim().to_lowercase()).unwrap_or_default().as_str() {
+        "none" | "plain" | "off" => Ok(TlsMode::None),
+        "starttls" => Ok(TlsMode::StartTls),
+        "tls" | "ssl" => Ok(TlsMode::Tls),
+        "" | "auto" => {
+            let port = env::var("SMTP_PORT").unwrap_or_default();
+            Ok(match port.trim() {
+                _ if matches!(smtp_server, "localhost" | "127.0.0.1" | "::1") => TlsMode::None,
+                "465" => TlsMode::Tls,
+                "587" => TlsMode::StartTls,
+                _ if authenticated => TlsMode::Tls,
+                _ => TlsMode::None,
+            })
+        }
+        other => Err(format!("Unknown SMTP_TLS '{}'; use none, starttls or tls", other)),
+    }
+}
+
+/// Entries of EMAIL_ALLOWED_RECIPIENTS: addresses, domains written as @example.com, or *
+/// for anyone. Without it, only DESTINATION_EMAIL may receive mail.
+fn allowed_recipients(destination: Option<&str>) -> Vec<String> {
+    match env::var("EMAIL_ALLOWED_RECIPIENTS") {
+        Ok(list) if !list.trim().is_empty() => list
+            .split(',')
+            .map(|entry| entry.trim().to_lowercase())
+            .filter(|entry| !entry.is_empty())
+            .collect(),
+        _ => destination
+            .map(|d| d.parse::<Mailbox>().map(|m| m.email.to_string()).unwrap_or_else(|_| d.trim().to_string()))
+            .map(|d| vec![d.to_lowercase()])
+            .unwrap_or_default(),
+    }
+}
+
+fn is_allowed(address: &str, allowed: &[String]) -> bool {
+    let address = address.to_lowercase();
+    allowed.iter().any(|entry| {
+        entry == "*" || *entry == address || (entry.starts_with('@') && address.ends_with(entry.as_str()))
+    })
+}
+
+/// The parts of the message: plain text, or plain text and HTML, followed by the attachments.
+fn content(body: &str, html: Option<&str>, attachments: &[String]) -> Result<MultiPart, String> {
+    let mut content = match html {
+        Some(html) if attachments.is_empty() => {
+            return Ok(MultiPart::alternative_plain_html(body.to_string(), html.to_string()))
+        }
+        Some(html) => MultiPart::mixed().multipart(MultiPart::alternative_plain_html(body.to_string(), html.to_string())),
+        None => MultiPart::mixed().singlepart(SinglePart::plain(body.to_string())),
+    };
+
+    let mut total = 0;
+    for filename in attachments {
+        let path = sandbox_path(filename).map_err(|e| format!("Cannot attach {}", e))?;
+        let metadata = fs::metadata(&path).map_err(|e| format!("Cannot attach '{}': {}", filename, e))?;
+        if !metadata.is_file() {
+            return Err(format!("Cannot attach '{}': it is not a file", filename));
+        }
+        total += metadata.len();
+        if total > MAX_ATTACHMENT_BYTES {
+            return Err(format!(
+                "Cannot attach '{}': the attachments would exceed {} MB",
+                filename,
+                MAX_ATTACHMENT_BYTES / 1_000_000
+            ));
+        }
+        let data = fs::read(&path).map_err(|e| format!("Cannot attach '{}': {}", filename, e))?;
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| filename.clone());
+        let content_type = ContentType::parse(media_type(&path)).unwrap_or(ContentType::TEXT_PLAIN);
+        content = content.singlepart(Attachment::new(name).body(data, content_type));
+    }
+    Ok(content)
+}
+
+/// Media type of an attachment, from its extension.
+fn media_type(path: &Path) -> &'static str {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "txt" | "log" | "md" | "rs" | "py" | "toml" | "yaml" | "yml" | "sh" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "html" | "htm" => "text/html; charset=utf-8",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,65 @@
+use thiserror::Error;
+
+use crate::api_error::ApiError;
+
+/// Why a request to the model, a tool call or loading the configuration failed. The kinds are
+/// kept apart so the message can come with what to do about it. There is no conversion from a
+/// plain string: each failure picks its kind where it happens.
+#[derive(Debug, Error)]
+pub enum CliError {
+    /// The API answered with an error that retrying won't fix
+    #[error("API request failed: {}", .0.headline())]
+    Api(Box<ApiError>),
+    /// The model, and the fallback model if there is one, kept failing through every retry
+    #[error("API request failed: {models} kept failing ({})", .last.headline())]
+    Unavailable { models: String, last: Box<ApiError> },
+    /// The request didn't reach the API, or the reply couldn't be read
+    #[error("API request failed: {0}")]
+    Network(String),
+    /// The prompt or the answer was blocked, or the model stopped without answering; the
+    /// message says which and what to change
+    #[error("{0}")]
+    NoAnswer(String),
+    /// A configured spending or token budget is used up
+    #[error("{0}")]
+    Budget(String),
+    /// Ctrl-C was pressed while waiting for the model
+    #[error("Cancelled with Ctrl-C")]
+    Cancelled,
+    /// A tool call couldn't be carried out: the tool is disabled or unknown, an argument is
+    /// missing, or the tool itself failed
+    #[error("{0}")]
+    Tool(String),
+    /// The configuration is missing a setting or has one the program can't use
+    #[error("{0}")]
+    Config(String),
+}
+
+impl CliError {
+    /// What the user can do about it, when there is more to say than the message.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            CliError::Api(error) | CliError::Unavailable { last: error, .. } => Some(error.hint()),
+            CliError::Network(_) => Some(
+                "Check the network connection and any proxy (HTTPS_PROXY); GEMINI_CONNECT_TIMEOUT sets how long \
+                 to wait for a connection."
+                    .to_string(),
+            ),
+            CliError::Config(_) => Some(
+                "Settings are read from ~/.gemini.conf; 'gemini-cli-rs config show' lists them and \
+                 'gemini-cli-rs config set KEY VALUE' changes one."
+                    .to_string(),
+            ),
+            CliError::NoAnswer(_) | CliError::Budget(_) | CliError::Cancelled | CliError::Tool(_) => None,
+        }
+    }
+}
+
+impl From<CliError> for String {
+    fn from(error: CliError) -> Self {
+        match error.hint() {
+            Some(hint) => format!("{}\nHint: {}", error, hint),
+            None => error.to_string(),
+        }
+    }
+}
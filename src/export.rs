@@ -0,0 +1,374 @@
+use chrono::Local;
+use once_cell::sync::Lazy;
+use pulldown_cmark::{html, CodeBlockKind, Event, Parser, Tag};
+use regex::Regex;
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::images;
+use crate::locale;
+use crate::{HistoryEntry, SANDBOX_ROOT};
+
+/// A renderable piece of a single history message.
+enum Segment {
+    Text(String),
+    ToolCall { name: String, args: Value },
+    ToolResult { name: String, body: String, is_error: bool },
+}
+
+// Generic tokenizer used for code highlighting: comments, strings, numbers, identifiers
+static TOKEN_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?P<comment>//[^\n]*|/\*[\s\S]*?\*/)|(?P<hash>#[^\n]*)|(?P<string>"(?:\\.|[^"\\])*"|'(?:\\.|[^'\\\n])*'|`(?:\\.|[^`\\])*`)|(?P<number>\b\d+(?:\.\d+)?\b)|(?P<word>\b[A-Za-z_][A-Za-z0-9_]*\b)"#,
+    )
+    .unwrap()
+});
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "case", "catch", "class", "const", "continue", "crate",
+    "def", "default", "do", "done", "elif", "else", "enum", "esac", "export", "extern", "false",
+    "fi", "fn", "for", "from", "func", "function", "if", "impl", "import", "in", "interface",
+    "let", "loop", "match", "mod", "module", "mut", "new", "None", "null", "package", "pub",
+    "return", "self", "Self", "static", "struct", "switch", "then", "this", "throw", "trait",
+    "true", "True", "False", "try", "type", "typeof", "use", "var", "where", "while", "with",
+    "yield",
+];
+
+/// Writes the conversation to a Markdown or HTML file and returns the path written.
+pub fn write_transcript(
+    history: &[HistoryEntry],
+    format: &str,
+    path: Option<&str>,
+) -> Result<PathBuf, String> {
+    let (content, extension) = match format {
+        "md" | "markdown" => (to_markdown(history), "md"),
+        "html" => (to_html(history), "html"),
+        _ => return Err(format!("Unknown export format '{}' (use md or html)", format)),
+    };
+    let path = match path {
+        Some(p) => PathBuf::from(p),
+        None => PathBuf::from(format!(
+            "transcript-{}.{}",
+            Local::now().format("%Y%m%d-%H%M%S"),
+            extension
+        )),
+    };
+    fs::write(&path, content).map_err(|e| format!("Error writing to '{}': {}", path.display(), e))?;
+    Ok(path)
+}
+
+pub fn to_markdown(history: &[HistoryEntry]) -> String {
+    let mut output = format!(
+        "# Gemini CLI transcript\n\nExported {} · sandbox `{}`\n",
+        export_time(),
+        *SANDBOX_ROOT
+    );
+
+    for entry in history {
+        let segments = segments(&entry.content);
+        output.push_str(&format!("\n### {}\n\n", label(entry, &segments)));
+        for segment in segments {
+            match segment {
+                Segment::Text(text) => {
+                    output.push_str(text.trim_end());
+                    output.push_str("\n\n");
+                }
+                Segment::ToolCall { name, args } => {
+                    let args = serde_json::to_string_pretty(&args).unwrap_or_default();
+                    output.push_str(&format!(
+                        "<details>\n<summary>Tool call: {}</summary>\n\n{}\n</details>\n\n",
+                        name,
+                        fence(&args, "json")
+                    ));
+                }
+                Segment::ToolResult { name, body, is_error } => {
+                    let kind = if is_error { "Tool error" } else { "Tool result" };
+                    output.push_str(&format!(
+                        "<details>\n<summary>{}: {}</summary>\n\n{}\n</details>\n\n",
+                        kind,
+                        name,
+                        fence(&body, "")
+                    ));
+                }
+            }
+        }
+    }
+
+    output
+}
+
+pub fn to_html(history: &[HistoryEntry]) -> String {
+    let mut body = String::new();
+
+    for entry in history {
+        let segments = segments(&entry.content);
+        let class = if segments.iter().all(|s| matches!(s, Segment::ToolResult { .. })) {
+            "tool"
+        } else if entry.content.get("role").and_then(|r| r.as_str()) == Some("model") {
+            "model"
+        } else {
+            "user"
+        };
+        body.push_str(&format!(
+            "<section class=\"msg {}\">\n<header>{}</header>\n",
+            class,
+            escape_html(&label(entry, &segments))
+        ));
+        for segment in segments {
+            match segment {
+                Segment::Text(text) => body.push_str(&markdown_to_html(&text)),
+                Segment::ToolCall { name, args } => {
+                    let args = serde_json::to_string_pretty(&args).unwrap_or_default();
+                    body.push_str(&format!(
+                        "<details class=\"tool-call\"><summary>Tool call: <code>{}</code></summary><pre><code>{}</code></pre></details>\n",
+                        escape_html(&name),
+                        highlight_code(&args, "json")
+                    ));
+                }
+                Segment::ToolResult { name, body: result, is_error } => {
+                    let (class, kind) = if is_error {
+                        ("tool-result error", "Tool error")
+                    } else {
+                        ("tool-result", "Tool result")
+                    };
+                    body.push_str(&format!(
+                        "<details class=\"{}\"><summary>{}: <code>{}</code></summary><pre>{}</pre></details>\n",
+                        class,
+                        kind,
+                        escape_html(&name),
+                        escape_html(&result)
+                    ));
+                }
+            }
+        }
+        body.push_str("</section>\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n<title>Gemini CLI transcript</title>\n<style>{}</style>\n</head>\n<body>\n<h1>Gemini CLI transcript</h1>\n<p class=\"meta\">Exported {} · sandbox <code>{}</code></p>\n{}</body>\n</html>\n",
+        locale::current().code,
+        STYLE,
+        escape_html(&export_time()),
+        escape_html(&SANDBOX_ROOT),
+        body
+    )
+}
+
+fn export_time() -> String {
+    let now = Local::now();
+    format!("{} {}", locale::format_date(&now), locale::format_time(&now))
+}
+
+fn label(entry: &HistoryEntry, segments: &[Segment]) -> String {
+    let speaker = match entry.content.get("role").and_then(|r| r.as_str()) {
+        Some("model") => "Gemini",
+        _ if !segments.is_empty()
+            && segments.iter().all(|s| matches!(s, Segment::ToolResult { .. })) =>
+        {
+            "Tool results"
+        }
+        _ => "You",
+    };
+    format!(
+        "#{} {} {} · {}",
+        entry.turn,
+        locale::format_date(&entry.timestamp),
+        locale::format_time(&entry.timestamp),
+        speaker
+    )
+}
+
+/// Splits a history message into text, tool calls, and tool results.
+fn segments(content: &Value) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let parts = match content.get("parts").and_then(|p| p.as_array()) {
+        Some(parts) => parts,
+        None => return segments,
+    };
+
+    for part in parts {
+        if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+            segments.extend(split_tool_results(text));
+        } else if let Some(call) = part.get("functionCall") {
+            segments.push(Segment::ToolCall {
+                name: call.get("name").and_then(|n| n.as_str()).unwrap_or("").to_string(),
+                args: call.get("args").cloned().unwrap_or(Value::Null),
+            });
+        } else if let Some((mime_type, _)) = images::inline_data(part) {
+            segments.push(Segment::Text(format!("[Image: {}]\n", mime_type)));
+        } else if let Some(response) = part.get("functionResponse") {
+            let payload = response.get("response").cloned().unwrap_or(Value::Null);
+            let is_error = payload.get("error").is_some();
+            let body = match payload.get("result").or_else(|| payload.get("error")) {
+                Some(Value::String(s)) => s.clone(),
+                Some(other) => serde_json::to_string_pretty(other).unwrap_or_default(),
+                None => serde_json::to_string_pretty(&payload).unwrap_or_default(),
+            };
+            segments.push(Segment::ToolResult {
+                name: response.get("name").and_then(|n| n.as_str()).unwrap_or("").to_string(),
+                body,
+                is_error,
+            });
+        }
+    }
+
+    segments
+}
+
+/// Tool results sent back as text look like "[Tool result] name: output"; split them out
+/// so each can be rendered as its own collapsible section.
+fn split_tool_results(text: &str) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut plain = String::new();
+
+    for line in text.split_inclusive('\n') {
+        let header = line
+            .strip_prefix("[Tool result] ")
+            .map(|rest| (rest, false))
+            .or_else(|| line.strip_prefix("[Tool error] ").map(|rest| (rest, true)));
+        match header {
+            Some((rest, is_error)) => {
+                if !plain.trim().is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut plain)));
+                }
+                let (name, body) = rest.split_once(": ").unwrap_or(("", rest));
+                segments.push(Segment::ToolResult {
+                    name: name.to_string(),
+                    body: body.to_string(),
+                    is_error,
+                });
+            }
+            None => match segments.last_mut() {
+                Some(Segment::ToolResult { body, .. }) if plain.is_empty() => body.push_str(line),
+                _ => plain.push_str(line),
+            },
+        }
+    }
+    if !plain.trim().is_empty() {
+        segments.push(Segment::Text(plain));
+    }
+    segments
+}
+
+/// Wraps content in a Markdown code fence long enough not to clash with fences inside it.
+fn fence(content: &str, lang: &str) -> String {
+    let mut ticks = "```".to_string();
+    while content.contains(&ticks) {
+        ticks.push('`');
+    }
+    format!("{}{}\n{}\n{}\n", ticks, lang, content.trim_end(), ticks)
+}
+
+fn markdown_to_html(text: &str) -> String {
+    let mut events = Vec::new();
+    let mut code: Option<(String, String)> = None;
+
+    for event in Parser::new(text) {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let lang = match kind {
+                    CodeBlockKind::Fenced(lang) => lang.to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code = Some((lang, String::new()));
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some((lang, source)) = code.take() {
+                    events.push(Event::Html(
+                        format!(
+                            "<pre><code class=\"language-{}\">{}</code></pre>\n",
+                            escape_html(&lang),
+                            highlight_code(&source, &lang)
+                        )
+                        .into(),
+                    ));
+                }
+            }
+            Event::Text(t) if code.is_some() => {
+                if let Some((_, source)) = code.as_mut() {
+                    source.push_str(&t);
+                }
+            }
+            other => events.push(other),
+        }
+    }
+
+    let mut output = String::new();
+    html::push_html(&mut output, events.into_iter());
+    output
+}
+
+/// Minimal language-agnostic syntax highlighting: comments, strings, numbers, keywords.
+fn highlight_code(code: &str, lang: &str) -> String {
+    let hash_comments = matches!(
+        lang,
+        "sh" | "bash" | "zsh" | "shell" | "python" | "py" | "ruby" | "rb" | "toml" | "yaml"
+            | "yml" | "perl" | "r" | "powershell" | "ps1" | "dockerfile" | "makefile"
+    );
+    let mut output = String::new();
+    let mut last = 0;
+
+    for caps in TOKEN_RE.captures_iter(code) {
+        let m = caps.get(0).unwrap();
+        let class = if caps.name("comment").is_some() {
+            Some("c")
+        } else if caps.name("hash").is_some() {
+            if !hash_comments {
+                continue;
+            }
+            Some("c")
+        } else if caps.name("string").is_some() {
+            // Rust lifetimes look like unterminated char literals
+            if lang == "rust" && m.as_str().starts_with('\'') && m.as_str().len() > 4 {
+                continue;
+            }
+            Some("s")
+        } else if caps.name("number").is_some() {
+            Some("n")
+        } else if KEYWORDS.contains(&m.as_str()) {
+            Some("k")
+        } else {
+            None
+        };
+
+        if let Some(class) = class {
+            output.push_str(&escape_html(&code[last..m.start()]));
+            output.push_str(&format!(
+                "<span class=\"{}\">{}</span>",
+                class,
+                escape_html(m.as_str())
+            ));
+            last = m.end();
+        }
+    }
+    output.push_str(&escape_html(&code[last..]));
+    output
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Roboto, sans-serif; max-width: 900px; margin: 2em auto; padding: 0 1em; color: #1f2328; background: #fff; line-height: 1.5; }
+h1 { font-size: 1.6em; margin-bottom: 0.2em; }
+.meta { color: #656d76; font-size: 0.9em; }
+.msg { border-left: 4px solid #d0d7de; padding: 0.2em 1em; margin: 1.2em 0; }
+.msg.user { border-color: #2da44e; }
+.msg.model { border-color: #bf8700; }
+.msg.tool { border-color: #8250df; }
+.msg header { font-size: 0.8em; color: #656d76; font-weight: 600; }
+pre { background: #f6f8fa; padding: 0.8em; overflow-x: auto; border-radius: 6px; font-size: 0.85em; }
+code { font-family: ui-monospace, SFMono-Regular, Consolas, monospace; }
+details { margin: 0.5em 0; }
+summary { cursor: pointer; color: #57606a; }
+.tool-result.error summary { color: #cf222e; }
+.k { color: #cf222e; font-weight: 600; }
+.s { color: #0a3069; }
+.n { color: #0550ae; }
+.c { color: #6e7781; font-style: italic; }
+"#;
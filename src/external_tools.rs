@@ -0,0 +1,320 @@
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::command;
+use crate::plugins;
+use crate::tools;
+use crate::ui;
+use crate::SANDBOX_ROOT;
+
+// Seconds a call may run before the tool is stopped, unless GEMINI_EXTERNAL_TOOL_TIMEOUT says otherwise
+const DEFAULT_TIMEOUT: u64 = 60;
+// Seconds an executable gets to print its declarations for --describe
+const DESCRIBE_TIMEOUT: u64 = 10;
+// Output beyond this many bytes is cut
+const MAX_OUTPUT_BYTES: usize = 1_000_000;
+
+static TOOLS: Lazy<Vec<ExternalTool>> = Lazy::new(load_all);
+
+/// A tool backed by a program in the tools folder.
+struct ExternalTool {
+    /// The manifest or executable it was declared by
+    source: PathBuf,
+    program: PathBuf,
+    args: Vec<String>,
+    declaration: Value,
+}
+
+pub fn tools_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("gemini-cli")
+        .join("tools")
+}
+
+/// Declarations of all external tools, in the same shape as the built-in ones.
+pub fn declarations() -> Vec<Value> {
+    TOOLS.iter().map(|tool| tool.declaration.clone()).collect()
+}
+
+/// Whether a tool name belongs to an external tool.
+pub fn provides(name: &str) -> bool {
+    find(name).is_some()
+}
+
+/// Reads the tools folder, reporting entries that can't be used. Called at startup so
+/// problems show up before the first turn.
+pub fn load() {
+    Lazy::force(&TOOLS);
+}
+
+/// Runs an external tool in the sandbox root with the arguments as JSON on stdin and returns
+/// what it prints. GEMINI_TOOL_NAME tells a program that serves several tools which one was
+/// called. Output of the form `{"result": ...}` or `{"error": ...}` is unwrapped.
+pub fn call(name: &str, args: &Value) -> Result<String, String> {
+    let tool = find(name).ok_or_else(|| format!("Unknown external tool: {}", name))?;
+    let timeout = env::var("GEMINI_EXTERNAL_TOOL_TIMEOUT")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|&seconds| seconds > 0)
+        .unwrap_or(DEFAULT_TIMEOUT);
+
+    let mut process = Command::new(&tool.program);
+    process
+        .args(&tool.args)
+        .current_dir(&*SANDBOX_ROOT)
+        .env("GEMINI_TOOL_NAME", name)
+        .env("GEMINI_SANDBOX", &*SANDBOX_ROOT);
+    command::scrub_environment(&mut process);
+    let (status, stdout, stderr) = run(process, Some(args.to_string()), Duration::from_secs(timeout))
+        .map_err(|e| format!("Tool {} ({}): {}", name, file_name(&tool.source), e))?;
+
+    if !status.success() {
+        let details = if stderr.trim().is_empty() { stdout.trim() } else { stderr.trim() };
+        return Err(format!("Tool {} failed ({}): {}", name, status, details));
+    }
+    match serde_json::from_str::<Value>(stdout.trim()) {
+        Ok(Value::Object(response)) if response.contains_key("error") => Err(match &response["error"] {
+            Value::String(error) => error.clone(),
+            error => error.to_string(),
+        }),
+        Ok(Value::Object(response)) if response.contains_key("result") => Ok(match &response["result"] {
+            Value::String(result) => result.clone(),
+            result => result.to_string(),
+        }),
+        _ => Ok(stdout),
+    }
+}
+
+fn find(name: &str) -> Option<&'static ExternalTool> {
+    TOOLS.iter().find(|tool| tool.declaration["name"] == name)
+}
+
+/// Manifests (`*.json`) come first, then executables that no manifest points to, which are
+/// asked for their declarations with `--describe`.
+fn load_all() -> Vec<ExternalTool> {
+    let dir = tools_dir();
+    let mut paths: Vec<PathBuf> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && !file_name(path).starts_with('.'))
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    paths.sort();
+
+    let mut loaded: Vec<ExternalTool> = Vec::new();
+    let (manifests, others): (Vec<PathBuf>, Vec<PathBuf>) =
+        paths.into_iter().partition(|path| path.extension().is_some_and(|e| e == "json"));
+    for path in &manifests {
+        match from_manifest(path) {
+            Ok(tools) => add(&mut loaded, tools),
+            Err(e) => warn(&format!("{}: {}", file_name(path), e)),
+        }
+    }
+    for path in others.iter().filter(|path| is_executable(path)) {
+        if loaded.iter().any(|tool| &tool.program == path) {
+            continue;
+        }
+        match described(path) {
+            Ok(tools) => add(&mut loaded, tools),
+            Err(e) => warn(&format!("{}: {}", file_name(path), e)),
+        }
+    }
+    loaded
+}
+
+/// Keeps the names of built-in tools, plugins and tools loaded earlier.
+fn add(loaded: &mut Vec<ExternalTool>, tools: Vec<ExternalTool>) {
+    for tool in tools {
+        let name = tool.declaration["name"].as_str().unwrap_or("");
+        let taken = tools::builtin_declarations().iter().any(|d| d["name"] == name)
+            || plugins::provides(name)
+            || loaded.iter().any(|t| t.declaration["name"] == name);
+        if taken {
+            warn(&format!("{}: tool name '{}' is already taken, skipping it", file_name(&tool.source), name));
+        } else {
+            loaded.push(tool);
+        }
+    }
+}
+
+/// A manifest holds one declaration or a list of them, each with a `command`: a program in
+/// the tools folder or on the PATH, and optionally `args` to start it with (arguments naming a
+/// file in the tools folder, like a script for an interpreter, get its full path). Without a
+/// command, the executable with the manifest's file name is used.
+fn from_manifest(path: &Path) -> Result<Vec<ExternalTool>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read the file: {}", e))?;
+    let manifest: Value = serde_json::from_str(&text).map_err(|e| format!("invalid JSON: {}", e))?;
+    let entries = match manifest {
+        Value::Array(entries) => entries,
+        entry => vec![entry],
+    };
+
+    let mut tools = Vec::new();
+    for mut entry in entries {
+        let program = match entry.get("command").and_then(Value::as_str) {
+            Some(command) => resolve(command),
+            None => path.with_extension(""),
+        };
+        if program.components().count() > 1 && !program.is_file() {
+            return Err(format!("the command {} does not exist", program.display()));
+        }
+        let args = entry
+            .get("args")
+            .and_then(Value::as_array)
+            .map(|args| {
+                args.iter()
+                    .filter_map(Value::as_str)
+                    .map(|arg| match tools_dir().join(arg) {
+                        file if file.is_file() => file.to_string_lossy().to_string(),
+                        _ => arg.to_string(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        if let Some(fields) = entry.as_object_mut() {
+            fields.remove("command");
+            fields.remove("args");
+        }
+        tools.push(ExternalTool { source: path.to_path_buf(), program, args, declaration: checked(entry)? });
+    }
+    Ok(tools)
+}
+
+/// Runs `program --describe`, which prints one declaration or a list of them.
+fn described(program: &Path) -> Result<Vec<ExternalTool>, String> {
+    let mut process = Command::new(program);
+    process.arg("--describe").current_dir(tools_dir());
+    command::scrub_environment(&mut process);
+    let (status, stdout, stderr) = run(process, None, Duration::from_secs(DESCRIBE_TIMEOUT))?;
+    if !status.success() {
+        return Err(format!("--describe failed ({}): {}", status, stderr.trim()));
+    }
+    let declarations = match serde_json::from_str(stdout.trim()) {
+        Ok(Value::Array(declarations)) => declarations,
+        Ok(declaration) => vec![declaration],
+        Err(e) => return Err(format!("--describe printed invalid JSON: {}", e)),
+    };
+    declarations
+        .into_iter()
+        .map(|declaration| {
+            Ok(ExternalTool {
+                source: program.to_path_buf(),
+                program: program.to_path_buf(),
+                args: Vec::new(),
+                declaration: checked(declaration)?,
+            })
+        })
+        .collect()
+}
+
+/// A declaration needs a name and a description; tools without parameters get an empty schema.
+fn checked(mut declaration: Value) -> Result<Value, String> {
+    let name = declaration["name"].as_str().unwrap_or("");
+    if name.is_empty() || declaration["description"].as_str().is_none() {
+        return Err("every tool needs a name and a description".to_string());
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(format!("tool name '{}' may only use letters, digits, _ and -", name));
+    }
+    if declaration.get("parameters").is_none() {
+        declaration["parameters"] = serde_json::json!({"type": "object", "properties": {}});
+    }
+    Ok(declaration)
+}
+
+/// A command from a manifest: a path relative to the tools folder, or a program name looked
+/// up on the PATH.
+fn resolve(command: &str) -> PathBuf {
+    let path = Path::new(command);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else if path.components().count() > 1 || tools_dir().join(path).is_file() {
+        tools_dir().join(path)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Runs a process to completion, feeding it `input` and stopping it after `timeout`.
+fn run(mut process: Command, input: Option<String>, timeout: Duration) -> Result<(ExitStatus, String, String), String> {
+    let mut child = process
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not start it: {}", e))?;
+
+    // Written from a thread, so a tool that answers before reading everything can't block us
+    let mut stdin = child.stdin.take();
+    let writer = thread::spawn(move || {
+        if let (Some(stdin), Some(input)) = (stdin.as_mut(), input) {
+            stdin.write_all(input.as_bytes()).ok();
+        }
+    });
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() >= timeout => {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(format!("stopped after {} seconds", timeout.as_secs()));
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(20)),
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+    writer.join().ok();
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    Ok((status, command::decode_output(&stdout), command::decode_output(&stderr)))
+}
+
+/// Reads a pipe to its end, keeping the first MAX_OUTPUT_BYTES.
+fn read_all<R: Read + Send + 'static>(source: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(source) = source {
+            let mut limited = source.take(MAX_OUTPUT_BYTES as u64);
+            limited.read_to_end(&mut bytes).ok();
+            // The rest is drained so the tool doesn't block on a full pipe
+            io::copy(&mut limited.into_inner(), &mut io::sink()).ok();
+        }
+        bytes
+    })
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| ["exe", "bat", "cmd", "com"].contains(&e.to_lowercase().as_str()))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn warn(message: &str) {
+    ui::status(&format!("External tool {}", message).color(Color::Yellow).to_string());
+}
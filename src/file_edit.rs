@@ -1,7 +1,20 @@
+use colored::{Color, Colorize};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+
+use crate::backups;
+use crate::confirm;
+use crate::diff_render::{print_patch, unified_diff};
+use crate::editor_bridge::{self, Review};
+use crate::snapshot;
+use crate::stats;
+use crate::ui;
+
+// Files up to this many lines (and PAGE_BYTES) are returned whole by a read without a range
+const PAGE_LINES: usize = 1000;
+const PAGE_BYTES: usize = 100_000;
 
 static SANDBOX_ROOT: Lazy<String> = Lazy::new(|| {
     let path = std::env::current_dir()
@@ -26,25 +39,37 @@ static SANDBOX_ROOT: Lazy<String> = Lazy::new(|| {
     }
 });
 
+// The sandbox root as canonicalize() returns it (with the \\?\ prefix on Windows), to compare
+// resolved paths against
+static CANONICAL_ROOT: Lazy<PathBuf> = Lazy::new(|| {
+    Path::new(&*SANDBOX_ROOT)
+        .canonicalize()
+        .unwrap_or_else(|_| PathBuf::from(&*SANDBOX_ROOT))
+});
+
 pub fn file_editor(
     subcommand: &str,
     filename: &str,
     data: Option<&str>,
     replacement: Option<&str>,
 ) -> String {
-    let file_path = PathBuf::from(&*SANDBOX_ROOT).join(filename);
+    let file_path = match sandbox_path(filename) {
+        Ok(path) => path,
+        Err(e) => return format!("Error: {}", e),
+    };
 
     match subcommand {
-        "read" => match fs::read_to_string(&file_path) {
-            Ok(content) => format!("File contents:\n{}", content),
-            Err(e) => format!("Error reading file '{}': {}", filename, e),
-        },
+        "read" => read_file(filename, None, None),
         "write" => {
             let content = data.unwrap_or("");
-            match fs::write(&file_path, content) {
-                Ok(()) => format!("Successfully wrote to '{}'", filename),
-                Err(e) => format!("Error writing to '{}': {}", filename, e),
-            }
+            let original = fs::read_to_string(&file_path).unwrap_or_default();
+            commit_edit(
+                &file_path,
+                filename,
+                &original,
+                content,
+                format!("Successfully wrote to '{}'", filename),
+            )
         }
         "search" => {
             let pattern = match data {
@@ -98,13 +123,16 @@ pub fn file_editor(
                 Ok(re) => match fs::read_to_string(&file_path) {
                     Ok(content) => {
                         let new_content = re.replace_all(&content, replace_with);
-                        match fs::write(&file_path, new_content.as_ref()) {
-                            Ok(()) => format!(
+                        commit_edit(
+                            &file_path,
+                            filename,
+                            &content,
+                            &new_content,
+                            format!(
                                 "Successfully replaced pattern '{}' with '{}' in '{}'",
                                 pattern, replace_with, filename
                             ),
-                            Err(e) => format!("Error writing to '{}': {}", filename, e),
-                        }
+                        )
                     }
                     Err(e) => format!("Error reading file '{}': {}", filename, e),
                 },
@@ -119,87 +147,714 @@ pub fn file_editor(
                         .to_string()
                 }
             };
-            
-            match fs::read_to_string(&file_path) {
-                Ok(original_content) => {
-                    // Parse and apply the diff
-                    match apply_patch(&original_content, diff_content) {
-                        Ok(new_content) => {
-                            // Write the new content back to the file
-                            match fs::write(&file_path, &new_content) {
-                                Ok(()) => format!("Successfully applied diff to '{}'", filename),
-                                Err(e) => format!("Error writing to '{}': {}", filename, e),
-                            }
-                        },
-                        Err(e) => format!("Error parsing or applying diff: {}", e),
-                    }
+            apply_diff(filename, diff_content)
+        }
+        "mkdir" => match fs::create_dir_all(&file_path) {
+            Ok(()) => {
+                stats::record_file_change(filename);
+                format!("Created directory '{}'", filename)
+            }
+            Err(e) => format!("Error creating directory '{}': {}", filename, e),
+        },
+        "delete" => {
+            if file_path == Path::new(&*SANDBOX_ROOT) {
+                return "Error: the sandbox root can't be deleted".to_string();
+            }
+            let is_dir = match fs::symlink_metadata(&file_path) {
+                Ok(metadata) => metadata.is_dir(),
+                Err(e) => return format!("Error deleting '{}': {}", filename, e),
+            };
+            snapshot::before_edit(filename);
+            let result = if is_dir {
+                fs::remove_dir_all(&file_path)
+            } else {
+                fs::remove_file(&file_path)
+            };
+            match result {
+                Ok(()) => {
+                    stats::record_file_change(filename);
+                    ui::status(&format!("{} {}", "Gemini deleted:".color(Color::Cyan).bold(), filename));
+                    format!("Deleted '{}'", filename)
                 }
-                Err(e) => format!("Error reading file '{}': {}", filename, e),
+                Err(e) => format!("Error deleting '{}': {}", filename, e),
+            }
+        }
+        "move" | "copy" => {
+            let destination = match data {
+                Some(d) => d,
+                None => return format!("Error: 'data' parameter with the destination path is required for {}", subcommand),
+            };
+            let destination_path = match sandbox_path(destination) {
+                Ok(path) => path,
+                Err(e) => return format!("Error: {}", e),
+            };
+            if !file_path.exists() {
+                return format!("Error: '{}' does not exist", filename);
+            }
+            if destination_path.exists() {
+                return format!("Error: '{}' already exists", destination);
+            }
+            if destination_path.starts_with(&file_path) {
+                return format!("Error: can't {} '{}' into itself", subcommand, filename);
+            }
+            snapshot::before_edit(filename);
+            if let Some(parent) = destination_path.parent() {
+                if let Err(e) = fs::create_dir_all(parent) {
+                    return format!("Error creating directory for '{}': {}", destination, e);
+                }
+            }
+            let result = if subcommand == "move" {
+                fs::rename(&file_path, &destination_path)
+            } else {
+                copy_recursive(&file_path, &destination_path)
+            };
+            match result {
+                Ok(()) => {
+                    stats::record_file_change(destination);
+                    let (label, verb) = match subcommand {
+                        "move" => ("Gemini moved:", "Moved"),
+                        _ => ("Gemini copied:", "Copied"),
+                    };
+                    ui::status(&format!("{} {} -> {}", label.color(Color::Cyan).bold(), filename, destination));
+                    format!("{} '{}' to '{}'", verb, filename, destination)
+                }
+                Err(e) => format!("Error: could not {} '{}' to '{}': {}", subcommand, filename, destination, e),
             }
         }
         _ => format!("Error: Unknown subcommand '{}'", subcommand),
     }
 }
 
-fn apply_patch(original: &str, diff: &str) -> Result<String, String> {
-    let original_lines: Vec<&str> = original.lines().collect();
-    let mut result_lines = original_lines.clone();
-    
-    // Current position in the parsing of the diff
-    let mut current_section_start_line = 0;
+/// Resolves a path relative to the sandbox root. Absolute paths, paths that climb out of the
+/// sandbox with .. and paths that lead out of it through a symbolic link are refused; the
+/// path doesn't have to exist yet.
+pub fn sandbox_path(filename: &str) -> Result<PathBuf, String> {
+    resolve_in(Path::new(&*SANDBOX_ROOT), &CANONICAL_ROOT, filename)
+}
+
+/// Like sandbox_path, for the sandbox at `root`, whose canonical form is `canonical_root`.
+fn resolve_in(root: &Path, canonical_root: &Path, filename: &str) -> Result<PathBuf, String> {
+    let path = root.join(relative_path(filename)?);
+
+    // The part of the path that exists may contain symbolic links; resolve them and check
+    // where they lead. A dangling link is refused too, since writing to it would create
+    // its target.
+    let existing = path
+        .ancestors()
+        .find(|ancestor| fs::symlink_metadata(ancestor).is_ok())
+        .unwrap_or(&path);
+    let resolved = existing
+        .canonicalize()
+        .map_err(|_| format!("'{}' contains a symbolic link that can't be resolved", filename))?;
+    if !resolved.starts_with(canonical_root) {
+        return Err(format!("'{}' leads outside the sandbox through a symbolic link", filename));
+    }
+    Ok(path)
+}
+
+/// The path with . and .. worked out, when it stays inside the sandbox. Backslashes separate
+/// directories and Windows drive, UNC and \\?\ paths are refused on every platform, so a
+/// path means the same whatever system the model thinks it is on.
+fn relative_path(filename: &str) -> Result<PathBuf, String> {
+    let bytes = filename.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        return Err(format!("'{}' is not a path relative to the sandbox", filename));
+    }
+    let mut relative = PathBuf::new();
+    for component in Path::new(&filename.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !relative.pop() {
+                    return Err(format!("'{}' is outside the sandbox", filename));
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(format!("'{}' is not a path relative to the sandbox", filename))
+            }
+        }
+    }
+    Ok(relative)
+}
+
+/// Copies a file or a directory tree. Symbolic links are copied as links rather than
+/// followed, so a link can't bring in what it points to outside the sandbox, and one that
+/// points to a parent directory doesn't make the copy go round in circles.
+fn copy_recursive(source: &Path, destination: &Path) -> std::io::Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+    if metadata.file_type().is_symlink() {
+        return copy_link(source, destination);
+    }
+    if !metadata.is_dir() {
+        return fs::copy(source, destination).map(|_| ());
+    }
+    fs::create_dir_all(destination)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        copy_recursive(&entry.path(), &destination.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_link(source: &Path, destination: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, destination)
+}
+
+// Creating links on Windows needs privileges most users don't have
+#[cfg(not(unix))]
+fn copy_link(source: &Path, _destination: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::other(format!("'{}' is a symbolic link, which can't be copied", source.display())))
+}
+
+/// Reads a file of the sandbox. A small file without a range is returned as is; a line range,
+/// or a file too large to return whole, comes back as numbered lines, one page at a time,
+/// with a note on how to read the next page.
+pub fn read_file(filename: &str, start_line: Option<usize>, end_line: Option<usize>) -> String {
+    let file_path = match sandbox_path(filename) {
+        Ok(path) => path,
+        Err(e) => return format!("Error: {}", e),
+    };
+    let content = match fs::read_to_string(&file_path) {
+        Ok(content) => content,
+        Err(e) => return format!("Error reading file '{}': {}", filename, e),
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    let ranged = start_line.is_some() || end_line.is_some();
+    if !ranged && lines.len() <= PAGE_LINES && content.len() <= PAGE_BYTES {
+        return format!("File contents:\n{}", content);
+    }
+
+    let start = start_line.unwrap_or(1).max(1);
+    let end = end_line.unwrap_or(lines.len()).min(lines.len());
+    if start > lines.len() {
+        return format!("Error: '{}' has only {} lines", filename, lines.len());
+    }
+    if end < start {
+        return format!("Error: end_line {} is before start_line {}", end, start);
+    }
+
+    let width = end.to_string().len();
+    let mut numbered = Vec::new();
+    let mut bytes = 0;
+    for (index, line) in lines.iter().enumerate().take(end).skip(start - 1) {
+        bytes += line.len() + 1;
+        if numbered.len() == PAGE_LINES || (bytes > PAGE_BYTES && !numbered.is_empty()) {
+            break;
+        }
+        numbered.push(format!("{:>width$} | {}", index + 1, line, width = width));
+    }
+    let last = start + numbered.len() - 1;
+    let mut result = format!(
+        "Lines {}-{} of {} in '{}':\n{}",
+        start,
+        last,
+        lines.len(),
+        filename,
+        numbered.join("\n")
+    );
+    if last < end {
+        result.push_str(&format!(
+            "\n... {} more lines; read them with start_line={}",
+            end - last,
+            last + 1
+        ));
+    }
+    result
+}
+
+/// Writes a modified file. When an editor is attached the change is sent there for review
+/// first; otherwise it is previewed in the terminal and written directly.
+fn commit_edit(file_path: &Path, filename: &str, original: &str, proposed: &str, success: String) -> String {
+    try_commit_edit(file_path, filename, original, proposed, success).unwrap_or_else(|e| e)
+}
+
+/// Like commit_edit, with a rejected or failed edit as an error.
+fn try_commit_edit(file_path: &Path, filename: &str, original: &str, proposed: &str, success: String) -> Result<String, String> {
+    let diff = unified_diff(original, proposed, filename);
+    if confirm::dry_run() {
+        print_edit_header(filename);
+        print_patch(&diff);
+        return Ok(format!(
+            "Dry run: '{}' was not changed. The edit would change it like this:\n{}",
+            filename, diff
+        ));
+    }
+    let absolute_path = file_path.to_string_lossy();
+    let content = match editor_bridge::propose_edit(filename, &absolute_path, original, proposed, &diff) {
+        Review::Accepted(content) => content,
+        Review::Rejected(reason) => {
+            return Err(format!(
+                "The user rejected the edit to '{}' in their editor: {}",
+                filename, reason
+            ))
+        }
+        Review::Unavailable => {
+            print_edit_header(filename);
+            print_patch(&diff);
+            if confirm::edit_required() && !confirm::ask_edit() {
+                return Err(format!("The user rejected the edit to '{}' after seeing the diff", filename));
+            }
+            proposed.to_string()
+        }
+    };
+
+    snapshot::before_edit(filename);
+    if let Err(e) = backups::save(filename, file_path) {
+        ui::status(&format!("Could not back up '{}': {}", filename, e).color(Color::Yellow).to_string());
+    }
+    let result = fs::write(file_path, &content);
+    if result.is_ok() {
+        stats::record_file_change(filename);
+    }
+    match result {
+        Ok(()) if content == proposed => Ok(success),
+        Ok(()) => Ok(format!("{} (the user adjusted the change in their editor before accepting it)", success)),
+        Err(e) => Err(format!("Error writing to '{}': {}", filename, e)),
+    }
+}
+
+fn print_edit_header(filename: &str) {
+    ui::status(&format!("{} {}", "Gemini is editing:".color(Color::Cyan).bold(), filename));
+}
+
+/// One @@ hunk of a unified diff.
+struct Hunk {
+    header: String,
+    /// 1-based line the hunk starts at in the original file; None for a bare "@@ @@"
+    old_start: Option<usize>,
+    /// (' ', '-' or '+', line) in diff order
+    lines: Vec<(char, String)>,
+}
+
+impl Hunk {
+    /// The lines the hunk expects in the file, and the lines it puts there instead, with
+    /// `fuzz` context lines dropped from each end.
+    fn blocks(&self, fuzz: usize) -> (Vec<&str>, Vec<&str>) {
+        let leading = self.lines.iter().take_while(|(kind, _)| *kind == ' ').count().min(fuzz);
+        let trailing = self.lines.iter().rev().take_while(|(kind, _)| *kind == ' ').count().min(fuzz);
+        let end = self.lines.len().saturating_sub(trailing).max(leading);
+        let lines = &self.lines[leading..end];
+        let old = lines.iter().filter(|(kind, _)| *kind != '+').map(|(_, line)| line.as_str()).collect();
+        let new = lines.iter().filter(|(kind, _)| *kind != '-').map(|(_, line)| line.as_str()).collect();
+        (old, new)
+    }
+}
+
+/// The part of a diff for one file.
+struct FilePatch {
+    /// Path from the +++ (or, for a deleted file, ---) header
+    path: Option<String>,
+    hunks: Vec<Hunk>,
+}
+
+/// A patched file waiting to be written.
+struct Change {
+    path: PathBuf,
+    name: String,
+    existed: bool,
+    original: String,
+    patched: String,
+    /// Hunks that didn't apply exactly as stated
+    notes: Vec<String>,
+}
+
+/// Applies a unified diff. A diff for several files (one ---/+++ header pair each) is applied
+/// to the files it names; a diff for one file goes to `filename`. Either every hunk applies or
+/// no file is changed.
+fn apply_diff(filename: &str, diff: &str) -> String {
+    match prepare_diff(filename, diff) {
+        Ok(changes) => write_changes(changes),
+        Err(e) => format!("{}. No file was changed; read the file again and send a diff that matches it.", e),
+    }
+}
+
+/// Applies diffs to several files as one change: every diff is checked against its file
+/// before anything is written, and files already written are restored if a later one fails
+/// or is rejected.
+pub fn apply_changeset(changes: &[(String, String)]) -> String {
+    if changes.is_empty() {
+        return "Error: the changeset is empty".to_string();
+    }
+    let mut prepared: Vec<Change> = Vec::new();
+    for (filename, diff) in changes {
+        match prepare_diff(filename, diff) {
+            Ok(files) => prepared.extend(files),
+            Err(e) => {
+                return format!(
+                    "Error in the change to '{}': {}. No file was changed; fix that diff and send the whole changeset again.",
+                    filename, e
+                )
+            }
+        }
+    }
+    for (index, change) in prepared.iter().enumerate() {
+        if prepared[..index].iter().any(|earlier| earlier.path == change.path) {
+            return format!(
+                "Error: '{}' is changed more than once; combine its hunks into one diff. No file was changed.",
+                change.name
+            );
+        }
+    }
+    write_changes(prepared)
+}
+
+/// Checks a diff against the files it changes and returns the patched contents.
+fn prepare_diff(filename: &str, diff: &str) -> Result<Vec<Change>, String> {
+    let patches = parse_diff(diff).map_err(|e| format!("Error parsing diff: {}", e))?;
+
+    let mut changes = Vec::new();
+    for patch in &patches {
+        let name = match (&patch.path, patches.len()) {
+            (Some(path), count) if count > 1 => path.clone(),
+            _ => filename.to_string(),
+        };
+        let path = sandbox_path(&name).map_err(|e| format!("Error: {}", e))?;
+        let (original, existed) = match fs::read_to_string(&path) {
+            Ok(content) => (content, true),
+            // A diff that only adds lines can create a file
+            Err(_) if patch.hunks.iter().all(|h| h.lines.iter().all(|(kind, _)| *kind == '+')) => (String::new(), false),
+            Err(e) => return Err(format!("Error reading file '{}': {}", name, e)),
+        };
+        let (patched, notes) =
+            apply_patch(&original, &patch.hunks).map_err(|e| format!("Error applying diff to '{}': {}", name, e))?;
+        changes.push(Change { path, name, existed, original, patched, notes });
+    }
+    Ok(changes)
+}
+
+/// Writes patched files one after the other. When one is rejected or can't be written, the
+/// files written before it are put back the way they were.
+fn write_changes(changes: Vec<Change>) -> String {
+    let mut results = Vec::new();
+    for (index, change) in changes.iter().enumerate() {
+        if let Some(parent) = change.path.parent().filter(|_| !confirm::dry_run()) {
+            fs::create_dir_all(parent).ok();
+        }
+        let mut success = format!("Successfully applied diff to '{}'", change.name);
+        if !change.notes.is_empty() {
+            success.push_str(&format!(" ({})", change.notes.join("; ")));
+        }
+        match try_commit_edit(&change.path, &change.name, &change.original, &change.patched, success) {
+            Ok(result) => results.push(result),
+            Err(e) if index == 0 => return format!("{}. No file was changed.", e),
+            Err(e) => {
+                let written = &changes[..index];
+                for earlier in written {
+                    let restored = if earlier.existed {
+                        fs::write(&earlier.path, &earlier.original)
+                    } else {
+                        fs::remove_file(&earlier.path)
+                    };
+                    if let Err(restore_error) = restored {
+                        ui::status(
+                            &format!("Could not roll back '{}': {}", earlier.name, restore_error)
+                                .color(Color::Red)
+                                .to_string(),
+                        );
+                    }
+                }
+                let names: Vec<&str> = written.iter().map(|c| c.name.as_str()).collect();
+                ui::status(&format!("Rolled back {}", names.join(", ")).color(Color::Yellow).to_string());
+                return format!(
+                    "{}. The changes already written to {} were rolled back, so no file was changed.",
+                    e,
+                    names.join(", ")
+                );
+            }
+        }
+    }
+    results.join("\n")
+}
+
+/// Splits a unified diff into files and hunks. Lines outside hunks (git headers, index lines,
+/// "\\ No newline at end of file") are skipped.
+fn parse_diff(diff: &str) -> Result<Vec<FilePatch>, String> {
+    static HUNK_HEADER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^@@ -(\d+)(?:,\d+)? \+\d+(?:,\d+)? @@").unwrap());
+
+    let lines: Vec<&str> = diff.lines().collect();
+    let mut patches: Vec<FilePatch> = Vec::new();
     let mut in_hunk = false;
-    
-    // Regular expression for unified diff hunk headers: @@ -a,b +c,d @@
-    let hunk_header_re = Regex::new(r"@@ -(\d+),(\d+) \+(\d+),(\d+) @@").map_err(|e| e.to_string())?;
-    
-    // Process the diff line by line
-    for line in diff.lines() {
-        // Check if this is a hunk header line
-        if let Some(caps) = hunk_header_re.captures(line) {
-            in_hunk = true;
-            
-            // Parse the line numbers and counts from the hunk header
-            let original_start: usize = caps[1].parse().map_err(|_| "Invalid line number in diff".to_string())?;
-            let _original_count: usize = caps[2].parse().map_err(|_| "Invalid line count in diff".to_string())?;
-            
-            // In unified diffs, line numbers are 1-based, so we subtract 1 for 0-based indexing
-            current_section_start_line = original_start - 1;
+    for (index, line) in lines.iter().enumerate() {
+        // "--- " followed by "+++ " starts the next file, even right after a hunk
+        let next = lines.get(index + 1).copied().unwrap_or("");
+        let file_header = line.starts_with("--- ") && next.starts_with("+++ ");
+        if in_hunk && !file_header && !line.starts_with("@@") {
+            let hunk = patches.last_mut().and_then(|p| p.hunks.last_mut()).unwrap();
+            match line.chars().next() {
+                Some(kind @ (' ' | '-' | '+')) => hunk.lines.push((kind, line[1..].to_string())),
+                // Some tools drop the space of empty context lines
+                None => hunk.lines.push(('e', String::new())),
+                Some('\\') => {}
+                Some(_) => in_hunk = false,
+            }
             continue;
         }
-        
-        // Skip file header lines in unified diff
-        if line.starts_with("---") || line.starts_with("+++") {
+        in_hunk = false;
+
+        if file_header {
+            let path = header_path(&next[4..]).or_else(|| header_path(&line[4..]));
+            patches.push(FilePatch { path, hunks: Vec::new() });
+        } else if line.starts_with("@@") {
+            if patches.is_empty() {
+                patches.push(FilePatch { path: None, hunks: Vec::new() });
+            }
+            let old_start = HUNK_HEADER.captures(line).and_then(|caps| caps[1].parse().ok());
+            patches.last_mut().unwrap().hunks.push(Hunk {
+                header: line.to_string(),
+                old_start,
+                lines: Vec::new(),
+            });
+            in_hunk = true;
+        }
+    }
+
+    for hunk in patches.iter_mut().flat_map(|patch| patch.hunks.iter_mut()) {
+        // Blank lines after a hunk separate it from what follows; blank lines inside are context
+        while hunk.lines.last().map(|(kind, _)| *kind == 'e').unwrap_or(false) {
+            hunk.lines.pop();
+        }
+        for line in hunk.lines.iter_mut().filter(|(kind, _)| *kind == 'e') {
+            line.0 = ' ';
+        }
+    }
+    patches.retain(|patch| !patch.hunks.is_empty());
+    if patches.is_empty() {
+        return Err("no hunks found (expected lines starting with @@)".to_string());
+    }
+    Ok(patches)
+}
+
+/// The file named in a ---/+++ header, without the a/ or b/ prefix git adds. None for
+/// /dev/null.
+fn header_path(header: &str) -> Option<String> {
+    let path = header.split('\t').next().unwrap_or("").trim();
+    if path.is_empty() || path == "/dev/null" {
+        return None;
+    }
+    let stripped = path.strip_prefix("a/").or_else(|| path.strip_prefix("b/"));
+    match stripped {
+        Some(stripped) if !Path::new(&*SANDBOX_ROOT).join(path).exists() => Some(stripped.to_string()),
+        _ => Some(path.to_string()),
+    }
+}
+
+/// Applies hunks in order. Each hunk's lines have to be found in the file: first where the
+/// header says, corrected by how far earlier hunks moved it, then at the nearest position
+/// elsewhere. When that fails, up to two context lines are dropped from each end, and then
+/// trailing whitespace is ignored. Returns the new content and notes on hunks that didn't
+/// apply exactly as stated.
+fn apply_patch(original: &str, hunks: &[Hunk]) -> Result<(String, Vec<String>), String> {
+    let mut lines: Vec<&str> = original.lines().collect();
+    let mut notes = Vec::new();
+    // Where the next hunk may start, and how far the file has shifted from the header numbers
+    let mut next_free = 0;
+    let mut shift: isize = 0;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let number = index + 1;
+        let expected = hunk
+            .old_start
+            .map(|start| (start.max(1) as isize - 1 + shift).max(0) as usize)
+            .unwrap_or(next_free);
+
+        let (full_old, _) = hunk.blocks(0);
+        if full_old.is_empty() {
+            // Only additions: insert where the header says
+            let (_, new) = hunk.blocks(0);
+            let at = match hunk.old_start {
+                Some(0) => 0,
+                Some(_) => (expected + 1).min(lines.len()),
+                None => lines.len(),
+            }
+            .max(next_free);
+            lines.splice(at..at, new.iter().copied());
+            next_free = at + new.len();
+            shift += new.len() as isize;
             continue;
         }
-        
-        // If we're in a hunk, process addition/removal/context lines
-        if in_hunk {
-            match line.chars().next() {
-                Some('+') => {
-                    // Addition line: insert at current position
-                    let content = &line[1..]; // Skip the '+' prefix
-                    result_lines.insert(current_section_start_line, content);
-                    current_section_start_line += 1;
-                },
-                Some('-') => {
-                    // Removal line: remove at current position
-                    if current_section_start_line < result_lines.len() {
-                        result_lines.remove(current_section_start_line);
-                    } else {
-                        return Err(format!("Diff removal line {} is out of bounds", current_section_start_line));
-                    }
-                },
-                Some(' ') => {
-                    // Context line: just advance position
-                    current_section_start_line += 1;
-                },
-                _ => {
-                    // Other lines in the diff (could be comments, etc.)
-                    // Ignore them
+
+        let mut found = None;
+        'search: for loose in [false, true] {
+            for fuzz in 0..=2 {
+                let (old, new) = hunk.blocks(fuzz);
+                if old.is_empty() || (fuzz > 0 && old.len() == hunk.blocks(fuzz - 1).0.len()) {
+                    continue;
+                }
+                let expected_here = expected + hunk.lines.iter().take_while(|(kind, _)| *kind == ' ').count().min(fuzz);
+                if let Some(at) = find_block(&lines, &old, next_free, expected_here, loose) {
+                    found = Some((at, old.len(), new, fuzz, loose, expected_here));
+                    break 'search;
                 }
             }
         }
+        let (at, old_len, new, fuzz, loose, expected_here) = match found {
+            Some(found) => found,
+            None => {
+                let preview: Vec<&str> = full_old.iter().take(3).copied().collect();
+                return Err(format!(
+                    "hunk {} ({}) does not match the file; it expects these lines{}:\n{}",
+                    number,
+                    hunk.header,
+                    hunk.old_start.map(|start| format!(" at line {}", start)).unwrap_or_default(),
+                    preview.join("\n")
+                ));
+            }
+        };
+
+        let mut adjustments = Vec::new();
+        if hunk.old_start.is_some() && at != expected_here {
+            let moved = at as isize - expected_here as isize;
+            adjustments.push(format!("{} lines {}", moved.abs(), if moved > 0 { "later" } else { "earlier" }));
+        }
+        if fuzz > 0 {
+            adjustments.push(format!("ignoring {} context lines at each end", fuzz));
+        }
+        if loose {
+            adjustments.push("ignoring trailing whitespace".to_string());
+        }
+        if !adjustments.is_empty() {
+            notes.push(format!("hunk {} applied at line {}, {}", number, at + 1, adjustments.join(", ")));
+        }
+
+        shift += at as isize - expected_here as isize + new.len() as isize - old_len as isize;
+        lines.splice(at..at + old_len, new.iter().copied());
+        next_free = at + new.len();
+    }
+
+    let mut patched = lines.join("\n");
+    if original.ends_with('\n') || (original.is_empty() && !patched.is_empty()) {
+        patched.push('\n');
+    }
+    Ok((patched, notes))
+}
+
+/// The position of `block` in `lines` at or after `from` that is closest to `expected`.
+fn find_block(lines: &[&str], block: &[&str], from: usize, expected: usize, loose: bool) -> Option<usize> {
+    if block.len() > lines.len() {
+        return None;
+    }
+    let last = lines.len() - block.len();
+    let matches = |at: usize| {
+        lines[at..at + block.len()]
+            .iter()
+            .zip(block)
+            .all(|(line, expected)| if loose { line.trim_end() == expected.trim_end() } else { line == expected })
+    };
+    let expected = expected.clamp(from, last.max(from));
+    (0..=lines.len())
+        .flat_map(|distance| [expected.checked_add(distance), expected.checked_sub(distance)])
+        .flatten()
+        .filter(|at| *at >= from && *at <= last)
+        .find(|at| matches(*at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty sandbox in the temporary directory, removed when dropped.
+    struct Sandbox(PathBuf);
+
+    impl Sandbox {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("gemini-cli-rs-{}-{}", name, std::process::id()));
+            fs::remove_dir_all(&root).ok();
+            fs::create_dir_all(&root).unwrap();
+            Sandbox(root.canonicalize().unwrap())
+        }
+
+        fn resolve(&self, filename: &str) -> Result<PathBuf, String> {
+            resolve_in(&self.0, &self.0, filename)
+        }
+    }
+
+    impl Drop for Sandbox {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn relative_paths_stay_in_the_sandbox() {
+        let sandbox = Sandbox::new("relative");
+        assert_eq!(sandbox.resolve("src/main.rs").unwrap(), sandbox.0.join("src").join("main.rs"));
+        assert_eq!(sandbox.resolve("./a/../b.txt").unwrap(), sandbox.0.join("b.txt"));
+        assert_eq!(sandbox.resolve("a\\b.txt").unwrap(), sandbox.0.join("a").join("b.txt"));
+        assert_eq!(sandbox.resolve("new/dir/file.txt").unwrap(), sandbox.0.join("new/dir/file.txt"));
+    }
+
+    #[test]
+    fn parent_directories_cant_leave_the_sandbox() {
+        for path in ["..", "../secret", "a/../../secret", "a/b/../../..", "..\\secret", "a\\..\\..\\secret"] {
+            assert!(relative_path(path).is_err(), "{} was accepted", path);
+        }
+    }
+
+    #[test]
+    fn absolute_paths_are_refused() {
+        for path in ["/etc/passwd", "/", "//server/share/file"] {
+            assert!(relative_path(path).is_err(), "{} was accepted", path);
+        }
     }
-    
-    Ok(result_lines.join("\n"))
-}
\ No newline at end of file
+
+    #[test]
+    fn windows_prefixes_are_refused_everywhere() {
+        for path in [
+            "C:\\Windows\\win.ini",
+            "c:/Windows",
+            "C:relative.txt",
+            "\\Windows\\win.ini",
+            "\\\\?\\C:\\Windows",
+            "\\\\server\\share\\file",
+            "\\\\.\\pipe\\name",
+        ] {
+            assert!(relative_path(path).is_err(), "{} was accepted", path);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symbolic_links_out_of_the_sandbox_are_refused() {
+        let sandbox = Sandbox::new("escape");
+        let outside = Sandbox::new("escape-target");
+        std::os::unix::fs::symlink(&outside.0, sandbox.0.join("out")).unwrap();
+        assert!(sandbox.resolve("out").is_err());
+        assert!(sandbox.resolve("out/new.txt").is_err());
+
+        fs::create_dir(sandbox.0.join("inner")).unwrap();
+        std::os::unix::fs::symlink(sandbox.0.join("inner"), sandbox.0.join("alias")).unwrap();
+        assert_eq!(sandbox.resolve("alias/new.txt").unwrap(), sandbox.0.join("alias/new.txt"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copies_keep_symbolic_links_as_links() {
+        let sandbox = Sandbox::new("copy");
+        let outside = Sandbox::new("copy-target");
+        fs::write(outside.0.join("secret.txt"), "outside").unwrap();
+        let source = sandbox.0.join("source");
+        fs::create_dir(&source).unwrap();
+        fs::write(source.join("file.txt"), "inside").unwrap();
+        std::os::unix::fs::symlink(outside.0.join("secret.txt"), source.join("secret.txt")).unwrap();
+        std::os::unix::fs::symlink("..", source.join("parent")).unwrap();
+
+        copy_recursive(&source, &sandbox.0.join("copy")).unwrap();
+        let copy = sandbox.0.join("copy");
+        assert_eq!(fs::read_to_string(copy.join("file.txt")).unwrap(), "inside");
+        for link in ["secret.txt", "parent"] {
+            assert!(fs::symlink_metadata(copy.join(link)).unwrap().file_type().is_symlink(), "{} was followed", link);
+        }
+        assert_eq!(fs::read_link(copy.join("parent")).unwrap(), Path::new(".."));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symbolic_links_are_refused() {
+        let sandbox = Sandbox::new("dangling");
+        std::os::unix::fs::symlink(sandbox.0.join("missing"), sandbox.0.join("dangling")).unwrap();
+        assert!(sandbox.resolve("dangling").is_err());
+        assert!(sandbox.resolve("dangling/new.txt").is_err());
+    }
+}
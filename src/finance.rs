@@ -0,0 +1,42 @@
+use crate::alpha_vantage;
+
+/// Exchange rate between two currencies, physical (USD, EUR) or digital (BTC, ETH).
+pub fn fx_rate(from: &str, to: &str) -> Result<String, String> {
+    let (from, to) = (code(from), code(to));
+    alpha_vantage::query(
+        &[("function", "CURRENCY_EXCHANGE_RATE"), ("from_currency", &from), ("to_currency", &to)],
+        &format!("{}/{}", from, to),
+    )
+}
+
+/// Current price of a cryptocurrency in a market currency, and with `history` its daily
+/// prices as well.
+pub fn crypto_price(symbol: &str, market: &str, history: bool) -> Result<String, String> {
+    let (symbol, market) = (code(symbol), code(market));
+    let price = fx_rate(&symbol, &market)?;
+    if !history {
+        return Ok(price);
+    }
+    let daily = alpha_vantage::query(
+        &[("function", "DIGITAL_CURRENCY_DAILY"), ("symbol", &symbol), ("market", &market)],
+        &symbol,
+    )?;
+    Ok(format!("{}\n\n{}", price, daily))
+}
+
+/// Company profile and key figures: sector, market capitalization, P/E ratio, dividend
+/// yield, 52-week range and the like.
+pub fn company_overview(symbol: &str) -> Result<String, String> {
+    let symbol = code(symbol);
+    alpha_vantage::query(&[("function", "OVERVIEW"), ("symbol", &symbol)], &symbol)
+}
+
+/// Reported and estimated earnings per share, by year and by quarter.
+pub fn company_earnings(symbol: &str) -> Result<String, String> {
+    let symbol = code(symbol);
+    alpha_vantage::query(&[("function", "EARNINGS"), ("symbol", &symbol)], &symbol)
+}
+
+fn code(symbol: &str) -> String {
+    symbol.trim().to_uppercase()
+}
@@ -0,0 +1,262 @@
+use chrono::{DateTime, Local};
+use globset::Glob;
+use ignore::WalkBuilder;
+use regex::{Regex, RegexBuilder};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::SANDBOX_ROOT;
+
+// Most entries in one listing; the model can narrow it down with a path or pattern
+const MAX_ENTRIES: usize = 300;
+// Levels listed below the starting directory when no depth is given
+const DEFAULT_DEPTH: usize = 3;
+// Matching lines shown per file and per search, unless the call asks for fewer
+const DEFAULT_MATCHES_PER_FILE: usize = 5;
+const MAX_MATCHES: usize = 100;
+// Lines shown around a match by default, and at most
+const DEFAULT_CONTEXT: usize = 2;
+const MAX_CONTEXT: usize = 10;
+// Longest line shown; minified code and data are cut
+const MAX_LINE_CHARS: usize = 300;
+// Larger files are not searched
+const MAX_SEARCH_FILE_BYTES: u64 = 2_000_000;
+// A NUL byte in this many leading bytes marks a binary file, which is skipped
+const BINARY_SNIFF_BYTES: usize = 8000;
+
+/// Lists a directory of the sandbox as an indented tree with sizes and modification times,
+/// or, with a glob pattern such as `**/*.rs`, the matching files as a flat list. Hidden
+/// files and whatever .gitignore excludes are left out.
+pub fn list_files(path: Option<&str>, pattern: Option<&str>, depth: Option<usize>) -> Result<String, String> {
+    let root = Path::new(&*SANDBOX_ROOT);
+    let path = path.map(str::trim).filter(|p| !p.is_empty() && *p != ".");
+    let start = match path {
+        Some(path) => resolve(root, path)?,
+        None => root.to_path_buf(),
+    };
+    let matcher = match pattern.map(str::trim).filter(|p| !p.is_empty()) {
+        Some(pattern) => Some(
+            Glob::new(pattern)
+                .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?
+                .compile_matcher(),
+        ),
+        None => None,
+    };
+
+    let mut walker = WalkBuilder::new(&start);
+    walker.require_git(false).sort_by_file_name(|a, b| a.cmp(b));
+    // A pattern searches the whole tree unless a depth is given
+    match (depth, &matcher) {
+        (Some(depth), _) => walker.max_depth(Some(depth.max(1))),
+        (None, None) => walker.max_depth(Some(DEFAULT_DEPTH)),
+        (None, Some(_)) => walker.max_depth(None),
+    };
+
+    let mut lines = Vec::new();
+    let mut skipped = 0;
+    for entry in walker.build().flatten() {
+        if entry.depth() == 0 {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(&start).unwrap_or(entry.path());
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        let line = match &matcher {
+            Some(matcher) => {
+                if is_dir || !matcher.is_match(relative) {
+                    continue;
+                }
+                describe(&relative.to_string_lossy(), entry.path(), false)
+            }
+            None => {
+                let name = entry.file_name().to_string_lossy();
+                format!("{}{}", "  ".repeat(entry.depth() - 1), describe(&name, entry.path(), is_dir))
+            }
+        };
+        if lines.len() == MAX_ENTRIES {
+            skipped += 1;
+        } else {
+            lines.push(line);
+        }
+    }
+
+    let shown = path.unwrap_or(".");
+    if lines.is_empty() {
+        return Ok(match pattern {
+            Some(pattern) => format!("No files matching '{}' in '{}'", pattern, shown),
+            None => format!("'{}' is empty", shown),
+        });
+    }
+    if skipped > 0 {
+        lines.push(format!(
+            "... and {} more entries; list a subdirectory or use a pattern to see them",
+            skipped
+        ));
+    }
+    Ok(format!("Files in '{}':\n{}", shown, lines.join("\n")))
+}
+
+/// Searches the text files of the sandbox (or of a directory in it) for a literal string or,
+/// with `regex`, a regular expression. Hidden, .gitignored and binary files are skipped. The
+/// search ignores case unless the query has capitals. Each file shows at most
+/// `max_per_file` matching lines, with `context` lines around them, and the whole search
+/// at most MAX_MATCHES.
+pub fn search_codebase(
+    query: &str,
+    regex: bool,
+    path: Option<&str>,
+    glob: Option<&str>,
+    context: Option<usize>,
+    max_per_file: Option<usize>,
+) -> Result<String, String> {
+    if query.is_empty() {
+        return Err("The query is empty".to_string());
+    }
+    let root = Path::new(&*SANDBOX_ROOT);
+    let path = path.map(str::trim).filter(|p| !p.is_empty() && *p != ".");
+    let start = match path {
+        Some(path) => resolve(root, path)?,
+        None => root.to_path_buf(),
+    };
+    let matcher = match glob.map(str::trim).filter(|p| !p.is_empty()) {
+        Some(glob) => Some(
+            Glob::new(glob)
+                .map_err(|e| format!("Invalid pattern '{}': {}", glob, e))?
+                .compile_matcher(),
+        ),
+        None => None,
+    };
+    let pattern = if regex { query.to_string() } else { regex::escape(query) };
+    let pattern: Regex = RegexBuilder::new(&pattern)
+        .case_insensitive(!query.chars().any(char::is_uppercase))
+        .build()
+        .map_err(|e| format!("Invalid regular expression '{}': {}", query, e))?;
+    let context = context.unwrap_or(DEFAULT_CONTEXT).min(MAX_CONTEXT);
+    let max_per_file = max_per_file.unwrap_or(DEFAULT_MATCHES_PER_FILE).max(1);
+
+    let mut walker = WalkBuilder::new(&start);
+    walker.require_git(false).sort_by_file_name(|a, b| a.cmp(b));
+    let mut sections = Vec::new();
+    let (mut shown, mut total, mut files) = (0, 0, 0);
+    for entry in walker.build().flatten() {
+        if !entry.file_type().is_some_and(|t| t.is_file())
+            || entry.metadata().map(|m| m.len() > MAX_SEARCH_FILE_BYTES).unwrap_or(true)
+        {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(&start).unwrap_or(entry.path());
+        if matcher.as_ref().is_some_and(|matcher| !matcher.is_match(relative)) {
+            continue;
+        }
+        let bytes = match fs::read(entry.path()) {
+            Ok(bytes) if !bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) => bytes,
+            _ => continue,
+        };
+        let text = String::from_utf8_lossy(&bytes);
+        let lines: Vec<&str> = text.lines().collect();
+        let matches: Vec<usize> = (0..lines.len()).filter(|&i| pattern.is_match(lines[i])).collect();
+        if matches.is_empty() {
+            continue;
+        }
+        files += 1;
+        total += matches.len();
+        if shown >= MAX_MATCHES {
+            continue;
+        }
+        let kept = &matches[..matches.len().min(max_per_file).min(MAX_MATCHES - shown)];
+        shown += kept.len();
+        let shown_path = entry.path().strip_prefix(root).unwrap_or(entry.path()).to_string_lossy().replace('\\', "/");
+        sections.push(excerpt(&shown_path, &lines, kept, matches.len(), context));
+    }
+
+    let place = path.map(|p| format!(" in '{}'", p)).unwrap_or_default();
+    if sections.is_empty() {
+        return Ok(format!("No matches for '{}'{}", query, place));
+    }
+    let mut summary = format!(
+        "{} matching line{} in {} file{} for '{}'{}",
+        total,
+        if total == 1 { "" } else { "s" },
+        files,
+        if files == 1 { "" } else { "s" },
+        query,
+        place
+    );
+    if shown < total {
+        summary.push_str(&format!(
+            " ({} shown; narrow the search with a path or glob, or read the files for the rest)",
+            shown
+        ));
+    }
+    Ok(format!("{}\n\n{}", summary, sections.join("\n\n")))
+}
+
+/// The matching lines of one file with their context, grep style: `12:` marks a match and
+/// `11-` a context line, and `--` separates groups that aren't adjacent.
+fn excerpt(path: &str, lines: &[&str], matches: &[usize], count: usize, context: usize) -> String {
+    let mut out = vec![path.to_string()];
+    let mut last: Option<usize> = None;
+    for &line in matches {
+        let from = line.saturating_sub(context).max(last.map_or(0, |l| l + 1));
+        let to = (line + context).min(lines.len() - 1);
+        if last.is_some_and(|l| from > l + 1) {
+            out.push("--".to_string());
+        }
+        for (i, text) in lines.iter().enumerate().take(to + 1).skip(from) {
+            let marker = if matches.contains(&i) { ':' } else { '-' };
+            out.push(format!("{}{} {}", i + 1, marker, cut(text)));
+        }
+        last = Some(to);
+    }
+    if count > matches.len() {
+        out.push(format!("... {} more matches in this file", count - matches.len()));
+    }
+    out.join("\n")
+}
+
+fn cut(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((end, _)) => format!("{} [...]", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// A directory of the sandbox; paths leading outside it are refused.
+fn resolve(root: &Path, path: &str) -> Result<PathBuf, String> {
+    let resolved = root
+        .join(path)
+        .canonicalize()
+        .map_err(|e| format!("Cannot list '{}': {}", path, e))?;
+    // Compare like with like: on Windows canonicalize() adds a \\?\ prefix the root doesn't have
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    if !resolved.starts_with(&root) {
+        return Err(format!("'{}' is outside the sandbox", path));
+    }
+    if !resolved.is_dir() {
+        return Err(format!("'{}' is not a directory", path));
+    }
+    Ok(resolved)
+}
+
+/// "name/" for directories, "name  12.3 KB  2024-05-01 14:02" for files.
+fn describe(name: &str, path: &Path, is_dir: bool) -> String {
+    if is_dir {
+        return format!("{}/", name);
+    }
+    let metadata = match path.metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return name.to_string(),
+    };
+    let modified = metadata
+        .modified()
+        .map(|time| DateTime::<Local>::from(time).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default();
+    format!("{}  {}  {}", name, size(metadata.len()), modified)
+}
+
+fn size(bytes: u64) -> String {
+    match bytes {
+        b if b < 1024 => format!("{} B", b),
+        b if b < 1024 * 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+    }
+}
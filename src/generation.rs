@@ -0,0 +1,185 @@
+use once_cell::sync::Lazy;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+
+// The API accepts at most this many stop sequences and candidates
+const MAX_STOP_SEQUENCES: usize = 5;
+const MAX_CANDIDATES: u64 = 8;
+// Largest thinking budget of the 2.5 models, in tokens
+const MAX_THINKING_BUDGET: i64 = 32_768;
+
+/// A generationConfig field the user can set.
+struct Setting {
+    /// Name used with /gen
+    name: &'static str,
+    /// Variable in ~/.gemini.conf
+    env: &'static str,
+    /// Field of generationConfig; `thinkingConfig.` ones go into that object
+    field: &'static str,
+}
+
+const SETTINGS: &[Setting] = &[
+    Setting { name: "temperature", env: "GEMINI_TEMPERATURE", field: "temperature" },
+    Setting { name: "top_p", env: "GEMINI_TOP_P", field: "topP" },
+    Setting { name: "top_k", env: "GEMINI_TOP_K", field: "topK" },
+    Setting { name: "max_tokens", env: "GEMINI_MAX_OUTPUT_TOKENS", field: "maxOutputTokens" },
+    Setting { name: "stop", env: "GEMINI_STOP_SEQUENCES", field: "stopSequences" },
+    Setting { name: "candidates", env: "GEMINI_CANDIDATE_COUNT", field: "candidateCount" },
+    Setting { name: "thinking_budget", env: "GEMINI_THINKING_BUDGET", field: "thinkingConfig.thinkingBudget" },
+    Setting { name: "thoughts", env: "GEMINI_SHOW_THOUGHTS", field: "thinkingConfig.includeThoughts" },
+];
+
+// Other spellings accepted by /gen
+const ALIASES: &[(&str, &str)] = &[
+    ("temp", "temperature"),
+    ("topp", "top_p"),
+    ("topk", "top_k"),
+    ("max_output_tokens", "max_tokens"),
+    ("maxtokens", "max_tokens"),
+    ("stop_sequences", "stop"),
+    ("n", "candidates"),
+    ("candidate_count", "candidates"),
+    ("thinking", "thinking_budget"),
+    ("budget", "thinking_budget"),
+    ("show_thoughts", "thoughts"),
+];
+
+// Values set with /gen for this session, by setting name; they win over the configuration
+static OVERRIDES: Lazy<Mutex<HashMap<&'static str, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The generationConfig for chat requests: the values set with /gen, or else those in
+/// GEMINI_TEMPERATURE, GEMINI_TOP_P, GEMINI_TOP_K, GEMINI_MAX_OUTPUT_TOKENS,
+/// GEMINI_STOP_SEQUENCES, GEMINI_CANDIDATE_COUNT, GEMINI_THINKING_BUDGET and
+/// GEMINI_SHOW_THOUGHTS. None when nothing is set, so the model's defaults apply. Invalid
+/// values are left out (/gen shows why).
+pub fn config() -> Option<Value> {
+    let mut fields = Map::new();
+    for setting in SETTINGS {
+        let Some(value) = current(setting).and_then(|(value, _)| parse(setting, &value).ok()) else { continue };
+        match setting.field.split_once('.') {
+            Some((object, field)) => {
+                fields.entry(object).or_insert_with(|| json!({}))[field] = value;
+            }
+            None => {
+                fields.insert(setting.field.to_string(), value);
+            }
+        }
+    }
+    (!fields.is_empty()).then_some(Value::Object(fields))
+}
+
+/// Whether the model is asked to include summaries of its thinking in replies.
+pub fn shows_thoughts() -> bool {
+    config().is_some_and(|config| config["thinkingConfig"]["includeThoughts"] == json!(true))
+}
+
+/// Sets a value for the rest of the session. `name` may be an alias such as `temp`.
+/// Returns the setting's full name.
+pub fn set(name: &str, value: &str) -> Result<&'static str, String> {
+    let setting = find(name)?;
+    parse(setting, value)?;
+    OVERRIDES.lock().unwrap().insert(setting.name, value.trim().to_string());
+    Ok(setting.name)
+}
+
+/// Drops the value set with /gen for one setting, or for all of them, so the configured
+/// values apply again.
+pub fn reset(name: Option<&str>) -> Result<(), String> {
+    let mut overrides = OVERRIDES.lock().unwrap();
+    match name {
+        Some(name) => {
+            overrides.remove(find(name)?.name);
+        }
+        None => overrides.clear(),
+    }
+    Ok(())
+}
+
+/// The settings with their current value and where it comes from, for /gen.
+pub fn report() -> String {
+    let mut lines = vec!["Generation settings:".to_string()];
+    for setting in SETTINGS {
+        let line = match current(setting) {
+            None => format!("  {:<16} model default", setting.name),
+            Some((value, source)) => match parse(setting, &value) {
+                Ok(_) => format!("  {:<16} {} ({})", setting.name, value, source),
+                Err(e) => format!("  {:<16} {} ({}; ignored: {})", setting.name, value, source, e),
+            },
+        };
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// The value in effect for a setting and where it was set.
+fn current(setting: &Setting) -> Option<(String, &'static str)> {
+    if let Some(value) = OVERRIDES.lock().unwrap().get(setting.name) {
+        return Some((value.clone(), "set with /gen"));
+    }
+    env::var(setting.env)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .map(|v| (v, setting.env))
+}
+
+fn find(name: &str) -> Result<&'static Setting, String> {
+    let name = name.trim().to_lowercase().replace('-', "_");
+    let name = ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map(|(_, full)| full.to_string())
+        .unwrap_or(name);
+    SETTINGS.iter().find(|setting| setting.name == name).ok_or_else(|| {
+        let names: Vec<&str> = SETTINGS.iter().map(|setting| setting.name).collect();
+        format!("Unknown generation setting '{}'; use one of {}.", name, names.join(", "))
+    })
+}
+
+/// Checks a value against the range the API accepts and turns it into JSON.
+fn parse(setting: &Setting, value: &str) -> Result<Value, String> {
+    let value = value.trim();
+    match setting.name {
+        "temperature" => number(setting.name, value, 2.0),
+        "top_p" => number(setting.name, value, 1.0),
+        "top_k" | "max_tokens" => match value.parse::<u64>() {
+            Ok(n) if n >= 1 => Ok(json!(n)),
+            _ => Err(format!("{} must be a whole number of at least 1", setting.name)),
+        },
+        "candidates" => match value.parse::<u64>() {
+            Ok(n) if (1..=MAX_CANDIDATES).contains(&n) => Ok(json!(n)),
+            _ => Err(format!("candidates must be a whole number from 1 to {}", MAX_CANDIDATES)),
+        },
+        // -1 lets the model decide, 0 turns thinking off where the model allows it
+        "thinking_budget" => match value.parse::<i64>() {
+            Ok(n) if (-1..=MAX_THINKING_BUDGET).contains(&n) => Ok(json!(n)),
+            _ => Err(format!("thinking_budget must be -1 (dynamic), 0 (off) or up to {} tokens", MAX_THINKING_BUDGET)),
+        },
+        "thoughts" => match value.to_lowercase().as_str() {
+            "1" | "true" | "on" | "yes" | "show" => Ok(json!(true)),
+            "0" | "false" | "off" | "no" | "hide" => Ok(json!(false)),
+            _ => Err("thoughts must be on or off".to_string()),
+        },
+        // Separated by commas; \n stands for a line break
+        _ => {
+            let sequences: Vec<String> = value
+                .split(',')
+                .map(|s| s.trim().replace("\\n", "\n"))
+                .filter(|s| !s.is_empty())
+                .collect();
+            if sequences.is_empty() || sequences.len() > MAX_STOP_SEQUENCES {
+                return Err(format!("give 1 to {} stop sequences, separated by commas", MAX_STOP_SEQUENCES));
+            }
+            Ok(json!(sequences))
+        }
+    }
+}
+
+fn number(name: &str, value: &str, max: f64) -> Result<Value, String> {
+    match value.parse::<f64>() {
+        Ok(n) if (0.0..=max).contains(&n) => Ok(json!(n)),
+        _ => Err(format!("{} must be a number from 0 to {}", name, max)),
+    }
+}
@@ -0,0 +1,263 @@
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::blocking::RequestBuilder;
+use serde_json::{json, Value};
+use std::env;
+use std::process::Command;
+
+use crate::diff_render::print_patch;
+use crate::http;
+use crate::ui;
+
+const API_ROOT: &str = "https://api.github.com";
+
+// Matches both https://github.com/owner/repo(.git) and git@github.com:owner/repo(.git)
+static REMOTE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"github\.com[:/]([^/\s]+)/([^/\s]+?)(?:\.git)?/?$").unwrap());
+
+/// Runs a GitHub operation: list_issues, view_issue, create_issue, view_pr_diff or comment_pr.
+/// The repository defaults to the `origin` remote of the current directory.
+pub fn github_query(operation: &str, args: &Value) -> Result<String, String> {
+    let get = |key: &str| args.get(key).and_then(|v| v.as_str());
+    let repo = match get("repo") {
+        Some(r) => r.trim().to_string(),
+        None => detect_repo()?,
+    };
+    let number = || {
+        args.get("number")
+            .and_then(|n| n.as_u64().or_else(|| n.as_str().and_then(|s| s.trim_start_matches('#').parse().ok())))
+            .ok_or_else(|| format!("Missing 'number' parameter for {}", operation))
+    };
+
+    ui::status(&format!(
+        "{} {} {}",
+        "Gemini is querying GitHub:".color(Color::Cyan).bold(),
+        operation,
+        repo
+    ));
+
+    match operation {
+        "list_issues" => {
+            let state = get("state").unwrap_or("open");
+            let mut url = format!("{}/repos/{}/issues?state={}&per_page=50", API_ROOT, repo, state);
+            if let Some(labels) = get("labels") {
+                url.push_str(&format!("&labels={}", urlencoding::encode(labels)));
+            }
+            let issues = send(request(http::client().get(&url)))?;
+            Ok(format_issue_list(&issues))
+        }
+        "view_issue" => {
+            let n = number()?;
+            let client = http::client();
+            let issue = send(request(client.get(format!("{}/repos/{}/issues/{}", API_ROOT, repo, n))))?;
+            let comments = send(request(
+                client.get(format!("{}/repos/{}/issues/{}/comments?per_page=50", API_ROOT, repo, n)),
+            ))?;
+            Ok(format_issue(&issue, &comments))
+        }
+        "create_issue" => {
+            let title = get("title").ok_or("Missing 'title' parameter for create_issue")?;
+            let mut payload = json!({"title": title, "body": get("body").unwrap_or("")});
+            if let Some(labels) = get("labels") {
+                payload["labels"] = json!(split_labels(labels));
+            }
+            require_token()?;
+            let issue = send(request(
+                http::client()
+                    .post(format!("{}/repos/{}/issues", API_ROOT, repo))
+                    .json(&payload),
+            ))?;
+            Ok(format!(
+                "Created issue #{}: {}",
+                issue["number"],
+                issue["html_url"].as_str().unwrap_or("")
+            ))
+        }
+        "view_pr_diff" => {
+            let n = number()?;
+            let diff = fetch_pr_diff(&repo, n)?;
+            print_patch(&diff);
+            Ok(format!("Diff of pull request #{}:\n{}", n, diff))
+        }
+        "comment_pr" => {
+            let n = number()?;
+            let body = get("body").ok_or("Missing 'body' parameter for comment_pr")?;
+            // Pull request conversation comments live on the issues endpoint
+            require_token()?;
+            let comment = send(request(
+                http::client()
+                    .post(format!("{}/repos/{}/issues/{}/comments", API_ROOT, repo, n))
+                    .json(&json!({"body": body})),
+            ))?;
+            Ok(format!(
+                "Posted comment on #{}: {}",
+                n,
+                comment["html_url"].as_str().unwrap_or("")
+            ))
+        }
+        _ => Err(format!("Unknown GitHub operation: {}", operation)),
+    }
+}
+
+/// Fetches the unified diff of a pull request. The repository defaults to the `origin` remote.
+pub fn pr_diff(repo: Option<&str>, number: u64) -> Result<String, String> {
+    let repo = match repo {
+        Some(r) => r.to_string(),
+        None => detect_repo()?,
+    };
+    fetch_pr_diff(&repo, number)
+}
+
+fn fetch_pr_diff(repo: &str, number: u64) -> Result<String, String> {
+    let response = request(http::client().get(format!("{}/repos/{}/pulls/{}", API_ROOT, repo, number)))
+        .header("Accept", "application/vnd.github.v3.diff")
+        .send()
+        .map_err(|e| format!("GitHub request failed: {}", e))?;
+    let status = response.status();
+    let diff = response
+        .text()
+        .map_err(|e| format!("Failed to read GitHub response: {}", e))?;
+    if !status.is_success() {
+        return Err(format!("GitHub API error ({}): {}", status, api_message(&diff)));
+    }
+    Ok(diff)
+}
+
+/// Login of the account GITHUB_TOKEN belongs to, used by `auth test`.
+pub fn authenticated_user() -> Result<String, String> {
+    require_token()?;
+    let user = send(request(http::client().get(format!("{}/user", API_ROOT))))?;
+    Ok(user["login"].as_str().unwrap_or("unknown").to_string())
+}
+
+/// Uploads a file as a secret gist and returns its URL. The token needs the gist scope.
+pub fn create_gist(description: &str, filename: &str, content: &str) -> Result<String, String> {
+    require_token()?;
+    let gist = send(request(http::client().post(format!("{}/gists", API_ROOT)).json(&json!({
+        "description": description,
+        "public": false,
+        "files": {filename: {"content": content}},
+    }))))?;
+    Ok(gist["html_url"].as_str().unwrap_or("").to_string())
+}
+
+/// Finds owner/repo from the `origin` remote of the working directory.
+fn detect_repo() -> Result<String, String> {
+    let output = Command::new("git")
+        .args(["remote", "get-url", "origin"])
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    let url = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    REMOTE_RE
+        .captures(&url)
+        .map(|caps| format!("{}/{}", &caps[1], &caps[2]))
+        .ok_or_else(|| "No 'repo' given and the origin remote is not a GitHub repository".to_string())
+}
+
+/// Adds the User-Agent GitHub requires, plus authorization when GITHUB_TOKEN is configured.
+fn request(builder: RequestBuilder) -> RequestBuilder {
+    let builder = builder.header("User-Agent", "gemini-cli-rs");
+    match env::var("GITHUB_TOKEN") {
+        Ok(token) if !token.is_empty() => builder.bearer_auth(token),
+        _ => builder,
+    }
+}
+
+/// Write operations need a token; reads of public repositories work without one.
+fn require_token() -> Result<(), String> {
+    match env::var("GITHUB_TOKEN") {
+        Ok(token) if !token.is_empty() => Ok(()),
+        _ => Err("GITHUB_TOKEN not found in ~/.gemini.conf (required to create issues or comment)".to_string()),
+    }
+}
+
+fn send(builder: RequestBuilder) -> Result<Value, String> {
+    let response = builder
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .map_err(|e| format!("GitHub request failed: {}", e))?;
+    let status = response.status();
+    let text = response
+        .text()
+        .map_err(|e| format!("Failed to read GitHub response: {}", e))?;
+    if !status.is_success() {
+        return Err(format!("GitHub API error ({}): {}", status, api_message(&text)));
+    }
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse GitHub response: {}", e))
+}
+
+fn api_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v.get("message").and_then(|m| m.as_str()).map(String::from))
+        .unwrap_or_else(|| body.trim().to_string())
+}
+
+fn split_labels(labels: &str) -> Vec<String> {
+    labels
+        .split(',')
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+fn label_names(issue: &Value) -> String {
+    issue["labels"]
+        .as_array()
+        .map(|labels| {
+            labels
+                .iter()
+                .filter_map(|l| l["name"].as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default()
+}
+
+fn format_issue_list(issues: &Value) -> String {
+    let issues = issues.as_array().cloned().unwrap_or_default();
+    if issues.is_empty() {
+        return "No issues found.".to_string();
+    }
+    issues
+        .iter()
+        .map(|issue| {
+            // The issues endpoint also returns pull requests
+            let kind = if issue.get("pull_request").is_some() { "PR" } else { "issue" };
+            let labels = label_names(issue);
+            format!(
+                "#{} [{} {}] {}{} (by {})",
+                issue["number"],
+                issue["state"].as_str().unwrap_or(""),
+                kind,
+                issue["title"].as_str().unwrap_or(""),
+                if labels.is_empty() { String::new() } else { format!(" [labels: {}]", labels) },
+                issue["user"]["login"].as_str().unwrap_or("unknown")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_issue(issue: &Value, comments: &Value) -> String {
+    let mut output = format!(
+        "#{} {} [{}]\nAuthor: {}\nLabels: {}\nURL: {}\n\n{}",
+        issue["number"],
+        issue["title"].as_str().unwrap_or(""),
+        issue["state"].as_str().unwrap_or(""),
+        issue["user"]["login"].as_str().unwrap_or("unknown"),
+        label_names(issue),
+        issue["html_url"].as_str().unwrap_or(""),
+        issue["body"].as_str().unwrap_or("(no description)")
+    );
+    for comment in comments.as_array().into_iter().flatten() {
+        output.push_str(&format!(
+            "\n\n--- Comment by {} ({}) ---\n{}",
+            comment["user"]["login"].as_str().unwrap_or("unknown"),
+            comment["created_at"].as_str().unwrap_or(""),
+            comment["body"].as_str().unwrap_or("")
+        ));
+    }
+    output
+}
@@ -0,0 +1,93 @@
+use rustyline::history::{DefaultHistory, History};
+use rustyline::{Config, Editor, Helper};
+use std::env;
+use std::path::PathBuf;
+
+// Prompts kept in the history file unless GEMINI_HISTORY_SIZE says otherwise
+const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+/// File the prompt history is kept in between sessions.
+pub fn history_path() -> PathBuf {
+    dirs::home_dir()
+        .expect("Could not determine home directory")
+        .join(".gemini_cli_history")
+}
+
+/// Whether prompts are saved to the history file; GEMINI_HISTORY=0 keeps them in memory only.
+pub fn enabled() -> bool {
+    !env::var("GEMINI_HISTORY")
+        .map(|v| v == "0" || v == "false")
+        .unwrap_or(false)
+}
+
+/// Line editor settings: a bounded history that skips repeated entries.
+pub fn config() -> Config {
+    let size = env::var("GEMINI_HISTORY_SIZE")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|size| *size > 0)
+        .unwrap_or(DEFAULT_HISTORY_SIZE);
+    Config::builder()
+        .max_history_size(size)
+        .and_then(|builder| builder.history_ignore_dups(true))
+        .map(|builder| builder.build())
+        .unwrap_or_default()
+}
+
+/// Loads earlier prompts into the editor. Older copies of a prompt are dropped so each one
+/// appears once, at its most recent position, and the file is rewritten that way.
+pub fn load<H: Helper>(editor: &mut Editor<H, DefaultHistory>) {
+    if !enabled() || editor.load_history(&history_path()).is_err() {
+        return;
+    }
+    let entries: Vec<String> = editor.history().iter().cloned().collect();
+    let mut unique: Vec<String> = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.iter().enumerate() {
+        if !entries[index + 1..].contains(entry) {
+            unique.push(entry.clone());
+        }
+    }
+    if unique.len() == entries.len() {
+        return;
+    }
+    let history = editor.history_mut();
+    history.clear().ok();
+    for entry in unique {
+        history.add_owned(entry).ok();
+    }
+    editor.save_history(&history_path()).ok();
+}
+
+/// Adds a prompt to the history and appends it to the file right away, so it survives a
+/// crash or a forced exit. Prompts starting with a space are not recorded.
+pub fn add<H: Helper>(editor: &mut Editor<H, DefaultHistory>, line: &str) {
+    let entry = line.trim();
+    if entry.is_empty() || line.starts_with(' ') {
+        return;
+    }
+    editor.add_history_entry(entry).ok();
+    if enabled() {
+        editor.append_history(&history_path()).ok();
+    }
+}
+
+/// Earlier prompts for an input box outside the line editor (the TUI), oldest first.
+pub fn load_entries() -> DefaultHistory {
+    let mut history = DefaultHistory::with_config(config());
+    if enabled() {
+        history.load(&history_path()).ok();
+    }
+    history
+}
+
+/// Like `add`, for a history from `load_entries`.
+pub fn record(history: &mut DefaultHistory, line: &str) {
+    let entry = line.trim();
+    if entry.is_empty() || line.starts_with(' ') {
+        return;
+    }
+    history.add(entry).ok();
+    if enabled() {
+        history.append(&history_path()).ok();
+    }
+}
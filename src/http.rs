@@ -0,0 +1,154 @@
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use reqwest::blocking::{Client, ClientBuilder};
+use reqwest::{Certificate, NoProxy, Proxy};
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+use crate::politeness;
+use crate::ui;
+
+// Connections stay open this long between requests, so the next one to the same host skips
+// the TCP and TLS handshakes
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+// Seconds to wait for a connection when GEMINI_CONNECT_TIMEOUT isn't set
+const DEFAULT_CONNECT_TIMEOUT: u64 = 30;
+
+static CLIENT: Lazy<Client> = Lazy::new(|| builder().build().unwrap_or_else(|_| Client::new()));
+static ASYNC_CLIENT: Lazy<reqwest::Client> =
+    Lazy::new(|| async_builder().build().unwrap_or_else(|_| reqwest::Client::new()));
+static PAGE_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    async_builder()
+        .user_agent(politeness::user_agent())
+        .build()
+        .unwrap_or_else(|_| async_client())
+});
+
+/// Shared client for API traffic, with the proxy and certificate settings applied. Requests
+/// that need a shorter or longer limit than its 30 seconds set their own with `timeout`.
+pub fn client() -> Client {
+    CLIENT.clone()
+}
+
+/// Like `client`, for requests sent from the async runtime (model requests, Alpha Vantage).
+/// It sets no overall timeout, since streamed replies may take minutes.
+pub fn async_client() -> reqwest::Client {
+    ASYNC_CLIENT.clone()
+}
+
+/// Shared async client for search engines and web pages, with the CLI's user agent.
+pub fn page_client() -> reqwest::Client {
+    PAGE_CLIENT.clone()
+}
+
+/// The settings every client shares. Clones of a client use the same connection pool.
+///
+/// HTTPS_PROXY, HTTP_PROXY and NO_PROXY are honored as usual. On top of that:
+/// - GEMINI_PROXY sends all traffic through the given proxy (NO_PROXY still applies)
+/// - GEMINI_CA_BUNDLE adds the certificates of a PEM file to the trusted roots
+/// - GEMINI_INSECURE_TLS=1 accepts invalid and self-signed certificates
+/// - GEMINI_CONNECT_TIMEOUT sets the seconds to wait for a connection (default 30)
+fn builder() -> ClientBuilder {
+    configure(ClientBuilder::new())
+}
+
+fn async_builder() -> reqwest::ClientBuilder {
+    configure(reqwest::ClientBuilder::new())
+}
+
+/// The settings both kinds of client builder share.
+trait Configure: Sized {
+    fn proxy(self, proxy: Proxy) -> Self;
+    fn add_root_certificate(self, certificate: Certificate) -> Self;
+    fn danger_accept_invalid_certs(self, accept: bool) -> Self;
+    fn connect_timeout(self, timeout: Duration) -> Self;
+    fn pool_idle_timeout(self, timeout: Duration) -> Self;
+    fn tcp_keepalive(self, interval: Duration) -> Self;
+}
+
+impl Configure for ClientBuilder {
+    fn proxy(self, proxy: Proxy) -> Self {
+        ClientBuilder::proxy(self, proxy)
+    }
+    fn add_root_certificate(self, certificate: Certificate) -> Self {
+        ClientBuilder::add_root_certificate(self, certificate)
+    }
+    fn danger_accept_invalid_certs(self, accept: bool) -> Self {
+        ClientBuilder::danger_accept_invalid_certs(self, accept)
+    }
+    fn connect_timeout(self, timeout: Duration) -> Self {
+        ClientBuilder::connect_timeout(self, timeout)
+    }
+    fn pool_idle_timeout(self, timeout: Duration) -> Self {
+        ClientBuilder::pool_idle_timeout(self, timeout)
+    }
+    fn tcp_keepalive(self, interval: Duration) -> Self {
+        ClientBuilder::tcp_keepalive(self, interval)
+    }
+}
+
+impl Configure for reqwest::ClientBuilder {
+    fn proxy(self, proxy: Proxy) -> Self {
+        reqwest::ClientBuilder::proxy(self, proxy)
+    }
+    fn add_root_certificate(self, certificate: Certificate) -> Self {
+        reqwest::ClientBuilder::add_root_certificate(self, certificate)
+    }
+    fn danger_accept_invalid_certs(self, accept: bool) -> Self {
+        reqwest::ClientBuilder::danger_accept_invalid_certs(self, accept)
+    }
+    fn connect_timeout(self, timeout: Duration) -> Self {
+        reqwest::ClientBuilder::connect_timeout(self, timeout)
+    }
+    fn pool_idle_timeout(self, timeout: Duration) -> Self {
+        reqwest::ClientBuilder::pool_idle_timeout(self, timeout)
+    }
+    fn tcp_keepalive(self, interval: Duration) -> Self {
+        reqwest::ClientBuilder::tcp_keepalive(self, interval)
+    }
+}
+
+fn configure<B: Configure>(mut builder: B) -> B {
+    if let Some(proxy) = setting("GEMINI_PROXY") {
+        match Proxy::all(&proxy) {
+            Ok(proxy) => builder = builder.proxy(proxy.no_proxy(NoProxy::from_env())),
+            Err(e) => warn(&format!("Ignoring GEMINI_PROXY '{}': {}", proxy, e)),
+        }
+    }
+
+    if let Some(path) = setting("GEMINI_CA_BUNDLE") {
+        let certificates = fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|pem| Certificate::from_pem_bundle(&pem).map_err(|e| e.to_string()));
+        match certificates {
+            Ok(certificates) => {
+                for certificate in certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+            }
+            Err(e) => warn(&format!("Ignoring GEMINI_CA_BUNDLE '{}': {}", path, e)),
+        }
+    }
+
+    if setting("GEMINI_INSECURE_TLS").is_some_and(|v| v == "1" || v == "true") {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    let connect_timeout = setting("GEMINI_CONNECT_TIMEOUT")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_CONNECT_TIMEOUT);
+    builder
+        .connect_timeout(Duration::from_secs(connect_timeout))
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(TCP_KEEPALIVE)
+}
+
+fn setting(key: &str) -> Option<String> {
+    env::var(key).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn warn(message: &str) {
+    ui::status(&message.color(Color::Yellow).to_string());
+}
@@ -0,0 +1,145 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::{Method, Url};
+use serde_json::{json, Map, Value};
+use std::env;
+use std::io::Read;
+use std::time::Duration;
+
+use crate::http;
+use crate::search::NETWORK_TIMEOUT;
+use crate::redact;
+
+// Most bytes of a response body returned to the model
+const DEFAULT_MAX_RESPONSE: usize = 100_000;
+// Largest request body the model may send
+const MAX_REQUEST_BODY: usize = 1_000_000;
+// Response headers whose values are credentials
+const SECRET_HEADERS: &[&str] = &["set-cookie", "authorization", "proxy-authorization", "cookie"];
+
+/// Sends an HTTP request to a REST API, webhook or local dev server and returns the status,
+/// headers and body as JSON. JSON bodies are parsed, other text is returned as a string and
+/// binary content is only described. Bodies longer than GEMINI_HTTP_MAX_RESPONSE bytes are cut,
+/// and credentials in the response are redacted. Configured credentials are taken out of the
+/// URL, headers and body before the request goes out.
+pub fn http_request(method: &str, url: &str, headers: Option<&str>, body: Option<&str>) -> Result<String, String> {
+    let method = Method::from_bytes(method.trim().to_uppercase().as_bytes())
+        .map_err(|_| format!("Error: invalid HTTP method '{}'", method))?;
+    let url = &redact::outgoing(url, "the request URL");
+    let headers = headers.map(|headers| redact::outgoing(headers, "the request headers"));
+    let headers = headers.as_deref();
+    let body = body.map(|body| redact::outgoing(body, "the request body"));
+    let body = body.as_deref();
+    let parsed = Url::parse(url).map_err(|e| format!("Error: invalid URL '{}': {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("Error: only http and https URLs are supported, not '{}'", parsed.scheme()));
+    }
+    if body.is_some_and(|body| body.len() > MAX_REQUEST_BODY) {
+        return Err(format!("Error: the request body is larger than {} bytes", MAX_REQUEST_BODY));
+    }
+
+    let mut header_map = HeaderMap::new();
+    for line in headers.unwrap_or("").lines().filter(|line| !line.trim().is_empty()) {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| format!("Error: header '{}' should look like 'Name: value'", line.trim()))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| format!("Error: invalid header name '{}'", name.trim()))?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|_| format!("Error: invalid value for header '{}'", name))?;
+        header_map.append(name, value);
+    }
+    if let Some(body) = body {
+        if !header_map.contains_key(CONTENT_TYPE) && serde_json::from_str::<Value>(body).is_ok() {
+            header_map.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        }
+    }
+
+    let mut request = http::client()
+        .request(method, parsed)
+        .headers(header_map)
+        .timeout(Duration::from_secs(NETWORK_TIMEOUT));
+    if let Some(body) = body {
+        request = request.body(body.to_string());
+    }
+    let response = request.send().map_err(|e| {
+        if e.is_timeout() {
+            format!("Error: the request to {} timed out after {} seconds", url, NETWORK_TIMEOUT)
+        } else {
+            format!("Error: request to {} failed: {}", url, e.without_url())
+        }
+    })?;
+
+    let status = response.status();
+    let mut response_headers = Map::new();
+    for (name, value) in response.headers() {
+        let value = if SECRET_HEADERS.contains(&name.as_str()) {
+            "[REDACTED]".to_string()
+        } else {
+            redact::redact_secrets(&String::from_utf8_lossy(value.as_bytes()))
+        };
+        // Repeated headers are joined as HTTP allows
+        match response_headers.get_mut(name.as_str()) {
+            Some(Value::String(existing)) => *existing = format!("{}, {}", existing, value),
+            _ => {
+                response_headers.insert(name.as_str().to_string(), Value::String(value));
+            }
+        }
+    }
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+
+    let limit = max_response();
+    let mut bytes = Vec::new();
+    response
+        .take(limit as u64 + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Error reading the response from {}: {}", url, e))?;
+    let truncated = bytes.len() > limit;
+    bytes.truncate(limit);
+
+    let mut result = json!({
+        "status": status.as_u16(),
+        "status_text": status.canonical_reason().unwrap_or(""),
+        "headers": response_headers,
+        "body": response_body(&bytes, &content_type, truncated),
+    });
+    if truncated {
+        result["note"] = json!(format!(
+            "The body was cut after {} bytes; raise GEMINI_HTTP_MAX_RESPONSE or request less (e.g. with paging parameters).",
+            limit
+        ));
+    }
+    serde_json::to_string_pretty(&result).map_err(|e| e.to_string())
+}
+
+fn max_response() -> usize {
+    env::var("GEMINI_HTTP_MAX_RESPONSE")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|&limit| limit > 0)
+        .unwrap_or(DEFAULT_MAX_RESPONSE)
+}
+
+/// The body as parsed JSON when it is complete JSON, as text when it is text, and otherwise
+/// as a description of what was received.
+fn response_body(bytes: &[u8], content_type: &str, truncated: bool) -> Value {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        // A cut can split the last character
+        Err(e) if truncated && e.error_len().is_none() => std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or(""),
+        Err(_) => {
+            let kind = if content_type.is_empty() { "binary data" } else { content_type };
+            return json!(format!("[{} bytes of {}]", bytes.len(), kind));
+        }
+    };
+    let text = redact::redact_secrets(text);
+    if !truncated {
+        if let Ok(json) = serde_json::from_str::<Value>(&text) {
+            return json;
+        }
+    }
+    Value::String(text)
+}
@@ -0,0 +1,126 @@
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::PathBuf;
+
+// Catalogs shipped with the CLI; translations are contributed as new files in locales/
+const BUILT_IN: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.txt")),
+    ("de", include_str!("../locales/de.txt")),
+    ("fr", include_str!("../locales/fr.txt")),
+];
+
+/// Messages in the interface language, with English filling the gaps.
+struct Catalog {
+    code: String,
+    messages: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+static CATALOG: OnceCell<Catalog> = OnceCell::new();
+
+/// Picks the interface language: GEMINI_UI_LANG, then the language given with --lang or
+/// GEMINI_LANG, then the system locale (LC_ALL, LC_MESSAGES, LANG). Only the first call
+/// has an effect.
+pub fn init(response_language: Option<&str>) {
+    let code = env::var("GEMINI_UI_LANG")
+        .ok()
+        .or_else(|| response_language.map(String::from))
+        .or_else(system_locale)
+        .map(|code| short_code(&code))
+        .unwrap_or_else(|| "en".to_string());
+    let _ = CATALOG.set(load(&code));
+}
+
+/// Code of the interface language, e.g. "de".
+pub fn language() -> &'static str {
+    &catalog().code
+}
+
+/// The message for `key` in the interface language.
+pub fn t(key: &str) -> String {
+    let catalog = catalog();
+    catalog
+        .messages
+        .get(key)
+        .or_else(|| catalog.fallback.get(key))
+        .cloned()
+        .unwrap_or_else(|| key.to_string())
+}
+
+/// The message for `key` with its {placeholders} filled in.
+pub fn tf(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut message = t(key);
+    for (name, value) in args {
+        message = message.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    message
+}
+
+/// Whether an answer to a y/n prompt means yes in the interface language.
+pub fn is_yes(answer: &str) -> bool {
+    let answer = answer.trim().to_lowercase();
+    t("policy.yes_answers").split(',').any(|yes| yes.trim() == answer)
+}
+
+/// Whether an answer to a y/n/always prompt means always.
+pub fn is_always(answer: &str) -> bool {
+    let answer = answer.trim().to_lowercase();
+    t("policy.always_answers").split(',').any(|always| always.trim() == answer)
+}
+
+/// Folder for community translations: `<code>.txt` files there add languages or override
+/// messages of the built-in ones.
+pub fn locales_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("gemini-cli")
+        .join("locales")
+}
+
+fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(|| load("en"))
+}
+
+fn load(code: &str) -> Catalog {
+    let built_in = |code: &str| {
+        BUILT_IN
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, text)| parse(text))
+            .unwrap_or_default()
+    };
+    let mut messages = built_in(code);
+    if let Ok(text) = fs::read_to_string(locales_dir().join(format!("{}.txt", code))) {
+        messages.extend(parse(&text));
+    }
+    Catalog {
+        code: code.to_string(),
+        messages,
+        fallback: built_in("en"),
+    }
+}
+
+/// Reads `key = text` lines; # starts a comment and \n a new line.
+fn parse(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().replace("\\n", "\n")))
+        .collect()
+}
+
+fn system_locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty() && value != "C" && value != "POSIX" && !value.starts_with("C."))
+}
+
+/// "de_DE.UTF-8" and "de-DE" become "de".
+fn short_code(code: &str) -> String {
+    code.split(['-', '_', '.']).next().unwrap_or("en").to_lowercase()
+}
@@ -0,0 +1,78 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::file_edit;
+
+// Largest image sent with a prompt; a request takes at most 20 MB of inline data, and base64
+// makes images a third larger
+const MAX_IMAGE_BYTES: usize = 7_000_000;
+// Tokens an image counts for in the context (Gemini's count for up to 384 pixels per side)
+pub const IMAGE_TOKENS: usize = 258;
+
+// Images attached with /image, sent with the next message
+static PENDING: Lazy<Mutex<Vec<(String, Value)>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Media type of the image formats the models accept, by file extension.
+pub fn media_type(path: &str) -> Option<&'static str> {
+    let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "webp" => Some("image/webp"),
+        "heic" => Some("image/heic"),
+        "heif" => Some("image/heif"),
+        _ => None,
+    }
+}
+
+/// Reads an image from the sandbox into an inline_data part.
+pub fn inline_part(path: &str) -> Result<Value, String> {
+    let mime_type = media_type(path).ok_or_else(|| {
+        format!("{} is not a supported image (PNG, JPEG, WebP, HEIC or HEIF)", path)
+    })?;
+    let resolved = file_edit::sandbox_path(path)?;
+    let bytes = fs::read(&resolved).map_err(|e| format!("Cannot attach {}: {}", path, e))?;
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return Err(format!(
+            "{} is {} KB, more than the {} KB limit for images. Scale it down or crop it first.",
+            path,
+            bytes.len() / 1000,
+            MAX_IMAGE_BYTES / 1000
+        ));
+    }
+    Ok(json!({"inline_data": {"mime_type": mime_type, "data": STANDARD.encode(&bytes)}}))
+}
+
+/// Attaches an image to the next message. Returns a description of it.
+pub fn attach(path: &str) -> Result<String, String> {
+    let part = inline_part(path)?;
+    let size = part["inline_data"]["data"].as_str().map(|data| data.len() * 3 / 4).unwrap_or(0);
+    PENDING.lock().unwrap().push((path.to_string(), part));
+    Ok(format!("{} ({} KB)", path, size.div_ceil(1000)))
+}
+
+/// Names of the images waiting for the next message.
+pub fn pending() -> Vec<String> {
+    PENDING.lock().unwrap().iter().map(|(path, _)| path.clone()).collect()
+}
+
+pub fn clear() {
+    PENDING.lock().unwrap().clear();
+}
+
+/// The attached images, which are then no longer pending.
+pub fn take() -> Vec<Value> {
+    PENDING.lock().unwrap().drain(..).map(|(_, part)| part).collect()
+}
+
+/// The image in a part, as (media type, base64 data), in either of the API's spellings.
+pub fn inline_data(part: &Value) -> Option<(&str, &str)> {
+    let data = part.get("inline_data").or_else(|| part.get("inlineData"))?;
+    let mime_type = data.get("mime_type").or_else(|| data.get("mimeType"))?.as_str()?;
+    Some((mime_type, data["data"].as_str()?))
+}
@@ -0,0 +1,540 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use colored::{Color, Colorize};
+use ignore::WalkBuilder;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::http;
+use crate::keys;
+use crate::overview;
+use crate::providers;
+use crate::search;
+use crate::ui;
+use crate::SANDBOX_ROOT;
+
+// Lines per chunk; a chunk ends early at a blank line in its second half
+const CHUNK_LINES: usize = 40;
+// Chunks added to a message, unless GEMINI_INDEX_CHUNKS says otherwise
+const DEFAULT_CHUNKS: usize = 4;
+// Shorter messages ("go on", "yes") are answers rather than questions about the code
+const MIN_MESSAGE_WORDS: usize = 3;
+// Files indexed at most, unless GEMINI_INDEX_MAX_FILES says otherwise
+const DEFAULT_MAX_FILES: usize = 2_000;
+// Larger files are usually generated or data, and are left out
+const MAX_FILE_BYTES: u64 = 200_000;
+// Similarity a chunk needs to be added, for each way of ranking
+const MIN_TFIDF_SCORE: f32 = 0.12;
+const MIN_EMBEDDING_SCORE: f32 = 0.55;
+const EMBEDDING_MODEL: &str = "gemini-embedding-001";
+// Size of the stored embeddings; the model allows 768 to 3072
+const EMBEDDING_DIMENSIONS: usize = 768;
+// Texts embedded in one request, the API's limit
+const EMBEDDING_BATCH: usize = 100;
+
+// Extensions of the files that are indexed: source code, configuration and documentation
+const EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "jsx", "ts", "tsx", "mjs", "cjs", "go", "java", "kt", "kts", "scala", "c", "h", "cc", "cpp",
+    "hpp", "cs", "rb", "php", "swift", "m", "lua", "sh", "bash", "zsh", "ps1", "sql", "ex", "exs", "erl", "hs", "ml",
+    "clj", "dart", "r", "jl", "zig", "nim", "vue", "svelte", "html", "css", "scss", "toml", "yaml", "yml", "json",
+    "xml", "gradle", "proto", "graphql", "md", "rst", "txt",
+];
+
+static INDEX: Lazy<Mutex<Index>> = Lazy::new(|| Mutex::new(Index::default()));
+
+#[derive(Default)]
+struct Index {
+    files: BTreeMap<String, IndexedFile>,
+    /// Chunks each term occurs in
+    doc_freq: HashMap<String, usize>,
+    /// Files left out because of GEMINI_INDEX_MAX_FILES
+    skipped: usize,
+    /// Embeddings by text hash, with the file they are stored in
+    embeddings: Option<(PathBuf, HashMap<u64, Vec<f32>>)>,
+}
+
+struct IndexedFile {
+    modified: Option<SystemTime>,
+    len: u64,
+    chunks: Vec<Chunk>,
+}
+
+struct Chunk {
+    start: usize,
+    end: usize,
+    text: String,
+    /// Term counts, for TF-IDF
+    terms: HashMap<String, usize>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Off,
+    TfIdf,
+    Embeddings,
+}
+
+/// GEMINI_INDEX: `tfidf` (the default) ranks chunks locally, `embeddings` with the Gemini
+/// embedding API, and `0` or `off` turns retrieval off. Outside code projects it is off.
+fn mode() -> Mode {
+    let mode = match env::var("GEMINI_INDEX").map(|v| v.trim().to_lowercase()).as_deref() {
+        Ok("0") | Ok("off") | Ok("false") => Mode::Off,
+        Ok("embeddings") | Ok("embedding") => Mode::Embeddings,
+        _ => Mode::TfIdf,
+    };
+    if mode != Mode::Off && !overview::is_code_project(Path::new(&*SANDBOX_ROOT)) {
+        return Mode::Off;
+    }
+    mode
+}
+
+/// The chunks of the sandbox's code most related to a message, as a context part to send
+/// with it, or None when retrieval is off or nothing is related. `attached` are the parts
+/// already going with the message; files included there are not added again.
+///
+/// The index is built on first use and brought up to date with the files' modification
+/// times before each lookup, so edits made during the session are found.
+pub fn context_for(message: &str, attached: &[Value]) -> Option<Value> {
+    let mode = mode();
+    if mode == Mode::Off || message.split_whitespace().count() < MIN_MESSAGE_WORDS {
+        return None;
+    }
+    let included: Vec<&str> = attached
+        .iter()
+        .filter_map(|part| part["text"].as_str()?.lines().next()?.strip_prefix("File: "))
+        .collect();
+
+    let mut index = INDEX.lock().unwrap();
+    index.refresh();
+    let ranked = match mode {
+        Mode::Embeddings => match index.rank_by_embeddings(message) {
+            Ok(ranked) => ranked,
+            Err(e) => {
+                warn(&format!("Index: {}; ranking by keywords instead", e));
+                index.rank_by_terms(message)
+            }
+        },
+        _ => index.rank_by_terms(message),
+    };
+
+    let wanted = setting("GEMINI_INDEX_CHUNKS", DEFAULT_CHUNKS);
+    let mut sections = Vec::new();
+    for (path, chunk) in ranked {
+        if sections.len() == wanted {
+            break;
+        }
+        if included.iter().any(|label| label.split(':').next() == Some(path.as_str())) {
+            continue;
+        }
+        let fence = if chunk.text.contains("```") { "````" } else { "```" };
+        sections.push(format!(
+            "{} lines {}-{}:\n{}{}\n{}\n{}",
+            path,
+            chunk.start,
+            chunk.end,
+            fence,
+            extension(&path),
+            chunk.text.trim_end(),
+            fence
+        ));
+    }
+    if sections.is_empty() {
+        return None;
+    }
+    ui::status(
+        &format!("Index: added {} related code chunk{}", sections.len(), if sections.len() == 1 { "" } else { "s" })
+            .color(Color::BrightBlack)
+            .to_string(),
+    );
+    Some(json!({"text": format!(
+        "Project code that may be related to this message, found by the index (excerpts; read the files for more):\n\n{}",
+        sections.join("\n\n")
+    )}))
+}
+
+/// Summary for /index: what is indexed and how chunks are ranked.
+pub fn report() -> String {
+    let mode = mode();
+    if mode == Mode::Off {
+        return "The index is off (GEMINI_INDEX=0, or the sandbox is not a code project).".to_string();
+    }
+    let mut index = INDEX.lock().unwrap();
+    index.refresh();
+    let chunks: usize = index.files.values().map(|file| file.chunks.len()).sum();
+    let mut report = format!(
+        "Index: {} files in {} chunks, ranked by {}; up to {} chunks are added to each message.",
+        index.files.len(),
+        chunks,
+        if mode == Mode::Embeddings { "embeddings" } else { "TF-IDF" },
+        setting("GEMINI_INDEX_CHUNKS", DEFAULT_CHUNKS)
+    );
+    if index.skipped > 0 {
+        report.push_str(&format!(
+            "\n{} more files were left out; raise GEMINI_INDEX_MAX_FILES to include them.",
+            index.skipped
+        ));
+    }
+    report
+}
+
+/// Forgets the index (and the stored embeddings of this sandbox) so the next lookup reads
+/// every file again.
+pub fn rebuild() -> String {
+    *INDEX.lock().unwrap() = Index::default();
+    fs::remove_file(embeddings_path()).ok();
+    report()
+}
+
+impl Index {
+    /// Reads new and changed files and drops deleted ones.
+    fn refresh(&mut self) {
+        let root = Path::new(&*SANDBOX_ROOT);
+        let max_files = setting("GEMINI_INDEX_MAX_FILES", DEFAULT_MAX_FILES);
+        let mut walker = WalkBuilder::new(root);
+        walker.require_git(false).sort_by_file_name(|a, b| a.cmp(b));
+
+        let mut seen = HashSet::new();
+        let mut changed = false;
+        self.skipped = 0;
+        for entry in walker.build().flatten() {
+            if !entry.file_type().is_some_and(|t| t.is_file()) || !indexable(entry.path()) {
+                continue;
+            }
+            let metadata = match entry.metadata() {
+                Ok(metadata) if metadata.len() <= MAX_FILE_BYTES => metadata,
+                _ => continue,
+            };
+            if seen.len() == max_files {
+                self.skipped += 1;
+                continue;
+            }
+            let relative = entry
+                .path()
+                .strip_prefix(root)
+                .unwrap_or(entry.path())
+                .to_string_lossy()
+                .replace('\\', "/");
+            let modified = metadata.modified().ok();
+            seen.insert(relative.clone());
+            if self
+                .files
+                .get(&relative)
+                .is_some_and(|file| file.modified == modified && file.len == metadata.len())
+            {
+                continue;
+            }
+            // Unreadable and non-UTF-8 files are left out
+            let chunks = fs::read_to_string(entry.path()).map(|text| chunk(&text)).unwrap_or_default();
+            self.files.insert(relative, IndexedFile { modified, len: metadata.len(), chunks });
+            changed = true;
+        }
+        let before = self.files.len();
+        self.files.retain(|path, _| seen.contains(path));
+        if changed || self.files.len() != before {
+            self.doc_freq.clear();
+            for chunk in self.files.values().flat_map(|file| &file.chunks) {
+                for term in chunk.terms.keys() {
+                    *self.doc_freq.entry(term.clone()).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    fn chunks(&self) -> impl Iterator<Item = (&String, &Chunk)> {
+        self.files.iter().flat_map(|(path, file)| file.chunks.iter().map(move |chunk| (path, chunk)))
+    }
+
+    /// Chunks by the cosine similarity of their TF-IDF weights to the message's, best first.
+    fn rank_by_terms(&self, message: &str) -> Vec<(String, &Chunk)> {
+        let total = self.chunks().count() as f32;
+        let query_terms = terms(message);
+        let query = self.weights(&query_terms, total);
+        let query_norm = query.values().map(|w| w * w).sum::<f32>().sqrt();
+        if query_norm == 0.0 {
+            return Vec::new();
+        }
+        let mut scored: Vec<(f32, String, &Chunk)> = self
+            .chunks()
+            .filter_map(|(path, chunk)| {
+                if !query.keys().any(|term| chunk.terms.contains_key(*term)) {
+                    return None;
+                }
+                let weights = self.weights(&chunk.terms, total);
+                let norm = weights.values().map(|w| w * w).sum::<f32>().sqrt();
+                let dot: f32 = query.iter().filter_map(|(term, q)| Some(q * weights.get(term)?)).sum();
+                let score = dot / (query_norm * norm);
+                (score >= MIN_TFIDF_SCORE).then(|| (score, path.clone(), chunk))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().map(|(_, path, chunk)| (path, chunk)).collect()
+    }
+
+    /// TF-IDF weights of terms, with sublinear term frequency. Terms no chunk has are left out.
+    fn weights<'a>(&self, terms: &'a HashMap<String, usize>, total: f32) -> HashMap<&'a str, f32> {
+        terms
+            .iter()
+            .filter_map(|(term, &count)| {
+                let idf = (total / *self.doc_freq.get(term)? as f32).ln() + 1.0;
+                Some((term.as_str(), (1.0 + (count as f32).ln()) * idf))
+            })
+            .collect()
+    }
+
+    /// Chunks by the similarity of their embeddings to the message's, best first. Embeddings
+    /// are stored per sandbox and computed only for chunks whose text is new.
+    fn rank_by_embeddings(&mut self, message: &str) -> Result<Vec<(String, &Chunk)>, String> {
+        if providers::current().name() != "gemini" || keys::configured().is_empty() {
+            return Err("embeddings need the Gemini API and a GEMINI_API_KEY".to_string());
+        }
+        if self.embeddings.is_none() {
+            let path = embeddings_path();
+            self.embeddings = Some((path.clone(), load_embeddings(&path)));
+        }
+        let missing: Vec<(u64, String)> = {
+            let stored = &self.embeddings.as_ref().unwrap().1;
+            let mut missing = Vec::new();
+            let mut queued = HashSet::new();
+            for (path, chunk) in self.chunks() {
+                let key = hash(&chunk.text);
+                if !stored.contains_key(&key) && queued.insert(key) {
+                    missing.push((key, format!("{}\n{}", path, chunk.text)));
+                }
+            }
+            missing
+        };
+        if !missing.is_empty() {
+            ui::status(&format!("Indexing {} code chunks", missing.len()).color(Color::BrightBlack).to_string());
+            let (path, stored) = self.embeddings.as_mut().unwrap();
+            for batch in missing.chunks(EMBEDDING_BATCH) {
+                let texts: Vec<&str> = batch.iter().map(|(_, text)| text.as_str()).collect();
+                let vectors = embed(&texts, "RETRIEVAL_DOCUMENT")?;
+                stored.extend(batch.iter().map(|(key, _)| *key).zip(vectors));
+            }
+            // Vectors of chunks that no longer exist are dropped when saving
+            let current: HashSet<u64> = self.files.values().flat_map(|f| &f.chunks).map(|c| hash(&c.text)).collect();
+            stored.retain(|key, _| current.contains(key));
+            save_embeddings(path, stored);
+        }
+
+        let query = embed(&[message], "RETRIEVAL_QUERY")?.pop().unwrap_or_default();
+        let stored = &self.embeddings.as_ref().unwrap().1;
+        let mut scored: Vec<(f32, String, &Chunk)> = self
+            .chunks()
+            .filter_map(|(path, chunk)| {
+                let score = search::cosine_similarity(&query, stored.get(&hash(&chunk.text))?);
+                (score >= MIN_EMBEDDING_SCORE).then(|| (score, path.clone(), chunk))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(scored.into_iter().map(|(_, path, chunk)| (path, chunk)).collect())
+    }
+}
+
+/// Splits a file into chunks of about CHUNK_LINES lines, preferring to end at blank lines.
+fn chunk(text: &str) -> Vec<Chunk> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let mut end = (start + CHUNK_LINES).min(lines.len());
+        if end < lines.len() {
+            if let Some(blank) = (start + CHUNK_LINES / 2..end).rev().find(|&i| lines[i].trim().is_empty()) {
+                end = blank + 1;
+            }
+        }
+        let text = lines[start..end].join("\n");
+        if !text.trim().is_empty() {
+            chunks.push(Chunk { start: start + 1, end, terms: terms(&text), text });
+        }
+        start = end;
+    }
+    chunks
+}
+
+/// Lowercase words and identifiers with their parts: `parseConfigFile` and `parse_config`
+/// also count as `parse`, `config` and `file`.
+fn terms(text: &str) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    let mut add = |term: String| {
+        if term.len() > 1 && !term.chars().all(|c| c.is_ascii_digit()) {
+            *counts.entry(term).or_insert(0) += 1;
+        }
+    };
+    for word in text.split(|c: char| !c.is_alphanumeric() && c != '_') {
+        let mut parts = Vec::new();
+        for piece in word.split('_') {
+            let mut current = String::new();
+            let mut previous_lower = false;
+            for c in piece.chars() {
+                if c.is_uppercase() && previous_lower {
+                    parts.push(std::mem::take(&mut current));
+                }
+                previous_lower = c.is_lowercase() || c.is_ascii_digit();
+                current.extend(c.to_lowercase());
+            }
+            parts.push(current);
+        }
+        parts.retain(|part| !part.is_empty());
+        if parts.len() > 1 {
+            add(word.to_lowercase());
+        }
+        parts.into_iter().for_each(&mut add);
+    }
+    counts
+}
+
+fn indexable(path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if name.ends_with(".lock") || name.ends_with(".min.js") || name == "package-lock.json" {
+        return false;
+    }
+    matches!(name.as_str(), "makefile" | "dockerfile" | "readme")
+        || EXTENSIONS.contains(&extension(&name).to_lowercase().as_str())
+}
+
+fn extension(path: &str) -> &str {
+    Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("")
+}
+
+/// Embeds texts with the Gemini embedding API (GEMINI_EMBEDDING_MODEL, default
+/// gemini-embedding-001).
+fn embed(texts: &[&str], task_type: &str) -> Result<Vec<Vec<f32>>, String> {
+    let model = embedding_model();
+    let requests: Vec<Value> = texts
+        .iter()
+        .map(|text| {
+            json!({
+                "model": format!("models/{}", model),
+                "content": {"parts": [{"text": text}]},
+                "taskType": task_type,
+                "outputDimensionality": EMBEDDING_DIMENSIONS,
+            })
+        })
+        .collect();
+    let response = http::client()
+        .post(format!(
+            "https://generativelanguage.googleapis.com/v1beta/models/{}:batchEmbedContents",
+            model
+        ))
+        .query(&[("key", keys::current())])
+        .json(&json!({"requests": requests}))
+        .send()
+        .map_err(|e| format!("embedding request failed: {}", e.without_url()))?;
+    let status = response.status();
+    let body: Value = response.json().map_err(|e| format!("invalid embedding response: {}", e))?;
+    if !status.is_success() {
+        let message = body["error"]["message"].as_str().unwrap_or("no details");
+        return Err(format!("embedding request failed ({}): {}", status, message));
+    }
+    let vectors: Vec<Vec<f32>> = body["embeddings"]
+        .as_array()
+        .map(|embeddings| {
+            embeddings
+                .iter()
+                .map(|e| e["values"].as_array().map(|v| v.iter().filter_map(Value::as_f64).map(|x| x as f32).collect()))
+                .collect::<Option<_>>()
+                .unwrap_or_default()
+        })
+        .unwrap_or_default();
+    if vectors.len() != texts.len() {
+        return Err("the embedding response is incomplete".to_string());
+    }
+    Ok(vectors)
+}
+
+/// Embeddings are stored per sandbox outside it, like backups and snapshots.
+fn embeddings_path() -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    SANDBOX_ROOT.hash(&mut hasher);
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("gemini-cli")
+        .join("index")
+        .join(format!("{:016x}.json", hasher.finish()))
+}
+
+/// Stored embeddings, as base64 little-endian floats by text hash. Embeddings of another
+/// model or size are not used.
+fn load_embeddings(path: &Path) -> HashMap<u64, Vec<f32>> {
+    let stored: Value = fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default();
+    if stored["model"] != embedding_key() {
+        return HashMap::new();
+    }
+    stored["vectors"]
+        .as_object()
+        .map(|vectors| {
+            vectors
+                .iter()
+                .filter_map(|(key, data)| {
+                    let bytes = STANDARD.decode(data.as_str()?).ok()?;
+                    let vector = bytes
+                        .chunks_exact(4)
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect();
+                    Some((u64::from_str_radix(key, 16).ok()?, vector))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn save_embeddings(path: &Path, vectors: &HashMap<u64, Vec<f32>>) {
+    let encoded: serde_json::Map<String, Value> = vectors
+        .iter()
+        .map(|(key, vector)| {
+            let bytes: Vec<u8> = vector.iter().flat_map(|x| x.to_le_bytes()).collect();
+            (format!("{:016x}", key), json!(STANDARD.encode(bytes)))
+        })
+        .collect();
+    let stored = json!({"model": embedding_key(), "vectors": encoded});
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    if let Err(e) = fs::write(path, stored.to_string()) {
+        warn(&format!("Index: could not store embeddings: {}", e));
+    }
+}
+
+fn embedding_model() -> String {
+    env::var("GEMINI_EMBEDDING_MODEL")
+        .ok()
+        .map(|m| m.trim().to_string())
+        .filter(|m| !m.is_empty())
+        .unwrap_or_else(|| EMBEDDING_MODEL.to_string())
+}
+
+/// Identifies what stored embeddings were made with.
+fn embedding_key() -> String {
+    format!("{}/{}", embedding_model(), EMBEDDING_DIMENSIONS)
+}
+
+fn hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A positive number from the environment, or the default.
+fn setting(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(default)
+}
+
+fn warn(message: &str) {
+    ui::status(&message.color(Color::Yellow).to_string());
+}
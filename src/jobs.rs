@@ -0,0 +1,234 @@
+use once_cell::sync::Lazy;
+use std::io::Read;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::command;
+use crate::stats;
+
+// Output kept per job; the oldest is dropped beyond this
+const MAX_BUFFER: usize = 1_000_000;
+// Most new output one list_jobs call returns per job; the end is kept
+const MAX_NEW_OUTPUT: usize = 8_000;
+// How long a job gets to exit after it was asked to stop before it is killed
+const STOP_GRACE: Duration = Duration::from_secs(2);
+
+/// What a job printed so far, with stdout and stderr interleaved as they arrived.
+#[derive(Default)]
+struct Output {
+    bytes: Vec<u8>,
+    /// Bytes dropped from the front to stay within MAX_BUFFER
+    dropped: usize,
+}
+
+/// A command started with run_in_background.
+struct Job {
+    id: usize,
+    command: String,
+    started: Instant,
+    child: Child,
+    output: Arc<Mutex<Output>>,
+    /// Offset up to which the output has been returned, counted from the very first byte
+    shown: usize,
+    /// How it ended, once it did
+    ended: Option<String>,
+}
+
+static JOBS: Lazy<Mutex<Vec<Job>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Starts a command in the sandbox without waiting for it, for servers, watchers and other
+/// long-running processes. Its output is collected for list_jobs.
+pub fn run_in_background(command: &str) -> Result<String, String> {
+    if command.trim().is_empty() {
+        return Err("Error: No command provided".to_string());
+    }
+    stats::record_command(command);
+    let mut child = command::background_command(command)?
+        .spawn()
+        .map_err(|e| format!("Error spawning command '{}': {}", command, e))?;
+
+    let output = Arc::new(Mutex::new(Output::default()));
+    if let Some(stdout) = child.stdout.take() {
+        collect(stdout, Arc::clone(&output));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        collect(stderr, Arc::clone(&output));
+    }
+
+    let mut jobs = JOBS.lock().unwrap();
+    let id = jobs.len() + 1;
+    let pid = child.id();
+    jobs.push(Job {
+        id,
+        command: command.to_string(),
+        started: Instant::now(),
+        child,
+        output,
+        shown: 0,
+        ended: None,
+    });
+    Ok(format!(
+        "Started job {} (pid {}): {}\nUse list_jobs to see its output and kill_job to stop it.",
+        id, pid, command
+    ))
+}
+
+/// The status of every job, or of one, with the output each produced since the last call.
+/// Output of running jobs is returned up to the last complete line.
+pub fn list_jobs(id: Option<usize>) -> Result<String, String> {
+    let mut jobs = JOBS.lock().unwrap();
+    if jobs.is_empty() {
+        return Ok("No background jobs".to_string());
+    }
+    let mut sections = Vec::new();
+    for job in jobs.iter_mut().filter(|job| id.is_none_or(|id| job.id == id)) {
+        update(job);
+        let mut section = format!("Job {} ({}): {}", job.id, status(job), job.command);
+        let new_output = take_new_output(job);
+        if !new_output.trim().is_empty() {
+            section.push_str(&format!("\n{}", new_output.trim_end()));
+        }
+        sections.push(section);
+    }
+    match (sections.is_empty(), id) {
+        (true, Some(id)) => Err(format!("No job {}", id)),
+        _ => Ok(sections.join("\n\n")),
+    }
+}
+
+/// Stops a job and whatever it started: it is asked to terminate and killed if it is still
+/// running after a moment.
+pub fn kill_job(id: usize) -> Result<String, String> {
+    let mut jobs = JOBS.lock().unwrap();
+    let job = jobs.iter_mut().find(|job| job.id == id).ok_or_else(|| format!("No job {}", id))?;
+    update(job);
+    if job.ended.is_some() {
+        return Ok(format!("Job {} already {}", id, status(job)));
+    }
+    stop(job);
+    let new_output = take_new_output(job);
+    let mut result = format!("Stopped job {}: {}", id, job.command);
+    if !new_output.trim().is_empty() {
+        result.push_str(&format!("\nLast output:\n{}", new_output.trim_end()));
+    }
+    Ok(result)
+}
+
+/// Stops the jobs that are still running, when the CLI exits. Returns how many there were.
+pub fn kill_all() -> usize {
+    let mut jobs = JOBS.lock().unwrap();
+    let mut stopped = 0;
+    for job in jobs.iter_mut() {
+        update(job);
+        if job.ended.is_none() {
+            stop(job);
+            stopped += 1;
+        }
+    }
+    stopped
+}
+
+fn collect<R: Read + Send + 'static>(mut source: R, output: Arc<Mutex<Output>>) {
+    thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        while let Ok(n) = source.read(&mut buffer) {
+            if n == 0 {
+                break;
+            }
+            let mut output = output.lock().unwrap();
+            output.bytes.extend_from_slice(&buffer[..n]);
+            let excess = output.bytes.len().saturating_sub(MAX_BUFFER);
+            if excess > 0 {
+                output.bytes.drain(..excess);
+                output.dropped += excess;
+            }
+        }
+    });
+}
+
+fn update(job: &mut Job) {
+    if job.ended.is_none() {
+        if let Ok(Some(status)) = job.child.try_wait() {
+            job.ended = Some(describe_exit(status));
+        }
+    }
+}
+
+fn status(job: &Job) -> String {
+    match &job.ended {
+        Some(ended) => ended.clone(),
+        None => format!("running for {}", minutes(job.started.elapsed())),
+    }
+}
+
+fn describe_exit(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exited with code {}", code),
+        None => "was stopped by a signal".to_string(),
+    }
+}
+
+/// The output not returned yet. While the job runs, an unfinished last line is held back.
+fn take_new_output(job: &mut Job) -> String {
+    let output = job.output.lock().unwrap();
+    let mut start = job.shown.max(output.dropped);
+    let mut skipped = start - job.shown;
+    let mut new = &output.bytes[start - output.dropped..];
+    if job.ended.is_none() {
+        match new.iter().rposition(|&b| b == b'\n') {
+            Some(last) => new = &new[..=last],
+            None => return String::new(),
+        }
+    }
+    if new.len() > MAX_NEW_OUTPUT {
+        let cut = new.len() - MAX_NEW_OUTPUT;
+        let cut = new[cut..].iter().position(|&b| b == b'\n').map(|i| cut + i + 1).unwrap_or(cut);
+        skipped += cut;
+        start += cut;
+        new = &new[cut..];
+    }
+    job.shown = start + new.len();
+
+    let text = command::decode_output(new);
+    if skipped > 0 {
+        format!("[... {} earlier bytes of output skipped ...]\n{}", skipped, text)
+    } else {
+        text
+    }
+}
+
+/// Asks the job's process group to terminate, then kills it if it doesn't.
+fn stop(job: &mut Job) {
+    let pid = job.child.id().to_string();
+    if cfg!(target_os = "windows") {
+        quietly(Command::new("taskkill").args(["/T", "/F", "/PID", &pid]));
+    } else {
+        quietly(Command::new("kill").args(["-TERM", "--", &format!("-{}", pid)]));
+        let deadline = Instant::now() + STOP_GRACE;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = job.child.try_wait() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        quietly(Command::new("kill").args(["-KILL", "--", &format!("-{}", pid)]));
+    }
+    job.child.kill().ok();
+    job.child.wait().ok();
+    job.ended = Some("was stopped".to_string());
+}
+
+fn quietly(command: &mut Command) {
+    command.stdout(Stdio::null()).stderr(Stdio::null()).status().ok();
+}
+
+fn minutes(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else {
+        format!("{}m {}s", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,65 @@
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::models::{self, Task};
+use crate::ui;
+use crate::usage;
+
+// The tool calls of a --json run, in order; None when the mode is off
+static CALLS: Lazy<Mutex<Option<Vec<Value>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Turns on machine-readable output for a single prompt: everything meant for the user goes
+/// to stderr, and `finish` prints one JSON document on stdout.
+pub fn start() {
+    ui::reserve_stdout();
+    *CALLS.lock().unwrap() = Some(Vec::new());
+}
+
+pub fn enabled() -> bool {
+    CALLS.lock().unwrap().is_some()
+}
+
+/// Records a tool call with its arguments and what it returned.
+pub fn record_call(name: &str, args: &Value, result: &Result<String, String>, duration: Duration) {
+    if let Some(calls) = CALLS.lock().unwrap().as_mut() {
+        let mut call = json!({
+            "name": name,
+            "args": args,
+            "ok": matches!(result, Ok(output) if !ui::looks_like_failure(output)),
+            "duration_ms": duration.as_millis() as u64,
+        });
+        match result {
+            Ok(output) => call["result"] = json!(output),
+            Err(e) => call["error"] = json!(e),
+        }
+        calls.push(call);
+    }
+}
+
+/// Prints the outcome of the run: the final text, the tool calls, token usage and the exit
+/// code the process ends with (0, or 1 when `error` is set).
+pub fn finish(text: Option<String>, error: Option<&str>) -> i32 {
+    let exit_code = if error.is_some() { 1 } else { 0 };
+    let usage = usage::session();
+    let calls = CALLS.lock().unwrap().take().unwrap_or_default();
+    let mut output = json!({
+        "status": if error.is_some() { "error" } else { "ok" },
+        "exit_code": exit_code,
+        "model": models::model_for(Task::Main),
+        "text": text,
+        "tool_calls": calls,
+        "usage": {
+            "requests": usage.requests,
+            "input_tokens": usage.input_tokens,
+            "output_tokens": usage.output_tokens,
+            "estimated_cost_usd": usage.cost,
+        },
+    });
+    if let Some(error) = error {
+        output["error"] = json!(error);
+    }
+    println!("{}", serde_json::to_string_pretty(&output).unwrap_or_default());
+    exit_code
+}
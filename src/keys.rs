@@ -0,0 +1,63 @@
+use once_cell::sync::Lazy;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// How long a key rests after hitting its quota, unless the API says otherwise
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+struct KeyPool {
+    keys: Vec<String>,
+    current: usize,
+    /// When each key may be used again after a 429
+    cooling_until: Vec<Option<Instant>>,
+}
+
+static POOL: Lazy<Mutex<KeyPool>> = Lazy::new(|| {
+    let keys = configured();
+    Mutex::new(KeyPool {
+        cooling_until: vec![None; keys.len()],
+        keys,
+        current: 0,
+    })
+});
+
+/// All configured Gemini keys: GEMINI_API_KEY followed by the comma-separated GEMINI_API_KEYS.
+pub fn configured() -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    let primary = env::var("GEMINI_API_KEY").unwrap_or_default();
+    let extra = env::var("GEMINI_API_KEYS").unwrap_or_default();
+    for key in std::iter::once(primary.as_str()).chain(extra.split(',')) {
+        let key = key.trim();
+        if !key.is_empty() && !keys.iter().any(|k| k == key) {
+            keys.push(key.to_string());
+        }
+    }
+    keys
+}
+
+/// The key to use for the next request.
+pub fn current() -> String {
+    let pool = POOL.lock().unwrap();
+    pool.keys.get(pool.current).cloned().unwrap_or_default()
+}
+
+/// Puts the current key on cooldown after a quota error and switches to the next key that
+/// isn't cooling down. Returns the 1-based numbers of the old and new key, or None when
+/// no other key is available.
+pub fn rotate(cooldown: Option<Duration>) -> Option<(usize, usize)> {
+    let mut pool = POOL.lock().unwrap();
+    let count = pool.keys.len();
+    if count < 2 {
+        return None;
+    }
+    let now = Instant::now();
+    let previous = pool.current;
+    pool.cooling_until[previous] = Some(now + cooldown.unwrap_or(DEFAULT_COOLDOWN));
+
+    let next = (1..count)
+        .map(|offset| (previous + offset) % count)
+        .find(|&i| pool.cooling_until[i].is_none_or(|until| until <= now))?;
+    pool.current = next;
+    Some((previous + 1, next + 1))
+}
@@ -0,0 +1,100 @@
+use chrono::{DateTime, Local};
+use once_cell::sync::OnceCell;
+
+/// The user's preferred response language and the formatting conventions that go with it.
+pub struct Language {
+    pub code: String,
+    pub name: &'static str,
+    date_format: &'static str,
+    time_format: &'static str,
+    thousands_separator: char,
+}
+
+static LANGUAGE: OnceCell<Language> = OnceCell::new();
+
+// Known languages: code, English name, date format, time format, thousands separator
+const LANGUAGES: &[(&str, &str, &str, &str, char)] = &[
+    ("en", "English", "%Y-%m-%d", "%H:%M:%S", ','),
+    ("de", "German", "%d.%m.%Y", "%H:%M:%S", '.'),
+    ("fr", "French", "%d/%m/%Y", "%H:%M:%S", ' '),
+    ("es", "Spanish", "%d/%m/%Y", "%H:%M:%S", '.'),
+    ("it", "Italian", "%d/%m/%Y", "%H:%M:%S", '.'),
+    ("pt", "Portuguese", "%d/%m/%Y", "%H:%M:%S", '.'),
+    ("nl", "Dutch", "%d-%m-%Y", "%H:%M:%S", '.'),
+    ("pl", "Polish", "%d.%m.%Y", "%H:%M:%S", ' '),
+    ("ru", "Russian", "%d.%m.%Y", "%H:%M:%S", ' '),
+    ("ja", "Japanese", "%Y/%m/%d", "%H:%M:%S", ','),
+    ("zh", "Chinese", "%Y-%m-%d", "%H:%M:%S", ','),
+    ("ko", "Korean", "%Y.%m.%d", "%H:%M:%S", ','),
+];
+
+impl Language {
+    /// Looks up a language by code ("de", "de-DE", "de_DE.UTF-8" all work).
+    /// Unknown codes keep the code as the name and use English formatting.
+    pub fn from_code(code: &str) -> Language {
+        let short = code
+            .split(['-', '_', '.'])
+            .next()
+            .unwrap_or("en")
+            .to_lowercase();
+        let (name, date_format, time_format, thousands_separator) = LANGUAGES
+            .iter()
+            .find(|(c, ..)| *c == short)
+            .map(|(_, name, date, time, sep)| (*name, *date, *time, *sep))
+            .unwrap_or(("", "%Y-%m-%d", "%H:%M:%S", ','));
+        Language {
+            code: short,
+            name,
+            date_format,
+            time_format,
+            thousands_separator,
+        }
+    }
+
+    pub fn is_english(&self) -> bool {
+        self.code == "en"
+    }
+
+    /// Directive appended to the system instruction so answers come back in this language.
+    pub fn instruction(&self) -> String {
+        let name = if self.name.is_empty() {
+            self.code.as_str()
+        } else {
+            self.name
+        };
+        format!(
+            " Always respond in {} (language code '{}') unless the user explicitly asks for another language. Keep code, commands, and file contents in their original language.",
+            name, self.code
+        )
+    }
+}
+
+/// Sets the process-wide language. Only the first call has an effect.
+pub fn init(code: &str) {
+    let _ = LANGUAGE.set(Language::from_code(code));
+}
+
+pub fn current() -> &'static Language {
+    LANGUAGE.get_or_init(|| Language::from_code("en"))
+}
+
+pub fn format_date(date: &DateTime<Local>) -> String {
+    date.format(current().date_format).to_string()
+}
+
+pub fn format_time(date: &DateTime<Local>) -> String {
+    date.format(current().time_format).to_string()
+}
+
+/// Formats an integer with the language's thousands separator, e.g. 12,345 or 12.345.
+pub fn format_number(value: usize) -> String {
+    let digits = value.to_string();
+    let mut output = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            output.push(current().thousands_separator);
+        }
+        output.push(c);
+    }
+    output
+}
@@ -1,30 +1,157 @@
 use build_time::build_time_local;
-use chrono::Local;
-use clap::Parser;
+use chrono::{DateTime, Local};
+use clap::{Parser, Subcommand};
 use colored::{Color, Colorize};
-use ctrlc;
 #[allow(unused_imports)]
 use dotenv::from_path;
 use once_cell::sync::Lazy;
-use reqwest::blocking::Client;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
 use std::io::{self, Write};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::env;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use dirs;
+use std::time::{Duration, Instant};
 
 #[derive(Parser)]
 #[command(name = "gemini-cli-rs")]
 #[command(about = "A proactive assistant for coding tasks")]
 struct Args {
-    /// Single prompt to send to the LLM and exit
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Print version, build and configuration details
+    #[arg(short = 'V', long)]
+    version: bool,
+
+    /// Single prompt to send to the LLM and exit (same as `ask`)
     #[arg(short, long)]
     prompt: Option<String>,
 
     /// Enable debug output for troubleshooting
-    #[arg(long)]
+    #[arg(long, global = true)]
     debug: bool,
+
+    /// Prefix each turn with its timestamp and turn number
+    #[arg(long, global = true)]
+    timestamps: bool,
+
+    /// Preferred response language (e.g. de, fr, ja)
+    #[arg(long, global = true)]
+    lang: Option<String>,
+
+    /// Listen on this local port for an editor that reviews proposed edits
+    #[arg(long, global = true)]
+    editor_port: Option<u16>,
+
+    /// Model for the conversation, e.g. gemini-2.5-pro (or pro, flash, flash-lite)
+    #[arg(long, global = true)]
+    model: Option<String>,
+
+    /// Start with a persona preset (e.g. reviewer, sysadmin, tutor)
+    #[arg(long, global = true)]
+    persona: Option<String>,
+
+    /// Keep sending requests even when a configured budget is used up
+    #[arg(long, global = true)]
+    ignore_budget: bool,
+
+    /// Resume a saved chat session (the most recent one when no name is given)
+    #[arg(long, global = true, value_name = "NAME")]
+    resume: Option<Option<String>>,
+
+    /// Safe mode: show every tool call that can change something and ask before running it
+    #[arg(long, global = true)]
+    confirm: bool,
+
+    /// Dry run: commands, file changes and other actions are only simulated, and file edits show their diff
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Don't save the chat session on exit (overrides GEMINI_AUTOSAVE)
+    #[arg(long, global = true)]
+    no_save: bool,
+
+    /// Chat in a full-screen interface with separate panes for the conversation and tool activity
+    #[arg(long, global = true)]
+    tui: bool,
+
+    /// Write the final answer to this file (used with --prompt)
+    #[arg(short, long, requires = "prompt")]
+    output: Option<PathBuf>,
+
+    /// Print the answer, tool calls and token usage as JSON on stdout (used with --prompt)
+    #[arg(long, requires = "prompt")]
+    json: bool,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Start an interactive chat (the default)
+    Chat,
+    /// Send a single prompt and exit; input piped on stdin is attached to it
+    Ask {
+        /// The prompt (read from stdin when left out)
+        prompt: Option<String>,
+        /// Write the final answer to this file
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Print the answer, tool calls and token usage as JSON on stdout
+        #[arg(long)]
+        json: bool,
+    },
+    /// Draft a commit message for the staged changes and commit them
+    Commit {
+        /// Commit with the drafted message without asking
+        #[arg(short, long)]
+        yes: bool,
+        /// Extra context for the message, e.g. the motivation for the change
+        #[arg(long)]
+        hint: Option<String>,
+    },
+    /// Review a diff file by file and print the findings grouped by severity
+    Review {
+        /// Review the staged changes
+        #[arg(long, group = "source")]
+        staged: bool,
+        /// Review a commit range, e.g. main..HEAD
+        #[arg(long, group = "source")]
+        range: Option<String>,
+        /// Review a GitHub pull request by number
+        #[arg(long, group = "source")]
+        pr: Option<u64>,
+        /// Also write the Markdown report to this file
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Show or change settings in ~/.gemini.conf
+    Config {
+        #[command(subcommand)]
+        action: Option<config::ConfigAction>,
+    },
+    /// List, show or delete saved chat sessions
+    Sessions {
+        #[command(subcommand)]
+        action: Option<sessions::SessionsAction>,
+    },
+    /// List the tools offered to the model, or enable/disable them
+    Tools {
+        #[command(subcommand)]
+        action: Option<tools::ToolsAction>,
+    },
+    /// Check that the configured API keys work
+    Auth {
+        #[command(subcommand)]
+        action: Option<auth::AuthAction>,
+    },
+    /// Serve the sandboxed tools over the Model Context Protocol on stdio
+    #[command(name = "mcp-serve")]
+    McpServe,
+    /// Exercise every tool against a temporary sandbox and local mock services
+    Selftest,
 }
 
 // Declare and import the search module
@@ -36,13 +163,81 @@ mod command;
 mod email;
 mod alpha_vantage;
 mod file_edit;
+mod diff_render;
 mod spinner; // Spinner module
+mod ui;
+mod locale;
+mod export;
+mod tools;
+mod mcp;
+mod editor_bridge;
+mod github;
+mod tracker;
+mod config;
+mod sessions;
+mod commit;
+mod review;
+mod title;
+mod snapshot;
+mod usage;
+mod stats;
+mod models;
+mod persona;
+mod mentions;
+mod api_error;
+mod auth;
+mod keys;
+mod http;
+mod version;
+mod tool_loop;
+mod overview;
+mod context;
+mod share;
+mod plugins;
+mod selftest;
+mod i18n;
+mod stream;
+mod providers;
+mod retry;
+mod project;
+mod confirm;
+mod history;
+mod multiline;
+mod fs_tools;
+mod backups;
+mod jobs;
+mod search_providers;
+mod readability;
+mod documents;
+mod politeness;
+mod http_tool;
+mod finance;
+mod json_output;
+mod external_tools;
+mod images;
+mod index;
+mod audit;
+mod redact;
+mod runtime;
+mod generation;
+mod thoughts;
+mod safety;
+mod error;
+mod tui;
 
 use command::execute_command;
-use email::send_email;
+use email::{send_email, Email};
 use alpha_vantage::alpha_vantage_query;
-use file_edit::file_editor;
+use file_edit::{apply_changeset, file_editor, read_file};
+use fs_tools::{list_files, search_codebase};
+use github::github_query;
+use tracker::tracker_query;
 use crate::spinner::Spinner; // Import the Spinner
+use crate::ui::ToolBlock;
+use crate::tool_loop::{LoopGuard, TurnBudget};
+use crate::models::Task;
+use crate::api_error::ApiError;
+use crate::error::CliError;
 
 static SANDBOX_ROOT: Lazy<String> = Lazy::new(|| {
     let path = std::env::current_dir()
@@ -67,6 +262,11 @@ static SANDBOX_ROOT: Lazy<String> = Lazy::new(|| {
     }
 });
 
+const MODEL: &str = "gemini-2.5-flash";
+
+// A second Ctrl-C within this window quits
+const CONFIRM_EXIT_WINDOW: Duration = Duration::from_secs(3);
+
 const COMPILE_TIME: &str = build_time_local!("%Y-%m-%d %H:%M:%S");
 
 fn detect_shell_info() -> String {
@@ -179,16 +379,28 @@ fn detect_shell_info() -> String {
     }
 }
 
+/// A single message in the conversation, along with when and in which turn it happened.
+#[derive(Clone, Serialize, Deserialize)]
+struct HistoryEntry {
+    content: Value,
+    timestamp: DateTime<Local>,
+    turn: usize,
+}
+
 struct ChatManager {
-    api_key: String,
-    history: Vec<Value>, // Stores user and assistant messages
+    history: Vec<HistoryEntry>, // Stores user and assistant messages
+    turn: usize,                // Number of user turns so far
     cleaned_up: bool,
-    system_instruction: String, // Stored separately for Gemini
+    system_instruction: String, // Stored separately for Gemini; a persona may replace it
+    session_context: String,    // Language, GEMINI.md and project overview, kept with any persona
     smtp_server: String,
+    save_session: bool,         // Save the conversation to ~/.gemini_sessions on exit
+    session_name: Option<String>, // Name the session is saved under, set by /save and /load
+    measured: Option<(usize, usize)>, // (history entries, tokens) counted by the API for the last reply
 }
 
 impl ChatManager {
-    fn new(api_key: String, smtp_server: String) -> Self {
+    fn new(smtp_server: String) -> Self {
         let today = Local::now().format("%Y-%m-%d").to_string();
         let os_name = if cfg!(target_os = "windows") {
             "Windows"
@@ -206,199 +418,910 @@ impl ChatManager {
             "Today's date is {}. You are a proactive assistant running in a sandboxed {} terminal environment with a full set of command line utilities. The default shell is {}. Your role is to assist with coding tasks, file operations, online searches, email sending, and shell commands efficiently and decisively. Assume the current directory (the sandbox root) is the target for all commands. Take initiative to provide solutions, execute commands, and analyze results immediately without asking for confirmation unless the action is explicitly ambiguous (e.g., multiple repos) or potentially destructive (e.g., deleting files). Use the `execute_command` tool to interact with the system but only when needed. Deliver concise, clear responses. After running a command, always summarize its output immediately and proceed with logical next steps, without waiting for the user to prompt you further. When reading files or executing commands, summarize the results intelligently for the user without dumping raw output unless explicitly requested. Stay within the sandbox directory. Users can run shell commands directly with `!`, and you'll receive the output to assist further. Act confidently and anticipate the user's needs to streamline their workflow.",
             today, os_name, shell_info
         );
+        persona::set_variables(&[
+            ("date", &today),
+            ("os", os_name),
+            ("shell", &shell_info),
+            ("sandbox", &SANDBOX_ROOT),
+        ]);
+        let mut session_context = String::new();
+        if !locale::current().is_english() {
+            session_context.push_str(&locale::current().instruction());
+        }
+        session_context.push_str(&project::instruction());
+        if let Some(overview) = overview::build() {
+            session_context.push_str(&format!(
+                "\n\nProject overview, gathered when the session started (check files before relying on details):\n{}",
+                overview
+            ));
+        }
         ChatManager {
-            api_key,
             history: Vec::new(), // Start empty; system_instruction is separate
+            turn: 0,
             cleaned_up: false,
             system_instruction,
+            session_context,
             smtp_server,
+            save_session: false,
+            session_name: None,
+            measured: None,
         }
     }
 
     fn create_chat(&mut self) {
         self.history.clear(); // Reset history, system_instruction persists
+        self.turn = 0;
+        self.measured = None;
+    }
+
+    /// Sends a new user message, starting a new turn.
+    fn send_message(&mut self, message: &str) -> Result<Value, CliError> {
+        self.send_message_with(message, Vec::new())
+    }
+
+    /// Starts a turn with a message and context parts attached to it: referenced files and
+    /// pages, and images.
+    fn send_message_with(&mut self, message: &str, mut attached: Vec<Value>) -> Result<Value, CliError> {
+        self.turn += 1;
+        snapshot::new_turn(self.turn);
+        if attached.is_empty() {
+            return self.send_text(message);
+        }
+        // Images are left alone; their data is base64 and can look like anything
+        for part in attached.iter_mut().filter(|part| part.get("text").is_some()) {
+            let count = redact::before_sending(part, "an attached file or page");
+            if count > 0 {
+                let text = format!("{}\n{}", part["text"].as_str().unwrap_or(""), redact::note(count));
+                part["text"] = json!(text);
+            }
+        }
+        let mut parts = vec![json!({"text": message})];
+        parts.extend(attached);
+        self.send_parts(parts)
     }
 
-    fn send_message(&mut self, message: &str) -> Result<Value, String> {
-        let client = Client::new();
+    /// Sends tool results back to the model as part of the current turn, as functionResponse
+    /// parts in the same order as the calls.
+    fn send_tool_results(&mut self, responses: Vec<Value>) -> Result<Value, CliError> {
+        self.send_parts(responses)
+    }
 
+    fn push_history(&mut self, content: Value) {
+        self.history.push(HistoryEntry {
+            content,
+            timestamp: Local::now(),
+            turn: self.turn,
+        });
+    }
+
+    fn send_text(&mut self, message: &str) -> Result<Value, CliError> {
+        self.send_parts(vec![json!({"text": message})])
+    }
+
+    fn send_parts(&mut self, parts: Vec<Value>) -> Result<Value, CliError> {
         // Add user message to history
         let user_message = json!({
             "role": "user",
-            "parts": [{"text": message}]
+            "parts": parts
         });
-        self.history.push(user_message);
+        self.push_history(user_message);
+        self.make_room();
+        let body = self.request_body();
 
-        // Construct the body with system_instruction and full history
-        let body = json!({
-            "system_instruction": {"parts": [{"text": &self.system_instruction}]},
-            "contents": self.history.clone(), // Full history of user/assistant messages
-            "tools": [
-                {
-                    "function_declarations": [
-                        {
-                            "name": "search_online",
-                            "description": "Searches the web for a given query. Use it to retrieve up to date information.",
-                            "parameters": {
-                                "type": "object",
-                                "properties": {
-                                    "query": {
-                                        "type": "string",
-                                        "description": "The search query",
-                                    }
-                                },
-                                "required": ["query"]
-                            }
-                        },
-                        {
-                            "name": "execute_command",
-                            "description": "Execute a system command. Use this for any shell task.",
-                            "parameters": {
-                                "type": "object",
-                                "properties": {
-                                    "command": {"type": "string"}
-                                },
-                                "required": ["command"]
-                            }
-                        },
-                        {
-                            "name": "send_email",
-                            "description": "Sends an email to a fixed address using SMTP.",
-                            "parameters": {
-                                "type": "object",
-                                "properties": {
-                                    "subject": {"type": "string", "description": "Email subject line"},
-                                    "body": {"type": "string", "description": "Email message body"}
-                                },
-                                "required": ["subject", "body"]
-                            }
-                        },
-                        {
-                            "name": "alpha_vantage_query",
-                            "description": "Query the Alpha Vantage API for stock/financial data",
-                            "parameters": {
-                                "type": "object",
-                                "properties": {
-                                    "function": {
-                                        "type": "string",
-                                        "description": "The Alpha Vantage function (e.g., TIME_SERIES_DAILY)"
-                                    },
-                                    "symbol": {
-                                        "type": "string",
-                                        "description": "The stock symbol (e.g., IBM)"
-                                    }
-                                },
-                                "required": ["function", "symbol"]
-                            }
-                        },
-                        {
-                            "name": "scrape_url",
-                            "description": "Scrapes the content of a single URL",
-                            "parameters": {
-                                "type": "object",
-                                "properties": {
-                                    "url": {
-                                        "type": "string",
-                                        "description": "The URL to scrape",
-                                    }
-                                },
-                                "required": ["url"]
-                            }
-                        },
-                        {
-                            "name": "file_editor",
-                            "description": "Edit files in the sandbox with sub-commands: read, write, search, search_and_replace, apply_diff.",
-                            "parameters": {
-                                "type": "object",
-                                "properties": {
-                                    "subcommand": {
-                                        "type": "string",
-                                        "description": "The sub-command to execute: read, write, search, search_and_replace, apply_diff",
-                                        "enum": ["read", "write", "search", "search_and_replace", "apply_diff"]
-                                    },
-                                    "filename": {
-                                        "type": "string",
-                                        "description": "The name of the file in the sandbox to operate on"
-                                    },
-                                    "data": {
-                                        "type": "string",
-                                        "description": "Content to write (for write), regex pattern (for search/search_and_replace), or diff content (for apply_diff)"
-                                    },
-                                    "replacement": {
-                                        "type": "string",
-                                        "description": "Replacement text for search_and_replace"
-                                    }
-                                },
-                                "required": ["subcommand", "filename"]
-                            }
-                        }
-                    ]
-                }
-            ]
+        let response_json = match self.request(Task::Main, &body, "calling model", stream::enabled() && !ui::stdout_reserved() && !tui::active()) {
+            Ok(response) => response,
+            Err(e) => {
+                // Don't leave a message the model never answered (e.g. a blocked prompt) in the history
+                self.history.pop();
+                return Err(e);
+            }
+        };
+
+        // Add assistant response to history; further candidates are only alternatives to show
+        if let Some(content) = response_json["candidates"][0].get("content") {
+            self.push_history(thoughts::without_thoughts(content));
+        }
+        let metadata = &response_json["usageMetadata"];
+        if let Some(prompt_tokens) = metadata["promptTokenCount"].as_u64() {
+            let reply_tokens = metadata["candidatesTokenCount"].as_u64().unwrap_or(0);
+            self.measured = Some((self.history.len(), (prompt_tokens + reply_tokens) as usize));
+        }
+
+        Ok(response_json)
+    }
+
+    /// Replaces the conversation, except the turn `keep` if given, with a summary written by
+    /// the model. The summary becomes a single note at the start of the history. Returns a
+    /// message describing what was compacted.
+    fn compact(&mut self, keep: Option<usize>) -> Result<String, String> {
+        let (older, kept): (Vec<HistoryEntry>, Vec<HistoryEntry>) =
+            self.history.iter().cloned().partition(|entry| Some(entry.turn) != keep);
+        let (first, last) = match (older.first(), older.last()) {
+            (Some(first), Some(last)) => (first.turn, last.turn),
+            _ => return Err(i18n::t("command.nothing_to_compact")),
+        };
+        let before = self.context_tokens();
+
+        let instruction = "You compact a conversation between a user and an AI coding assistant so the assistant \
+            can continue it with only your summary. Keep the user's goals and preferences, decisions made, \
+            files created or changed (with paths), commands that matter and their outcome, open problems and \
+            the agreed next steps. Quote exact names, paths, identifiers, numbers and error messages. Leave out \
+            pleasantries, raw tool output and anything that was superseded. Reply with the summary only, as \
+            concise Markdown.";
+        let summary = self.complete(Task::Compact, instruction, &export::to_markdown(&older), "compacting conversation")?;
+
+        let note = format!(
+            "[Summary of turns {} to {}, which were compacted to save context]\n\n{}",
+            first,
+            last,
+            summary.trim()
+        );
+        self.history = vec![HistoryEntry {
+            content: json!({"role": "user", "parts": [{"text": note}]}),
+            timestamp: Local::now(),
+            turn: last,
+        }];
+        self.history.extend(kept);
+        self.measured = None;
+        Ok(i18n::tf(
+            "chat.compacted",
+            &[
+                ("first", &first),
+                ("last", &last),
+                ("before", &locale::format_number(before)),
+                ("after", &locale::format_number(self.context_tokens())),
+            ],
+        ))
+    }
+
+    /// The system instruction as sent: the default one or the active persona's, followed by
+    /// the language, project instructions and overview.
+    fn full_instruction(&self) -> String {
+        format!("{}{}", persona::system_instruction(&self.system_instruction), self.session_context)
+    }
+
+    /// The generateContent body for the conversation so far: system instruction, full history,
+    /// the enabled tools and the generation settings.
+    fn request_body(&self) -> Value {
+        let contents: Vec<Value> = self.history.iter().map(|entry| entry.content.clone()).collect();
+        let mut body = json!({
+            "system_instruction": {"parts": [{"text": self.full_instruction()}]},
+            "contents": contents,
         });
+        let declarations = tools::enabled_declarations();
+        if !declarations.is_empty() {
+            body["tools"] = json!([{"function_declarations": declarations}]);
+        }
+        if let Some(config) = generation::config() {
+            body["generationConfig"] = config;
+        }
+        body
+    }
+
+    /// Makes room when the conversation gets close to the context window, so the next request
+    /// still fits: earlier turns are summarized, or dropped oldest first when that is turned
+    /// off or fails. Estimates are confirmed with countTokens before anything is changed; the
+    /// current turn is always kept.
+    fn make_room(&mut self) {
+        let threshold = context::trim_threshold();
+        let mut used = self.context_tokens();
+        if used <= threshold {
+            return;
+        }
+        if self.measured.is_none() {
+            if let Ok(counted) = context::count_tokens(&self.request_body()) {
+                used = counted;
+            }
+            if used <= threshold {
+                return;
+            }
+        }
+
+        if context::auto_compact() {
+            match self.compact(Some(self.turn)) {
+                Ok(message) => {
+                    ui::status(&message.color(Color::Yellow).to_string());
+                    used = self.context_tokens();
+                    if used <= threshold {
+                        return;
+                    }
+                }
+                Err(e) => ui::status(&i18n::tf("chat.compact_failed", &[("error", &e)]).color(Color::Yellow).to_string()),
+            }
+        }
 
+        let before = used;
+        let target = context::trim_target();
+        let mut dropped = Vec::new();
+        while used > target {
+            let oldest = match self.history.first() {
+                Some(entry) if entry.turn != self.turn => entry.turn,
+                _ => break,
+            };
+            let removed: usize = self
+                .history
+                .iter()
+                .filter(|entry| entry.turn == oldest)
+                .map(|entry| context::estimate_content_tokens(&entry.content))
+                .sum();
+            self.history.retain(|entry| entry.turn != oldest);
+            used = used.saturating_sub(removed);
+            dropped.push(oldest);
+        }
+        if let (Some(first), Some(last)) = (dropped.first(), dropped.last()) {
+            self.measured = None;
+            ui::status(
+                &i18n::tf(
+                    "chat.context_trimmed",
+                    &[
+                        ("used", &locale::format_number(before)),
+                        ("window", &locale::format_number(context::context_window())),
+                        ("first", first),
+                        ("last", last),
+                    ],
+                )
+                .color(Color::Yellow)
+                .to_string(),
+            );
+        }
+    }
+
+    /// Posts a request body to the model routed for the task, showing a spinner with the given label.
+    /// When the model stays overloaded (429/5xx) the request moves to GEMINI_FALLBACK_MODEL.
+    fn generate(&self, task: Task, body: &Value, label: &str) -> Result<Value, CliError> {
+        self.request(task, body, label, false)
+    }
+
+    /// Like `generate`; with `stream` the reply text is printed as it arrives, and the
+    /// returned response is marked "streamed" so display_response doesn't print it again.
+    fn request(&self, task: Task, body: &Value, label: &str, stream: bool) -> Result<Value, CliError> {
+        usage::check_budget().map_err(CliError::Budget)?;
+        let provider = providers::current();
+        // Streamed chunks carry one candidate; more are only shown when the reply is complete
+        let stream = stream && provider.streams() && body["generationConfig"]["candidateCount"].as_u64().unwrap_or(1) <= 1;
+        let client = http::async_client();
+        title::set("thinking");
         let mut spinner = Spinner::new();
-        spinner.start();
+        spinner.start(label);
 
-        let response = client
-            .post("https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash:generateContent")
-            .query(&[("key", &self.api_key)])
-            .json(&body)
-            .send()
-            .map_err(|e| format!("API request failed: {}", e))?;
+        let primary = models::model_for(task);
+        let mut candidates = vec![primary.clone()];
+        if let Some(fallback) = models::fallback_model() {
+            if fallback != primary {
+                candidates.push(fallback);
+            }
+        }
 
-        spinner.stop();
+        let mut last_error: Option<ApiError> = None;
+        for (index, model) in candidates.iter().enumerate() {
+            if index > 0 {
+                spinner.stop();
+                let reason = last_error.as_ref().map(|e| e.summary()).unwrap_or_default();
+                ui::status(
+                    &format!("{} is unavailable ({}); retrying on {}", candidates[index - 1], reason, model)
+                        .color(Color::Yellow)
+                        .to_string(),
+                );
+                spinner.start(label);
+            }
+            let mut attempt = 0;
+            loop {
+                let started = Instant::now();
+                // Ctrl-C drops the request; the error leaves the turn like any failed request
+                let response = match runtime::block_on(provider.request(&client, model, body, stream).send())
+                    .map_err(|_| CliError::Cancelled)?
+                {
+                    Ok(response) => response,
+                    Err(e) if retry::is_transient(&e) && attempt < retry::max_retries() => {
+                        attempt += 1;
+                        retry::wait(&spinner, label, attempt, retry::backoff(attempt, None)).map_err(|_| CliError::Cancelled)?;
+                        continue;
+                    }
+                    Err(e) => {
+                        spinner.stop();
+                        return Err(CliError::Network(e.without_url().to_string()));
+                    }
+                };
+                stats::record_api_call(started.elapsed());
 
-        let response_json: Value = response
-            .json()
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
+                let status = response.status();
+                if !status.is_success() {
+                    let error = ApiError::parse(status.as_u16(), &runtime::block_on(response.text()).map_err(|_| CliError::Cancelled)?.unwrap_or_default());
+                    // Another key may still have quota left; switching costs no retry
+                    if error.status == 429 {
+                        if let Some((from, to)) = provider.rotate_key(error.retry_delay) {
+                            spinner.stop();
+                            ui::status(
+                                &format!("API key {} hit its quota; switching to key {}", from, to)
+                                    .color(Color::Yellow)
+                                    .to_string(),
+                            );
+                            spinner.start(label);
+                            continue;
+                        }
+                    }
+                    if !error.is_retryable() {
+                        spinner.stop();
+                        return Err(CliError::Api(Box::new(error)));
+                    }
+                    let server_delay = error.retry_delay;
+                    // No retry gets around a used-up daily quota; the fallback model has its own
+                    let daily_quota = error.is_daily_quota();
+                    last_error = Some(error);
+                    if daily_quota || attempt >= retry::max_retries() {
+                        break;
+                    }
+                    attempt += 1;
+                    retry::wait(&spinner, label, attempt, retry::backoff(attempt, server_delay))
+                        .map_err(|_| CliError::Cancelled)?;
+                    continue;
+                }
 
-        // Add assistant response to history
-        if let Some(candidates) = response_json.get("candidates").and_then(|c| c.as_array()) {
-            for candidate in candidates {
-                if let Some(content) = candidate.get("content") {
-                    self.history.push(content.clone());
+                let response_json: Value = if stream {
+                    let mut writer: Option<ui::StreamWriter> = None;
+                    // Thoughts come before the answer; their start is shown once it begins
+                    let mut thinking = String::new();
+                    let mut response_json = runtime::block_on(stream::read(response, |text, thought| {
+                        if thought {
+                            thinking.push_str(text);
+                            return;
+                        }
+                        let writer = writer.get_or_insert_with(|| {
+                            spinner.stop();
+                            thoughts::show(&thinking);
+                            if ui::timestamps_enabled() {
+                                println!("{}", ui::turn_label(self.turn, &Local::now(), "gemini").dimmed());
+                            }
+                            ui::StreamWriter::new()
+                        });
+                        writer.write(text);
+                    }))
+                    .map_err(|_| CliError::Cancelled)
+                    .and_then(|result| result.map_err(CliError::Network));
+                    spinner.stop();
+                    if writer.is_none() {
+                        thoughts::show(&thinking);
+                    }
+                    if let Some(mut writer) = writer {
+                        writer.finish();
+                        response_json = response_json.map(|mut r| {
+                            r["streamed"] = json!(true);
+                            r
+                        });
+                    }
+                    response_json?
+                } else {
+                    spinner.stop();
+                    let response_json: Value = runtime::block_on(response.json())
+                        .map_err(|_| CliError::Cancelled)?
+                        .map_err(|e| CliError::Network(format!("Failed to parse response: {}", e)))?;
+                    provider.translate_response(response_json)
+                };
+                usage::record(model, &response_json);
+                if let Some(reason) = api_error::blocked(&response_json) {
+                    return Err(CliError::NoAnswer(reason));
                 }
+                return Ok(response_json);
             }
         }
+        spinner.stop();
+        let models = candidates.join(" and ");
+        Err(match last_error {
+            Some(last) => CliError::Unavailable { models, last: Box::new(last) },
+            None => CliError::Network(format!("{} kept failing", models)),
+        })
+    }
 
-        Ok(response_json)
+    /// One-off request outside the conversation, without history or tools. Returns the reply text.
+    fn complete(&self, task: Task, instruction: &str, prompt: &str, label: &str) -> Result<String, CliError> {
+        let body = json!({
+            "system_instruction": {"parts": [{"text": instruction}]},
+            "contents": [{"role": "user", "parts": [{"text": prompt}]}]
+        });
+        let response = self.generate(task, &body, label)?;
+
+        let text: String = response["candidates"][0]["content"]["parts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|part| part["text"].as_str())
+            .collect();
+        if text.trim().is_empty() {
+            let reason = response["error"]["message"]
+                .as_str()
+                .unwrap_or("the model returned no text");
+            return Err(CliError::NoAnswer(format!("Generation failed: {}", reason)));
+        }
+        Ok(text)
     }
 
-    fn cleanup(&mut self, is_signal: bool) {
+    /// Drops the most recent turn (the user message, tool calls and replies) from the
+    /// history and returns its number.
+    fn undo_last_turn(&mut self) -> Option<usize> {
+        let turn = self.history.last()?.turn;
+        self.history.retain(|entry| entry.turn != turn);
+        self.turn = turn.saturating_sub(1);
+        self.measured = None;
+        Some(turn)
+    }
+
+    /// Tokens taken by the system instruction and the conversation so far: the count the API
+    /// reported for the last reply plus estimates for what was added since, or an estimate of
+    /// everything when there is no count yet.
+    fn context_tokens(&self) -> usize {
+        let estimate = |entries: &[HistoryEntry]| {
+            entries
+                .iter()
+                .map(|entry| context::estimate_content_tokens(&entry.content))
+                .sum::<usize>()
+        };
+        match self.measured {
+            Some((entries, tokens)) if entries <= self.history.len() => tokens + estimate(&self.history[entries..]),
+            _ => context::estimate_tokens(&self.full_instruction()) + estimate(&self.history),
+        }
+    }
+
+    /// Replaces the conversation with a saved session (the most recent one when no name is
+    /// given); later saves go to the same session. Returns a message describing it.
+    fn load_session(&mut self, name: Option<&str>) -> Result<String, String> {
+        let (name, session) = match name {
+            Some(name) => (name.trim_end_matches(".json").to_string(), sessions::load(name)?),
+            None => sessions::latest()?,
+        };
+        let mut message = i18n::tf("chat.session_resumed", &[("name", &name), ("turns", &session.turn)]);
+        if session.sandbox != *SANDBOX_ROOT {
+            message.push('\n');
+            message.push_str(&i18n::tf("chat.session_other_sandbox", &[("path", &session.sandbox)]));
+        }
+        self.history = session.history;
+        self.turn = session.turn;
+        self.session_name = Some(name);
+        self.measured = None;
+        Ok(message)
+    }
+
+    /// Saves the conversation under `name`, or under the name it was last saved or loaded as.
+    fn save_session_as(&mut self, name: Option<&str>) -> Result<String, String> {
+        let name = sessions::save(&self.history, self.turn, name.or(self.session_name.as_deref()))?;
+        self.session_name = Some(name.clone());
+        Ok(name)
+    }
+
+    /// Label for the most recent history entry, shown when timestamps are enabled.
+    fn reply_label(&self) -> String {
+        match self.history.last() {
+            Some(entry) => ui::turn_label(entry.turn, &entry.timestamp, "gemini"),
+            None => String::new(),
+        }
+    }
+
+    /// Returns the text of the most recent model reply, ignoring tool calls.
+    fn last_model_text(&self) -> Option<String> {
+        self.history
+            .iter()
+            .rev()
+            .map(|entry| &entry.content)
+            .filter(|msg| msg.get("role").and_then(|r| r.as_str()) == Some("model"))
+            .find_map(|msg| {
+                let text: Vec<&str> = msg
+                    .get("parts")
+                    .and_then(|p| p.as_array())?
+                    .iter()
+                    .filter_map(|part| part.get("text").and_then(|t| t.as_str()))
+                    .collect();
+                if text.is_empty() {
+                    None
+                } else {
+                    Some(text.join(""))
+                }
+            })
+    }
+
+    fn cleanup(&mut self) {
         if !self.cleaned_up {
+            if self.save_session && !self.history.is_empty() {
+                match self.save_session_as(None) {
+                    Ok(name) => ui::status(&i18n::tf("chat.session_saved", &[("name", &name)]).color(Color::Cyan).to_string()),
+                    Err(e) => ui::status(&e.color(Color::Red).to_string()),
+                }
+            }
+            if let Some(summary) = stats::activity_summary() {
+                ui::status(&summary);
+            }
+            let stopped = jobs::kill_all();
+            if stopped > 0 {
+                ui::status(&i18n::tf("chat.jobs_stopped", &[("count", &stopped)]).color(Color::Cyan).to_string());
+            }
             self.history.clear();
             self.cleaned_up = true;
-            println!("{}", "Shutting down...".color(Color::Cyan));
-            std::thread::sleep(std::time::Duration::from_secs(if is_signal {
-                3
-            } else {
-                2
-            }));
+            title::restore();
         }
     }
 }
 
-fn display_response(response: &Value) {
+/// Applies `/gen <name> <value>` (or `reset` as the value) and prints the outcome.
+fn print_generation_change(name: &str, value: &str) {
+    let result = if value == "reset" {
+        generation::reset(Some(name)).map(|_| i18n::tf("command.generation_setting_reset", &[("name", &name)]))
+    } else {
+        generation::set(name, value).map(|name| i18n::tf("command.generation_set", &[("name", &name), ("value", &value)]))
+    };
+    match result {
+        Ok(message) => println!("{}", message.color(Color::Cyan)),
+        Err(e) => println!("{}", e.color(Color::Red)),
+    }
+}
+
+fn display_response(response: &Value, chat_manager: &Arc<Mutex<ChatManager>>) {
+    let cut_short = api_error::cut_short(response).map(|note| note.color(Color::Yellow).to_string());
+    // The text was already printed while it streamed in
+    if response.get("streamed").is_some() {
+        if let Some(note) = &cut_short {
+            ui::status(note);
+        }
+        ui::status("");
+        return;
+    }
+    thoughts::show(&thoughts::of(response));
+    if ui::timestamps_enabled() {
+        let label = chat_manager.lock().unwrap().reply_label();
+        ui::status(&label.dimmed().to_string());
+    }
     if let Some(candidates) = response.get("candidates").and_then(|c| c.as_array()) {
-        for candidate in candidates {
+        for (index, candidate) in candidates.iter().enumerate() {
+            // With GEMINI_CANDIDATE_COUNT the first reply continues the conversation
+            if index > 0 {
+                let label = i18n::tf("chat.alternative", &[("number", &(index + 1).to_string())]);
+                if tui::active() {
+                    tui::answer(&label);
+                } else {
+                    ui::status(&label.dimmed().to_string());
+                }
+            }
             if let Some(parts) = candidate
                 .get("content")
                 .and_then(|c| c.get("parts").and_then(|p| p.as_array()))
             {
-                for part in parts {
+                for part in parts.iter().filter(|part| !thoughts::is_thought(part)) {
                     if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                        println!("{}", text.color(Color::Yellow));
+                        if tui::active() {
+                            // The conversation pane wraps it to its own width
+                            tui::answer(text);
+                        } else {
+                            let wrapped = ui::wrap_text(text, ui::terminal_width());
+                            ui::status(&wrapped.color(Color::Yellow).to_string());
+                        }
                     }
                 }
             }
         }
     }
-    println!(); // Add a newline after the response
+    if let Some(note) = &cut_short {
+        ui::status(note);
+    }
+    ui::status(""); // Add a newline after the response
+}
+
+fn run_tool(func_name: &str, args: &Value, chat_manager: &Arc<Mutex<ChatManager>>, debug: bool) -> Result<String, CliError> {
+    if !tools::is_enabled(func_name) {
+        return Err(CliError::Tool(i18n::tf("tool.disabled", &[("tool", &func_name)])));
+    }
+    if confirm::simulated(func_name, args) {
+        let action = format!("{} {}", func_name, ui::summarize_args(func_name, args));
+        ui::status(&i18n::tf("policy.dry_run_skipped", &[("action", &action.trim())]).color(Color::Yellow).to_string());
+        return Ok(format!(
+            "Dry run: this call was simulated and not performed, so nothing was changed ({}). \
+             Continue with the plan as if it had succeeded, without repeating the call, and note that \
+             its real output is unknown.",
+            action.trim()
+        ));
+    }
+    if confirm::required(func_name, args) && !confirm::ask(func_name, args) {
+        return Ok(match func_name {
+            "execute_command" | "run_in_background" => i18n::t("policy.rejected"),
+            _ => i18n::tf("policy.rejected_tool", &[("tool", &func_name)]),
+        });
+    }
+    dispatch_tool(func_name, args, chat_manager, debug).map_err(CliError::Tool)
+}
+
+/// Runs a tool that is enabled and confirmed.
+fn dispatch_tool(func_name: &str, args: &Value, chat_manager: &Arc<Mutex<ChatManager>>, debug: bool) -> Result<String, String> {
+    match func_name {
+        "execute_command" => {
+            let cmd = args
+                .get("command")
+                .and_then(|c| c.as_str())
+                .ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"command")]))?;
+            println!("{}", i18n::tf("policy.executing", &[("command", &cmd.color(Color::Magenta))]));
+            Ok(execute_command(cmd))
+        }
+        "search_online" => {
+            let query = args
+                .get("query")
+                .and_then(|q| q.as_str())
+                .ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"query")]))?;
+            let result = search_online(query);
+            if !search_came_back_empty(&result) {
+                return Ok(result);
+            }
+            match expand_query(query, chat_manager) {
+                Some(alternative) => Ok(format!(
+                    "No results for '{}'; searched for '{}' instead.\n{}",
+                    query,
+                    alternative,
+                    search_online(&alternative)
+                )),
+                None => Ok(result),
+            }
+        }
+        "scrape_url" => {
+            let url = args
+                .get("url")
+                .and_then(|u| u.as_str())
+                .ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"url")]))?;
+            Ok(summarize_document(url, search::scrape_url(url), chat_manager))
+        }
+        "send_email" => {
+            let subject = args.get("subject").and_then(|s| s.as_str());
+            let body = args.get("body").and_then(|b| b.as_str());
+            let list = |key: &str| -> Vec<String> {
+                args.get(key)
+                    .and_then(|a| a.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|a| a.as_str().map(String::from))
+                    .collect()
+            };
+            if let (Some(subj), Some(bod)) = (subject, body) {
+                let smtp_server = {
+                    let manager = chat_manager.lock().unwrap();
+                    manager.smtp_server.clone()
+                };
+                let email = Email {
+                    subject: subj.to_string(),
+                    body: bod.to_string(),
+                    html: args.get("html").and_then(|h| h.as_str()).filter(|h| !h.trim().is_empty()).map(String::from),
+                    attachments: list("attachments"),
+                    to: list("to"),
+                    cc: list("cc"),
+                    bcc: list("bcc"),
+                };
+                Ok(send_email(&email, &smtp_server, debug))
+            } else {
+                Err(i18n::t("tool.missing_parameters"))
+            }
+        }
+        "alpha_vantage_query" => {
+            let get = |key: &str| args.get(key).and_then(|v| v.as_str()).unwrap_or("");
+            let function = args
+                .get("function")
+                .and_then(|f| f.as_str())
+                .ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"function")]))?;
+            let extra: Vec<(&str, &str)> = ["interval", "outputsize", "month"].iter().map(|key| (*key, get(key))).collect();
+            alpha_vantage_query(function, get("symbol"), &extra)
+        }
+        "fx_rate" => {
+            let from = args.get("from_currency").and_then(|v| v.as_str());
+            let to = args.get("to_currency").and_then(|v| v.as_str());
+            match (from, to) {
+                (Some(from), Some(to)) => finance::fx_rate(from, to),
+                (None, _) => Err(i18n::tf("tool.missing_parameter", &[("parameter", &"from_currency")])),
+                (_, None) => Err(i18n::tf("tool.missing_parameter", &[("parameter", &"to_currency")])),
+            }
+        }
+        "crypto_price" => {
+            let symbol = args
+                .get("symbol")
+                .and_then(|s| s.as_str())
+                .ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"symbol")]))?;
+            let market = args.get("market").and_then(|m| m.as_str()).unwrap_or("USD");
+            let history = args.get("history").and_then(|h| h.as_bool()).unwrap_or(false);
+            finance::crypto_price(symbol, market, history)
+        }
+        "company_overview" | "company_earnings" => {
+            let symbol = args
+                .get("symbol")
+                .and_then(|s| s.as_str())
+                .ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"symbol")]))?;
+            if func_name == "company_overview" {
+                finance::company_overview(symbol)
+            } else {
+                finance::company_earnings(symbol)
+            }
+        }
+        "file_editor" => {
+            let subcommand = args.get("subcommand").and_then(|s| s.as_str());
+            let filename = args.get("filename").and_then(|f| f.as_str());
+            let data = args.get("data").and_then(|d| d.as_str());
+            let replacement = args.get("replacement").and_then(|r| r.as_str());
+            let line = |key: &str| args.get(key).and_then(|l| l.as_u64()).map(|l| l as usize);
+
+            if let (Some("read"), Some(fname)) = (subcommand, filename) {
+                Ok(read_file(fname, line("start_line"), line("end_line")))
+            } else if let (Some(subcmd), Some(fname)) = (subcommand, filename) {
+                Ok(file_editor(subcmd, fname, data, replacement))
+            } else {
+                Err(i18n::t("tool.missing_file_parameters"))
+            }
+        }
+        "apply_changeset" => {
+            let changes: Vec<(String, String)> = args
+                .get("changes")
+                .and_then(|c| c.as_array())
+                .ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"changes")]))?
+                .iter()
+                .map(|change| {
+                    let get = |key: &str| change.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string();
+                    (get("filename"), get("diff"))
+                })
+                .collect();
+            Ok(apply_changeset(&changes))
+        }
+        "undo_last_edit" => backups::undo_last_edit(),
+        "run_in_background" => {
+            let cmd = args
+                .get("command")
+                .and_then(|c| c.as_str())
+                .ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"command")]))?;
+            jobs::run_in_background(cmd)
+        }
+        "list_jobs" => jobs::list_jobs(args.get("id").and_then(|i| i.as_u64()).map(|i| i as usize)),
+        "kill_job" => {
+            let id = args
+                .get("id")
+                .and_then(|i| i.as_u64())
+                .ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"id")]))?;
+            jobs::kill_job(id as usize)
+        }
+        "http_request" => {
+            let get = |key: &str| args.get(key).and_then(|v| v.as_str());
+            let url = get("url").ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"url")]))?;
+            http_tool::http_request(get("method").unwrap_or("GET"), url, get("headers"), get("body"))
+        }
+        "list_files" => {
+            let path = args.get("path").and_then(|p| p.as_str());
+            let pattern = args.get("pattern").and_then(|p| p.as_str());
+            let depth = args.get("depth").and_then(|d| d.as_u64()).map(|d| d as usize);
+            list_files(path, pattern, depth)
+        }
+        "search_codebase" => {
+            let query = args
+                .get("query")
+                .and_then(|q| q.as_str())
+                .ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"query")]))?;
+            let get = |key: &str| args.get(key).and_then(|v| v.as_str());
+            let number = |key: &str| args.get(key).and_then(|v| v.as_u64()).map(|v| v as usize);
+            let regex = args.get("regex").and_then(|r| r.as_bool()).unwrap_or(false);
+            search_codebase(query, regex, get("path"), get("glob"), number("context"), number("max_per_file"))
+        }
+        "github" => {
+            let operation = args
+                .get("operation")
+                .and_then(|o| o.as_str())
+                .ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"operation")]))?;
+            github_query(operation, args)
+        }
+        "tracker" => {
+            let operation = args
+                .get("operation")
+                .and_then(|o| o.as_str())
+                .ok_or_else(|| i18n::tf("tool.missing_parameter", &[("parameter", &"operation")]))?;
+            let key = args.get("key").and_then(|k| k.as_str());
+            let body = args.get("body").and_then(|b| b.as_str());
+            tracker_query(operation, key, body)
+        }
+        _ if plugins::provides(func_name) => plugins::call(func_name, args),
+        _ if external_tools::provides(func_name) => external_tools::call(func_name, args),
+        _ => Err(i18n::tf("tool.unknown", &[("tool", &func_name)])),
+    }
+}
+
+fn search_came_back_empty(result: &str) -> bool {
+    result.starts_with("No results found")
+        || result.starts_with("No relevant results found")
+        || result.starts_with("No valid content")
+}
+
+/// Asks the auxiliary model to reword a search that found nothing.
+fn expand_query(query: &str, chat_manager: &Arc<Mutex<ChatManager>>) -> Option<String> {
+    let instruction = "You rewrite web search queries that returned no useful results. \
+        Reply with a single alternative query only: broader, with synonyms or the most likely intended terms.";
+    let alternative = chat_manager
+        .lock()
+        .unwrap()
+        .complete(Task::QueryExpansion, instruction, query, "rewording search")
+        .ok()?;
+    let alternative = alternative.trim().trim_matches('"').to_string();
+    if alternative.is_empty() || alternative.eq_ignore_ascii_case(query) {
+        None
+    } else {
+        Some(alternative)
+    }
+}
+
+// Tool output longer than this many characters is condensed before it's sent back
+const DEFAULT_CONDENSE_THRESHOLD: usize = 20_000;
+
+/// Shrinks oversized tool output with the auxiliary model. File reads are left alone,
+/// since editing needs their exact contents.
+fn condense_output(func_name: &str, output: String, chat_manager: &Arc<Mutex<ChatManager>>) -> String {
+    let threshold = env::var("GEMINI_CONDENSE_THRESHOLD")
+        .ok()
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(DEFAULT_CONDENSE_THRESHOLD);
+    if threshold == 0 || output.len() <= threshold || func_name == "file_editor" {
+        return output;
+    }
+    let instruction = "You condense tool output for another AI assistant. Keep every detail that could matter: \
+        errors and warnings, numbers, names, paths, URLs, identifiers and short code excerpts. \
+        Drop repetition, boilerplate and navigation text. Reply with the condensed output only.";
+    let prompt = format!("Output of the {} tool:\n\n{}", func_name, output);
+    let result = chat_manager
+        .lock()
+        .unwrap()
+        .complete(Task::Condense, instruction, &prompt, "condensing output");
+    match result {
+        Ok(condensed) => format!("[Condensed from {} characters]\n{}", output.len(), condensed),
+        Err(_) => output,
+    }
+}
+
+// Scraped documents longer than this many characters are summarized a piece at a time
+const DEFAULT_DOCUMENT_CHUNK: usize = 20_000;
+// Most pieces of one document that are summarized; the rest is left out
+const DEFAULT_DOCUMENT_CHUNKS: usize = 10;
+
+/// Summarizes a long scraped document with the auxiliary model, one piece at a time, so a
+/// manual or report too large for one request still comes back whole. The full text is
+/// returned when a summary fails.
+fn summarize_document(url: &str, text: String, chat_manager: &Arc<Mutex<ChatManager>>) -> String {
+    let setting = |key: &str, default: usize| env::var(key).ok().and_then(|v| v.trim().parse().ok()).unwrap_or(default);
+    let chunk_size = setting("GEMINI_DOCUMENT_CHUNK", DEFAULT_DOCUMENT_CHUNK);
+    let max_chunks = setting("GEMINI_DOCUMENT_CHUNKS", DEFAULT_DOCUMENT_CHUNKS).max(1);
+    if chunk_size == 0 || text.len() <= chunk_size || text.starts_with("Error") || text.starts_with("Skipped") {
+        return text;
+    }
+    let chunks = documents::chunks(&text, chunk_size);
+    let instruction = "You summarize one part of a longer document for another AI assistant that could not \
+        read it whole. Keep headings, facts, numbers, names, identifiers, commands, code excerpts, URLs and page \
+        numbers; drop repetition and boilerplate. Reply with the summary only, as concise Markdown.";
+
+    let mut summary = format!(
+        "[Summary of {} ({} characters) in {} parts]",
+        url,
+        text.len(),
+        chunks.len().min(max_chunks)
+    );
+    for (index, chunk) in chunks.iter().take(max_chunks).enumerate() {
+        let prompt = format!("Part {} of {} of {}:\n\n{}", index + 1, chunks.len(), url, chunk);
+        let label = format!("summarizing part {} of {}", index + 1, chunks.len());
+        let result = chat_manager
+            .lock()
+            .unwrap()
+            .complete(Task::Condense, instruction, &prompt, &label);
+        match result {
+            Ok(part) => summary.push_str(&format!("\n\n## Part {}\n\n{}", index + 1, part.trim())),
+            Err(_) => return text,
+        }
+    }
+    if chunks.len() > max_chunks {
+        let left_out: usize = chunks[max_chunks..].iter().map(|chunk| chunk.len()).sum();
+        summary.push_str(&format!(
+            "\n\n[{} more part(s) ({} characters) were left out; raise GEMINI_DOCUMENT_CHUNKS to include them]",
+            chunks.len() - max_chunks,
+            left_out
+        ));
+    }
+    summary
 }
 
-fn process_tool_calls(response: &Value, chat_manager: &Arc<Mutex<ChatManager>>, debug: bool) -> Result<(), String> {
+fn process_tool_calls(response: &Value, chat_manager: &Arc<Mutex<ChatManager>>, debug: bool) -> Result<(), CliError> {
     let mut current_response = response.clone();
+    let mut guard = LoopGuard::new();
+    let max_rounds = tool_loop::max_rounds();
+    let mut rounds = 0;
+    let mut budget = TurnBudget::start();
 
     loop {
         let tool_calls: Vec<(String, Value)> = current_response
@@ -434,118 +1357,87 @@ fn process_tool_calls(response: &Value, chat_manager: &Arc<Mutex<ChatManager>>,
         if tool_calls.is_empty() {
             break;
         }
+        let ids = call_ids(&current_response);
 
-        let mut results = Vec::new();
-        for (func_name, args) in tool_calls {
-            match func_name.as_str() {
-                "execute_command" => {
-                    let command = args.get("command").and_then(|c| c.as_str());
-                    if let Some(cmd) = command {
-                        println!("LLM wants to execute command: {} | Confirm execution? (y/n)", cmd.color(Color::Magenta));
-                        let mut input = String::new();
-                        std::io::stdin().read_line(&mut input).expect("Failed to read input");
-                        let input = input.trim().to_lowercase();
-                        if input == "y" || input == "yes" {
-                            println!("Executing command: {}", cmd.color(Color::Magenta));
-                            let result = execute_command(cmd);
-                            results.push(format!("[Tool result] execute_command: {}", result));
-                        } else {
-                            //println!("Command execution rejected by user.");
-                            results.push("[Tool result] execute_command: User rejected the command execution.".to_string());
-                        }
-                    } else {
-                        results.push(
-                            "[Tool error] execute_command: Missing 'command' parameter"
-                                .to_string(),
-                        );
-                    }
-                }
-                "search_online" => {
-                    let query = args.get("query").and_then(|q| q.as_str());
-                    if let Some(q) = query {
-                        let result = search_online(q);
-                        results.push(format!("[Tool result] search_online: {}", result));
-                    } else {
-                        results.push(
-                            "[Tool error] search_online: Missing 'query' parameter"
-                                .to_string(),
-                        );
-                    }
-                }
-                "scrape_url" => {
-                    let url = args.get("url").and_then(|u| u.as_str());
-                    if let Some(u) = url {
-                        let result = search::scrape_url(u);
-                        if result.starts_with("Error") || result.starts_with("Skipped") {
-                            println!("Scrape failed: {}", result);
-                        }
-                        results.push(format!("[Tool result] scrape_url: {}", result));
-                    } else {
-                        results.push(
-                            "[Tool error] scrape_url: Missing 'url' parameter".to_string(),
-                        );
-                    }
-                }
-                "send_email" => {
-                    let subject = args.get("subject").and_then(|s| s.as_str());
-                    let body = args.get("body").and_then(|b| b.as_str());
-
-                    if let (Some(subj), Some(bod)) = (subject, body) {
-                        let smtp_server = {
-                            let manager = chat_manager.lock().unwrap();
-                            manager.smtp_server.clone()
-                        };
-                        let result = send_email(subj, bod, &smtp_server, debug);
-                        results.push(format!("[Tool result] send_email: {}", result));
-                    } else {
-                        results.push(
-                            "[Tool error] send_email: Missing required parameters"
-                                .to_string(),
-                        );
-                    }
-                }
-                "alpha_vantage_query" => {
-                    let function = args.get("function").and_then(|f| f.as_str());
-                    let symbol = args.get("symbol").and_then(|s| s.as_str());
-                    if let (Some(func), Some(sym)) = (function, symbol) {
-                        match alpha_vantage_query(func, sym) {
-                            Ok(result) => results.push(format!(
-                                "[Tool result] alpha_vantage_query: {}",
-                                result
-                            )),
-                            Err(e) => results
-                                .push(format!("[Tool error] alpha_vantage_query: {}", e)),
-                        }
-                    } else {
-                        results.push(
-                            "[Tool error] alpha_vantage_query: Missing required parameters"
-                                .to_string(),
-                        );
-                    }
-                }
-                "file_editor" => {
-                    let subcommand = args.get("subcommand").and_then(|s| s.as_str());
-                    let filename = args.get("filename").and_then(|f| f.as_str());
-                    let data = args.get("data").and_then(|d| d.as_str());
-                    let replacement = args.get("replacement").and_then(|r| r.as_str());
-
-                    if let (Some(subcmd), Some(fname)) = (subcommand, filename) {
-                        let result = file_editor(subcmd, fname, data, replacement);
-                        results.push(format!("[Tool result] file_editor: {}", result));
-                    } else {
-                        results.push("[Tool error] file_editor: Missing required parameters 'subcommand' or 'filename'".to_string());
-                    }
-                }
-                _ => {
-                    results.push(format!("[Tool error] Unknown function: {}", func_name));
+        if max_rounds > 0 && rounds == max_rounds {
+            ui::status(
+                &i18n::tf("tool.round_limit", &[("limit", &max_rounds)])
+                .color(Color::Yellow)
+                .to_string(),
+            );
+            let notice = format!(
+                "Not run: the limit of {} tool rounds for this turn was reached. Summarize what you have done \
+                 so far and what remains, so the user can decide whether to continue.",
+                max_rounds
+            );
+            current_response = chat_manager.lock().unwrap().send_tool_results(declined(&tool_calls, &ids, &notice))?;
+            display_response(&current_response, chat_manager);
+            break;
+        }
+        rounds += 1;
+
+        if let Some(reason) = budget.exhausted() {
+            ui::status(
+                &i18n::tf("tool.budget_reached", &[("reason", &reason), ("summary", &budget.summary())])
+                    .color(Color::Yellow)
+                    .to_string(),
+            );
+            let notice = format!(
+                "Not run: {}. So far this turn: {}. Summarize what you have done and what remains, \
+                 so the user can decide whether to continue.",
+                reason,
+                budget.summary()
+            );
+            current_response = chat_manager.lock().unwrap().send_tool_results(declined(&tool_calls, &ids, &notice))?;
+            display_response(&current_response, chat_manager);
+            break;
+        }
+
+        if let Some(repetition) = guard.check(&tool_calls) {
+            ui::status(&i18n::tf("tool.loop_stopped", &[("reason", &repetition)]).color(Color::Yellow).to_string());
+            let notice = format!(
+                "Not run: {}. Running it again won't give a different result. Answer with what you have, \
+                 or explain what is blocking you instead of repeating the call.",
+                repetition
+            );
+            current_response = chat_manager.lock().unwrap().send_tool_results(declined(&tool_calls, &ids, &notice))?;
+            display_response(&current_response, chat_manager);
+            break;
+        }
+
+        budget.record_round(tool_calls.len());
+        let mut results: Vec<Value> = Vec::new();
+        for ((func_name, args), id) in tool_calls.into_iter().zip(&ids) {
+            title::set(&match func_name.as_str() {
+                "execute_command" => format!("running {}", args["command"].as_str().unwrap_or("")),
+                _ => format!("using {}", func_name),
+            });
+            let block = ToolBlock::start(&func_name, &args);
+            // The model, the audit log and --json take the failure as text
+            let result = run_tool(&func_name, &args, chat_manager, debug).map_err(String::from);
+            // execute_command already streamed its output to the terminal
+            let duration = block.finish(&result, func_name != "execute_command");
+            json_output::record_call(&func_name, &args, &result, duration);
+            audit::record("chat", &func_name, &args, &result, duration);
+            let ok = matches!(&result, Ok(output) if !ui::looks_like_failure(output));
+            stats::record_tool(&func_name, duration, ok);
+            let response = match result {
+                Ok(output) => {
+                    let output = redact::text_before_sending(output, &format!("the {} result", func_name));
+                    let output = condense_output(&func_name, output, chat_manager);
+                    // Results of this round count too, they are sent together
+                    let used = chat_manager.lock().unwrap().context_tokens()
+                        + results.iter().map(|r| context::estimate_tokens(&r.to_string())).sum::<usize>();
+                    json!({"result": context::fit(&func_name, output, used)})
                 }
-            }
+                Err(e) => json!({"error": e}),
+            };
+            results.push(function_response(&func_name, id, response));
         }
 
         if !results.is_empty() {
-            let combined_results = results.join("\n");
-            current_response = chat_manager.lock().unwrap().send_message(&combined_results)?;
-            display_response(&current_response);
+            current_response = chat_manager.lock().unwrap().send_tool_results(results)?;
+            display_response(&current_response, chat_manager);
         } else {
             break;
         }
@@ -554,8 +1446,66 @@ fn process_tool_calls(response: &Value, chat_manager: &Arc<Mutex<ChatManager>>,
     Ok(())
 }
 
+/// functionResponse errors telling the model its calls were not run, and why.
+fn declined(tool_calls: &[(String, Value)], ids: &[Value], notice: &str) -> Vec<Value> {
+    tool_calls
+        .iter()
+        .zip(ids)
+        .map(|((name, _), id)| function_response(name, id, json!({"error": notice})))
+        .collect()
+}
+
+/// The ids of the function calls in a response, in order (null for calls without one).
+fn call_ids(response: &Value) -> Vec<Value> {
+    response["candidates"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|candidate| candidate["content"]["parts"].as_array())
+        .flatten()
+        .filter_map(|part| part.get("functionCall"))
+        .map(|call| call["id"].clone())
+        .collect()
+}
+
+/// A functionResponse part for a call, keyed by the tool name and, when the model gave the
+/// call an id, by that id too, so calls to the same tool in one round can't be mixed up.
+fn function_response(name: &str, id: &Value, response: Value) -> Value {
+    let mut function_response = json!({"name": name, "response": response});
+    if !id.is_null() {
+        function_response["id"] = id.clone();
+    }
+    json!({"functionResponse": function_response})
+}
+
+/// Prints a failed request in red under the given message key, with what to do about it below.
+fn print_error(key: &str, error: &CliError) {
+    ui::status(&i18n::tf(key, &[("error", &error.to_string())]).color(Color::Red).to_string());
+    if let Some(hint) = error.hint() {
+        ui::status(&i18n::tf("chat.hint", &[("hint", &hint)]).color(Color::Yellow).to_string());
+    }
+}
+
+/// Prints a startup error with its hint and quits.
+fn exit_with(error: &CliError) -> ! {
+    eprintln!("{}", error.to_string().color(Color::Red));
+    if let Some(hint) = error.hint() {
+        eprintln!("{}", i18n::tf("chat.hint", &[("hint", &hint)]).color(Color::Yellow));
+    }
+    std::process::exit(1);
+}
+
+fn save_last_answer(chat_manager: &Arc<Mutex<ChatManager>>, path: &std::path::Path) -> Result<(), String> {
+    let answer = chat_manager
+        .lock()
+        .unwrap()
+        .last_model_text()
+        .ok_or("No answer to save yet")?;
+    std::fs::write(path, answer).map_err(|e| format!("Error writing to '{}': {}", path.display(), e))
+}
+
 fn interactive_shell() -> String {
-    println!("{}", "Entering interactive shell mode. Type 'exit' to return.".color(Color::Cyan));
+    println!("{}", i18n::t("shell.enter").color(Color::Cyan));
     let mut accumulated_output = String::new();
     loop {
         print!("shell> ");
@@ -573,30 +1523,135 @@ fn interactive_shell() -> String {
                 accumulated_output.push_str(&format!("Command: {}\nOutput: {}\n\n", input, output));
             }
             Err(e) => {
-                println!("{}", format!("Input error: {}", e).color(Color::Red));
+                println!("{}", i18n::tf("chat.input_error", &[("error", &e)]).color(Color::Red));
                 break;
             }
         }
     }
-    println!("{}", "Exiting interactive shell mode.".color(Color::Cyan));
+    println!("{}", i18n::t("shell.exit").color(Color::Cyan));
     accumulated_output
 }
 
 fn main() {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    ui::init_console();
 
     let home_dir = dirs::home_dir()
         .expect("Could not determine home directory")
         .to_string_lossy()
         .to_string();
     dotenv::from_path(format!("{}/.gemini.conf", home_dir)).ok();
-    let api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY not found in ~/.gemini.conf");
+    project::apply();
+
+    let lang = args.lang.clone().or_else(|| env::var("GEMINI_LANG").ok());
+    locale::init(lang.as_deref().unwrap_or("en"));
+    i18n::init(lang.as_deref());
+
+    if let Some(model) = &args.model {
+        models::set_main(model);
+    }
+    confirm::set_safe_mode(args.confirm);
+    confirm::set_dry_run(args.dry_run);
+
+    if args.version {
+        println!("{}", version::report());
+        return;
+    }
+
+    // A bare invocation keeps the old behavior: -p runs a single prompt, otherwise chat
+    let command = match (args.command.take(), args.prompt.take()) {
+        (Some(command), _) => command,
+        (None, Some(prompt)) => Commands::Ask {
+            prompt: Some(prompt),
+            output: args.output.take(),
+            json: args.json,
+        },
+        (None, None) => Commands::Chat,
+    };
+
+    let result = match command {
+        Commands::McpServe => {
+            mcp::serve();
+            Ok(())
+        }
+        Commands::Selftest => selftest::run(),
+        Commands::Config { action } => config::run(action),
+        Commands::Sessions { action } => sessions::run(action),
+        Commands::Tools { action } => tools::run(action),
+        Commands::Auth { action } => auth::run(action),
+        Commands::Ask { prompt, output, json } => {
+            if json {
+                json_output::start();
+            }
+            let prompt = match mentions::with_piped_input(prompt) {
+                Ok(prompt) => prompt,
+                Err(e) => {
+                    eprintln!("{}", e.color(Color::Red));
+                    std::process::exit(1);
+                }
+            };
+            let chat_manager = start_chat_manager(&args);
+            run_ask(&chat_manager, &prompt, output.as_deref(), args.debug);
+            Ok(())
+        }
+        Commands::Commit { yes, hint } => {
+            let chat_manager = start_chat_manager(&args);
+            commit::run(&chat_manager, yes, hint.as_deref())
+        }
+        Commands::Review { staged, range, pr, output } => {
+            let source = match (staged, range, pr) {
+                (true, _, _) => review::Source::Staged,
+                (_, Some(range), _) => review::Source::Range(range),
+                (_, _, Some(number)) => review::Source::PullRequest(number),
+                _ => review::Source::WorkingTree,
+            };
+            let chat_manager = start_chat_manager(&args);
+            review::run(&chat_manager, source, output.as_deref())
+        }
+        Commands::Chat => {
+            let chat_manager = start_chat_manager(&args);
+            if let Err(e) = auth::startup_check() {
+                exit_with(&e);
+            }
+            plugins::load();
+            external_tools::load();
+            chat_manager.lock().unwrap().save_session = !args.no_save && sessions::autosave_enabled();
+            if let Some(name) = &args.resume {
+                match chat_manager.lock().unwrap().load_session(name.as_deref()) {
+                    Ok(message) => println!("{}", message.color(Color::Cyan)),
+                    Err(e) => {
+                        eprintln!("{}", e.color(Color::Red));
+                        std::process::exit(1);
+                    }
+                }
+            }
+            if args.tui {
+                tui::run(&chat_manager, args.debug)
+            } else {
+                run_chat(&chat_manager, args.debug);
+                Ok(())
+            }
+        }
+    };
+
+    title::restore();
+    if let Err(e) = result {
+        eprintln!("{}", e.color(Color::Red));
+        std::process::exit(1);
+    }
+}
+
+/// Reads the API configuration, applies the display options and installs the Ctrl-C handler.
+fn start_chat_manager(args: &Args) -> Arc<Mutex<ChatManager>> {
+    if let Err(e) = providers::check() {
+        exit_with(&e);
+    }
     let smtp_server = env::var("SMTP_SERVER_IP").unwrap_or_else(|_| "localhost".to_string());
 
     // Debug output for SMTP configuration
     if args.debug {
-        println!("{}", "=== SMTP Configuration ===".color(Color::Cyan));
-        println!("SMTP_SERVER_IP: {}", smtp_server);
+        ui::status(&"=== SMTP Configuration ===".color(Color::Cyan).to_string());
+        ui::status(&format!("SMTP_SERVER_IP: {}", smtp_server));
 
         let smtp_username = env::var("SMTP_USERNAME").unwrap_or_else(|_| "<not set>".to_string());
         let smtp_password = if env::var("SMTP_PASSWORD").is_ok() {
@@ -604,176 +1659,625 @@ fn main() {
         } else {
             "<not set>".to_string()
         };
-        println!("SMTP_USERNAME: {}", smtp_username);
-        println!("SMTP_PASSWORD: {}", smtp_password);
+        ui::status(&format!("SMTP_USERNAME: {}", smtp_username));
+        ui::status(&format!("SMTP_PASSWORD: {}", smtp_password));
 
         let destination_email = env::var("DESTINATION_EMAIL").unwrap_or_else(|_| "<not set>".to_string());
         let sender_email = env::var("SENDER_EMAIL").unwrap_or_else(|_| "<not set>".to_string());
-        println!("DESTINATION_EMAIL: {}", destination_email);
-        println!("SENDER_EMAIL: {}", sender_email);
-        println!("{}", "==========================".color(Color::Cyan));
-        println!();
+        ui::status(&format!("DESTINATION_EMAIL: {}", destination_email));
+        ui::status(&format!("SENDER_EMAIL: {}", sender_email));
+        ui::status(&"==========================".color(Color::Cyan).to_string());
+        ui::status("");
+    }
+
+    ui::set_timestamps_enabled(
+        args.timestamps
+            || env::var("GEMINI_SHOW_TIMESTAMPS").map(|v| v == "1" || v == "true").unwrap_or(false),
+    );
+
+    let editor_port = args
+        .editor_port
+        .or_else(|| env::var("GEMINI_EDITOR_PORT").ok().and_then(|p| p.parse().ok()));
+    if let Some(port) = editor_port {
+        match editor_bridge::listen(port) {
+            Ok(()) => ui::status(&i18n::tf("chat.editor_listening", &[("port", &port)]).color(Color::Cyan).to_string()),
+            Err(e) => ui::status(&e.color(Color::Red).to_string()),
+        }
     }
 
-    let chat_manager = Arc::new(Mutex::new(ChatManager::new(api_key, smtp_server)));
+    let chat_manager = Arc::new(Mutex::new(ChatManager::new(smtp_server)));
     let chat_manager_clone = Arc::clone(&chat_manager);
 
+    // At the chat prompt Ctrl-C is handled by the line editor; this covers the time a request
+    // or tool is running. The first press cancels the network request in flight (a running
+    // command is interrupted by the same signal), a second press within a few seconds saves
+    // the session and quits.
+    let mut last_interrupt: Option<Instant> = None;
     ctrlc::set_handler(move || {
-        let mut manager = chat_manager_clone.lock().unwrap();
-        manager.cleanup(true);
-        std::process::exit(0);
+        if last_interrupt.is_some_and(|at| at.elapsed() < CONFIRM_EXIT_WINDOW) {
+            // The manager is locked while a request is in flight; don't wait for it forever
+            let deadline = Instant::now() + Duration::from_millis(500);
+            loop {
+                if let Ok(mut manager) = chat_manager_clone.try_lock() {
+                    manager.cleanup();
+                    break;
+                }
+                if Instant::now() >= deadline {
+                    eprintln!("{}", i18n::t("chat.request_running").color(Color::Yellow));
+                    title::restore();
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            tui::leave();
+            std::process::exit(130);
+        }
+        last_interrupt = Some(Instant::now());
+        runtime::interrupt();
+        eprintln!("\n{}", i18n::t("chat.interrupt_running").color(Color::Yellow));
     })
     .expect("Error setting Ctrl-C handler");
 
-    // Handle single prompt mode
-    if let Some(prompt) = args.prompt {
-        println!("{}", "Processing single prompt...".color(Color::Cyan));
-        let response = match chat_manager.lock().unwrap().send_message(&prompt) {
-            Ok(resp) => resp,
-            Err(e) => {
-                println!("{}", format!("Error: {}", e).color(Color::Red));
-                chat_manager.lock().unwrap().cleanup(false);
-                std::process::exit(1);
+    if args.ignore_budget {
+        usage::override_budget();
+    }
+
+    if let Some(name) = args.persona.clone().or_else(|| env::var("GEMINI_PERSONA").ok()) {
+        match persona::load(&name) {
+            Ok(preset) => {
+                println!("{}", i18n::tf("command.persona", &[("name", &preset.name)]).color(Color::Cyan));
+                persona::set(Some(preset));
             }
-        };
-        display_response(&response);
-        if let Err(e) = process_tool_calls(&response, &chat_manager, args.debug) {
-            println!("{}", format!("Error processing tool calls: {}", e).color(Color::Red));
+            Err(e) => exit_with(&CliError::Config(e)),
+        }
+    }
+
+    title::init();
+    chat_manager
+}
+
+/// Sends a single prompt, runs any tool calls, and exits.
+/// With --json the outcome is printed as JSON and the exit code tells whether it failed.
+fn run_ask(chat_manager: &Arc<Mutex<ChatManager>>, prompt: &str, output: Option<&std::path::Path>, debug: bool) {
+    ui::status(&i18n::t("chat.single_prompt").color(Color::Cyan).to_string());
+    let attached: Vec<Value> = index::context_for(prompt, &[]).into_iter().collect();
+    let result = chat_manager.lock().unwrap().send_message_with(prompt, attached);
+    let response = match result {
+        Ok(resp) => resp,
+        Err(e) => {
+            print_error("chat.error", &e);
+            chat_manager.lock().unwrap().cleanup();
+            if json_output::enabled() {
+                json_output::finish(None, Some(&String::from(e)));
+            }
+            std::process::exit(1);
+        }
+    };
+    display_response(&response, chat_manager);
+    let mut error = None;
+    if let Err(e) = process_tool_calls(&response, chat_manager, debug) {
+        print_error("chat.tool_calls_error", &e);
+        error = Some(String::from(e));
+    }
+    if let Some(path) = output {
+        match save_last_answer(chat_manager, path) {
+            Ok(()) => ui::status(&i18n::tf("command.answer_saved", &[("path", &path.display())]).color(Color::Cyan).to_string()),
+            Err(e) => ui::status(&e.color(Color::Red).to_string()),
         }
-        chat_manager.lock().unwrap().cleanup(false);
-        return;
     }
+    let text = chat_manager.lock().unwrap().last_model_text();
+    chat_manager.lock().unwrap().cleanup();
+    if json_output::enabled() {
+        let exit_code = json_output::finish(text, error.as_deref());
+        if exit_code != 0 {
+            std::process::exit(exit_code);
+        }
+    }
+}
 
+/// The welcome text shown when a chat starts.
+fn print_banner() {
     println!(
         "{}",
-        "Welcome to Gemini Code! Chat with me (type 'exit' to quit, 'clear' to reset conversation)."
-            .color(Color::Cyan)
+        i18n::t("chat.welcome").color(Color::Cyan)
             .bold()
     );
     println!(
         "{}",
-        format!("Version: {}", COMPILE_TIME).color(Color::Cyan)
+        i18n::tf("chat.version", &[("version", &version::short())]).color(Color::Cyan)
+    );
+    println!(
+        "{}",
+        i18n::tf("chat.model", &[("model", &models::model_for(Task::Main))]).color(Color::Cyan)
     );
     println!(
         "{}",
-        format!("Working in sandbox: {}", *SANDBOX_ROOT).color(Color::Cyan)
+        i18n::tf("chat.sandbox", &[("path", &*SANDBOX_ROOT)]).color(Color::Cyan)
     );
+    if project::current().is_some() {
+        println!(
+            "{}",
+            i18n::tf("chat.project", &[("path", &project::project_path().display())]).color(Color::Cyan)
+        );
+    }
+    if confirm::dry_run() {
+        println!("{}", i18n::t("chat.dry_run").color(Color::Yellow).bold());
+    }
     println!(
         "{}",
-        "Use !command to run shell commands directly (e.g., !ls or !dir). Use ! alone to enter interactive shell mode.".color(Color::Cyan)
+        i18n::t("chat.help").color(Color::Cyan)
     );
     println!();
+}
+
+/// The interactive REPL.
+fn run_chat(chat_manager: &Arc<Mutex<ChatManager>>, debug: bool) {
+    print_banner();
+
+    // The prompt is colored by the line editor, which measures the plain text for redraws
+    let mut editor: Editor<mentions::ReplHelper, DefaultHistory> = match Editor::with_config(history::config()) {
+        Ok(editor) => editor,
+        Err(e) => {
+            println!("{}", i18n::tf("chat.editor_error", &[("error", &e)]).color(Color::Red));
+            return;
+        }
+    };
+    editor.set_helper(Some(mentions::ReplHelper));
+    history::load(&mut editor);
+    // Set by Ctrl-C at the prompt; a second Ctrl-C in a row quits
+    let mut interrupted = false;
 
-    // Simple input handling for better Windows compatibility
     loop {
-        let conv_length: usize = {
-            let manager = chat_manager.lock().unwrap();
-            manager
-                .history
-                .iter()
-                .filter_map(|msg| {
-                    msg.get("parts")
-                        .and_then(|parts| parts.as_array())
-                        .map(|parts_array| {
-                            parts_array
-                                .iter()
-                                .filter_map(|part| {
-                                    part.get("text").and_then(|t| t.as_str()).map(|s| s.len())
-                                })
-                                .sum::<usize>()
-                        })
-                })
-                .sum()
+        title::set("idle");
+        let next_turn = chat_manager.lock().unwrap().turn + 1;
+        let label = if ui::timestamps_enabled() {
+            format!("{} ", ui::turn_label(next_turn, &Local::now(), "you"))
+        } else {
+            String::new()
+        };
+        let label = match persona::current() {
+            Some(preset) => format!("{}({}) ", label, preset.name),
+            None => label,
         };
 
-        let prompt = {
-            #[cfg(target_os = "windows")]
-            {
-                // On Windows, avoid colored prompts due to compatibility issues
-                format!("[{}] > ", conv_length)
+        let prompt = format!("{}[{}] > ", label, prompt_status(chat_manager));
+
+        match editor.readline(&prompt) {
+            Ok(line) => {
+                interrupted = false;
+                let line = if line.trim() == "/paste" {
+                    match multiline::read_paste(&mut editor) {
+                        Some(text) => text,
+                        None => continue,
+                    }
+                } else {
+                    multiline::join(&line)
+                };
+                let user_input = line.trim();
+                history::add(&mut editor, &line);
+
+                if !handle_input(user_input, chat_manager, debug) {
+                    break;
+                }
             }
-            #[cfg(not(target_os = "windows"))]
-            {
-                format!("[{}] > ", conv_length).color(Color::Green).bold().to_string()
+            Err(ReadlineError::Interrupted) => {
+                if interrupted {
+                    println!("{}", i18n::t("chat.goodbye").color(Color::Cyan).bold());
+                    break;
+                }
+                interrupted = true;
+                println!("{}", i18n::t("chat.interrupt_again").color(Color::Yellow));
+                continue;
             }
-        };
+            Err(ReadlineError::Eof) => {
+                println!("{}", i18n::t("chat.goodbye").color(Color::Cyan).bold());
+                break;
+            }
+            Err(e) => {
+                println!("{}", i18n::tf("chat.input_error", &[("error", &e)]).color(Color::Red));
+                continue;
+            }
+        }
+    }
 
-        print!("{}", prompt);
-        io::stdout().flush().expect("Failed to flush stdout");
+    chat_manager.lock().unwrap().cleanup();
+}
 
-        let mut user_input = String::new();
-        match io::stdin().read_line(&mut user_input) {
-            Ok(_) => {
-                let user_input = user_input.trim();
+/// The size of the conversation shown in the prompt, with its cost when known.
+fn prompt_status(chat_manager: &Arc<Mutex<ChatManager>>) -> String {
+    let context_tokens = chat_manager.lock().unwrap().context_tokens();
+    let status = i18n::tf("chat.prompt_tokens", &[("tokens", &locale::format_number(context_tokens))]);
+    match usage::prompt_cost() {
+        Some(cost) => format!("{}, {}", status, cost),
+        None => status,
+    }
+}
 
-                match user_input.to_lowercase().as_str() {
-                    "exit" => {
-                        println!("{}", "Goodbye!".color(Color::Cyan).bold());
-                        break;
-                    }
-                    "clear" => {
-                        chat_manager.lock().unwrap().create_chat();
-                        println!(
-                            "{}",
-                            "Conversation cleared! Starting fresh.".color(Color::Cyan)
-                        );
-                        println!();
-                        continue;
-                    }
-                    "" => {
-                        println!("{}", "Please enter a command or message.".color(Color::Red));
-                        println!();
-                        continue;
-                    }
-                    _ => {}
+/// Handles one line from the user: a slash command, a shell command or a message for the
+/// model. Returns false when the user asked to quit.
+fn handle_input(user_input: &str, chat_manager: &Arc<Mutex<ChatManager>>, debug: bool) -> bool {
+    match user_input.to_lowercase().as_str() {
+        "exit" => {
+            println!("{}", i18n::t("chat.goodbye").color(Color::Cyan).bold());
+            return false;
+        }
+        "clear" => {
+            chat_manager.lock().unwrap().create_chat();
+            command::reset_session();
+            println!(
+                "{}",
+                i18n::t("chat.cleared").color(Color::Cyan)
+            );
+            println!();
+            return true;
+        }
+        "" => {
+            println!("{}", i18n::t("chat.empty_input").color(Color::Red));
+            println!();
+            return true;
+        }
+        _ => {}
+    }
+
+    // The command word and what follows it, so that "/models" isn't "/model" with an argument
+    let (command, rest) = match user_input.split_once(char::is_whitespace) {
+        Some((command, rest)) => (command, rest.trim()),
+        None => (user_input, ""),
+    };
+
+    if command == "/save-last" {
+        let path = rest;
+        if path.is_empty() {
+            println!("{}", i18n::t("command.save_last_usage").color(Color::Red));
+        } else {
+            match save_last_answer(chat_manager, std::path::Path::new(path)) {
+                Ok(()) => println!("{}", i18n::tf("command.answer_saved", &[("path", &path)]).color(Color::Cyan)),
+                Err(e) => println!("{}", e.color(Color::Red)),
+            }
+        }
+        println!();
+        return true;
+    }
+
+    if command == "/save" {
+        let name = Some(rest).filter(|name| !name.is_empty());
+        if chat_manager.lock().unwrap().history.is_empty() {
+            println!("{}", i18n::t("command.nothing_to_save").color(Color::Red));
+        } else {
+            match chat_manager.lock().unwrap().save_session_as(name) {
+                Ok(name) => println!("{}", i18n::tf("chat.session_saved", &[("name", &name)]).color(Color::Cyan)),
+                Err(e) => println!("{}", e.color(Color::Red)),
+            }
+        }
+        println!();
+        return true;
+    }
+
+    if command == "/load" {
+        match rest {
+            "" => println!("{}", i18n::t("command.load_usage").color(Color::Red)),
+            name => match chat_manager.lock().unwrap().load_session(Some(name)) {
+                Ok(message) => println!("{}", message.color(Color::Cyan)),
+                Err(e) => println!("{}", e.color(Color::Red)),
+            },
+        }
+        println!();
+        return true;
+    }
+
+    if user_input == "/sessions" {
+        if let Err(e) = sessions::print_list() {
+            println!("{}", e.color(Color::Red));
+        }
+        println!();
+        return true;
+    }
+
+    if user_input == "/continue" {
+        let result = chat_manager
+            .lock()
+            .unwrap()
+            .send_message("Continue with the task where you left off.");
+        match result {
+            Ok(response) => {
+                display_response(&response, chat_manager);
+                if let Err(e) = process_tool_calls(&response, chat_manager, debug) {
+                    print_error("chat.tool_calls_error", &e);
                 }
+            }
+            Err(e) => print_error("chat.error", &e),
+        }
+        return true;
+    }
 
-                if user_input.starts_with('!') {
-                    let command = user_input[1..].trim();
-                    if command.is_empty() {
-                        let output = interactive_shell();
-                        let llm_input = format!("User ran interactive shell session with output:\n{}", output);
-                        match chat_manager.lock().unwrap().send_message(&llm_input) {
-                            Ok(response) => display_response(&response),
-                            Err(e) => println!("{}", format!("Error: {}", e).color(Color::Red)),
-                        }
-                    } else {
-                        let output = execute_command(command);
-                        println!(
-                            "{}",
-                            format!("Command output: {}", output).color(Color::Magenta)
-                        );
-                        let llm_input = format!("User ran command '!{}' with output: {}", command, output);
-                        match chat_manager.lock().unwrap().send_message(&llm_input) {
-                            Ok(response) => display_response(&response),
-                            Err(e) => println!("{}", format!("Error: {}", e).color(Color::Red)),
-                        }
-                    }
-                } else {
-                    let response = match chat_manager.lock().unwrap().send_message(user_input) {
-                        Ok(resp) => resp,
-                        Err(e) => {
-                            println!(
-                                "{}",
-                                format!("Error: A generative AI error occurred: {}", e).color(Color::Red)
-                            );
-                            continue;
-                        }
-                    };
+    if user_input == "/version" {
+        println!("{}", version::report());
+        println!();
+        return true;
+    }
+
+    if command == "/model" {
+        match rest {
+            "" => {
+                println!("{}", i18n::tf("chat.model", &[("model", &models::model_for(Task::Main))]).color(Color::Cyan));
+                for (alias, model) in models::aliases() {
+                    println!("  {:<12} {}", alias, model.dimmed());
+                }
+            }
+            name => {
+                let model = models::set_main(name);
+                println!("{}", i18n::tf("command.model_switched", &[("model", &model)]).color(Color::Cyan));
+            }
+        }
+        println!();
+        return true;
+    }
+
+    if user_input == "/compact" {
+        let result = chat_manager.lock().unwrap().compact(None);
+        match result {
+            Ok(message) => println!("{}", message.color(Color::Cyan)),
+            Err(e) => println!("{}", e.color(Color::Red)),
+        }
+        println!();
+        return true;
+    }
 
-                    display_response(&response);
+    if user_input == "/stats" {
+        let turns = chat_manager.lock().unwrap().turn;
+        println!("{}", stats::report(turns));
+        println!();
+        return true;
+    }
 
-                    if let Err(e) = process_tool_calls(&response, &chat_manager, args.debug) {
-                        println!("{}", format!("Error processing tool calls: {}", e).color(Color::Red));
-                    }
+    if command == "/persona" {
+        match rest {
+            "" => persona::print_list(),
+            "show" => match persona::describe() {
+                Some(description) => println!("{}", description),
+                None => println!("{}", i18n::t("command.no_persona")),
+            },
+            "off" | "none" => {
+                persona::set(None);
+                println!("{}", i18n::t("command.persona_cleared").color(Color::Cyan));
+            }
+            name => match persona::load(name) {
+                Ok(preset) => {
+                    println!("{}", i18n::tf("command.persona_switched", &[("name", &preset.name)]).color(Color::Cyan));
+                    persona::set(Some(preset));
                 }
+                Err(e) => println!("{}", e.color(Color::Red)),
+            },
+        }
+        println!();
+        return true;
+    }
+
+    if user_input == "/usage" {
+        println!("{}", usage::report());
+        println!();
+        return true;
+    }
+
+    if command == "/budget" {
+        match rest {
+            "" => println!("{}", usage::budget_status()),
+            "override" => {
+                usage::override_budget();
+                println!("{}", i18n::t("command.budget_lifted").color(Color::Yellow));
             }
-            Err(e) => {
-                println!("{}", format!("Input error: {}", e).color(Color::Red));
-                continue;
+            _ => println!("{}", i18n::t("command.budget_usage").color(Color::Red)),
+        }
+        println!();
+        return true;
+    }
+
+    if command == "/temp" {
+        if rest.is_empty() {
+            println!("{}", generation::report());
+        } else {
+            print_generation_change("temperature", rest);
+        }
+        println!();
+        return true;
+    }
+
+    if user_input == "/thoughts" {
+        match thoughts::last() {
+            Some(text) => println!("{}", ui::wrap_text(&text, ui::terminal_width()).dimmed().italic()),
+            None if generation::shows_thoughts() => println!("{}", i18n::t("command.no_thoughts")),
+            None => println!("{}", i18n::t("command.thoughts_off")),
+        }
+        println!();
+        return true;
+    }
+
+    if command == "/gen" {
+        let words: Vec<&str> = rest.split_whitespace().collect();
+        match words.as_slice() {
+            [] => println!("{}", generation::report()),
+            ["reset"] => {
+                let _ = generation::reset(None);
+                println!("{}", i18n::t("command.generation_reset").color(Color::Cyan));
             }
+            [name, value @ ..] if !value.is_empty() => print_generation_change(name, &value.join(" ")),
+            _ => println!("{}", i18n::t("command.generation_usage").color(Color::Red)),
+        }
+        println!();
+        return true;
+    }
+
+    if command == "/audit" {
+        match rest {
+            "" => println!("{}", audit::report(None)),
+            count => match count.parse() {
+                Ok(count) => println!("{}", audit::report(Some(count))),
+                Err(_) => println!("{}", i18n::t("command.audit_usage").color(Color::Red)),
+            },
+        }
+        println!();
+        return true;
+    }
+
+    if command == "/index" {
+        match rest {
+            "" => println!("{}", index::report()),
+            "rebuild" => println!("{}", index::rebuild()),
+            _ => println!("{}", i18n::t("command.index_usage").color(Color::Red)),
+        }
+        println!();
+        return true;
+    }
+
+    if command == "/image" {
+        match rest {
+            "" => {
+                let pending = images::pending();
+                if pending.is_empty() {
+                    println!("{}", i18n::t("command.image_usage").color(Color::Cyan));
+                } else {
+                    println!("{}", i18n::tf("command.images_pending", &[("images", &pending.join(", "))]).color(Color::Cyan));
+                }
+            }
+            "clear" => {
+                images::clear();
+                println!("{}", i18n::t("command.images_cleared").color(Color::Cyan));
+            }
+            path => match images::attach(path) {
+                Ok(image) => println!("{}", i18n::tf("command.image_attached", &[("image", &image)]).color(Color::Cyan)),
+                Err(e) => println!("{}", e.color(Color::Red)),
+            },
+        }
+        println!();
+        return true;
+    }
+
+    if user_input == "/undo" {
+        let undone = chat_manager.lock().unwrap().undo_last_turn();
+        match undone {
+            Some(turn) => {
+                println!("{}", i18n::tf("command.turn_removed", &[("turn", &turn)]).color(Color::Cyan));
+                match snapshot::undo_turn(turn) {
+                    Ok(Some(message)) => println!("{}", message.color(Color::Cyan)),
+                    Ok(None) => println!("{}", i18n::t("command.turn_without_edits").color(Color::Cyan)),
+                    Err(e) => println!("{}", i18n::tf("command.revert_failed", &[("error", &e)]).color(Color::Red)),
+                }
+            }
+            None => println!("{}", i18n::t("command.nothing_to_undo").color(Color::Red)),
+        }
+        println!();
+        return true;
+    }
+
+    if user_input == "/undo-edit" {
+        match backups::undo_last_edit() {
+            Ok(message) => println!("{}", message.color(Color::Cyan)),
+            Err(e) => println!("{}", e.color(Color::Red)),
         }
+        println!();
+        return true;
+    }
+
+    if command == "/restore" {
+        match rest {
+            "" => snapshot::print_list(),
+            number => match number.parse::<usize>() {
+                Ok(number) => match snapshot::restore(number) {
+                    Ok(message) => println!("{}", message.color(Color::Cyan)),
+                    Err(e) => println!("{}", e.color(Color::Red)),
+                },
+                Err(_) => println!("{}", i18n::t("command.restore_usage").color(Color::Red)),
+            },
+        }
+        println!();
+        return true;
+    }
+
+    if command == "/export" {
+        let mut export_args = rest.split_whitespace();
+        let format = export_args.next().unwrap_or("md");
+        let path = export_args.next();
+        let result = export::write_transcript(&chat_manager.lock().unwrap().history, format, path);
+        match result {
+            Ok(path) => println!("{}", i18n::tf("command.exported", &[("path", &path.display())]).color(Color::Cyan)),
+            Err(e) => println!("{}", e.color(Color::Red)),
+        }
+        println!();
+        return true;
+    }
+
+    if command == "/share" {
+        let words: Vec<&str> = rest.split_whitespace().collect();
+        let upload = words.contains(&"gist");
+        // Gists render Markdown, a local file is easiest to open as HTML
+        let default_format = if upload { "md" } else { "html" };
+        let format = words.iter().find(|w| **w != "gist").copied().unwrap_or(default_format);
+        if upload {
+            println!("{}", i18n::t("command.uploading_gist").color(Color::Cyan));
+        }
+        let result = share::share(&chat_manager.lock().unwrap().history, format, upload);
+        match result {
+            Ok(message) => println!("{}", message.color(Color::Cyan)),
+            Err(e) => println!("{}", e.color(Color::Red)),
+        }
+        println!();
+        return true;
     }
 
-    chat_manager.lock().unwrap().cleanup(false);
+    // "!{command} ..." is a message with command substitution, not a shell command
+    if let Some(command) = user_input.strip_prefix('!').filter(|_| !user_input.starts_with("!{")) {
+        let command = command.trim();
+        if command.is_empty() {
+            let output = interactive_shell();
+            let output = redact::text_before_sending(output, "the shell session output");
+            let llm_input = format!("User ran interactive shell session with output:\n{}", output);
+            let result = chat_manager.lock().unwrap().send_message(&llm_input);
+            match result {
+                Ok(response) => display_response(&response, chat_manager),
+                Err(e) => print_error("chat.error", &e),
+            }
+        } else {
+            title::set(&format!("running {}", command));
+            let output = execute_command(command);
+            println!(
+                "{}",
+                i18n::tf("chat.command_output", &[("output", &output)]).color(Color::Magenta)
+            );
+            let output = redact::text_before_sending(output, "the command output");
+            let llm_input = format!("User ran command '!{}' with output: {}", command, output);
+            let result = chat_manager.lock().unwrap().send_message(&llm_input);
+            match result {
+                Ok(response) => display_response(&response, chat_manager),
+                Err(e) => print_error("chat.error", &e),
+            }
+        }
+    } else {
+        let (message, mut attached) = match mentions::expand(user_input) {
+            Ok(expanded) => expanded,
+            Err(e) => {
+                println!("{}", e.color(Color::Red));
+                println!();
+                return true;
+            }
+        };
+        attached.splice(0..0, images::take());
+        if let Some(related) = index::context_for(&message, &attached) {
+            attached.push(related);
+        }
+        let result = chat_manager.lock().unwrap().send_message_with(&message, attached);
+        let response = match result {
+            Ok(resp) => resp,
+            Err(e) => {
+                print_error("chat.ai_error", &e);
+                return true;
+            }
+        };
+
+        display_response(&response, chat_manager);
+
+        if let Err(e) = process_tool_calls(&response, chat_manager, debug) {
+            print_error("chat.tool_calls_error", &e);
+        }
+    }
+    true
 }
@@ -0,0 +1,172 @@
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Instant;
+
+use crate::audit;
+use crate::command::execute_command;
+use crate::file_edit::{file_editor, read_file};
+use crate::fs_tools::{list_files, search_codebase};
+use crate::plugins;
+use crate::external_tools;
+use crate::search::{scrape_url, search_online};
+use crate::tools;
+use crate::ui;
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+// Tools exposed to MCP clients, along with plugin tools. Email and finance tools stay local to the chat.
+const EXPOSED_TOOLS: &[&str] = &["execute_command", "file_editor", "list_files", "search_codebase", "search_online", "scrape_url"];
+
+/// Runs a Model Context Protocol server over stdio (newline-delimited JSON-RPC 2.0),
+/// exposing the sandboxed tools to other AI clients.
+pub fn serve() {
+    ui::reserve_stdout();
+    eprintln!("gemini-cli MCP server listening on stdio");
+
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => handle_request(&request),
+            Err(e) => Some(error_response(Value::Null, -32700, &format!("Parse error: {}", e))),
+        };
+
+        if let Some(response) = response {
+            let mut stdout = io::stdout().lock();
+            writeln!(stdout, "{}", response).ok();
+            stdout.flush().ok();
+        }
+    }
+}
+
+/// Handles a single JSON-RPC message. Notifications (no id) get no response.
+fn handle_request(request: &Value) -> Option<Value> {
+    let id = request.get("id").cloned();
+    let method = request.get("method").and_then(|m| m.as_str()).unwrap_or("");
+    let params = request.get("params").cloned().unwrap_or(json!({}));
+
+    let result = match method {
+        "initialize" => Ok(json!({
+            "protocolVersion": params
+                .get("protocolVersion")
+                .and_then(|v| v.as_str())
+                .unwrap_or(PROTOCOL_VERSION),
+            "capabilities": {"tools": {}},
+            "serverInfo": {
+                "name": "gemini-cli-rs",
+                "version": env!("CARGO_PKG_VERSION")
+            }
+        })),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({"tools": list_tools()})),
+        "tools/call" => {
+            let name = params.get("name").and_then(|n| n.as_str()).unwrap_or("");
+            let args = params.get("arguments").cloned().unwrap_or(json!({}));
+            if !EXPOSED_TOOLS.contains(&name) && !plugins::provides(name) && !external_tools::provides(name) {
+                Err((-32602, format!("Unknown tool: {}", name)))
+            } else {
+                let started = Instant::now();
+                let result = call_tool(name, &args);
+                audit::record("mcp", name, &args, &result, started.elapsed());
+                let (text, is_error) = match result {
+                    Ok(output) => {
+                        let is_error = ui::looks_like_failure(&output);
+                        (output, is_error)
+                    }
+                    Err(e) => (e, true),
+                };
+                Ok(json!({
+                    "content": [{"type": "text", "text": text}],
+                    "isError": is_error
+                }))
+            }
+        }
+        _ if method.starts_with("notifications/") => return None,
+        _ => Err((-32601, format!("Method not found: {}", method))),
+    };
+
+    // Notifications never get a response, even on error
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err((code, message)) => error_response(id, code, &message),
+    })
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": code, "message": message}
+    })
+}
+
+fn list_tools() -> Vec<Value> {
+    tools::function_declarations()
+        .into_iter()
+        .filter(|decl| {
+            decl.get("name")
+                .and_then(|n| n.as_str())
+                .map(|n| EXPOSED_TOOLS.contains(&n) || plugins::provides(n) || external_tools::provides(n))
+                .unwrap_or(false)
+        })
+        .map(|decl| {
+            json!({
+                "name": decl["name"],
+                "description": decl["description"],
+                "inputSchema": decl["parameters"]
+            })
+        })
+        .collect()
+}
+
+fn call_tool(name: &str, args: &Value) -> Result<String, String> {
+    let get = |key: &str| args.get(key).and_then(|v| v.as_str());
+
+    // Tools may panic on missing configuration; keep the server alive and report it instead
+    panic::catch_unwind(AssertUnwindSafe(|| match name {
+        "execute_command" => get("command")
+            .map(execute_command)
+            .ok_or_else(|| "Missing 'command' parameter".to_string()),
+        "search_online" => get("query")
+            .map(search_online)
+            .ok_or_else(|| "Missing 'query' parameter".to_string()),
+        "scrape_url" => get("url")
+            .map(scrape_url)
+            .ok_or_else(|| "Missing 'url' parameter".to_string()),
+        "file_editor" => match (get("subcommand"), get("filename")) {
+            (Some("read"), Some(fname)) => Ok(read_file(fname, number(args, "start_line"), number(args, "end_line"))),
+            (Some(subcmd), Some(fname)) => Ok(file_editor(subcmd, fname, get("data"), get("replacement"))),
+            _ => Err("Missing required parameters 'subcommand' or 'filename'".to_string()),
+        },
+        "list_files" => list_files(get("path"), get("pattern"), number(args, "depth")),
+        "search_codebase" => match get("query") {
+            Some(query) => search_codebase(
+                query,
+                args.get("regex").and_then(|r| r.as_bool()).unwrap_or(false),
+                get("path"),
+                get("glob"),
+                number(args, "context"),
+                number(args, "max_per_file"),
+            ),
+            None => Err("Missing 'query' parameter".to_string()),
+        },
+        _ if plugins::provides(name) => plugins::call(name, args),
+        _ if external_tools::provides(name) => external_tools::call(name, args),
+        _ => Err(format!("Unknown tool: {}", name)),
+    }))
+    .unwrap_or_else(|_| Err(format!("Tool '{}' failed unexpectedly (check its configuration)", name)))
+}
+
+/// A non-negative integer argument, such as a line number or a depth.
+fn number(args: &Value, key: &str) -> Option<usize> {
+    args.get(key).and_then(|v| v.as_u64()).map(|v| v as usize)
+}
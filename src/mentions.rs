@@ -0,0 +1,380 @@
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+
+use crate::command::{self, execute_command};
+use crate::images;
+use crate::multiline;
+use crate::redact;
+use crate::search;
+use crate::ui;
+use crate::SANDBOX_ROOT;
+
+// Largest file (or line range) that is inlined without asking for a narrower range
+const MAX_FILE_BYTES: usize = 100_000;
+// A NUL byte in this many leading bytes marks a file as binary
+const BINARY_SNIFF_BYTES: usize = 8_000;
+// Total size of all inlined files in one message
+const MAX_TOTAL_BYTES: usize = 300_000;
+
+// Piped input attached to a single prompt (GEMINI_STDIN_MAX_BYTES); longer input is cut in the middle
+const DEFAULT_STDIN_BYTES: usize = 200_000;
+
+// "@path" or "@path:10-40" at the start of the input or after whitespace
+static MENTION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:^|\s)@([^\s:]+)(?::(\d+)(?:-(\d+))?)?").unwrap());
+// "@https://..." at the start of the input or after whitespace
+static URL_MENTION: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|\s)@(https?://\S+)").unwrap());
+
+/// Replaces every `!{command}` in the input with the command's output, and returns the
+/// message with a context part for every `@file` and `@url` reference: the file's or page's
+/// text in a code fence, or the image itself. Words starting with @ that aren't files in the
+/// sandbox are left alone.
+pub fn expand(input: &str) -> Result<(String, Vec<Value>), String> {
+    let mut parts = Vec::new();
+    let mut labels: Vec<String> = Vec::new();
+    let (message, mut total) = substitute_commands(input)?;
+
+    for caps in MENTION.captures_iter(input) {
+        let path = &caps[1];
+        // "@https://..." is a page reference, handled below
+        if input[caps.get(1).unwrap().end()..].starts_with("://") {
+            continue;
+        }
+        let resolved = match resolve(path) {
+            Some(resolved) => resolved,
+            None => continue,
+        };
+        if images::media_type(path).is_some() {
+            if !labels.iter().any(|label| label == path) {
+                parts.push(images::inline_part(path)?);
+                labels.push(path.to_string());
+                ui::status(&format!("Attached {}", path).dimmed().to_string());
+            }
+            continue;
+        }
+        let content = read_text(&resolved, path)?;
+
+        let (content, label) = match caps.get(2) {
+            Some(start) => {
+                let start: usize = start.as_str().parse().unwrap_or(1).max(1);
+                let end: usize = caps
+                    .get(3)
+                    .and_then(|e| e.as_str().parse().ok())
+                    .unwrap_or(start);
+                if end < start {
+                    return Err(format!("Invalid line range in @{}:{}-{}", path, start, end));
+                }
+                let selected: Vec<&str> = content.lines().skip(start - 1).take(end - start + 1).collect();
+                if selected.is_empty() {
+                    return Err(format!("@{} has fewer than {} lines", path, start));
+                }
+                (selected.join("\n"), format!("{} (lines {}-{})", path, start, start + selected.len() - 1))
+            }
+            None => (content, path.to_string()),
+        };
+
+        if content.len() > MAX_FILE_BYTES {
+            return Err(format!(
+                "@{} is {} KB, more than the {} KB limit. Reference a line range instead, e.g. @{}:1-200",
+                path,
+                content.len() / 1000,
+                MAX_FILE_BYTES / 1000,
+                path
+            ));
+        }
+        total += content.len();
+        if total > MAX_TOTAL_BYTES {
+            return Err(format!(
+                "The referenced files add up to more than {} KB. Reference fewer files or line ranges.",
+                MAX_TOTAL_BYTES / 1000
+            ));
+        }
+
+        // The same file mentioned twice is included once
+        if labels.contains(&label) {
+            continue;
+        }
+        ui::status(&format!("Inlined {}", label).dimmed().to_string());
+        parts.push(json!({"text": format!("File: {}\n{}", label, fence(&content, language(path)))}));
+        labels.push(label);
+    }
+
+    for caps in URL_MENTION.captures_iter(input) {
+        // Punctuation right after a link belongs to the sentence, not the URL
+        let url = caps[1].trim_end_matches(['.', ',', ';', ':', '!', '?', ')']);
+        if labels.iter().any(|label| label == url) {
+            continue;
+        }
+        let text = search::scrape_url(url);
+        if text.starts_with("Skipped:") || text.starts_with("Error ") {
+            return Err(format!("Cannot inline @{}: {}", url, text));
+        }
+        let text = truncate(text, "page");
+        total += text.len();
+        if total > MAX_TOTAL_BYTES {
+            return Err(format!(
+                "The referenced files and pages add up to more than {} KB. Reference fewer of them.",
+                MAX_TOTAL_BYTES / 1000
+            ));
+        }
+
+        ui::status(&format!("Inlined {}", url).dimmed().to_string());
+        parts.push(json!({"text": format!("Page: {}\n{}", url, fence(&text, ""))}));
+        labels.push(url.to_string());
+    }
+
+    Ok((message, parts))
+}
+
+/// A referenced file as text. Binary files are refused, and text that isn't UTF-8 is decoded
+/// from its detected encoding.
+fn read_text(resolved: &Path, path: &str) -> Result<String, String> {
+    let bytes = fs::read(resolved).map_err(|e| format!("Cannot inline @{}: {}", path, e))?;
+    if bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+        return Err(format!(
+            "@{} is a binary file. Only text files and images (PNG, JPEG, WebP, HEIC, HEIF) can be referenced.",
+            path
+        ));
+    }
+    Ok(command::decode_output(&bytes))
+}
+
+/// Runs each `!{command}` and splices its output into the text. Braces inside the
+/// command are allowed as long as they are balanced, e.g. `!{awk '{print $1}' log}`.
+/// Returns the new text and the number of bytes of output inserted.
+fn substitute_commands(input: &str) -> Result<(String, usize), String> {
+    let mut output = String::new();
+    let mut total = 0;
+    let mut rest = input;
+
+    while let Some(start) = rest.find("!{") {
+        output.push_str(&rest[..start]);
+        let body = &rest[start + 2..];
+        let mut depth = 1;
+        let end = body
+            .char_indices()
+            .find(|&(_, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .map(|(i, _)| i)
+            .ok_or_else(|| "Unclosed !{ in message".to_string())?;
+
+        let command = body[..end].trim();
+        if command.is_empty() {
+            return Err("Empty !{} in message".to_string());
+        }
+        ui::status(&format!("Running {}", command).dimmed().to_string());
+        let result = truncate(execute_command(command), "output");
+        total += result.len();
+        if total > MAX_TOTAL_BYTES {
+            return Err(format!(
+                "Command output adds up to more than {} KB. Narrow the commands down, e.g. with | tail -50.",
+                MAX_TOTAL_BYTES / 1000
+            ));
+        }
+        output.push_str(&format!("\n$ {}\n{}\n", command, fence(&result, "")));
+        rest = &body[end + 1..];
+    }
+    output.push_str(rest);
+    Ok((output, total))
+}
+
+/// Combines a single prompt with what was piped into the CLI, as in `cat error.log | gemini-cli-rs
+/// -p "explain this failure"`. Piped input without a prompt is the prompt.
+pub fn with_piped_input(prompt: Option<String>) -> Result<String, String> {
+    let input = piped_input()?.map(|input| redact::text_before_sending(input, "the piped input"));
+    match (prompt, input) {
+        (Some(prompt), Some(input)) => {
+            ui::status(&"Attached the input piped on stdin".dimmed().to_string());
+            Ok(format!("{}\n\nInput piped to the CLI:\n{}", prompt, fence(&input, "")))
+        }
+        (Some(prompt), None) => Ok(prompt),
+        (None, Some(input)) => Ok(input),
+        (None, None) => Err("No prompt given: pass it as an argument or pipe it on stdin.".to_string()),
+    }
+}
+
+/// Reads stdin to the end when it isn't a terminal. Input longer than GEMINI_STDIN_MAX_BYTES
+/// keeps its first quarter and its end, where logs usually show what went wrong.
+fn piped_input() -> Result<Option<String>, String> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return Ok(None);
+    }
+    let mut bytes = Vec::new();
+    stdin
+        .lock()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Error reading stdin: {}", e))?;
+    let text = String::from_utf8_lossy(&bytes).into_owned();
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let limit = env::var("GEMINI_STDIN_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|&limit: &usize| limit > 0)
+        .unwrap_or(DEFAULT_STDIN_BYTES);
+    if text.len() <= limit {
+        return Ok(Some(text));
+    }
+    let mut head_end = limit / 4;
+    while !text.is_char_boundary(head_end) {
+        head_end -= 1;
+    }
+    let mut tail_start = text.len() - (limit - head_end);
+    while !text.is_char_boundary(tail_start) {
+        tail_start += 1;
+    }
+    // Whole lines read better than cut ones
+    let head_end = text[..head_end].rfind('\n').map(|i| i + 1).unwrap_or(head_end);
+    let tail_start = text[tail_start..].find('\n').map(|i| tail_start + i + 1).filter(|&i| i < text.len()).unwrap_or(tail_start);
+    Ok(Some(format!(
+        "{}[... {} bytes of piped input left out ...]\n{}",
+        &text[..head_end],
+        tail_start - head_end,
+        &text[tail_start..]
+    )))
+}
+
+/// Cuts text down to MAX_FILE_BYTES, noting what was cut.
+fn truncate(mut text: String, what: &str) -> String {
+    if text.len() > MAX_FILE_BYTES {
+        let mut cut = MAX_FILE_BYTES;
+        while !text.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        text.truncate(cut);
+        text.push_str(&format!("\n[{} truncated]", what));
+    }
+    text
+}
+
+/// Resolves a referenced path to a file inside the sandbox.
+fn resolve(path: &str) -> Option<PathBuf> {
+    let resolved = Path::new(&*SANDBOX_ROOT).join(path).canonicalize().ok()?;
+    let root = Path::new(&*SANDBOX_ROOT).canonicalize().ok()?;
+    if resolved.starts_with(&root) && resolved.is_file() {
+        Some(resolved)
+    } else {
+        None
+    }
+}
+
+/// Wraps content in a code fence longer than any backtick run inside it.
+fn fence(content: &str, lang: &str) -> String {
+    let mut ticks = 3;
+    while content.contains(&"`".repeat(ticks)) {
+        ticks += 1;
+    }
+    let fence = "`".repeat(ticks);
+    format!("{}{}\n{}\n{}", fence, lang, content.trim_end_matches('\n'), fence)
+}
+
+fn language(path: &str) -> &'static str {
+    match Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("") {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" | "tsx" => "typescript",
+        "go" => "go",
+        "java" => "java",
+        "c" | "h" => "c",
+        "cpp" | "cc" | "hpp" => "cpp",
+        "sh" | "bash" => "bash",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "md" => "markdown",
+        "html" => "html",
+        "css" => "css",
+        "sql" => "sql",
+        _ => "",
+    }
+}
+
+/// rustyline helper that completes `@path` references and colors the prompt. rustyline
+/// only asks for the colored prompt when the terminal handles ANSI escapes (on Windows,
+/// after enabling virtual terminal processing), and lays out the line from the plain text.
+pub struct ReplHelper;
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let word_start = before.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+        let word = &before[word_start..];
+        let partial = match word.strip_prefix('@') {
+            Some(partial) => partial,
+            None => return Ok((pos, Vec::new())),
+        };
+
+        // Split "src/ma" into the directory to list and the prefix to match
+        let (dir, prefix) = match partial.rfind('/') {
+            Some(i) => (&partial[..=i], &partial[i + 1..]),
+            None => ("", partial),
+        };
+        let entries = match fs::read_dir(Path::new(&*SANDBOX_ROOT).join(dir)) {
+            Ok(entries) => entries,
+            Err(_) => return Ok((pos, Vec::new())),
+        };
+        let mut candidates: Vec<Pair> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                    return None;
+                }
+                let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                let suffix = if is_dir { "/" } else { "" };
+                Some(Pair {
+                    display: format!("{}{}", name, suffix),
+                    replacement: format!("@{}{}{}", dir, name, suffix),
+                })
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        Ok((word_start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(&'s self, prompt: &'p str, _default: bool) -> Cow<'b, str> {
+        Cow::Owned(prompt.color(Color::Green).bold().to_string())
+    }
+}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if multiline::is_incomplete(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Helper for ReplHelper {}
@@ -0,0 +1,112 @@
+use once_cell::sync::Lazy;
+use std::env;
+use std::sync::Mutex;
+
+use crate::providers;
+
+// Cheaper, faster model used for internal tasks unless routed elsewhere
+const AUXILIARY_MODEL: &str = "gemini-2.5-flash-lite";
+
+// Short names accepted by --model and /model
+const ALIASES: &[(&str, &str)] = &[
+    ("pro", "gemini-2.5-pro"),
+    ("flash", "gemini-2.5-flash"),
+    ("flash-lite", "gemini-2.5-flash-lite"),
+];
+
+// The conversation model: --model, /model, GEMINI_MODEL, or the provider's default
+static MAIN_MODEL: Lazy<Mutex<String>> = Lazy::new(|| {
+    Mutex::new(
+        env::var("GEMINI_MODEL")
+            .ok()
+            .map(|m| resolve(&m))
+            .filter(|m| !m.is_empty())
+            .unwrap_or_else(|| providers::current().default_model().to_string()),
+    )
+});
+
+/// What a model request is for. The conversation itself uses the main model (GEMINI_MODEL,
+/// --model or /model); internal tasks are routed through GEMINI_MODEL_ROUTES.
+#[derive(Clone, Copy)]
+pub enum Task {
+    /// The conversation and user-facing generation (commit messages, reviews)
+    Main,
+    /// Shrinking oversized tool output before it reaches the conversation
+    Condense,
+    /// Rewording a web search that came back empty
+    QueryExpansion,
+    /// Summarizing earlier turns to make room in the context window
+    Compact,
+}
+
+impl Task {
+    fn key(self) -> &'static str {
+        match self {
+            Task::Main => "main",
+            Task::Condense => "condense",
+            Task::QueryExpansion => "query_expansion",
+            Task::Compact => "compact",
+        }
+    }
+}
+
+/// Picks the model for a task. GEMINI_MODEL_ROUTES is a comma-separated list of
+/// task=model pairs; "auxiliary=..." sets the default for every internal task. With other
+/// providers than Gemini, unrouted tasks use the main model.
+pub fn model_for(task: Task) -> String {
+    if let Task::Main = task {
+        return MAIN_MODEL.lock().unwrap().clone();
+    }
+    let routes = routes();
+    let lookup = |key: &str| {
+        routes
+            .iter()
+            .find(|(task, _)| task == key)
+            .map(|(_, model)| model.clone())
+    };
+    lookup(task.key())
+        .or_else(|| lookup("auxiliary"))
+        .unwrap_or_else(|| match providers::current().name() {
+            "gemini" => AUXILIARY_MODEL.to_string(),
+            _ => MAIN_MODEL.lock().unwrap().clone(),
+        })
+}
+
+/// Switches the conversation model; short names like "pro" are expanded. Returns the full name.
+pub fn set_main(model: &str) -> String {
+    let model = resolve(model);
+    *MAIN_MODEL.lock().unwrap() = model.clone();
+    model
+}
+
+/// The short names and the models they stand for, for `/model` without an argument.
+pub fn aliases() -> &'static [(&'static str, &'static str)] {
+    ALIASES
+}
+
+fn resolve(model: &str) -> String {
+    let model = model.trim();
+    ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(model))
+        .map(|(_, full)| full.to_string())
+        .unwrap_or_else(|| model.to_string())
+}
+
+fn routes() -> Vec<(String, String)> {
+    env::var("GEMINI_MODEL_ROUTES")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(task, model)| (task.trim().to_lowercase(), model.trim().to_string()))
+        .filter(|(task, model)| !task.is_empty() && !model.is_empty())
+        .collect()
+}
+
+/// Model to switch to when the routed model keeps failing with 429/5xx, from GEMINI_FALLBACK_MODEL.
+pub fn fallback_model() -> Option<String> {
+    env::var("GEMINI_FALLBACK_MODEL")
+        .ok()
+        .map(|m| m.trim().to_string())
+        .filter(|m| !m.is_empty())
+}
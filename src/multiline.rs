@@ -0,0 +1,45 @@
+use colored::{Color, Colorize};
+use rustyline::history::DefaultHistory;
+use rustyline::{Editor, Helper};
+
+use crate::i18n;
+
+// Prompt shown for the lines of a /paste block
+const PASTE_PROMPT: &str = "… ";
+// Line that ends a /paste block
+const PASTE_END: &str = ".";
+
+/// Whether Enter should start a new line instead of sending: the input ends with a
+/// backslash, or a ``` code block is still open.
+pub fn is_incomplete(input: &str) -> bool {
+    let open_fences = input
+        .lines()
+        .filter(|line| line.trim_start().starts_with("```"))
+        .count();
+    input.ends_with('\\') || open_fences % 2 == 1
+}
+
+/// The message for an input typed over several lines: backslash continuations are removed,
+/// the line breaks are kept.
+pub fn join(input: &str) -> String {
+    input.replace("\\\r\n", "\n").replace("\\\n", "\n")
+}
+
+/// Reads a block of lines for /paste until a line with a single "." or Ctrl-D. Returns None
+/// when the block is abandoned with Ctrl-C.
+pub fn read_paste<H: Helper>(editor: &mut Editor<H, DefaultHistory>) -> Option<String> {
+    println!("{}", i18n::tf("command.paste_started", &[("end", &PASTE_END)]).color(Color::Cyan));
+    let mut lines = Vec::new();
+    loop {
+        match editor.readline(PASTE_PROMPT) {
+            Ok(line) if line.trim() == PASTE_END => break,
+            Ok(line) => lines.push(line),
+            Err(rustyline::error::ReadlineError::Interrupted) => {
+                println!("{}", i18n::t("command.paste_cancelled").color(Color::Yellow));
+                return None;
+            }
+            Err(_) => break,
+        }
+    }
+    Some(lines.join("\n"))
+}
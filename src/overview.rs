@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use crate::SANDBOX_ROOT;
+
+// Most entries listed from the top level of the sandbox
+const MAX_ENTRIES: usize = 40;
+
+// Files that mark a code project, and what they say about it
+const BUILD_FILES: &[(&str, &str)] = &[
+    ("Cargo.toml", "Rust (Cargo)"),
+    ("package.json", "JavaScript/TypeScript (npm)"),
+    ("pyproject.toml", "Python (pyproject)"),
+    ("setup.py", "Python (setuptools)"),
+    ("requirements.txt", "Python (pip)"),
+    ("go.mod", "Go (modules)"),
+    ("pom.xml", "Java (Maven)"),
+    ("build.gradle", "Java/Kotlin (Gradle)"),
+    ("build.gradle.kts", "Kotlin (Gradle)"),
+    ("CMakeLists.txt", "C/C++ (CMake)"),
+    ("Makefile", "Make"),
+    ("Gemfile", "Ruby (Bundler)"),
+    ("composer.json", "PHP (Composer)"),
+    ("mix.exs", "Elixir (Mix)"),
+];
+
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules", ".venv", "venv", "__pycache__", "dist", "build", ".git"];
+
+/// A compact description of the project in the sandbox (build system, main languages,
+/// README headline, top-level layout) for the system instruction, so the model doesn't
+/// have to explore before its first answer. None outside code projects or with
+/// GEMINI_PROJECT_OVERVIEW=0.
+pub fn build() -> Option<String> {
+    let disabled = env::var("GEMINI_PROJECT_OVERVIEW")
+        .map(|v| v == "0" || v == "false")
+        .unwrap_or(false);
+    let root = Path::new(&*SANDBOX_ROOT);
+    if disabled || !is_code_project(root) {
+        return None;
+    }
+    let build_systems = build_systems(root);
+
+    let mut lines = Vec::new();
+    if !build_systems.is_empty() {
+        lines.push(format!("Build system: {}", build_systems.join(", ")));
+    }
+    let languages = languages(root);
+    if !languages.is_empty() {
+        lines.push(format!("Main languages: {}", languages.join(", ")));
+    }
+    if let Some(headline) = readme_headline(root) {
+        lines.push(format!("README: {}", headline));
+    }
+    lines.push(format!("Top level:\n{}", top_level(root)));
+    Some(lines.join("\n"))
+}
+
+/// A directory with a build file or a git repository.
+pub fn is_code_project(root: &Path) -> bool {
+    !build_systems(root).is_empty() || root.join(".git").exists()
+}
+
+fn build_systems(root: &Path) -> Vec<&'static str> {
+    BUILD_FILES
+        .iter()
+        .filter(|(file, _)| root.join(file).exists())
+        .map(|(_, description)| *description)
+        .collect()
+}
+
+fn top_level(root: &Path) -> String {
+    let mut entries: Vec<(bool, String)> = match fs::read_dir(root) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| {
+                let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                (is_dir, entry.file_name().to_string_lossy().to_string())
+            })
+            .filter(|(_, name)| !name.starts_with('.'))
+            .collect(),
+        Err(_) => return String::new(),
+    };
+    // Directories first, then files, each alphabetically
+    entries.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+    let total = entries.len();
+    let mut listing: Vec<String> = entries
+        .into_iter()
+        .take(MAX_ENTRIES)
+        .map(|(is_dir, name)| {
+            if !is_dir {
+                return format!("  {}", name);
+            }
+            let count = fs::read_dir(root.join(&name)).map(|d| d.count()).unwrap_or(0);
+            format!("  {}/ ({} entries)", name, count)
+        })
+        .collect();
+    if total > MAX_ENTRIES {
+        listing.push(format!("  ... and {} more", total - MAX_ENTRIES));
+    }
+    listing.join("\n")
+}
+
+/// The three most common source languages by file count, two directory levels deep.
+fn languages(root: &Path) -> Vec<String> {
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+    count_languages(root, 0, &mut counts);
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    counts.into_iter().take(3).map(|(language, _)| language.to_string()).collect()
+}
+
+fn count_languages(dir: &Path, depth: usize, counts: &mut HashMap<&'static str, usize>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        if is_dir {
+            if depth < 2 && !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str()) {
+                count_languages(&entry.path(), depth + 1, counts);
+            }
+        } else if let Some(language) = language(&name) {
+            *counts.entry(language).or_default() += 1;
+        }
+    }
+}
+
+fn language(file_name: &str) -> Option<&'static str> {
+    let extension = Path::new(file_name).extension()?.to_str()?;
+    Some(match extension {
+        "rs" => "Rust",
+        "py" => "Python",
+        "js" | "mjs" | "cjs" | "jsx" => "JavaScript",
+        "ts" | "tsx" => "TypeScript",
+        "go" => "Go",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "c" | "h" => "C",
+        "cpp" | "cc" | "hpp" => "C++",
+        "cs" => "C#",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "swift" => "Swift",
+        "ex" | "exs" => "Elixir",
+        "sh" => "Shell",
+        _ => return None,
+    })
+}
+
+/// First heading (or first line) of the README, plus the paragraph after it when short.
+fn readme_headline(root: &Path) -> Option<String> {
+    let readme = fs::read_dir(root)
+        .ok()?
+        .flatten()
+        .find(|entry| entry.file_name().to_string_lossy().to_lowercase().starts_with("readme"))?;
+    let text = fs::read_to_string(readme.path()).ok()?;
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with("[!["));
+    let title = lines.next()?.trim_start_matches('#').trim().to_string();
+    let summary = lines
+        .find(|l| !l.starts_with('#') && !l.starts_with("```"))
+        .filter(|l| l.len() <= 200);
+    Some(match summary {
+        Some(summary) => format!("{} - {}", title, summary),
+        None => title,
+    })
+}
@@ -0,0 +1,234 @@
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// A named preset that adds to (or replaces) the system instruction and narrows the tools
+/// offered.
+#[derive(Clone)]
+pub struct Persona {
+    pub name: String,
+    pub prompt: String,
+    /// The prompt replaces the default instructions instead of following them
+    pub replace: bool,
+    /// Only these tools are offered, when set
+    pub allowed_tools: Option<Vec<String>>,
+    /// These tools are never offered
+    pub disabled_tools: Vec<String>,
+}
+
+static CURRENT: Lazy<Mutex<Option<Persona>>> = Lazy::new(|| Mutex::new(None));
+// Values for the {placeholders} of persona prompts, set when the chat starts
+static VARIABLES: Lazy<Mutex<Vec<(String, String)>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+// Presets available without any files; a file with the same name overrides them
+const BUILT_IN: &[(&str, &str)] = &[
+    (
+        "reviewer",
+        "---\ntools: file_editor, execute_command, search_online, github\n---\n\
+         Act as a demanding senior code reviewer. Read the relevant code before judging it, \
+         point out bugs, security issues, missing error handling and unclear naming, and rank \
+         findings by severity. Do not modify files unless the user explicitly asks for a fix.",
+    ),
+    (
+        "sysadmin",
+        "---\ntools: execute_command, file_editor, search_online, scrape_url\n---\n\
+         Act as an experienced Linux/Unix system administrator. Prefer standard tools, check the \
+         current state before changing anything, explain the impact of each command, and always \
+         mention how to roll a change back.",
+    ),
+    (
+        "terse",
+        "Answer as briefly as the question allows: no preamble, no recap of the question and \
+         no closing summary. Give code and commands without explanation unless asked, and use \
+         lists instead of paragraphs.",
+    ),
+    (
+        "tutor",
+        "---\ndisable: execute_command, send_email\n---\n\
+         Act as a patient programming tutor. Explain concepts step by step with small examples, \
+         ask the user to try things themselves, and check their understanding instead of simply \
+         handing over complete solutions.",
+    ),
+];
+
+// Other names for built-in personas
+const ALIASES: &[(&str, &str)] = &[("teacher", "tutor")];
+
+/// Directory holding user-defined personas as <name>.md files.
+pub fn personas_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("gemini-cli")
+        .join("personas")
+}
+
+/// Loads a persona by name, from the personas directory or the built-in presets.
+pub fn load(name: &str) -> Result<Persona, String> {
+    if name.is_empty() || name.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid persona name '{}'", name));
+    }
+    let path = personas_dir().join(format!("{}.md", name));
+    let builtin = ALIASES.iter().find(|(alias, _)| *alias == name).map_or(name, |(_, builtin)| *builtin);
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(_) => BUILT_IN
+            .iter()
+            .find(|(preset, _)| *preset == builtin)
+            .map(|(_, source)| source.to_string())
+            .ok_or_else(|| format!("No persona named '{}' (see /persona)", name))?,
+    };
+    Ok(parse(name, &source))
+}
+
+/// Names of all personas: built-in ones plus the files in the personas directory.
+pub fn available() -> Vec<String> {
+    let mut names: Vec<String> = BUILT_IN.iter().map(|(name, _)| name.to_string()).collect();
+    if let Ok(entries) = fs::read_dir(personas_dir()) {
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if let Some(name) = file_name.strip_suffix(".md") {
+                if !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+    }
+    names.sort();
+    names
+}
+
+/// Switches persona; None goes back to the default assistant.
+pub fn set(persona: Option<Persona>) {
+    *CURRENT.lock().unwrap() = persona;
+}
+
+pub fn current() -> Option<Persona> {
+    CURRENT.lock().unwrap().clone()
+}
+
+/// Sets the values persona prompts can refer to, e.g. `{date}` or `{shell}`.
+pub fn set_variables(variables: &[(&str, &str)]) {
+    *VARIABLES.lock().unwrap() = variables.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+}
+
+/// The instructions for the active persona: `default` followed by the persona's prompt, or
+/// the prompt alone for a persona with `mode: replace`. Without a persona, `default`.
+pub fn system_instruction(default: &str) -> String {
+    match current() {
+        Some(persona) if persona.replace => render(&persona.prompt),
+        Some(persona) => format!("{}\n\nPersona: {}\n{}", default, persona.name, render(&persona.prompt)),
+        None => default.to_string(),
+    }
+}
+
+/// Fills in the {placeholders} of a prompt; unknown ones are left as they are.
+fn render(prompt: &str) -> String {
+    VARIABLES
+        .lock()
+        .unwrap()
+        .iter()
+        .fold(prompt.to_string(), |prompt, (name, value)| prompt.replace(&format!("{{{}}}", name), value))
+}
+
+/// Whether the active persona's tool policy allows a tool.
+pub fn allows_tool(name: &str) -> bool {
+    match current() {
+        Some(persona) => {
+            persona
+                .allowed_tools
+                .as_ref()
+                .map(|allowed| allowed.iter().any(|t| t == name))
+                .unwrap_or(true)
+                && !persona.disabled_tools.iter().any(|t| t == name)
+        }
+        None => true,
+    }
+}
+
+/// Prints the personas for `/persona` without an argument.
+pub fn print_list() {
+    let active = current().map(|p| p.name);
+    for name in available() {
+        if active.as_deref() == Some(name.as_str()) {
+            println!("{} {}", "*".color(Color::Green).bold(), name.bold());
+        } else {
+            println!("  {}", name);
+        }
+    }
+    println!(
+        "{}",
+        format!(
+            "Use /persona <name> to switch, /persona show to see the active one, /persona off for the default. \
+             Add your own as .md files in {}",
+            personas_dir().display()
+        )
+        .color(Color::Cyan)
+    );
+}
+
+/// The active persona for `/persona show`: how it applies, its tool policy and its prompt.
+pub fn describe() -> Option<String> {
+    let persona = current()?;
+    let mut lines = vec![format!(
+        "{} ({} the default instructions)",
+        persona.name.bold(),
+        if persona.replace { "replaces" } else { "adds to" }
+    )];
+    if let Some(allowed) = &persona.allowed_tools {
+        lines.push(format!("Tools: {}", allowed.join(", ")));
+    }
+    if !persona.disabled_tools.is_empty() {
+        lines.push(format!("Disabled tools: {}", persona.disabled_tools.join(", ")));
+    }
+    lines.push(String::new());
+    lines.push(render(&persona.prompt));
+    Some(lines.join("\n"))
+}
+
+/// A persona file is the prompt text, optionally preceded by a header between `---` lines:
+///
+/// ```text
+/// ---
+/// tools: execute_command, file_editor
+/// disable: send_email
+/// mode: replace
+/// ---
+/// Act as ... Today is {date}.
+/// ```
+fn parse(name: &str, source: &str) -> Persona {
+    let mut persona = Persona {
+        name: name.to_string(),
+        prompt: source.trim().to_string(),
+        replace: false,
+        allowed_tools: None,
+        disabled_tools: Vec::new(),
+    };
+    let rest = match source.trim_start().strip_prefix("---") {
+        Some(rest) => rest,
+        None => return persona,
+    };
+    let (header, prompt) = match rest.split_once("\n---") {
+        Some(parts) => parts,
+        None => return persona,
+    };
+
+    let list = |value: &str| -> Vec<String> {
+        value
+            .split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect()
+    };
+    for line in header.lines() {
+        match line.split_once(':') {
+            Some((key, value)) if key.trim() == "tools" => persona.allowed_tools = Some(list(value)),
+            Some((key, value)) if key.trim() == "disable" => persona.disabled_tools = list(value),
+            Some((key, value)) if key.trim() == "mode" => persona.replace = value.trim() == "replace",
+            _ => {}
+        }
+    }
+    persona.prompt = prompt.trim().to_string();
+    persona
+}
@@ -0,0 +1,204 @@
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use wasmi::{Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::tools;
+use crate::ui;
+
+// Version of the plugin interface described in the readme
+const ABI_VERSION: i32 = 1;
+// Instructions a plugin may run per call, about a second of work in a release build
+const FUEL_PER_CALL: u64 = 1_000_000_000;
+// Largest linear memory a plugin may grow to
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+static ENGINE: Lazy<Engine> = Lazy::new(|| {
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    Engine::new(&config)
+});
+
+static PLUGINS: Lazy<Vec<Plugin>> = Lazy::new(load_all);
+
+/// A WebAssembly module from the plugins directory and the tools it declares.
+struct Plugin {
+    path: PathBuf,
+    module: Module,
+    declarations: Vec<Value>,
+}
+
+pub fn plugins_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("gemini-cli")
+        .join("plugins")
+}
+
+/// Tool declarations of all loaded plugins, in the same shape as the built-in ones.
+pub fn declarations() -> Vec<Value> {
+    PLUGINS.iter().flat_map(|p| p.declarations.clone()).collect()
+}
+
+/// Whether a tool name belongs to a plugin.
+pub fn provides(name: &str) -> bool {
+    find(name).is_some()
+}
+
+/// Runs a plugin tool in a fresh sandbox: no host functions, limited memory and fuel, and
+/// no state kept between calls.
+pub fn call(name: &str, args: &Value) -> Result<String, String> {
+    let plugin = find(name).ok_or_else(|| format!("Unknown plugin tool: {}", name))?;
+    let input = json!({"name": name, "args": args}).to_string();
+    let output = invoke(&plugin.module, "gemini_call", Some(input.as_bytes()))
+        .map_err(|e| format!("Plugin {} failed: {}", file_name(&plugin.path), e))?;
+    let response: Value = serde_json::from_slice(&output)
+        .map_err(|e| format!("Plugin {} returned invalid JSON: {}", file_name(&plugin.path), e))?;
+    if let Some(error) = response.get("error") {
+        return Err(error.as_str().map(String::from).unwrap_or_else(|| error.to_string()));
+    }
+    Ok(match response.get("result") {
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+        None => response.to_string(),
+    })
+}
+
+/// Loads every .wasm file in the plugins directory, reporting the ones that can't be used.
+/// Called at startup so problems show up before the first turn.
+pub fn load() {
+    Lazy::force(&PLUGINS);
+}
+
+fn find(name: &str) -> Option<&'static Plugin> {
+    PLUGINS
+        .iter()
+        .find(|p| p.declarations.iter().any(|decl| decl["name"] == name))
+}
+
+fn load_all() -> Vec<Plugin> {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(plugins_dir()) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map(|e| e == "wasm").unwrap_or(false))
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    paths.sort();
+
+    let mut plugins: Vec<Plugin> = Vec::new();
+    for path in paths {
+        match load_plugin(&path) {
+            Ok(mut plugin) => {
+                // Built-in tools and earlier plugins keep their names
+                plugin.declarations.retain(|decl| {
+                    let name = decl["name"].as_str().unwrap_or("");
+                    let taken = tools::builtin_declarations().iter().any(|d| d["name"] == name)
+                        || plugins.iter().any(|p| p.declarations.iter().any(|d| d["name"] == name));
+                    if taken {
+                        warn(&format!("{}: tool name '{}' is already taken, skipping it", file_name(&path), name));
+                    }
+                    !taken
+                });
+                plugins.push(plugin);
+            }
+            Err(e) => warn(&format!("{}: {}", file_name(&path), e)),
+        }
+    }
+    plugins
+}
+
+fn load_plugin(path: &Path) -> Result<Plugin, String> {
+    let bytes = fs::read(path).map_err(|e| format!("could not read the file: {}", e))?;
+    let module = Module::new(&ENGINE, &bytes[..]).map_err(|e| format!("not a valid WebAssembly module: {}", e))?;
+    if let Some(import) = module.imports().next() {
+        return Err(format!(
+            "imports {}::{}, but plugins run without access to the host",
+            import.module(),
+            import.name()
+        ));
+    }
+
+    let version = invoke_version(&module)?;
+    if version != ABI_VERSION {
+        return Err(format!("built for plugin interface {}, this version supports {}", version, ABI_VERSION));
+    }
+    let tools = invoke(&module, "gemini_tools", None)?;
+    let declarations: Vec<Value> =
+        serde_json::from_slice(&tools).map_err(|e| format!("gemini_tools returned invalid JSON: {}", e))?;
+    for decl in &declarations {
+        if decl["name"].as_str().map(str::is_empty).unwrap_or(true) || decl["description"].as_str().is_none() {
+            return Err("every tool needs a name and a description".to_string());
+        }
+    }
+    Ok(Plugin { path: path.to_path_buf(), module, declarations })
+}
+
+/// A fresh instance of the module with its own memory and fuel.
+fn instantiate(module: &Module) -> Result<(Store<StoreLimits>, Instance), String> {
+    let mut store = Store::new(&ENGINE, StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).build());
+    store.limiter(|limits| limits);
+    store.add_fuel(FUEL_PER_CALL).map_err(|e| e.to_string())?;
+    let instance = Linker::new(&ENGINE)
+        .instantiate(&mut store, module)
+        .and_then(|pre| pre.start(&mut store))
+        .map_err(|e| e.to_string())?;
+    Ok((store, instance))
+}
+
+fn invoke_version(module: &Module) -> Result<i32, String> {
+    let (mut store, instance) = instantiate(module)?;
+    let version = instance
+        .get_typed_func::<(), i32>(&store, "gemini_abi_version")
+        .map_err(|_| "missing the gemini_abi_version export".to_string())?;
+    version.call(&mut store, ()).map_err(|e| e.to_string())
+}
+
+/// Instantiates the module, copies `input` (if any) into its memory, calls `export` and
+/// returns the bytes it points to. Results are packed as (pointer << 32) | length.
+fn invoke(module: &Module, export: &str, input: Option<&[u8]>) -> Result<Vec<u8>, String> {
+    let (mut store, instance) = instantiate(module)?;
+    let memory: Memory = instance
+        .get_memory(&store, "memory")
+        .ok_or("missing the memory export")?;
+
+    let packed = match input {
+        Some(input) => {
+            let alloc = instance
+                .get_typed_func::<i32, i32>(&store, "gemini_alloc")
+                .map_err(|_| "missing the gemini_alloc export".to_string())?;
+            let pointer = alloc.call(&mut store, input.len() as i32).map_err(|e| e.to_string())?;
+            memory
+                .write(&mut store, pointer as u32 as usize, input)
+                .map_err(|_| "gemini_alloc returned memory out of bounds".to_string())?;
+            instance
+                .get_typed_func::<(i32, i32), i64>(&store, export)
+                .map_err(|_| format!("missing the {} export", export))?
+                .call(&mut store, (pointer, input.len() as i32))
+        }
+        None => instance
+            .get_typed_func::<(), i64>(&store, export)
+            .map_err(|_| format!("missing the {} export", export))?
+            .call(&mut store, ()),
+    }
+    .map_err(|e| e.to_string())?;
+
+    let pointer = (packed as u64 >> 32) as usize;
+    let length = (packed as u64 & 0xffff_ffff) as usize;
+    let mut output = vec![0; length];
+    memory
+        .read(&store, pointer, &mut output)
+        .map_err(|_| format!("{} returned memory out of bounds", export))?;
+    Ok(output)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn warn(message: &str) {
+    ui::status(&format!("Plugin {}", message).color(Color::Yellow).to_string());
+}
@@ -0,0 +1,221 @@
+use once_cell::sync::Lazy;
+use reqwest::{Client, StatusCode, Url};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Name the CLI goes by in robots.txt groups
+const ROBOTS_TOKEN: &str = "gemini-cli-rs";
+// Time between two requests to the same host
+const DEFAULT_DOMAIN_DELAY_MS: u64 = 1_000;
+// A Crawl-delay from robots.txt is honored up to this many seconds
+const MAX_CRAWL_DELAY: u64 = 10;
+// robots.txt files larger than this are cut off
+const MAX_ROBOTS_BYTES: usize = 500_000;
+
+/// Allow and Disallow rules for one host, from the robots.txt group that applies to the CLI.
+#[derive(Default)]
+struct Robots {
+    /// (allowed, path pattern) in file order
+    rules: Vec<(bool, String)>,
+    crawl_delay: Option<Duration>,
+}
+
+/// What is known about a host: its robots.txt, once read, and when it was last contacted.
+#[derive(Default)]
+struct Host {
+    robots: Option<Robots>,
+    last_request: Option<Instant>,
+}
+
+// One lock per host, so pages of different sites are fetched in parallel while requests to
+// the same site wait for each other
+static HOSTS: Lazy<Mutex<HashMap<String, Arc<tokio::sync::Mutex<Host>>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// User agent for reading web pages: GEMINI_USER_AGENT, or one that names the CLI.
+pub fn user_agent() -> String {
+    env::var("GEMINI_USER_AGENT")
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| {
+            format!(
+                "Mozilla/5.0 (compatible; {}/{}; +https://github.com/camalolo/gemini-cli)",
+                ROBOTS_TOKEN,
+                env!("CARGO_PKG_VERSION")
+            )
+        })
+}
+
+/// Waits until the host of `url` may be contacted again and checks that its robots.txt
+/// allows the page. Returns the reason when the page must be skipped.
+///
+/// GEMINI_RESPECT_ROBOTS=0 turns the robots.txt check off. GEMINI_DOMAIN_DELAY_MS sets the
+/// time between requests to one host (default 1000, 0 for none); a longer Crawl-delay in
+/// robots.txt wins, up to ten seconds.
+pub async fn wait_turn(client: &Client, url: &str) -> Result<(), String> {
+    let parsed = Url::parse(url).map_err(|e| format!("Error: invalid URL '{}': {}", url, e))?;
+    let origin = parsed.origin().ascii_serialization();
+    let host = Arc::clone(HOSTS.lock().unwrap().entry(origin.clone()).or_default());
+    let mut host = host.lock().await;
+    let delay = env::var("GEMINI_DOMAIN_DELAY_MS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_millis(DEFAULT_DOMAIN_DELAY_MS));
+
+    if respect_robots() && matches!(parsed.scheme(), "http" | "https") {
+        if host.robots.is_none() {
+            pause(&host, delay).await;
+            host.robots = Some(fetch_robots(client, &origin).await);
+            host.last_request = Some(Instant::now());
+        }
+        let robots = host.robots.as_ref().unwrap();
+        let path = match parsed.query() {
+            Some(query) => format!("{}?{}", parsed.path(), query),
+            None => parsed.path().to_string(),
+        };
+        if !robots.allows(&path) {
+            return Err(format!("Skipped: {}/robots.txt asks crawlers not to read {}", origin, path));
+        }
+    }
+
+    let delay = match host.robots.as_ref().and_then(|robots| robots.crawl_delay) {
+        Some(crawl_delay) => delay.max(crawl_delay),
+        None => delay,
+    };
+    pause(&host, delay).await;
+    host.last_request = Some(Instant::now());
+    Ok(())
+}
+
+fn respect_robots() -> bool {
+    env::var("GEMINI_RESPECT_ROBOTS").map(|v| v.trim() != "0").unwrap_or(true)
+}
+
+/// Sleeps until `delay` has passed since the host's last request.
+async fn pause(host: &Host, delay: Duration) {
+    if let Some(last) = host.last_request {
+        let elapsed = last.elapsed();
+        if elapsed < delay {
+            tokio::time::sleep(delay - elapsed).await;
+        }
+    }
+}
+
+/// Reads and parses a host's robots.txt. A missing or unreadable file allows everything.
+async fn fetch_robots(client: &Client, origin: &str) -> Robots {
+    let response = client
+        .get(format!("{}/robots.txt", origin))
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.status() == StatusCode::OK => match response.text().await {
+            Ok(text) => {
+                let mut end = text.len().min(MAX_ROBOTS_BYTES);
+                while !text.is_char_boundary(end) {
+                    end -= 1;
+                }
+                Robots::parse(&text[..end])
+            }
+            Err(_) => Robots::default(),
+        },
+        _ => Robots::default(),
+    }
+}
+
+impl Robots {
+    /// Keeps the rules of the group naming the CLI, or of the `*` group when none does.
+    fn parse(text: &str) -> Robots {
+        let mut named = None;
+        let mut wildcard = None;
+        // User agents of the group being read, and whether its rules have started
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        let mut group = Robots::default();
+
+        let mut finish = |agents: &[String], group: Robots| {
+            if agents.iter().any(|agent| agent == ROBOTS_TOKEN) {
+                named.get_or_insert(group);
+            } else if agents.iter().any(|agent| agent == "*") {
+                wildcard.get_or_insert(group);
+            }
+        };
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "user-agent" => {
+                    if in_rules {
+                        finish(&agents, std::mem::take(&mut group));
+                        agents.clear();
+                        in_rules = false;
+                    }
+                    agents.push(value.to_lowercase());
+                }
+                "allow" | "disallow" if !agents.is_empty() => {
+                    in_rules = true;
+                    // An empty Disallow allows everything
+                    if !value.is_empty() {
+                        group.rules.push((key.trim().eq_ignore_ascii_case("allow"), value.to_string()));
+                    }
+                }
+                "crawl-delay" if !agents.is_empty() => {
+                    in_rules = true;
+                    if let Ok(seconds) = value.parse::<f64>() {
+                        let seconds = seconds.clamp(0.0, MAX_CRAWL_DELAY as f64);
+                        group.crawl_delay = Some(Duration::from_secs_f64(seconds));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !agents.is_empty() {
+            finish(&agents, group);
+        }
+        named.or(wildcard).unwrap_or_default()
+    }
+
+    /// The longest matching rule decides; Allow wins a tie, and no match allows.
+    fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| matches(pattern, path))
+            .max_by_key(|(allowed, pattern)| (pattern.len(), *allowed))
+            .is_none_or(|(allowed, _)| *allowed)
+    }
+}
+
+/// Whether a robots.txt path pattern matches, with `*` for any run of characters and a
+/// trailing `$` anchoring the end.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let decoded = urlencoding::decode(path).map(|p| p.into_owned()).unwrap_or_else(|_| path.to_string());
+    let pattern = urlencoding::decode(pattern).map(|p| p.into_owned()).unwrap_or_else(|_| pattern.to_string());
+    let parts: Vec<&str> = pattern.split('*').collect();
+
+    let mut rest = decoded.as_str();
+    for (index, part) in parts.iter().enumerate() {
+        if index == 0 {
+            match rest.strip_prefix(part) {
+                Some(after) => rest = after,
+                None => return false,
+            }
+        } else if index == parts.len() - 1 && anchored {
+            return rest.ends_with(part);
+        } else {
+            match rest.find(part) {
+                Some(at) => rest = &rest[at + part.len()..],
+                None => return false,
+            }
+        }
+    }
+    !anchored || rest.is_empty()
+}
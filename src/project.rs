@@ -0,0 +1,155 @@
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::ui;
+
+// Name of the project file in the sandbox root
+const PROJECT_FILE: &str = "GEMINI.md";
+
+// Settings a project file may override; keys, endpoints and providers stay with the user
+const PROJECT_SETTINGS: &[&str] = &[
+    "GEMINI_AUTO_COMPACT",
+    "GEMINI_CANDIDATE_COUNT",
+    "GEMINI_COMMAND_OUTPUT_LIMIT",
+    "GEMINI_CONDENSE_THRESHOLD",
+    "GEMINI_CONTEXT_WINDOW",
+    "GEMINI_DOCUMENT_CHUNK",
+    "GEMINI_DOCUMENT_CHUNKS",
+    "GEMINI_INDEX",
+    "GEMINI_INDEX_CHUNKS",
+    "GEMINI_INDEX_MAX_FILES",
+    "GEMINI_MAX_OUTPUT_TOKENS",
+    "GEMINI_MAX_RESULT_TOKENS",
+    "GEMINI_MAX_TOOL_ROUNDS",
+    "GEMINI_PERSONA",
+    "GEMINI_PROJECT_OVERVIEW",
+    "GEMINI_SEARCH_PAGE_CHARS",
+    "GEMINI_SEARCH_PARALLEL",
+    "GEMINI_SEARCH_RESULTS",
+    "GEMINI_SEARCH_TOTAL_CHARS",
+    "GEMINI_SHOW_THOUGHTS",
+    "GEMINI_STOP_SEQUENCES",
+    "GEMINI_TEMPERATURE",
+    "GEMINI_THINKING_BUDGET",
+    "GEMINI_TOOL_TIME_BUDGET",
+    "GEMINI_TOOL_TOKEN_BUDGET",
+    "GEMINI_TOP_K",
+    "GEMINI_TOP_P",
+    "GEMINI_TRIM_PERCENT",
+];
+
+/// Project-specific instructions and tool settings from GEMINI.md in the sandbox root.
+pub struct Project {
+    pub instructions: String,
+    /// Only these tools are offered, when set
+    pub allowed_tools: Option<Vec<String>>,
+    /// These tools are never offered
+    pub disabled_tools: Vec<String>,
+    /// Overrides for settings from ~/.gemini.conf
+    pub settings: Vec<(String, String)>,
+}
+
+static PROJECT: Lazy<Option<Project>> = Lazy::new(|| {
+    let source = fs::read_to_string(project_path()).ok()?;
+    Some(parse(&source))
+});
+
+/// Where the project file of the sandbox would be. Read from the working directory rather
+/// than SANDBOX_ROOT, which has to stay unresolved until `selftest` has moved into its
+/// temporary sandbox.
+pub fn project_path() -> PathBuf {
+    env::current_dir().unwrap_or_else(|_| PathBuf::from(".")).join(PROJECT_FILE)
+}
+
+/// The project file of the sandbox, if there is one.
+pub fn current() -> Option<&'static Project> {
+    PROJECT.as_ref()
+}
+
+/// Applies the project's settings on top of ~/.gemini.conf. Called at startup, before the
+/// settings are first read.
+pub fn apply() {
+    let project = match current() {
+        Some(project) => project,
+        None => return,
+    };
+    for (key, value) in &project.settings {
+        if PROJECT_SETTINGS.contains(&key.as_str()) {
+            env::set_var(key, value);
+        } else {
+            ui::status(
+                &format!("{}: the setting {} can't be changed per project, ignoring it", PROJECT_FILE, key)
+                    .color(Color::Yellow)
+                    .to_string(),
+            );
+        }
+    }
+}
+
+/// Text appended to the system instruction for the project's instructions.
+pub fn instruction() -> String {
+    match current() {
+        Some(project) if !project.instructions.is_empty() => format!(
+            "\n\nProject instructions from {} (follow them for this repository):\n{}",
+            PROJECT_FILE, project.instructions
+        ),
+        _ => String::new(),
+    }
+}
+
+/// Whether the project's tool policy allows a tool.
+pub fn allows_tool(name: &str) -> bool {
+    match current() {
+        Some(project) => {
+            project
+                .allowed_tools
+                .as_ref()
+                .map(|allowed| allowed.iter().any(|t| t == name))
+                .unwrap_or(true)
+                && !project.disabled_tools.iter().any(|t| t == name)
+        }
+        None => true,
+    }
+}
+
+/// Reads the optional front matter (`tools:`, `disable:` and `GEMINI_*: value` lines
+/// between `---` markers); the rest of the file is the instructions.
+fn parse(source: &str) -> Project {
+    let mut project = Project {
+        instructions: source.trim().to_string(),
+        allowed_tools: None,
+        disabled_tools: Vec::new(),
+        settings: Vec::new(),
+    };
+    let rest = match source.trim_start().strip_prefix("---") {
+        Some(rest) => rest,
+        None => return project,
+    };
+    let (header, instructions) = match rest.split_once("\n---") {
+        Some(parts) => parts,
+        None => return project,
+    };
+
+    let list = |value: &str| -> Vec<String> {
+        value
+            .split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect()
+    };
+    for line in header.lines() {
+        match line.split_once(':') {
+            Some((key, value)) if key.trim() == "tools" => project.allowed_tools = Some(list(value)),
+            Some((key, value)) if key.trim() == "disable" => project.disabled_tools = list(value),
+            Some((key, value)) if !key.trim().is_empty() => project
+                .settings
+                .push((key.trim().to_uppercase(), value.trim().to_string())),
+            _ => {}
+        }
+    }
+    project.instructions = instructions.trim().to_string();
+    project
+}
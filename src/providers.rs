@@ -0,0 +1,541 @@
+use once_cell::sync::Lazy;
+use reqwest::{Client, RequestBuilder};
+use serde_json::{json, Value};
+use std::env;
+use std::time::Duration;
+
+use crate::error::CliError;
+use crate::images;
+use crate::keys;
+use crate::safety;
+use crate::MODEL;
+
+// Names accepted by GEMINI_PROVIDER
+const PROVIDERS: &[&str] = &["gemini", "openai", "anthropic", "ollama"];
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const OLLAMA_HOST: &str = "http://localhost:11434";
+// The Messages API needs an upper bound on the reply length
+const ANTHROPIC_MAX_TOKENS: u64 = 8192;
+
+static PROVIDER: Lazy<Box<dyn Provider>> = Lazy::new(|| match setting().as_str() {
+    "openai" => Box::new(OpenAi {
+        name: "openai",
+        title: "OpenAI-compatible API",
+        base_url: configured("OPENAI_BASE_URL").unwrap_or_else(|| OPENAI_BASE_URL.to_string()),
+        api_key: configured("OPENAI_API_KEY"),
+        default_model: "gpt-4o-mini",
+    }),
+    // Ollama serves the OpenAI chat API under /v1
+    "ollama" => Box::new(OpenAi {
+        name: "ollama",
+        title: "Ollama",
+        base_url: format!("{}/v1", configured("OLLAMA_HOST").unwrap_or_else(|| OLLAMA_HOST.to_string())),
+        api_key: None,
+        default_model: "llama3.1",
+    }),
+    "anthropic" => Box::new(Anthropic {
+        base_url: configured("ANTHROPIC_BASE_URL").unwrap_or_else(|| ANTHROPIC_BASE_URL.to_string()),
+        api_key: configured("ANTHROPIC_API_KEY"),
+    }),
+    _ => Box::new(Gemini),
+});
+
+/// A model API. Request bodies and responses have the shape of Gemini's generateContent
+/// throughout the CLI (contents with text, functionCall and functionResponse parts); each
+/// provider translates them to and from its own API.
+pub trait Provider: Send + Sync {
+    /// Name used in GEMINI_PROVIDER
+    fn name(&self) -> &'static str;
+    /// Name shown in `--version`
+    fn title(&self) -> &'static str;
+    /// Model used when neither GEMINI_MODEL nor --model is given
+    fn default_model(&self) -> &'static str;
+    /// Fails with a hint when the provider's credentials are missing.
+    fn check_configured(&self) -> Result<(), String>;
+    /// Builds the HTTP request for a generateContent body.
+    fn request(&self, client: &Client, model: &str, body: &Value, stream: bool) -> RequestBuilder;
+    /// Translates a successful (non-streamed) response into the generateContent shape.
+    fn translate_response(&self, response: Value) -> Value;
+
+    /// Whether `request` can stream the reply as server-sent generateContent chunks.
+    fn streams(&self) -> bool {
+        false
+    }
+
+    /// Switches to another API key after a quota error, see `keys::rotate`.
+    fn rotate_key(&self, _cooldown: Option<Duration>) -> Option<(usize, usize)> {
+        None
+    }
+}
+
+/// The provider selected with GEMINI_PROVIDER (Gemini by default).
+pub fn current() -> &'static dyn Provider {
+    PROVIDER.as_ref()
+}
+
+/// Checks GEMINI_PROVIDER and the credentials the selected provider needs.
+pub fn check() -> Result<(), CliError> {
+    let setting = setting();
+    if !PROVIDERS.contains(&setting.as_str()) {
+        return Err(CliError::Config(format!(
+            "Unknown GEMINI_PROVIDER '{}'; use one of {}.",
+            setting,
+            PROVIDERS.join(", ")
+        )));
+    }
+    current().check_configured().map_err(CliError::Config)
+}
+
+fn setting() -> String {
+    configured("GEMINI_PROVIDER")
+        .unwrap_or_else(|| "gemini".to_string())
+        .to_lowercase()
+}
+
+fn configured(key: &str) -> Option<String> {
+    env::var(key)
+        .ok()
+        .map(|v| v.trim().trim_end_matches('/').to_string())
+        .filter(|v| !v.is_empty())
+}
+
+struct Gemini;
+
+impl Provider for Gemini {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn title(&self) -> &'static str {
+        "Gemini API"
+    }
+
+    fn default_model(&self) -> &'static str {
+        MODEL
+    }
+
+    fn check_configured(&self) -> Result<(), String> {
+        if keys::configured().is_empty() {
+            return Err(
+                "GEMINI_API_KEY not found in ~/.gemini.conf. Set it with 'gemini-cli-rs config set GEMINI_API_KEY <key>'."
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    fn request(&self, client: &Client, model: &str, body: &Value, stream: bool) -> RequestBuilder {
+        let (method, query) = if stream {
+            ("streamGenerateContent", vec![("key", keys::current()), ("alt", "sse".to_string())])
+        } else {
+            ("generateContent", vec![("key", keys::current())])
+        };
+        let request = client
+            .post(format!(
+                "https://generativelanguage.googleapis.com/v1beta/models/{}:{}",
+                model, method
+            ))
+            .query(&query);
+        match safety::settings() {
+            Some(settings) => {
+                let mut body = body.clone();
+                body["safetySettings"] = settings;
+                request.json(&body)
+            }
+            None => request.json(body),
+        }
+    }
+
+    fn translate_response(&self, response: Value) -> Value {
+        response
+    }
+
+    fn streams(&self) -> bool {
+        true
+    }
+
+    fn rotate_key(&self, cooldown: Option<Duration>) -> Option<(usize, usize)> {
+        keys::rotate(cooldown)
+    }
+}
+
+/// The OpenAI chat completions API, also offered by Ollama, vLLM, LM Studio and others.
+struct OpenAi {
+    name: &'static str,
+    title: &'static str,
+    base_url: String,
+    api_key: Option<String>,
+    default_model: &'static str,
+}
+
+impl Provider for OpenAi {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn title(&self) -> &'static str {
+        self.title
+    }
+
+    fn default_model(&self) -> &'static str {
+        self.default_model
+    }
+
+    fn check_configured(&self) -> Result<(), String> {
+        // Local servers usually take any key, or none
+        if self.name == "openai" && self.api_key.is_none() && self.base_url == OPENAI_BASE_URL {
+            return Err(
+                "OPENAI_API_KEY not found in ~/.gemini.conf. Set it with 'gemini-cli-rs config set OPENAI_API_KEY <key>', \
+                 or point OPENAI_BASE_URL at a server that doesn't need one."
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    fn request(&self, client: &Client, model: &str, body: &Value, _stream: bool) -> RequestBuilder {
+        let request = client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&to_openai(model, body));
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    fn translate_response(&self, response: Value) -> Value {
+        let choice = &response["choices"][0];
+        let message = &choice["message"];
+        let mut parts = Vec::new();
+        if let Some(text) = message["content"].as_str().filter(|t| !t.is_empty()) {
+            parts.push(json!({"text": text}));
+        }
+        for call in message["tool_calls"].as_array().into_iter().flatten() {
+            let args: Value = call["function"]["arguments"]
+                .as_str()
+                .and_then(|args| serde_json::from_str(args).ok())
+                .unwrap_or_else(|| json!({}));
+            parts.push(json!({"functionCall": {"name": call["function"]["name"], "args": args}}));
+        }
+        let finish = match choice["finish_reason"].as_str() {
+            Some("length") => "MAX_TOKENS",
+            Some("content_filter") => "SAFETY",
+            _ => "STOP",
+        };
+        let mut translated =
+            generate_content_response(parts, finish, &response["usage"]["prompt_tokens"], &response["usage"]["completion_tokens"]);
+        // Further choices (with GEMINI_CANDIDATE_COUNT) are only shown, so their text is enough
+        for choice in response["choices"].as_array().into_iter().flatten().skip(1) {
+            if let Some(text) = choice["message"]["content"].as_str().filter(|t| !t.is_empty()) {
+                if let Some(candidates) = translated["candidates"].as_array_mut() {
+                    candidates.push(json!({"content": {"role": "model", "parts": [{"text": text}]}, "finishReason": "STOP"}));
+                }
+            }
+        }
+        translated
+    }
+}
+
+struct Anthropic {
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl Provider for Anthropic {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn title(&self) -> &'static str {
+        "Anthropic API"
+    }
+
+    fn default_model(&self) -> &'static str {
+        "claude-3-5-haiku-latest"
+    }
+
+    fn check_configured(&self) -> Result<(), String> {
+        if self.api_key.is_none() {
+            return Err(
+                "ANTHROPIC_API_KEY not found in ~/.gemini.conf. Set it with 'gemini-cli-rs config set ANTHROPIC_API_KEY <key>'."
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    fn request(&self, client: &Client, model: &str, body: &Value, _stream: bool) -> RequestBuilder {
+        client
+            .post(format!("{}/messages", self.base_url))
+            .header("x-api-key", self.api_key.clone().unwrap_or_default())
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&to_anthropic(model, body))
+    }
+
+    fn translate_response(&self, response: Value) -> Value {
+        let mut parts = Vec::new();
+        for block in response["content"].as_array().into_iter().flatten() {
+            match block["type"].as_str() {
+                Some("text") => parts.push(json!({"text": block["text"]})),
+                Some("tool_use") => {
+                    parts.push(json!({"functionCall": {"name": block["name"], "args": block["input"]}}))
+                }
+                _ => {}
+            }
+        }
+        let finish = match response["stop_reason"].as_str() {
+            Some("max_tokens") => "MAX_TOKENS",
+            Some("refusal") => "SAFETY",
+            _ => "STOP",
+        };
+        generate_content_response(parts, finish, &response["usage"]["input_tokens"], &response["usage"]["output_tokens"])
+    }
+}
+
+/// Chat completions request for a generateContent body. Gemini's function calls carry no
+/// IDs, so each call gets one from its position, and the responses (which follow in the
+/// next message, in the same order) refer to it.
+fn to_openai(model: &str, body: &Value) -> Value {
+    let mut messages = Vec::new();
+    let system = system_text(body);
+    if !system.is_empty() {
+        messages.push(json!({"role": "system", "content": system}));
+    }
+    for (index, content) in contents(body).iter().enumerate() {
+        let parts = parts(content);
+        if content["role"] == "model" {
+            let text = text_of(parts);
+            let calls: Vec<Value> = parts
+                .iter()
+                .filter_map(|part| part.get("functionCall"))
+                .enumerate()
+                .map(|(k, call)| {
+                    json!({
+                        "id": call_id(index, k),
+                        "type": "function",
+                        "function": {"name": call["name"], "arguments": args_of(call).to_string()}
+                    })
+                })
+                .collect();
+            let mut message = json!({"role": "assistant", "content": if text.is_empty() { Value::Null } else { json!(text) }});
+            if !calls.is_empty() {
+                message["tool_calls"] = json!(calls);
+            }
+            messages.push(message);
+        } else {
+            for (k, response) in parts.iter().filter_map(|part| part.get("functionResponse")).enumerate() {
+                messages.push(json!({
+                    "role": "tool",
+                    "tool_call_id": call_id(index.saturating_sub(1), k),
+                    "content": response_text(response)
+                }));
+            }
+            let text = user_text(parts);
+            let images: Vec<Value> = parts
+                .iter()
+                .filter_map(images::inline_data)
+                .map(|(mime_type, data)| {
+                    json!({"type": "image_url", "image_url": {"url": format!("data:{};base64,{}", mime_type, data)}})
+                })
+                .collect();
+            if !images.is_empty() {
+                let mut content = vec![json!({"type": "text", "text": text})];
+                content.extend(images);
+                messages.push(json!({"role": "user", "content": content}));
+            } else if !text.is_empty() {
+                messages.push(json!({"role": "user", "content": text}));
+            }
+        }
+    }
+
+    let mut request = json!({"model": model, "messages": messages});
+    let tools: Vec<Value> = declarations(body)
+        .iter()
+        .map(|decl| {
+            json!({
+                "type": "function",
+                "function": {"name": decl["name"], "description": decl["description"], "parameters": parameters(decl)}
+            })
+        })
+        .collect();
+    if !tools.is_empty() {
+        request["tools"] = json!(tools);
+    }
+    copy_generation_config(
+        body,
+        &mut request,
+        &[("temperature", "temperature"), ("topP", "top_p"), ("maxOutputTokens", "max_tokens"), ("stopSequences", "stop"), ("candidateCount", "n")],
+    );
+    request
+}
+
+/// Messages API request for a generateContent body. Roles have to alternate there, so
+/// consecutive contents of the same role are merged.
+fn to_anthropic(model: &str, body: &Value) -> Value {
+    let mut messages: Vec<Value> = Vec::new();
+    for (index, content) in contents(body).iter().enumerate() {
+        let parts = parts(content);
+        let mut blocks = Vec::new();
+        let role = if content["role"] == "model" {
+            let text = text_of(parts);
+            if !text.is_empty() {
+                blocks.push(json!({"type": "text", "text": text}));
+            }
+            for (k, call) in parts.iter().filter_map(|part| part.get("functionCall")).enumerate() {
+                blocks.push(json!({"type": "tool_use", "id": call_id(index, k), "name": call["name"], "input": args_of(call)}));
+            }
+            "assistant"
+        } else {
+            // Tool results have to come first in the message
+            for (k, response) in parts.iter().filter_map(|part| part.get("functionResponse")).enumerate() {
+                blocks.push(json!({
+                    "type": "tool_result",
+                    "tool_use_id": call_id(index.saturating_sub(1), k),
+                    "content": response_text(response),
+                    "is_error": response["response"].get("error").is_some()
+                }));
+            }
+            let text = user_text(parts);
+            if !text.is_empty() {
+                blocks.push(json!({"type": "text", "text": text}));
+            }
+            for (mime_type, data) in parts.iter().filter_map(images::inline_data) {
+                blocks.push(json!({"type": "image", "source": {"type": "base64", "media_type": mime_type, "data": data}}));
+            }
+            "user"
+        };
+        if blocks.is_empty() {
+            continue;
+        }
+        match messages.last_mut() {
+            Some(last) if last["role"] == role => {
+                if let Some(existing) = last["content"].as_array_mut() {
+                    existing.extend(blocks);
+                }
+            }
+            _ => messages.push(json!({"role": role, "content": blocks})),
+        }
+    }
+
+    let mut request = json!({"model": model, "max_tokens": ANTHROPIC_MAX_TOKENS, "messages": messages});
+    let system = system_text(body);
+    if !system.is_empty() {
+        request["system"] = json!(system);
+    }
+    let tools: Vec<Value> = declarations(body)
+        .iter()
+        .map(|decl| json!({"name": decl["name"], "description": decl["description"], "input_schema": parameters(decl)}))
+        .collect();
+    if !tools.is_empty() {
+        request["tools"] = json!(tools);
+    }
+    // The Messages API returns a single reply, so candidateCount has no counterpart
+    copy_generation_config(
+        body,
+        &mut request,
+        &[("temperature", "temperature"), ("topP", "top_p"), ("topK", "top_k"), ("maxOutputTokens", "max_tokens"), ("stopSequences", "stop_sequences")],
+    );
+    request
+}
+
+/// Copies the generationConfig fields the other API knows, under its names.
+fn copy_generation_config(body: &Value, request: &mut Value, fields: &[(&str, &str)]) {
+    for (field, name) in fields {
+        if let Some(value) = body["generationConfig"].get(field) {
+            request[name] = value.clone();
+        }
+    }
+}
+
+/// A generateContent response with one candidate. Like Gemini, a reply without parts has no
+/// content.
+fn generate_content_response(parts: Vec<Value>, finish: &str, input_tokens: &Value, output_tokens: &Value) -> Value {
+    let mut candidate = json!({"finishReason": finish});
+    if !parts.is_empty() {
+        candidate["content"] = json!({"role": "model", "parts": parts});
+    }
+    let mut response = json!({"candidates": [candidate]});
+    if let (Some(input), Some(output)) = (input_tokens.as_u64(), output_tokens.as_u64()) {
+        response["usageMetadata"] = json!({
+            "promptTokenCount": input,
+            "candidatesTokenCount": output,
+            "totalTokenCount": input + output
+        });
+    }
+    response
+}
+
+fn call_id(content_index: usize, call_index: usize) -> String {
+    format!("call_{}_{}", content_index, call_index)
+}
+
+fn contents(body: &Value) -> Vec<Value> {
+    body["contents"].as_array().cloned().unwrap_or_default()
+}
+
+fn parts(content: &Value) -> &[Value] {
+    content["parts"].as_array().map(Vec::as_slice).unwrap_or(&[])
+}
+
+/// The visible text of a content, without thoughts.
+fn text_of(parts: &[Value]) -> String {
+    parts
+        .iter()
+        .filter(|part| part.get("thought").is_none())
+        .filter_map(|part| part["text"].as_str())
+        .collect()
+}
+
+/// The text of a user content: the message and the files and pages attached to it, which
+/// are separate parts, one after the other.
+fn user_text(parts: &[Value]) -> String {
+    parts
+        .iter()
+        .filter_map(|part| part["text"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn system_text(body: &Value) -> String {
+    text_of(parts(&body["system_instruction"]))
+}
+
+fn args_of(call: &Value) -> Value {
+    match &call["args"] {
+        Value::Object(_) => call["args"].clone(),
+        _ => json!({}),
+    }
+}
+
+/// Tool results are sent as text; a lone string field (e.g. {"result": "..."}) is unwrapped.
+fn response_text(response: &Value) -> String {
+    match response["response"].as_object() {
+        Some(fields) if fields.len() == 1 => match fields.values().next() {
+            Some(Value::String(text)) => text.clone(),
+            _ => response["response"].to_string(),
+        },
+        _ => response["response"].to_string(),
+    }
+}
+
+fn declarations(body: &Value) -> Vec<Value> {
+    body["tools"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|tool| tool["function_declarations"].as_array())
+        .flatten()
+        .cloned()
+        .collect()
+}
+
+/// The parameter schema of a declaration; both APIs want an object schema even for tools
+/// without parameters.
+fn parameters(decl: &Value) -> Value {
+    match decl.get("parameters") {
+        Some(schema) if schema.is_object() => schema.clone(),
+        _ => json!({"type": "object", "properties": {}}),
+    }
+}
@@ -0,0 +1,408 @@
+use reqwest::Url;
+use scraper::{ElementRef, Html, Node, Selector};
+use std::collections::HashMap;
+
+// Elements whose text counts towards the content score of their parent and grandparent
+const SCORED_TAGS: &str = "p, pre, td, blockquote, section, h2, h3, h4, h5, h6";
+// Elements that are never part of the content
+const SKIPPED_TAGS: &[&str] = &[
+    "script", "style", "noscript", "nav", "footer", "aside", "form", "iframe", "svg", "button", "select",
+    "input", "textarea", "template", "canvas", "object", "embed", "dialog", "img", "picture", "video", "audio",
+];
+// Class or id words of page furniture: menus, comments, cookie banners, share buttons
+const UNLIKELY: &[&str] = &[
+    "banner", "breadcrumb", "combx", "comment", "community", "cookie", "disqus", "extra", "footer", "gdpr",
+    "header", "menu", "modal", "navbar", "newsletter", "pagination", "pager", "popup", "related", "remark",
+    "replies", "rss", "share", "shoutbox", "sidebar", "skyscraper", "social", "sponsor", "subscribe",
+    "supplemental", "toolbar",
+];
+// Class or id words that keep an element even when it also looks unlikely
+const MAYBE: &[&str] = &["article", "body", "column", "content", "main", "shadow"];
+const POSITIVE: &[&str] = &["article", "body", "content", "entry", "main", "page", "post", "story", "text", "blog"];
+const NEGATIVE: &[&str] = &[
+    "hidden", "banner", "combx", "comment", "contact", "foot", "masthead", "meta", "outbrain", "promo",
+    "related", "scroll", "share", "shopping", "sidebar", "sponsor", "tags", "tool", "widget",
+];
+// Paragraphs shorter than this are ignored when scoring
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+/// The readable part of a web page.
+pub struct Article {
+    pub title: String,
+    /// The main content as Markdown, without navigation, footers, comments and ads
+    pub markdown: String,
+}
+
+/// Finds the main content of an HTML page the way Readability does: paragraphs add to the
+/// score of the elements that contain them, class names and link density adjust it, and the
+/// best-scoring element (with related siblings) is converted to Markdown. Links are made
+/// absolute against `url`.
+pub fn extract(html: &str, url: &str) -> Article {
+    let document = Html::parse_document(html);
+    let title = first_text(&document, "title")
+        .or_else(|| first_text(&document, "h1"))
+        .unwrap_or_default();
+    let body = document
+        .select(&Selector::parse("body").unwrap())
+        .next()
+        .unwrap_or_else(|| document.root_element());
+
+    let mut renderer = Renderer {
+        base: Url::parse(url).ok(),
+        out: String::new(),
+        space: false,
+    };
+    for element in main_content(body) {
+        renderer.element(element);
+    }
+    Article {
+        title,
+        markdown: renderer.finish(),
+    }
+}
+
+fn first_text(document: &Html, selector: &str) -> Option<String> {
+    document
+        .select(&Selector::parse(selector).unwrap())
+        .next()
+        .map(|element| collapse(&element.text().collect::<String>()))
+        .filter(|text| !text.is_empty())
+}
+
+/// The best-scoring element and those of its siblings that look like part of the same text,
+/// or the whole body when nothing scored.
+fn main_content(body: ElementRef) -> Vec<ElementRef> {
+    let mut scores = HashMap::new();
+    for paragraph in body.select(&Selector::parse(SCORED_TAGS).unwrap()) {
+        if excluded(paragraph) {
+            continue;
+        }
+        let text = collapse(&paragraph.text().collect::<String>());
+        let length = text.chars().count();
+        if length < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f32 + (length / 100).min(3) as f32;
+        for (level, ancestor) in paragraph.ancestors().filter_map(ElementRef::wrap).take(2).enumerate() {
+            let (_, total) = scores
+                .entry(ancestor.id())
+                .or_insert_with(|| (ancestor, initial_score(ancestor)));
+            *total += if level == 0 { score } else { score / 2.0 };
+        }
+    }
+
+    let final_score = |element: ElementRef, score: f32| score * (1.0 - link_density(element));
+    let best = scores
+        .values()
+        .map(|&(element, score)| (element, final_score(element, score)))
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    let (top, top_score) = match best {
+        Some(best) => best,
+        None => return vec![body],
+    };
+    let parent = match top.parent().and_then(ElementRef::wrap) {
+        Some(parent) => parent,
+        None => return vec![top],
+    };
+
+    let threshold = (top_score * 0.2).max(10.0);
+    parent
+        .children()
+        .filter_map(ElementRef::wrap)
+        .filter(|sibling| {
+            if sibling.id() == top.id() {
+                return true;
+            }
+            if let Some(&(_, score)) = scores.get(&sibling.id()) {
+                if final_score(*sibling, score) >= threshold {
+                    return true;
+                }
+            }
+            let text = collapse(&sibling.text().collect::<String>());
+            sibling.value().name() == "p" && text.chars().count() > 80 && link_density(*sibling) < 0.25
+        })
+        .collect()
+}
+
+fn initial_score(element: ElementRef) -> f32 {
+    let tag = match element.value().name() {
+        "div" | "article" | "main" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+    tag + class_weight(element)
+}
+
+/// +25 for each of class and id that suggests content, -25 for each that suggests furniture.
+fn class_weight(element: ElementRef) -> f32 {
+    let value = element.value();
+    let class = value.attr("class").unwrap_or("").to_lowercase();
+    let id = value.id().unwrap_or("").to_lowercase();
+    [class, id]
+        .iter()
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let mut weight = 0.0;
+            if NEGATIVE.iter().any(|word| name.contains(word)) {
+                weight -= 25.0;
+            }
+            if POSITIVE.iter().any(|word| name.contains(word)) {
+                weight += 25.0;
+            }
+            weight
+        })
+        .sum()
+}
+
+/// Share of the element's text that is link text.
+fn link_density(element: ElementRef) -> f32 {
+    let length = element.text().map(|t| t.trim().chars().count()).sum::<usize>();
+    if length == 0 {
+        return 0.0;
+    }
+    let links = element
+        .select(&Selector::parse("a").unwrap())
+        .flat_map(|link| link.text())
+        .map(|t| t.trim().chars().count())
+        .sum::<usize>();
+    links as f32 / length as f32
+}
+
+/// Whether the element or one of its ancestors is skipped, hidden or page furniture.
+fn excluded(element: ElementRef) -> bool {
+    std::iter::once(element)
+        .chain(element.ancestors().filter_map(ElementRef::wrap))
+        .any(skipped)
+}
+
+fn skipped(element: ElementRef) -> bool {
+    let value = element.value();
+    if SKIPPED_TAGS.contains(&value.name()) || value.attr("hidden").is_some() || value.attr("aria-hidden") == Some("true") {
+        return true;
+    }
+    if matches!(value.name(), "body" | "html" | "article" | "main") {
+        return false;
+    }
+    let names = format!("{} {}", value.attr("class").unwrap_or(""), value.id().unwrap_or("")).to_lowercase();
+    UNLIKELY.iter().any(|word| names.contains(word)) && !MAYBE.iter().any(|word| names.contains(word))
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Writes elements as Markdown.
+struct Renderer {
+    base: Option<Url>,
+    out: String,
+    /// Whitespace was seen and is written before the next word
+    space: bool,
+}
+
+impl Renderer {
+    fn finish(self) -> String {
+        let mut text = String::new();
+        let mut blank = 0;
+        for line in self.out.lines().map(str::trim_end) {
+            blank = if line.is_empty() { blank + 1 } else { 0 };
+            if blank < 2 {
+                text.push_str(line);
+                text.push('\n');
+            }
+        }
+        text.trim().to_string()
+    }
+
+    /// A renderer for the content of a list item, quote or table cell.
+    fn nested(&self) -> Renderer {
+        Renderer {
+            base: self.base.clone(),
+            out: String::new(),
+            space: false,
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        for word in text.split_inclusive(char::is_whitespace) {
+            let trimmed = word.trim_end();
+            if !trimmed.is_empty() {
+                if self.space && !self.out.is_empty() && !self.out.ends_with(['\n', ' ']) {
+                    self.out.push(' ');
+                }
+                self.out.push_str(trimmed);
+                self.space = false;
+            }
+            if trimmed.len() < word.len() {
+                self.space = true;
+            }
+        }
+    }
+
+    /// Starts a new paragraph.
+    fn block(&mut self) {
+        let trimmed = self.out.trim_end_matches(' ').len();
+        self.out.truncate(trimmed);
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push_str(if self.out.ends_with('\n') { "\n" } else { "\n\n" });
+        }
+        self.space = false;
+    }
+
+    fn children(&mut self, element: ElementRef) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => self.text(text),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.element(child);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn element(&mut self, element: ElementRef) {
+        if skipped(element) {
+            return;
+        }
+        let name = element.value().name();
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse().unwrap_or(1);
+                let text = collapse(&element.text().collect::<String>());
+                if !text.is_empty() {
+                    self.block();
+                    self.out.push_str(&format!("{} {}", "#".repeat(level), text));
+                    self.block();
+                }
+            }
+            "p" | "div" | "section" | "article" | "main" | "header" | "figure" | "figcaption" | "dl" | "dd" | "dt" | "address" => {
+                self.block();
+                self.children(element);
+                self.block();
+            }
+            "br" => {
+                self.out.push('\n');
+                self.space = false;
+            }
+            "hr" => {
+                self.block();
+                self.out.push_str("---");
+                self.block();
+            }
+            "pre" => {
+                let code = element.text().collect::<String>();
+                self.block();
+                self.out.push_str(&format!("```\n{}\n```", code.trim_end()));
+                self.block();
+            }
+            "code" | "kbd" | "samp" => {
+                let code = element.text().collect::<String>();
+                if !code.trim().is_empty() {
+                    self.text(&format!("`{}`", code.trim()));
+                }
+            }
+            "strong" | "b" => self.wrapped(element, "**"),
+            "em" | "i" => self.wrapped(element, "*"),
+            "a" => self.link(element),
+            "ul" | "ol" => self.list(element, name == "ol"),
+            "blockquote" => {
+                let mut quote = self.nested();
+                quote.children(element);
+                let quote = quote.finish();
+                if !quote.is_empty() {
+                    self.block();
+                    let lines: Vec<String> = quote.lines().map(|line| format!("> {}", line).trim_end().to_string()).collect();
+                    self.out.push_str(&lines.join("\n"));
+                    self.block();
+                }
+            }
+            "table" => self.table(element),
+            _ => self.children(element),
+        }
+    }
+
+    fn wrapped(&mut self, element: ElementRef, marker: &str) {
+        let text = collapse(&element.text().collect::<String>());
+        if !text.is_empty() {
+            self.text(&format!("{}{}{}", marker, text, marker));
+        }
+    }
+
+    fn link(&mut self, element: ElementRef) {
+        let text = collapse(&element.text().collect::<String>());
+        if text.is_empty() {
+            return;
+        }
+        let href = element.value().attr("href").unwrap_or("").trim();
+        let target = match &self.base {
+            _ if href.is_empty() || href.starts_with('#') || href.starts_with("javascript:") => None,
+            Some(base) => base.join(href).ok().map(|url| url.to_string()),
+            None => Some(href.to_string()),
+        };
+        match target {
+            Some(target) => self.text(&format!("[{}]({})", text, target)),
+            None => self.text(&text),
+        }
+    }
+
+    fn list(&mut self, element: ElementRef, ordered: bool) {
+        self.block();
+        let mut number = 0;
+        for item in element.children().filter_map(ElementRef::wrap) {
+            if item.value().name() != "li" || skipped(item) {
+                continue;
+            }
+            number += 1;
+            let marker = if ordered { format!("{}. ", number) } else { "- ".to_string() };
+            let mut content = self.nested();
+            content.children(item);
+            let content = content.finish();
+            if content.is_empty() {
+                continue;
+            }
+            // Items stay compact: paragraphs and sublists inside them are not spaced out
+            let indent = " ".repeat(marker.len());
+            for (index, line) in content.lines().filter(|line| !line.is_empty()).enumerate() {
+                if index == 0 {
+                    self.out.push_str(&format!("{}{}\n", marker, line));
+                } else {
+                    self.out.push_str(&format!("{}{}\n", indent, line));
+                }
+            }
+        }
+        self.block();
+    }
+
+    fn table(&mut self, element: ElementRef) {
+        let row_selector = Selector::parse("tr").unwrap();
+        let cell_selector = Selector::parse("th, td").unwrap();
+        let rows: Vec<Vec<String>> = element
+            .select(&row_selector)
+            .map(|row| {
+                row.select(&cell_selector)
+                    .map(|cell| {
+                        let mut content = self.nested();
+                        content.children(cell);
+                        content.finish().replace('\n', " ").replace('|', "\\|")
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .filter(|cells| cells.iter().any(|cell| !cell.is_empty()))
+            .collect();
+        if rows.is_empty() {
+            return;
+        }
+        self.block();
+        for (index, cells) in rows.iter().enumerate() {
+            self.out.push_str(&format!("| {} |\n", cells.join(" | ")));
+            if index == 0 {
+                self.out.push_str(&format!("|{}\n", " --- |".repeat(cells.len())));
+            }
+        }
+        self.block();
+    }
+}
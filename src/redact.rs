@@ -0,0 +1,204 @@
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde_json::Value;
+use std::env;
+
+use crate::config;
+use crate::ui;
+
+const REDACTED: &str = "[REDACTED]";
+
+// Credentials that look the same wherever they appear: Google, AWS, OpenAI, Anthropic,
+// Stripe and Slack keys, GitHub tokens, bearer headers, JSON web tokens and private keys
+static SECRET_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?x)
+        AIza[0-9A-Za-z_\-]{35}
+        | \b(?:AKIA|ASIA)[0-9A-Z]{16}\b
+        | \bsk-(?:ant-|proj-)?[0-9A-Za-z_\-]{32,}
+        | \b[sr]k_live_[0-9A-Za-z]{24,}
+        | \bxox[abposr]-[0-9A-Za-z\-]{10,}
+        | gh[pousr]_[0-9A-Za-z]{36,}
+        | github_pat_[0-9A-Za-z_]{40,}
+        | (?i:bearer)\s+[0-9A-Za-z._\-]{20,}
+        | \beyJ[0-9A-Za-z_\-]{10,}\.eyJ[0-9A-Za-z_\-]{10,}\.[0-9A-Za-z_\-]{10,}
+        | -----BEGIN[A-Z\ ]*PRIVATE\ KEY-----[\s\S]*?-----END[A-Z\ ]*PRIVATE\ KEY-----
+        "#,
+    )
+    .unwrap()
+});
+
+// password=..., "token": "...", API_KEY: ..., aws_secret_access_key = ... and the like; the
+// name is kept, and the value is only replaced when it looks like a credential
+static ASSIGNMENT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?i)(?P<name>(?:password|passwd|secret|token|api_key|apikey|aws_secret_access_key|client_secret)["']?\s*[=:]\s*["']?)(?P<value>[^\s"',;]{6,})"#,
+    )
+    .unwrap()
+});
+
+/// Replaces configured credentials and anything shaped like a key or token with [REDACTED].
+pub fn redact_secrets(text: &str) -> String {
+    redact(text, &known_secrets()).0
+}
+
+/// Redacts credentials in every string of a JSON value, such as a tool call's arguments.
+pub fn redact_json(value: &mut Value) {
+    redact_value(value, &known_secrets());
+}
+
+/// Redacts what is about to go to the model: tool results, files and pages attached to a
+/// message, and command output. `source` names it in the warning shown when something was
+/// found. Returns how many secrets were replaced. GEMINI_REDACT=0 sends everything as it is.
+pub fn before_sending(value: &mut Value, source: &str) -> usize {
+    if !enabled() {
+        return 0;
+    }
+    let count = redact_value(value, &known_secrets());
+    if count > 0 {
+        warn(count, source);
+    }
+    count
+}
+
+/// Like before_sending, for text. Returns the text with secrets replaced, followed by a note
+/// for the model when something was.
+pub fn text_before_sending(text: String, source: &str) -> String {
+    if !enabled() {
+        return text;
+    }
+    let (redacted, count) = redact(&text, &known_secrets());
+    if count == 0 {
+        return text;
+    }
+    warn(count, source);
+    format!("{}\n{}", redacted, note(count))
+}
+
+/// Replaces the credentials configured in ~/.gemini.conf or the environment in what a tool is
+/// about to send elsewhere, such as an HTTP request body, and warns when one was found.
+/// `destination` names it in the warning. Other tokens are left alone: the user may have
+/// handed them to the model for this very request.
+pub fn outgoing(text: &str, destination: &str) -> String {
+    if !enabled() {
+        return text.to_string();
+    }
+    let (text, count) = replace_known(text, &known_secrets());
+    if count > 0 {
+        ui::status(
+            &format!(
+                "Removed {} configured secret{} from {}",
+                count,
+                if count == 1 { "" } else { "s" },
+                destination
+            )
+            .color(Color::Yellow)
+            .to_string(),
+        );
+    }
+    text
+}
+
+/// Tells the model what the placeholders are, so it doesn't write them into files.
+pub fn note(count: usize) -> String {
+    format!(
+        "[Note: {} secret{} in this output {} replaced with {}. Don't write the placeholder into files; \
+         edit around it or ask the user to fill in the value.]",
+        count,
+        if count == 1 { "" } else { "s" },
+        if count == 1 { "was" } else { "were" },
+        REDACTED
+    )
+}
+
+fn enabled() -> bool {
+    !env::var("GEMINI_REDACT")
+        .map(|v| v == "0" || v == "false")
+        .unwrap_or(false)
+}
+
+fn warn(count: usize, source: &str) {
+    ui::status(
+        &format!(
+            "Redacted {} secret{} from {} before sending it to the model",
+            count,
+            if count == 1 { "" } else { "s" },
+            source
+        )
+        .color(Color::Yellow)
+        .to_string(),
+    );
+}
+
+/// Values of the credentials configured in ~/.gemini.conf or the environment, which
+/// tool output may have echoed verbatim.
+fn known_secrets() -> Vec<String> {
+    let mut secrets: Vec<String> = config::read_entries()
+        .into_iter()
+        .chain(env::vars())
+        .filter(|(key, value)| config::is_secret(key) && value.trim().len() >= 6)
+        .map(|(_, value)| value.trim().to_string())
+        .collect();
+    // Longest first, so a secret containing another is replaced whole
+    secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    secrets.dedup();
+    secrets
+}
+
+/// Redacts every string of a JSON value and returns how many secrets were replaced.
+fn redact_value(value: &mut Value, secrets: &[String]) -> usize {
+    match value {
+        Value::String(text) => {
+            let (redacted, count) = redact(text, secrets);
+            if count > 0 {
+                *text = redacted;
+            }
+            count
+        }
+        Value::Array(items) => items.iter_mut().map(|item| redact_value(item, secrets)).sum(),
+        Value::Object(map) => map.values_mut().map(|item| redact_value(item, secrets)).sum(),
+        _ => 0,
+    }
+}
+
+fn redact(text: &str, secrets: &[String]) -> (String, usize) {
+    let (text, mut count) = replace_known(text, secrets);
+    count += SECRET_RE.find_iter(&text).count();
+    let text = SECRET_RE.replace_all(&text, REDACTED);
+    let text = ASSIGNMENT_RE
+        .replace_all(&text, |captures: &Captures| {
+            if looks_like_credential(&captures["value"]) {
+                count += 1;
+                format!("{}{}", &captures["name"], REDACTED)
+            } else {
+                captures[0].to_string()
+            }
+        })
+        .to_string();
+    (text, count)
+}
+
+/// Replaces the given secret values and returns how many were found.
+fn replace_known(text: &str, secrets: &[String]) -> (String, usize) {
+    let mut text = text.to_string();
+    let mut count = 0;
+    for secret in secrets {
+        let found = text.matches(secret.as_str()).count();
+        if found > 0 {
+            text = text.replace(secret.as_str(), REDACTED);
+            count += found;
+        }
+    }
+    (text, count)
+}
+
+/// Whether an assigned value is a credential rather than code (`token: String`,
+/// `password = self.password`, `secret: ${SECRET}`) or a placeholder: at least eight
+/// characters with letters and digits, and none of the punctuation of expressions.
+fn looks_like_credential(value: &str) -> bool {
+    value.len() >= 8
+        && value.chars().any(|c| c.is_ascii_digit())
+        && value.chars().any(|c| c.is_alphabetic())
+        && !value.contains(['.', '(', ')', '[', ']', '{', '}', '<', '>', '$', '&'])
+}
@@ -0,0 +1,65 @@
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::runtime;
+use crate::spinner::Spinner;
+
+// Retries on the same model before giving up or moving to the fallback model
+const DEFAULT_MAX_RETRIES: u32 = 3;
+// Wait before the first retry; doubled for each further one
+const BASE_DELAY: Duration = Duration::from_secs(1);
+// Longest wait between two attempts
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Retries after a rate limit, overload or network failure, from GEMINI_MAX_RETRIES.
+pub fn max_retries() -> u32 {
+    env::var("GEMINI_MAX_RETRIES")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_RETRIES)
+}
+
+/// How long to wait before retry number `attempt` (1-based). A delay the server asked for
+/// is used up to MAX_DELAY, so a broken server can't stall the CLI; otherwise the wait doubles
+/// with each attempt, and a random half of it is dropped so clients that failed together
+/// don't retry together.
+pub fn backoff(attempt: u32, server_delay: Option<Duration>) -> Duration {
+    if let Some(delay) = server_delay {
+        return delay.min(MAX_DELAY);
+    }
+    let full = BASE_DELAY.saturating_mul(1 << attempt.saturating_sub(1).min(16)).min(MAX_DELAY);
+    full / 2 + full.mul_f64(random_fraction() / 2.0)
+}
+
+/// Connection failures, timeouts and dropped connections, which may work on another attempt.
+pub fn is_transient(error: &reqwest::Error) -> bool {
+    error.is_timeout() || error.is_connect() || error.is_request()
+}
+
+/// Sleeps before a retry, counting the seconds down in the spinner label. Fails when Ctrl-C
+/// cancels the wait.
+pub fn wait(spinner: &Spinner, label: &str, attempt: u32, delay: Duration) -> Result<(), String> {
+    let mut remaining = delay;
+    while !remaining.is_zero() {
+        spinner.set_label(&format!(
+            "{} (retry {}/{} in {}s)",
+            label,
+            attempt,
+            max_retries(),
+            remaining.as_secs_f32().ceil()
+        ));
+        let step = remaining.min(Duration::from_secs(1));
+        runtime::sleep(step)?;
+        remaining -= step;
+    }
+    spinner.set_label(&format!("{} (retry {}/{})", label, attempt, max_retries()));
+    Ok(())
+}
+
+/// A number in [0, 1), from the standard library's randomly seeded hasher.
+fn random_fraction() -> f64 {
+    let value = RandomState::new().build_hasher().finish();
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,254 @@
+use colored::{Color, Colorize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::commit::git;
+use crate::file_edit::{file_editor, read_file};
+use crate::function_response;
+use crate::github;
+use crate::models::Task;
+use crate::ChatManager;
+
+// Per-file diffs beyond this size are truncated before review
+const MAX_CHUNK_CHARS: usize = 30_000;
+// How many times the model may read files for context while reviewing one file
+const MAX_CONTEXT_ROUNDS: usize = 5;
+
+const SEVERITIES: &[&str] = &["critical", "major", "minor", "nit"];
+
+const INSTRUCTION: &str = "You are a meticulous senior code reviewer. You are given the diff of one file. \
+Look for bugs, security problems, incorrect error handling, race conditions, performance issues, \
+and unclear or inconsistent code. Only comment on the changed lines and their direct consequences. \
+If you need more context (the full file, callers, definitions), use the file_editor tool with the read or search sub-commands. \
+When you are done, reply with a JSON array only, without code fences. Each finding is an object with the keys \
+\"severity\" (one of critical, major, minor, nit), \"line\" (line number in the new file, or null), \
+\"title\" (one short sentence) and \"detail\" (what is wrong and how to fix it). \
+Reply with [] if the change looks good.";
+
+/// Which changes to review.
+pub enum Source {
+    /// Uncommitted changes to tracked files (staged and unstaged)
+    WorkingTree,
+    Staged,
+    Range(String),
+    PullRequest(u64),
+}
+
+struct Finding {
+    file: String,
+    severity: String,
+    line: Option<u64>,
+    title: String,
+    detail: String,
+}
+
+/// Handles `gemini-cli-rs review`: reviews the diff file by file and prints
+/// the findings grouped by severity as Markdown.
+pub fn run(chat_manager: &Arc<Mutex<ChatManager>>, source: Source, output: Option<&Path>) -> Result<(), String> {
+    let (diff, description) = match &source {
+        Source::WorkingTree => (git(&["diff", "HEAD"])?, "uncommitted changes".to_string()),
+        Source::Staged => (git(&["diff", "--staged"])?, "staged changes".to_string()),
+        Source::Range(range) => (git(&["diff", range])?, format!("`{}`", range)),
+        Source::PullRequest(number) => (github::pr_diff(None, *number)?, format!("pull request #{}", number)),
+    };
+
+    let chunks = split_by_file(&diff);
+    if chunks.is_empty() {
+        return Err(format!("No changes to review ({})", description.trim_matches('`')));
+    }
+
+    let mut findings = Vec::new();
+    for (index, (file, chunk)) in chunks.iter().enumerate() {
+        eprintln!(
+            "{} {} {}",
+            "Reviewing".color(Color::Cyan).bold(),
+            file,
+            format!("({}/{})", index + 1, chunks.len()).dimmed()
+        );
+        match review_file(chat_manager, file, chunk) {
+            Ok(mut file_findings) => findings.append(&mut file_findings),
+            Err(e) => eprintln!("{}", format!("Skipped {}: {}", file, e).color(Color::Red)),
+        }
+    }
+
+    let report = to_markdown(&description, chunks.len(), &findings);
+    println!("{}", report);
+    if let Some(path) = output {
+        fs::write(path, &report).map_err(|e| format!("Error writing to '{}': {}", path.display(), e))?;
+        eprintln!("{}", format!("Review saved to {}", path.display()).color(Color::Cyan));
+    }
+    Ok(())
+}
+
+/// Splits a multi-file diff into (path, diff) pairs, skipping binary files.
+fn split_by_file(diff: &str) -> Vec<(String, String)> {
+    let mut chunks: Vec<(String, String)> = Vec::new();
+    for line in diff.lines() {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            // "a/path b/path": the new path is the last one
+            let file = header
+                .rsplit(" b/")
+                .next()
+                .unwrap_or(header)
+                .to_string();
+            chunks.push((file, String::new()));
+        }
+        if let Some((_, chunk)) = chunks.last_mut() {
+            chunk.push_str(line);
+            chunk.push('\n');
+        }
+    }
+    chunks.retain(|(_, chunk)| !chunk.contains("\nBinary files "));
+    chunks
+}
+
+fn review_file(chat_manager: &Arc<Mutex<ChatManager>>, file: &str, chunk: &str) -> Result<Vec<Finding>, String> {
+    let chunk = if chunk.len() > MAX_CHUNK_CHARS {
+        let mut end = MAX_CHUNK_CHARS;
+        while !chunk.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}\n[diff truncated]", &chunk[..end])
+    } else {
+        chunk.to_string()
+    };
+
+    let mut contents = vec![json!({
+        "role": "user",
+        "parts": [{"text": format!("Review the changes to {}:\n\n{}", file, chunk)}]
+    })];
+
+    for round in 0..=MAX_CONTEXT_ROUNDS {
+        let mut body = json!({
+            "system_instruction": {"parts": [{"text": INSTRUCTION}]},
+            "contents": contents,
+        });
+        // On the last round the model has to answer with what it has
+        if round < MAX_CONTEXT_ROUNDS {
+            body["tools"] = json!([{"function_declarations": [read_only_file_editor()]}]);
+        }
+        let response = chat_manager.lock().unwrap().generate(Task::Main, &body, "reviewing")?;
+        let content = response["candidates"][0]["content"].clone();
+        if content.is_null() {
+            let reason = response["error"]["message"].as_str().unwrap_or("the model returned no answer");
+            return Err(reason.to_string());
+        }
+        let parts = content["parts"].as_array().cloned().unwrap_or_default();
+
+        let calls: Vec<&Value> = parts.iter().filter_map(|p| p.get("functionCall")).collect();
+        if calls.is_empty() {
+            let text: String = parts.iter().filter_map(|p| p["text"].as_str()).collect();
+            return Ok(parse_findings(file, &text));
+        }
+
+        let responses: Vec<Value> = calls
+            .iter()
+            .map(|call| {
+                let args = &call["args"];
+                let get = |key: &str| args.get(key).and_then(|v| v.as_str());
+                let subcommand = get("subcommand").unwrap_or("read");
+                let filename = get("filename").unwrap_or("");
+                eprintln!("  {}", format!("{} {}", subcommand, filename).dimmed());
+                let result = match subcommand {
+                    "read" => {
+                        let line = |key: &str| args.get(key).and_then(|l| l.as_u64()).map(|l| l as usize);
+                        read_file(filename, line("start_line"), line("end_line"))
+                    }
+                    "search" => file_editor(subcommand, filename, get("data"), None),
+                    _ => "Error: only read and search are available during review".to_string(),
+                };
+                function_response(call["name"].as_str().unwrap_or(""), &call["id"], json!({"result": result}))
+            })
+            .collect();
+        contents.push(content);
+        contents.push(json!({"role": "user", "parts": responses}));
+    }
+    Err("the model kept requesting context without answering".to_string())
+}
+
+fn read_only_file_editor() -> Value {
+    json!({
+        "name": "file_editor",
+        "description": "Read files in the repository, or search them with a regex, to get context for the review.",
+        "parameters": {
+            "type": "object",
+            "properties": {
+                "subcommand": {"type": "string", "enum": ["read", "search"]},
+                "filename": {"type": "string", "description": "Path relative to the repository root"},
+                "data": {"type": "string", "description": "Regex pattern (for search)"},
+                "start_line": {"type": "integer", "description": "First line to read, counting from 1 (for read)"},
+                "end_line": {"type": "integer", "description": "Last line to read (for read)"}
+            },
+            "required": ["subcommand", "filename"]
+        }
+    })
+}
+
+/// Parses the JSON array of findings. An answer that isn't JSON is kept as a single note.
+fn parse_findings(file: &str, text: &str) -> Vec<Finding> {
+    let json_text = match (text.find('['), text.rfind(']')) {
+        (Some(start), Some(end)) if start < end => &text[start..=end],
+        _ => text,
+    };
+    match serde_json::from_str::<Vec<Value>>(json_text) {
+        Ok(items) => items
+            .iter()
+            .map(|item| {
+                let severity = item["severity"].as_str().unwrap_or("minor").to_lowercase();
+                Finding {
+                    file: file.to_string(),
+                    severity: if SEVERITIES.contains(&severity.as_str()) { severity } else { "minor".to_string() },
+                    line: item["line"].as_u64(),
+                    title: item["title"].as_str().unwrap_or("").trim().to_string(),
+                    detail: item["detail"].as_str().unwrap_or("").trim().to_string(),
+                }
+            })
+            .collect(),
+        Err(_) if text.trim().is_empty() => Vec::new(),
+        Err(_) => vec![Finding {
+            file: file.to_string(),
+            severity: "minor".to_string(),
+            line: None,
+            title: "Reviewer notes".to_string(),
+            detail: text.trim().to_string(),
+        }],
+    }
+}
+
+fn to_markdown(description: &str, file_count: usize, findings: &[Finding]) -> String {
+    let mut output = format!(
+        "# Code review\n\nReviewed {} ({} file{}): {} finding{}.\n",
+        description,
+        file_count,
+        if file_count == 1 { "" } else { "s" },
+        findings.len(),
+        if findings.len() == 1 { "" } else { "s" }
+    );
+    if findings.is_empty() {
+        output.push_str("\nNo issues found.\n");
+        return output;
+    }
+
+    for severity in SEVERITIES {
+        let group: Vec<&Finding> = findings.iter().filter(|f| f.severity == *severity).collect();
+        if group.is_empty() {
+            continue;
+        }
+        let mut heading = severity.to_string();
+        heading[..1].make_ascii_uppercase();
+        output.push_str(&format!("\n## {} ({})\n\n", heading, group.len()));
+        for finding in group {
+            let location = match finding.line {
+                Some(line) => format!("{}:{}", finding.file, line),
+                None => finding.file.clone(),
+            };
+            output.push_str(&format!("- **`{}`** — {}\n", location, finding.title));
+            for line in finding.detail.lines() {
+                output.push_str(&format!("  {}\n", line));
+            }
+        }
+    }
+    output
+}
@@ -0,0 +1,58 @@
+use once_cell::sync::Lazy;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+use tokio::sync::Notify;
+
+const CANCELLED: &str = "Cancelled with Ctrl-C";
+
+// Network requests run on this runtime; the rest of the CLI stays synchronous and waits for
+// them with block_on
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("gemini-network")
+        .build()
+        .expect("Cannot start the async runtime")
+});
+
+// Ctrl-C presses so far, and the signal that wakes the requests waiting on them
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+static INTERRUPTED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Runs a future to completion on the shared runtime. A Ctrl-C while it runs drops the
+/// future, which closes its connections and stops any tasks it was waiting for, and returns
+/// an error instead.
+pub fn block_on<F: Future>(future: F) -> Result<F::Output, String> {
+    let seen = INTERRUPTS.load(Ordering::SeqCst);
+    RUNTIME.block_on(async {
+        tokio::select! {
+            output = future => Ok(output),
+            _ = interrupted(seen) => Err(CANCELLED.to_string()),
+        }
+    })
+}
+
+/// Sleeps for `duration`, or until Ctrl-C is pressed.
+pub fn sleep(duration: Duration) -> Result<(), String> {
+    block_on(tokio::time::sleep(duration))
+}
+
+/// Cancels whatever block_on is waiting for; called by the Ctrl-C handler.
+pub fn interrupt() {
+    INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+    INTERRUPTED.notify_waiters();
+}
+
+/// Completes once Ctrl-C has been pressed more often than `seen` times.
+async fn interrupted(seen: usize) {
+    loop {
+        // Created before the check, so a press in between still wakes it
+        let notified = INTERRUPTED.notified();
+        if INTERRUPTS.load(Ordering::SeqCst) != seen {
+            return;
+        }
+        notified.await;
+    }
+}
@@ -0,0 +1,104 @@
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::env;
+
+use crate::ui;
+
+// Harm categories by the short names GEMINI_SAFETY uses
+const CATEGORIES: &[(&str, &str)] = &[
+    ("harassment", "HARM_CATEGORY_HARASSMENT"),
+    ("hate", "HARM_CATEGORY_HATE_SPEECH"),
+    ("sexual", "HARM_CATEGORY_SEXUALLY_EXPLICIT"),
+    ("dangerous", "HARM_CATEGORY_DANGEROUS_CONTENT"),
+    ("civic", "HARM_CATEGORY_CIVIC_INTEGRITY"),
+];
+
+// Thresholds, from blocking nothing to blocking the most
+const THRESHOLDS: &[(&str, &str)] = &[
+    ("off", "OFF"),
+    ("none", "BLOCK_NONE"),
+    ("high", "BLOCK_ONLY_HIGH"),
+    ("medium", "BLOCK_MEDIUM_AND_ABOVE"),
+    ("low", "BLOCK_LOW_AND_ABOVE"),
+];
+
+// Read once; a mistake is reported when the first request is sent
+static SETTINGS: Lazy<Option<Value>> = Lazy::new(|| {
+    let setting = env::var("GEMINI_SAFETY").unwrap_or_default();
+    match parse(&setting) {
+        Ok(settings) => settings,
+        Err(e) => {
+            ui::status(&format!("Ignoring GEMINI_SAFETY: {}", e).color(Color::Yellow).to_string());
+            None
+        }
+    }
+});
+
+/// The safetySettings for Gemini requests, from GEMINI_SAFETY: a threshold for every
+/// category (`none`), thresholds per category (`dangerous=none, harassment=high`), or both
+/// (`high, dangerous=none`). None when it isn't set, so the API's defaults apply.
+pub fn settings() -> Option<Value> {
+    SETTINGS.clone()
+}
+
+fn parse(setting: &str) -> Result<Option<Value>, String> {
+    let mut thresholds: Vec<(&str, &str)> = Vec::new();
+    for entry in setting.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (categories, threshold) = match entry.split_once('=') {
+            Some((category, threshold)) => {
+                let category = CATEGORIES
+                    .iter()
+                    .find(|(name, api)| name.eq_ignore_ascii_case(category.trim()) || api.eq_ignore_ascii_case(category.trim()))
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = CATEGORIES.iter().map(|(name, _)| *name).collect();
+                        format!("unknown category '{}'; use {}", category.trim(), names.join(", "))
+                    })?;
+                (vec![category.1], threshold)
+            }
+            None => (CATEGORIES.iter().map(|(_, api)| *api).collect(), entry),
+        };
+        let threshold = THRESHOLDS
+            .iter()
+            .find(|(name, api)| name.eq_ignore_ascii_case(threshold.trim()) || api.eq_ignore_ascii_case(threshold.trim()))
+            .map(|(_, api)| *api)
+            .ok_or_else(|| {
+                let names: Vec<&str> = THRESHOLDS.iter().map(|(name, _)| *name).collect();
+                format!("unknown threshold '{}'; use {}", threshold.trim(), names.join(", "))
+            })?;
+        for category in categories {
+            // Later entries win, so a category can differ from a threshold given for all
+            thresholds.retain(|(c, _)| *c != category);
+            thresholds.push((category, threshold));
+        }
+    }
+    if thresholds.is_empty() {
+        return Ok(None);
+    }
+    let settings: Vec<Value> = thresholds
+        .iter()
+        .map(|(category, threshold)| json!({"category": category, "threshold": threshold}))
+        .collect();
+    Ok(Some(json!(settings)))
+}
+
+/// The categories a response's safety ratings flagged, e.g. "dangerous content (HIGH)".
+pub fn flagged(ratings: &Value) -> Vec<String> {
+    ratings
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|rating| {
+            rating["blocked"].as_bool().unwrap_or(false)
+                || matches!(rating["probability"].as_str(), Some("MEDIUM" | "HIGH"))
+        })
+        .filter_map(|rating| {
+            let category = rating["category"].as_str()?;
+            let name = category.trim_start_matches("HARM_CATEGORY_").replace('_', " ").to_lowercase();
+            Some(match rating["probability"].as_str() {
+                Some(probability) => format!("{} ({})", name, probability),
+                None => name,
+            })
+        })
+        .collect()
+}
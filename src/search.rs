@@ -1,179 +1,197 @@
 use colored::{Color, Colorize};
-use reqwest::blocking::{Client, ClientBuilder};
-use reqwest::StatusCode;
-use scraper::{Html, Selector};
+use reqwest::{Client, StatusCode};
 use serde_json::{json, Value};
 use std::collections::{HashMap, HashSet};
 use std::env;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
-use urlencoding;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::documents;
+use crate::search_providers::{self, SearchHit, SearchProvider};
+use crate::spinner::Spinner;
+use crate::http;
+use crate::politeness;
+use crate::runtime;
+use crate::ui;
 
 pub const RELEVANCE_THRESHOLD: f32 = 0.05;
 pub const NETWORK_TIMEOUT: u64 = 30;
-
+// Results requested from the search engine
+const DEFAULT_RESULTS: usize = 10;
+// Result pages read at the same time
+const DEFAULT_PARALLEL: usize = 5;
+// Characters of one page's text returned to the model
+const DEFAULT_PAGE_CHARS: usize = 6_000;
+// Characters of page text returned for one search, all pages together
+const DEFAULT_TOTAL_CHARS: usize = 15_000;
+// Documents larger than this are not downloaded
+const MAX_DOCUMENT_BYTES: u64 = 20_000_000;
+
+/// Searches the web with the configured provider, reads the result pages in parallel and
+/// returns the most relevant ones. Ctrl-C stops the search, including pages still loading.
 pub fn search_online(query: &str) -> String {
-    let api_key =
-        env::var("GOOGLE_SEARCH_API_KEY").expect("GOOGLE_SEARCH_API_KEY not found in ~/.gemini");
-    let cx = env::var("GOOGLE_SEARCH_ENGINE_ID")
-        .expect("GOOGLE_SEARCH_ENGINE_ID not found in ~/.gemini");
+    let provider = match search_providers::current() {
+        Ok(provider) => provider,
+        Err(e) => return format!("Search is not available: {}", e),
+    };
 
-    println!(
+    ui::status(&format!(
         "{} {}",
         "Gemini is searching online for:".color(Color::Cyan).bold(),
         query
-    );
-
-    // Create a client with timeout
-    let client = ClientBuilder::new()
-        .connect_timeout(Duration::from_secs(NETWORK_TIMEOUT))
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/134.0.0.0 Safari/537.36")
-        .build()
-        .unwrap_or_else(|_| Client::new());
-
-    let url = format!(
-        "https://www.googleapis.com/customsearch/v1?key={}&cx={}&q={}",
-        api_key,
-        cx,
-        urlencoding::encode(query)
-    );
-
-    match client.get(&url).send() {
-        Ok(response) => {
-            let json: Value = match response.json() {
-                Ok(j) => j,
-                Err(e) => return format!("Failed to parse search response: {}", e),
-            };
-            let items = json.get("items").and_then(|i| i.as_array());
-            if let Some(items) = items {
-                // Convert items to a Vec we can use for parallel processing
-                let item_values: Vec<Value> = items.iter().cloned().collect();
-
-                // Create thread-safe results container
-                let search_results: Arc<Mutex<Vec<(String, String, String)>>> =
-                    Arc::new(Mutex::new(Vec::with_capacity(item_values.len())));
-
-                // Create threads for parallel scraping
-                let mut handles = vec![];
-
-                for item in item_values {
-                    // Clone shared resources for the thread
-                    let search_results_clone = Arc::clone(&search_results);
-
-                    // Extract data before spawning the thread
-                    let title = item
-                        .get("title")
-                        .and_then(|t| t.as_str())
-                        .unwrap_or("No title")
-                        .to_string();
-                    let link = item
-                        .get("link")
-                        .and_then(|l| l.as_str())
-                        .unwrap_or("No link")
-                        .to_string();
-
-                    // Spawn a thread for each search result
-                    let handle = thread::spawn(move || {
-                        let content = scrape_url(&link);
+    ));
 
-                        // Store the result in our shared vector
-                        search_results_clone
-                            .lock()
-                            .unwrap()
-                            .push((title, link, content));
-                    });
+    let client = http::page_client();
+    let count = setting("GEMINI_SEARCH_RESULTS", DEFAULT_RESULTS);
+    let hits = match runtime::block_on(search_hits(provider.as_ref(), &client, query, count)) {
+        Ok(Ok(hits)) => hits,
+        Ok(Err(e)) | Err(e) => return format!("Search failed ({}): {}", provider.name(), e),
+    };
+    if hits.is_empty() {
+        return "No results found.".to_string();
+    }
+    // Shown when none of the pages can be read
+    let snippets: Vec<Value> = hits
+        .iter()
+        .filter(|hit| !hit.snippet.is_empty())
+        .map(|hit| json!({"title": hit.title, "link": hit.link, "snippet": hit.snippet}))
+        .collect();
+
+    // Read the pages on the runtime, at most GEMINI_SEARCH_PARALLEL at a time
+    let total = hits.len();
+    let mut spinner = Spinner::new();
+    spinner.start(&format!("scraping 0/{}", total));
+    let label = spinner.label();
+    let slots = Arc::new(Semaphore::new(setting("GEMINI_SEARCH_PARALLEL", DEFAULT_PARALLEL).max(1)));
+    let scraped = runtime::block_on(async {
+        // Dropping the set on Ctrl-C aborts the pages still loading
+        let mut tasks = JoinSet::new();
+        for (position, SearchHit { title, link, .. }) in hits.into_iter().enumerate() {
+            let client = client.clone();
+            let slots = Arc::clone(&slots);
+            tasks.spawn(async move {
+                let _slot = slots.acquire_owned().await;
+                let content = scrape(&client, &link).await;
+                (position, title, link, content)
+            });
+        }
+        let mut pages = Vec::with_capacity(total);
+        while let Some(page) = tasks.join_next().await {
+            pages.extend(page.ok());
+            label.set(&format!("scraping {}/{}", pages.len(), total));
+        }
+        pages
+    });
+    let mut scraped = match scraped {
+        Ok(pages) => pages,
+        Err(e) => return format!("Search failed ({}): {} before the result pages were read", provider.name(), e),
+    };
+    spinner.set_label("ranking results");
 
-                    handles.push(handle);
-                }
+    // In the search engine's order, so equal scores keep it
+    scraped.sort_by_key(|(position, ..)| *position);
+    let search_results: Vec<(String, String, String)> =
+        scraped.into_iter().map(|(_, title, link, content)| (title, link, content)).collect();
 
-                // Wait for all threads to complete
-                for handle in handles {
-                    let _ = handle.join();
-                }
+    let documents: Vec<&str> = search_results
+        .iter()
+        .filter_map(|(_, _, content)| {
+            if content.starts_with("Error") || content.starts_with("Skipped") {
+                None
+            } else {
+                Some(content.as_str())
+            }
+        })
+        .collect();
+
+    if documents.is_empty() {
+        spinner.stop();
+        if !snippets.is_empty() {
+            return format!(
+                "None of the result pages could be read; the search engine's excerpts:\n{}",
+                serde_json::to_string(&snippets).unwrap_or_default()
+            );
+        }
+        return "No valid content to process.".to_string();
+    }
 
-                // Get the results from the Mutex
-                let search_results = Arc::try_unwrap(search_results)
-                    .expect("Arc still has multiple owners")
-                    .into_inner()
-                    .expect("Mutex is poisoned");
-
-                let documents: Vec<&str> = search_results
-                    .iter()
-                    .filter_map(|(_, _, content)| {
-                        if content.starts_with("Error") || content.starts_with("Skipped") {
-                            None
-                        } else {
-                            Some(content.as_str())
-                        }
-                    })
-                    .collect();
+    let tfidf = compute_tfidf(&documents);
+    let query_vector = tf_vector(query, &tfidf);
+    let query_graph = build_term_graph(query);
 
-                if documents.is_empty() {
-                    return "No valid content to process.".to_string();
-                }
+    let mut scored_results: Vec<(f32, String, String, String)> = search_results
+        .into_iter()
+        .filter_map(|(title, link, content)| {
+            if content.starts_with("Error") || content.starts_with("Skipped") {
+                return None;
+            }
 
-                let tfidf = compute_tfidf(&documents);
-                let query_vector = tf_vector(query, &tfidf);
-                let query_graph = build_term_graph(query);
+            let doc_vector = tf_vector(&content, &tfidf);
+            let tfidf_similarity = cosine_similarity(&query_vector, &doc_vector);
 
-                let mut scored_results: Vec<(f32, String, String, String)> = search_results
-                    .into_iter()
-                    .filter_map(|(title, link, content)| {
-                        if content.starts_with("Error") || content.starts_with("Skipped") {
-                            return None;
-                        }
+            let doc_graph = build_term_graph(&content);
+            let graph_similarity = graph_similarity(&query_graph, &doc_graph);
 
-                        let doc_vector = tf_vector(&content, &tfidf);
-                        let tfidf_similarity = cosine_similarity(&query_vector, &doc_vector);
-
-                        let doc_graph = build_term_graph(&content);
-                        let graph_similarity = graph_similarity(&query_graph, &doc_graph);
-
-                        let combined_similarity = 0.7 * tfidf_similarity + 0.3 * graph_similarity;
-                        //println!(
-                        //    "Score for {}: TF-IDF={}, Graph={}, Combined={}",
-                        //    link, tfidf_similarity, graph_similarity, combined_similarity
-                        //);
-                        Some((combined_similarity, title, link, content))
-                    })
-                    .collect();
-
-                scored_results
-                    .sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
-                let filtered_results: Vec<_> = scored_results
-                    .into_iter()
-                    .take(3)
-                    .filter(|(score, _, _, _)| *score >= RELEVANCE_THRESHOLD)
-                    .map(|(_, title, link, content)| {
-                        json!({
-                            "title": title,
-                            "link": link,
-                            "content": content
-                        })
-                    })
-                    .collect();
-
-                if filtered_results.is_empty() {
-                    "No relevant results found, please ask the user if your should try a different search query.".to_string()
-                } else {
-                    serde_json::to_string(&filtered_results)
-                        .unwrap_or("Error serializing results".to_string())
-                }
-            } else {
-                "No results found.".to_string()
-            }
-        }
-        Err(e) => {
-            if e.is_timeout() {
-                "Search failed: Request timed out".to_string()
-            } else {
-                format!("Search failed: {}", e)
+            let combined_similarity = 0.7 * tfidf_similarity + 0.3 * graph_similarity;
+            //println!(
+            //    "Score for {}: TF-IDF={}, Graph={}, Combined={}",
+            //    link, tfidf_similarity, graph_similarity, combined_similarity
+            //);
+            Some((combined_similarity, title, link, content))
+        })
+        .collect();
+
+    spinner.stop();
+
+    scored_results
+        .sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    let page_chars = setting("GEMINI_SEARCH_PAGE_CHARS", DEFAULT_PAGE_CHARS);
+    let mut remaining_chars = setting("GEMINI_SEARCH_TOTAL_CHARS", DEFAULT_TOTAL_CHARS);
+    let filtered_results: Vec<_> = scored_results
+        .into_iter()
+        .take(3)
+        .filter(|(score, _, _, _)| *score >= RELEVANCE_THRESHOLD)
+        .map_while(|(_, title, link, content)| {
+            if remaining_chars == 0 {
+                return None;
             }
-        }
+            let content = shorten(&content, page_chars.min(remaining_chars));
+            remaining_chars = remaining_chars.saturating_sub(content.chars().count());
+            Some(json!({
+                "title": title,
+                "link": link,
+                "content": content
+            }))
+        })
+        .collect();
+
+    if filtered_results.is_empty() {
+        "No relevant results found, please ask the user if your should try a different search query.".to_string()
+    } else {
+        serde_json::to_string(&filtered_results)
+            .unwrap_or("Error serializing results".to_string())
     }
 }
 
+/// A positive number from the environment, or the default.
+fn setting(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(default)
+}
+
+/// Cuts page text to at most `max_chars` characters, at a word boundary where there is one.
+fn shorten(text: &str, max_chars: usize) -> String {
+    let Some((cut, _)) = text.char_indices().nth(max_chars) else {
+        return text.to_string();
+    };
+    let cut = text[..cut].rfind(' ').filter(|&space| space > cut / 2).unwrap_or(cut);
+    format!("{} [...]", &text[..cut])
+}
+
 // The rest of the functions remain unchanged
 pub struct TfIdf {
     pub vocab: HashSet<String>,
@@ -222,7 +240,7 @@ fn tf_vector(text: &str, tfidf: &TfIdf) -> Vec<f32> {
         .collect()
 }
 
-fn cosine_similarity(vec1: &[f32], vec2: &[f32]) -> f32 {
+pub fn cosine_similarity(vec1: &[f32], vec2: &[f32]) -> f32 {
     let dot_product: f32 = vec1.iter().zip(vec2.iter()).map(|(a, b)| a * b).sum();
     let norm1: f32 = vec1.iter().map(|x| x * x).sum::<f32>().sqrt();
     let norm2: f32 = vec2.iter().map(|x| x * x).sum::<f32>().sqrt();
@@ -288,49 +306,66 @@ fn graph_similarity(
     0.5 * term_similarity + 0.5 * edge_similarity
 }
 
+/// Reads a web page, PDF, JSON or text document and returns its text: the main content as
+/// Markdown for HTML pages, page by page for PDFs and pretty-printed for JSON.
 pub fn scrape_url(url: &str) -> String {
-    println!("{} {}", "Gemini is reading:".color(Color::Cyan).bold(), url);
+    runtime::block_on(scrape(&http::page_client(), url)).unwrap_or_else(|e| format!("Error fetching {}: {}", url, e))
+}
+
+/// Sends a query to the search engine and reads the result links from its answer.
+async fn search_hits(
+    provider: &dyn SearchProvider,
+    client: &Client,
+    query: &str,
+    count: usize,
+) -> Result<Vec<SearchHit>, String> {
+    let response = provider.request(client, query, count).send().await.map_err(|e| {
+        if e.is_timeout() {
+            "Request timed out".to_string()
+        } else {
+            e.without_url().to_string()
+        }
+    })?;
+    let status = response.status();
+    let body = response.text().await.map_err(|e| e.to_string())?;
+    provider.hits(status, &body, count)
+}
 
-    // Create a client with timeout
-    let client = ClientBuilder::new()
-        .connect_timeout(Duration::from_secs(NETWORK_TIMEOUT))
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
-        .build()
-        .unwrap_or_else(|_| Client::new());
+/// Fetches a document and extracts its text, as scrape_url describes.
+async fn scrape(client: &Client, url: &str) -> String {
+    ui::status(&format!("{} {}", "Gemini is reading:".color(Color::Cyan).bold(), url));
+    if let Err(reason) = politeness::wait_turn(client, url).await {
+        return reason;
+    }
 
-    match client.get(url).send() {
+    match client.get(url).send().await {
         Ok(resp) => {
             // Check status code first
             match resp.status() {
                 StatusCode::OK => {
-                    match resp.text() {
-                        Ok(text) => {
-                            let document = Html::parse_document(&text);
-                            // Target readable content: paragraphs, headings, articles
-                            let selector =
-                                Selector::parse("p, h1, h2, h3, h4, h5, h6, article").unwrap();
-                            let readable_text: Vec<String> = document
-                                .select(&selector)
-                                .flat_map(|element| {
-                                    // Only include text from elements not inside script/style
-                                    if element.value().name() != "script"
-                                        && element.value().name() != "style"
-                                    {
-                                        element
-                                            .text()
-                                            .map(|t| t.trim().to_string())
-                                            .collect::<Vec<_>>()
-                                    } else {
-                                        Vec::new()
-                                    }
-                                })
-                                .filter(|t| !t.is_empty()) // Skip empty strings
-                                .collect();
-
-                            if readable_text.is_empty() {
-                                "No readable content found on this page.".to_string()
-                            } else {
-                                readable_text.join(" ")
+                    if resp.content_length().is_some_and(|length| length > MAX_DOCUMENT_BYTES) {
+                        return format!("Skipped: the document is larger than {} MB", MAX_DOCUMENT_BYTES / 1_000_000);
+                    }
+                    let content_type = resp
+                        .headers()
+                        .get(reqwest::header::CONTENT_TYPE)
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or("")
+                        .to_string();
+                    match resp.bytes().await {
+                        Ok(bytes) => {
+                            // PDFs take a while to parse; keep that off the runtime's threads
+                            let url = url.to_string();
+                            let extracted = tokio::task::spawn_blocking(move || {
+                                let kind = documents::kind(&content_type, &url, &bytes);
+                                documents::extract(kind, &content_type, &url, &bytes)
+                            })
+                            .await
+                            .unwrap_or_else(|e| Err(format!("Error reading content: {}", e)));
+                            match extracted {
+                                Ok(text) if text.trim().is_empty() => "No readable content found on this page.".to_string(),
+                                Ok(text) => text,
+                                Err(e) => e,
                             }
                         }
                         Err(e) => format!("Error reading content: {}", e),
@@ -346,9 +381,9 @@ pub fn scrape_url(url: &str) -> String {
         }
         Err(e) => {
             if e.is_timeout() {
-                format!("Skipped: Request timed out")
+                "Skipped: Request timed out".to_string()
             } else if e.is_connect() {
-                format!("Skipped: Connection error")
+                "Skipped: Connection error".to_string()
             } else {
                 format!("Error fetching {}: {}", url, e)
             }
@@ -0,0 +1,233 @@
+use reqwest::{Client, RequestBuilder, StatusCode};
+use scraper::{Html, Selector};
+use serde_json::Value;
+use std::env;
+
+use crate::api_error::ApiError;
+
+// Names accepted by GEMINI_SEARCH_PROVIDER
+const SEARCH_PROVIDERS: &[&str] = &["google", "brave", "searxng", "duckduckgo"];
+const GOOGLE_ENDPOINT: &str = "https://www.googleapis.com/customsearch/v1";
+const BRAVE_ENDPOINT: &str = "https://api.search.brave.com/res/v1/web/search";
+const DUCKDUCKGO_ENDPOINT: &str = "https://html.duckduckgo.com/html/";
+
+/// One result of a web search, before its page is read.
+pub struct SearchHit {
+    pub title: String,
+    pub link: String,
+    /// Short excerpt shown by the search engine, when it has one
+    pub snippet: String,
+}
+
+/// A web search API that search_online gets its result links from.
+pub trait SearchProvider {
+    /// Name used in GEMINI_SEARCH_PROVIDER
+    fn name(&self) -> &'static str;
+    /// Builds the HTTP request for a query asking for `count` results.
+    fn request(&self, client: &Client, query: &str, count: usize) -> RequestBuilder;
+    /// Reads up to `count` results, in the engine's order, from the response to `request`.
+    fn hits(&self, status: StatusCode, body: &str, count: usize) -> Result<Vec<SearchHit>, String>;
+}
+
+/// The provider chosen with GEMINI_SEARCH_PROVIDER. Without it, the first one configured of
+/// Google (GOOGLE_SEARCH_API_KEY and GOOGLE_SEARCH_ENGINE_ID), Brave (BRAVE_SEARCH_API_KEY)
+/// and SearxNG (SEARXNG_URL) is used, and DuckDuckGo, which needs no key, otherwise.
+pub fn current() -> Result<Box<dyn SearchProvider>, String> {
+    let setting = configured("GEMINI_SEARCH_PROVIDER").map(|s| s.to_lowercase());
+    let name = match setting.as_deref() {
+        None | Some("auto") => {
+            if configured("GOOGLE_SEARCH_API_KEY").is_some() && configured("GOOGLE_SEARCH_ENGINE_ID").is_some() {
+                "google"
+            } else if configured("BRAVE_SEARCH_API_KEY").is_some() {
+                "brave"
+            } else if configured("SEARXNG_URL").is_some() {
+                "searxng"
+            } else {
+                "duckduckgo"
+            }
+        }
+        Some(name) => name,
+    };
+    match name {
+        "google" => Ok(Box::new(Google {
+            api_key: required("GOOGLE_SEARCH_API_KEY")?,
+            engine_id: required("GOOGLE_SEARCH_ENGINE_ID")?,
+            endpoint: configured("GOOGLE_SEARCH_ENDPOINT").unwrap_or_else(|| GOOGLE_ENDPOINT.to_string()),
+        })),
+        "brave" => Ok(Box::new(Brave { api_key: required("BRAVE_SEARCH_API_KEY")? })),
+        "searxng" => Ok(Box::new(Searxng { url: required("SEARXNG_URL")? })),
+        "duckduckgo" => Ok(Box::new(DuckDuckGo)),
+        other => Err(format!(
+            "Unknown GEMINI_SEARCH_PROVIDER '{}'; use one of {}.",
+            other,
+            SEARCH_PROVIDERS.join(", ")
+        )),
+    }
+}
+
+fn configured(key: &str) -> Option<String> {
+    env::var(key)
+        .ok()
+        .map(|v| v.trim().trim_end_matches('/').to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn required(key: &str) -> Result<String, String> {
+    configured(key).ok_or_else(|| format!("{} is not set in ~/.gemini.conf", key))
+}
+
+/// Reads a JSON response, turning HTTP errors into the API's own explanation.
+fn json_response(status: StatusCode, body: &str) -> Result<Value, String> {
+    if !status.is_success() {
+        let error = ApiError::parse(status.as_u16(), body);
+        return Err(format!("{}: {}", error.summary(), error.message));
+    }
+    serde_json::from_str(body).map_err(|e| format!("Failed to parse search response: {}", e))
+}
+
+/// The first `count` results from a JSON array, with the keys each API uses for title, link
+/// and snippet.
+fn hits(items: Option<&Value>, link_key: &str, snippet_key: &str, count: usize) -> Vec<SearchHit> {
+    items
+        .and_then(|items| items.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            Some(SearchHit {
+                title: item["title"].as_str().unwrap_or("No title").to_string(),
+                link: item[link_key].as_str()?.to_string(),
+                snippet: item[snippet_key].as_str().unwrap_or("").to_string(),
+            })
+        })
+        .take(count)
+        .collect()
+}
+
+/// Google Custom Search JSON API.
+struct Google {
+    api_key: String,
+    engine_id: String,
+    endpoint: String,
+}
+
+impl SearchProvider for Google {
+    fn name(&self) -> &'static str {
+        "google"
+    }
+
+    fn request(&self, client: &Client, query: &str, count: usize) -> RequestBuilder {
+        client
+            .get(&self.endpoint)
+            .query(&[("key", self.api_key.as_str()), ("cx", self.engine_id.as_str()), ("q", query)])
+            // The API returns at most 10 results per request
+            .query(&[("num", count.min(10))])
+    }
+
+    fn hits(&self, status: StatusCode, body: &str, count: usize) -> Result<Vec<SearchHit>, String> {
+        let json = json_response(status, body)?;
+        Ok(hits(json.get("items"), "link", "snippet", count))
+    }
+}
+
+/// Brave Search API.
+struct Brave {
+    api_key: String,
+}
+
+impl SearchProvider for Brave {
+    fn name(&self) -> &'static str {
+        "brave"
+    }
+
+    fn request(&self, client: &Client, query: &str, count: usize) -> RequestBuilder {
+        client
+            .get(BRAVE_ENDPOINT)
+            .header("X-Subscription-Token", &self.api_key)
+            .header("Accept", "application/json")
+            .query(&[("q", query)])
+            .query(&[("count", count.min(20))])
+    }
+
+    fn hits(&self, status: StatusCode, body: &str, count: usize) -> Result<Vec<SearchHit>, String> {
+        let json = json_response(status, body)?;
+        Ok(hits(json.pointer("/web/results"), "url", "description", count))
+    }
+}
+
+/// A SearxNG instance with the JSON output format enabled.
+struct Searxng {
+    url: String,
+}
+
+impl SearchProvider for Searxng {
+    fn name(&self) -> &'static str {
+        "searxng"
+    }
+
+    fn request(&self, client: &Client, query: &str, _count: usize) -> RequestBuilder {
+        client
+            .get(format!("{}/search", self.url))
+            .query(&[("q", query), ("format", "json")])
+    }
+
+    fn hits(&self, status: StatusCode, body: &str, count: usize) -> Result<Vec<SearchHit>, String> {
+        let json = json_response(status, body)
+            .map_err(|e| format!("{} (is the json format enabled in the instance's settings.yml?)", e))?;
+        Ok(hits(json.get("results"), "url", "content", count))
+    }
+}
+
+/// DuckDuckGo's HTML results page, which needs no key.
+struct DuckDuckGo;
+
+impl SearchProvider for DuckDuckGo {
+    fn name(&self) -> &'static str {
+        "duckduckgo"
+    }
+
+    fn request(&self, client: &Client, query: &str, _count: usize) -> RequestBuilder {
+        client.get(DUCKDUCKGO_ENDPOINT).query(&[("q", query)])
+    }
+
+    fn hits(&self, status: StatusCode, body: &str, count: usize) -> Result<Vec<SearchHit>, String> {
+        if !status.is_success() {
+            return Err(format!("DuckDuckGo answered with HTTP status {}", status));
+        }
+        let page = Html::parse_document(body);
+        let result = Selector::parse("div.result").unwrap();
+        let anchor = Selector::parse("a.result__a").unwrap();
+        let snippet = Selector::parse(".result__snippet").unwrap();
+
+        Ok(page
+            .select(&result)
+            // Ads link to DuckDuckGo's click tracker rather than the page
+            .filter(|element| !element.value().classes().any(|class| class == "result--ad"))
+            .filter_map(|element| {
+                let a = element.select(&anchor).next()?;
+                Some(SearchHit {
+                    title: a.text().collect::<String>().trim().to_string(),
+                    link: result_link(a.value().attr("href")?)?,
+                    snippet: element
+                        .select(&snippet)
+                        .next()
+                        .map(|s| s.text().collect::<String>().trim().to_string())
+                        .unwrap_or_default(),
+                })
+            })
+            .take(count)
+            .collect())
+    }
+}
+
+/// The target of a DuckDuckGo result link, which may go through its redirect
+/// (`//duckduckgo.com/l/?uddg=<encoded url>&...`).
+fn result_link(href: &str) -> Option<String> {
+    match href.split_once("uddg=") {
+        Some((_, rest)) => {
+            let encoded = rest.split('&').next()?;
+            urlencoding::decode(encoded).ok().map(|url| url.into_owned())
+        }
+        None if href.starts_with("http") => Some(href.to_string()),
+        None => None,
+    }
+}
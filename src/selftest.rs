@@ -0,0 +1,642 @@
+use colored::{Color, Colorize};
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::command::{self, execute_command};
+use crate::email::{send_email, Email};
+use crate::alpha_vantage::alpha_vantage_query;
+use crate::finance;
+use crate::fs_tools::search_codebase;
+use crate::backups;
+use crate::file_edit::{apply_changeset, file_editor, read_file};
+use crate::http_tool;
+use crate::jobs;
+use crate::plugins;
+use crate::external_tools;
+use crate::search::{scrape_url, search_online};
+use crate::snapshot;
+use crate::ui;
+
+const PAGE: &str = "<html><head><title>Selftest page</title></head><body>\
+    <nav><a href=\"/\">Home</a> <a href=\"/about\">Site menu</a></nav>\
+    <div class=\"content\"><h1>Selftest page</h1>\
+    <p>The gemini selftest harness serves this paragraph about sandboxed tool checks.</p>\
+    <p>Every tool is exercised against a temporary sandbox and <a href=\"/mocks\">local mock endpoints</a>.</p></div>\
+    <footer>Copyright notice of the selftest page</footer></body></html>";
+
+/// The outcome of one check.
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    Skip(String),
+}
+
+/// One check of a tool: which tool, what was tried, and how it went.
+pub struct CheckResult {
+    pub tool: String,
+    pub check: &'static str,
+    pub outcome: Outcome,
+}
+
+/// Handles `gemini-cli-rs selftest`: runs every check and prints a pass/fail report. Fails
+/// when any check failed.
+pub fn run() -> Result<(), String> {
+    // Tool chatter goes to stderr, the report to stdout
+    ui::reserve_stdout();
+    let sandbox = env::temp_dir().join(format!("gemini-selftest-{}", std::process::id()));
+    let results = run_all(&sandbox, &[])?;
+
+    let mut failed = 0;
+    let mut skipped = 0;
+    for result in &results {
+        let (label, detail) = match &result.outcome {
+            Outcome::Pass => ("PASS".color(Color::Green).bold(), String::new()),
+            Outcome::Fail(reason) => {
+                failed += 1;
+                ("FAIL".color(Color::Red).bold(), format!("\n        {}", reason))
+            }
+            Outcome::Skip(reason) => {
+                skipped += 1;
+                ("SKIP".color(Color::Yellow).bold(), format!(" ({})", reason).dimmed().to_string())
+            }
+        };
+        println!("  {}  {:<20} {}{}", label, result.tool, result.check, detail);
+    }
+    println!(
+        "\n{} passed, {} failed, {} skipped",
+        results.len() - failed - skipped,
+        failed,
+        skipped
+    );
+    if failed > 0 {
+        return Err(format!("{} check(s) failed", failed));
+    }
+    Ok(())
+}
+
+/// Runs every check in `sandbox`, which is created and removed again, with local mock servers
+/// standing in for the search API, web pages and SMTP. `settings` are applied on top of the
+/// ones the checks need, e.g. GEMINI_SANDBOX. The tools read the working directory and their
+/// settings from the process, so both are changed and this has to run before any tool is used.
+pub fn run_all(sandbox: &Path, settings: &[(&str, &str)]) -> Result<Vec<CheckResult>, String> {
+    fs::create_dir_all(sandbox).map_err(|e| format!("Error creating '{}': {}", sandbox.display(), e))?;
+    env::set_current_dir(sandbox).map_err(|e| format!("Error entering '{}': {}", sandbox.display(), e))?;
+
+    let http_port = serve_http()?;
+    let (smtp_port, mailbox) = serve_smtp()?;
+    env::set_var("NO_PROXY", "127.0.0.1,localhost");
+    env::set_var("GOOGLE_SEARCH_API_KEY", "selftest");
+    env::set_var("GOOGLE_SEARCH_ENGINE_ID", "selftest");
+    env::set_var("GOOGLE_SEARCH_ENDPOINT", format!("http://127.0.0.1:{}/search", http_port));
+    env::set_var("GEMINI_SEARCH_PROVIDER", "google");
+    for setting in ["GEMINI_SEARCH_RESULTS", "GEMINI_SEARCH_PARALLEL", "GEMINI_SEARCH_PAGE_CHARS", "GEMINI_SEARCH_TOTAL_CHARS"] {
+        env::remove_var(setting);
+    }
+    // The mock server may be asked as often as the checks need
+    env::set_var("GEMINI_DOMAIN_DELAY_MS", "0");
+    env::remove_var("GEMINI_RESPECT_ROBOTS");
+    env::set_var("ALPHA_VANTAGE_API_KEY", "selftest");
+    env::set_var("ALPHA_VANTAGE_ENDPOINT", format!("http://127.0.0.1:{}/alphavantage", http_port));
+    for setting in ["ALPHA_VANTAGE_ROWS", "ALPHA_VANTAGE_CALLS_PER_MINUTE", "ALPHA_VANTAGE_CACHE_SECONDS"] {
+        env::remove_var(setting);
+    }
+    env::set_var("SMTP_PORT", smtp_port.to_string());
+    env::set_var("DESTINATION_EMAIL", "selftest@localhost");
+    env::remove_var("SENDER_EMAIL");
+    env::remove_var("SMTP_USERNAME");
+    env::remove_var("SMTP_PASSWORD");
+    env::remove_var("SMTP_TLS");
+    env::remove_var("EMAIL_ALLOWED_RECIPIENTS");
+    // Edits are checked unattended
+    env::remove_var("GEMINI_CONFIRM");
+    env::remove_var("GEMINI_CONFIRM_EDITS");
+    env::remove_var("GEMINI_COMMAND_OUTPUT_LIMIT");
+    env::remove_var("GEMINI_COMMAND_OUTPUT_FILE");
+    env::remove_var("GEMINI_PERSISTENT_SHELL");
+    env::remove_var("GEMINI_ENV_PASSTHROUGH");
+    for (key, value) in settings {
+        env::set_var(key, value);
+    }
+
+    let mut results = vec![
+        command_check("execute_command", "echo output is returned", || {
+            // The shell has to compute the answer; an error quoting the command won't do
+            expect_contains(&execute_command("echo gemini-selftest-$((6*7))"), "gemini-selftest-42")
+        }),
+        command_check("execute_command", "missing program is reported", || {
+            let output = execute_command("gemini-selftest-no-such-program");
+            // cmd's message depends on the display language, but names the program
+            expect_contains(&output, if cfg!(windows) { "gemini-selftest-no-such-program" } else { "not found" })
+        }),
+        command_check("execute_command", "long output keeps its beginning and end", || {
+            let command = if cfg!(windows) { "for /L %i in (1,1,20000) do @echo line-%i" } else { "seq -f line-%g 1 20000" };
+            let output = execute_command(command);
+            if output.lines().next().map(str::trim) != Some("line-1") {
+                return Err(format!("expected the first line in: {}", output.chars().take(200).collect::<String>()));
+            }
+            expect_contains(&output, "of output omitted")?;
+            expect_contains(&output, "line-20000")
+        }),
+        command_check("execute_command", "working directory carries over", || {
+            fs::create_dir_all("subdir").map_err(|e| e.to_string())?;
+            expect_contains(&execute_command("cd subdir"), "subdir")?;
+            let output = execute_command(if cfg!(windows) { "cd" } else { "pwd" });
+            command::reset_session();
+            expect_contains(&output, "subdir")
+        }),
+        command_check("execute_command", "secrets stay out of the environment", || {
+            env::set_var("GEMINI_SELFTEST_TOKEN", "selftest-secret");
+            let command = if cfg!(windows) { "echo [%GEMINI_SELFTEST_TOKEN%]" } else { "echo \"[$GEMINI_SELFTEST_TOKEN]\"" };
+            let scrubbed = execute_command(command);
+            env::set_var("GEMINI_ENV_PASSTHROUGH", "GEMINI_SELFTEST_TOKEN");
+            let passed = execute_command(command);
+            env::remove_var("GEMINI_ENV_PASSTHROUGH");
+            env::remove_var("GEMINI_SELFTEST_TOKEN");
+            if scrubbed.contains("selftest-secret") {
+                return Err(format!("the token reached the command: {}", scrubbed.trim()));
+            }
+            expect_contains(&passed, "[selftest-secret]")
+        }),
+        command_check("run_in_background", "output is collected and the job stopped", || {
+            let command = if cfg!(windows) {
+                "echo gemini-background && ping -n 30 127.0.0.1 > nul"
+            } else {
+                "echo gemini-background && sleep 30"
+            };
+            expect_contains(&jobs::run_in_background(command)?, "Started job")?;
+            let mut output = String::new();
+            for _ in 0..50 {
+                output.push_str(&jobs::list_jobs(None)?);
+                if output.contains("gemini-background") {
+                    break;
+                }
+                thread::sleep(std::time::Duration::from_millis(100));
+            }
+            expect_contains(&output, "gemini-background")?;
+            expect_contains(&jobs::kill_job(1)?, "Stopped job 1")?;
+            expect_contains(&jobs::list_jobs(Some(1))?, "was stopped")
+        }),
+        check("file_editor", "write and read back", || {
+            expect_contains(&file_editor("write", "notes.txt", Some("alpha\nbeta\ngamma"), None), "Successfully")?;
+            expect_contains(&file_editor("read", "notes.txt", None, None), "alpha\nbeta\ngamma")
+        }),
+        check("file_editor", "read a line range", || {
+            expect_contains(&read_file("notes.txt", Some(2), Some(3)), "2 | beta\n3 | gamma")
+        }),
+        check("file_editor", "search", || {
+            expect_contains(&file_editor("search", "notes.txt", Some("b.ta"), None), "Found 1 matches")
+        }),
+        check("file_editor", "search and replace", || {
+            file_editor("search_and_replace", "notes.txt", Some("gamma"), Some("delta"));
+            expect_file("notes.txt", "alpha\nbeta\ndelta")
+        }),
+        check("file_editor", "apply diff", || {
+            let diff = "--- a/notes.txt\n+++ b/notes.txt\n@@ -1,3 +1,3 @@\n alpha\n-beta\n+BETA\n delta\n";
+            expect_contains(&file_editor("apply_diff", "notes.txt", Some(diff), None), "Successfully")?;
+            expect_file("notes.txt", "alpha\nBETA\ndelta")
+        }),
+        check("file_editor", "diff with drifted line numbers", || {
+            file_editor("write", "list.txt", Some("one\ntwo\nthree\nfour\nfive\nsix\n"), None);
+            let diff = "@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n@@ -2,3 +2,3 @@\n four\n-five\n+FIVE\n six\n";
+            expect_contains(&file_editor("apply_diff", "list.txt", Some(diff), None), "2 lines later")?;
+            expect_file("list.txt", "one\nTWO\nthree\nfour\nFIVE\nsix")
+        }),
+        check("file_editor", "mismatched diff changes nothing", || {
+            let diff = "@@ -1,2 +1,2 @@\n one\n-TWO\n+2\n@@ -5,1 +5,1 @@\n-nine\n+9\n";
+            expect_contains(&file_editor("apply_diff", "list.txt", Some(diff), None), "hunk 2")?;
+            expect_file("list.txt", "one\nTWO\nthree\nfour\nFIVE\nsix")
+        }),
+        check("file_editor", "diff for several files", || {
+            let diff = "--- a/list.txt\n+++ b/list.txt\n@@ -6 +6 @@\n-six\n+SIX\n--- /dev/null\n+++ b/new/created.txt\n@@ -0,0 +1,2 @@\n+created\n+by a diff\n";
+            expect_contains(&file_editor("apply_diff", "list.txt", Some(diff), None), "new/created.txt")?;
+            expect_file("list.txt", "one\nTWO\nthree\nfour\nFIVE\nSIX")?;
+            expect_file("new/created.txt", "created\nby a diff")
+        }),
+        check("apply_changeset", "all files or none", || {
+            file_editor("write", "a.txt", Some("a\n"), None);
+            file_editor("write", "b.txt", Some("b\n"), None);
+            let good = |file: &str, from: &str, to: &str| (file.to_string(), format!("@@ -1 +1 @@\n-{}\n+{}\n", from, to));
+            let failing = [good("a.txt", "a", "A"), good("b.txt", "x", "X")];
+            expect_contains(&apply_changeset(&failing), "No file was changed")?;
+            expect_file("a.txt", "a")?;
+            expect_contains(&apply_changeset(&[good("a.txt", "a", "A"), good("b.txt", "b", "B")]), "'b.txt'")?;
+            expect_file("a.txt", "A")?;
+            expect_file("b.txt", "B")
+        }),
+        check("undo_last_edit", "edits are undone newest first", || {
+            file_editor("write", "undo.txt", Some("first"), None);
+            file_editor("write", "undo.txt", Some("second"), None);
+            expect_contains(&backups::undo_last_edit()?, "Restored 'undo.txt'")?;
+            expect_file("undo.txt", "first")?;
+            expect_contains(&backups::undo_last_edit()?, "Removed 'undo.txt'")?;
+            if PathBuf::from("undo.txt").exists() {
+                return Err("undo.txt should have been removed".to_string());
+            }
+            Ok(())
+        }),
+        check("file_editor", "mkdir, copy, move and delete", || {
+            expect_contains(&file_editor("mkdir", "docs/old", None, None), "Created")?;
+            expect_contains(&file_editor("copy", "notes.txt", Some("docs/old/notes.txt"), None), "Copied")?;
+            expect_contains(&file_editor("move", "docs/old", Some("docs/new"), None), "Moved")?;
+            expect_file("docs/new/notes.txt", "alpha\nBETA\ndelta")?;
+            expect_contains(&file_editor("delete", "docs", None, None), "Deleted")
+        }),
+        check("search_codebase", "matches with context, .gitignore respected", || {
+            file_editor("mkdir", "code", None, None);
+            file_editor("write", "code/app.rs", Some("fn main() {\n    start_server();\n}\n\nfn start_server() {}\n"), None);
+            file_editor("write", "code/ignored.rs", Some("fn start_server() {}\n"), None);
+            file_editor("write", ".gitignore", Some("ignored.rs\n"), None);
+            let output = search_codebase("fn start_server", false, Some("code"), None, Some(1), None)?;
+            file_editor("delete", "code", None, None);
+            file_editor("delete", ".gitignore", None, None);
+            expect_contains(&output, "1 matching line in 1 file")?;
+            expect_contains(&output, "code/app.rs\n4- \n5: fn start_server() {}")
+        }),
+        check("file_editor", "paths outside the sandbox are refused", || {
+            expect_contains(&file_editor("read", "../outside.txt", None, None), "outside the sandbox")?;
+            expect_contains(&file_editor("write", "a/../../outside.txt", Some("x"), None), "outside the sandbox")?;
+            let absolute = if cfg!(windows) { "C:\\Windows\\win.ini" } else { "/etc/passwd" };
+            expect_contains(&file_editor("read", absolute, None, None), "not a path relative to the sandbox")?;
+            if cfg!(windows) {
+                expect_contains(&file_editor("read", "\\\\?\\C:\\Windows\\win.ini", None, None), "not a path relative")?;
+            }
+            escaping_link_is_refused()
+        }),
+        check("scrape_url", "page text from a local server", || {
+            expect_contains(
+                &scrape_url(&format!("http://127.0.0.1:{}/page", http_port)),
+                "sandboxed tool checks",
+            )
+        }),
+        check("scrape_url", "main content as markdown", || {
+            let page = scrape_url(&format!("http://127.0.0.1:{}/page", http_port));
+            if page.contains("Site menu") || page.contains("Copyright notice") {
+                return Err(format!("navigation or footer kept: {}", page));
+            }
+            expect_contains(&page, &format!("[local mock endpoints](http://127.0.0.1:{}/mocks)", http_port))
+        }),
+        check("scrape_url", "text of a PDF document", || {
+            expect_contains(
+                &scrape_url(&format!("http://127.0.0.1:{}/manual.pdf", http_port)),
+                "Selftest manual about sandboxed tool checks",
+            )
+        }),
+        check("scrape_url", "pages robots.txt disallows are skipped", || {
+            expect_contains(&scrape_url(&format!("http://127.0.0.1:{}/private/page", http_port)), "robots.txt")
+        }),
+        check("http_request", "status and JSON body, with secrets redacted", || {
+            let result = http_tool::http_request("GET", &format!("http://127.0.0.1:{}/api", http_port), None, None)?;
+            if result.contains("s3lftest-t0ken-value") {
+                return Err(format!("token not redacted: {}", result));
+            }
+            expect_contains(&result, "\"status\": 200")?;
+            expect_contains(&result, "\"ok\": true")
+        }),
+        check("search_online", "results from a mock search API", || {
+            expect_contains(&search_online("selftest harness sandboxed tool checks"), "Selftest page")
+        }),
+        check("search_online", "page text is capped", || {
+            env::set_var("GEMINI_SEARCH_PAGE_CHARS", "40");
+            let result = search_online("selftest harness sandboxed tool checks");
+            env::remove_var("GEMINI_SEARCH_PAGE_CHARS");
+            expect_contains(&result, "[...]")
+        }),
+        check("search_online", "results from a mock SearxNG instance", || {
+            env::set_var("GEMINI_SEARCH_PROVIDER", "searxng");
+            env::set_var("SEARXNG_URL", format!("http://127.0.0.1:{}/searxng", http_port));
+            let result = search_online("selftest harness sandboxed tool checks");
+            env::set_var("GEMINI_SEARCH_PROVIDER", "google");
+            expect_contains(&result, "Selftest page")
+        }),
+        check("alpha_vantage_query", "latest data points of a mock time series", || {
+            let result = alpha_vantage_query("TIME_SERIES_DAILY", "IBM", &[("outputsize", "compact")])?;
+            expect_contains(&result, "Time Series (Daily) (latest 10 of 12)")?;
+            expect_contains(&result, "| 2024-01-12 | 112.00 |")
+        }),
+        check("alpha_vantage_query", "a repeated query is answered from the cache", || {
+            // The endpoint is gone, so only the cache can answer
+            env::set_var("ALPHA_VANTAGE_ENDPOINT", "http://127.0.0.1:9/alphavantage");
+            let result = alpha_vantage_query("TIME_SERIES_DAILY", "ibm", &[("outputsize", "compact")]);
+            env::set_var("ALPHA_VANTAGE_ENDPOINT", format!("http://127.0.0.1:{}/alphavantage", http_port));
+            expect_contains(&result?, "2024-01-12")
+        }),
+        check("fx_rate", "exchange rate from a mock endpoint", || {
+            let result = finance::fx_rate("eur", "usd")?;
+            expect_contains(&result, "From_Currency Code: EUR")?;
+            expect_contains(&result, "Exchange Rate: 1.08500000")
+        }),
+        check("send_email", "delivery to a mock SMTP server", || {
+            let email = Email {
+                subject: "Selftest".to_string(),
+                body: "Hello from the selftest".to_string(),
+                ..Default::default()
+            };
+            expect_contains(&send_email(&email, "127.0.0.1", false), "successfully")?;
+            let received = mailbox.lock().unwrap().clone();
+            expect_contains(&received, "Subject: Selftest")
+        }),
+        check("send_email", "HTML body and an attachment", || {
+            fs::write("report.csv", "name,result\nselftest,ok\n").map_err(|e| e.to_string())?;
+            let email = Email {
+                subject: "Selftest report".to_string(),
+                body: "Hello from the selftest".to_string(),
+                html: Some("<p>Hello from the <b>selftest</b></p>".to_string()),
+                attachments: vec!["report.csv".to_string()],
+                ..Default::default()
+            };
+            let result = send_email(&email, "127.0.0.1", false);
+            expect_contains(&result, "1 attachment")?;
+            let received = mailbox.lock().unwrap().clone();
+            expect_contains(&received, "multipart/alternative")?;
+            expect_contains(&received, "filename=\"report.csv\"")
+        }),
+        check("send_email", "attachments outside the sandbox are refused", || {
+            let email = Email {
+                subject: "Selftest".to_string(),
+                attachments: vec!["../outside.txt".to_string()],
+                ..Default::default()
+            };
+            expect_contains(&send_email(&email, "127.0.0.1", false), "outside the sandbox")
+        }),
+        check("send_email", "recipients must be on the allowlist", || {
+            let email = Email {
+                subject: "Selftest".to_string(),
+                to: vec!["stranger@example.com".to_string()],
+                ..Default::default()
+            };
+            expect_contains(&send_email(&email, "127.0.0.1", false), "not allowed")?;
+            env::set_var("EMAIL_ALLOWED_RECIPIENTS", "@example.com");
+            let email = Email {
+                cc: vec!["colleague@example.com".to_string()],
+                ..email
+            };
+            let result = send_email(&email, "127.0.0.1", false);
+            env::remove_var("EMAIL_ALLOWED_RECIPIENTS");
+            expect_contains(&result, "stranger@example.com, colleague@example.com")
+        }),
+    ];
+    for tool in ["github", "tracker"] {
+        results.push(CheckResult {
+            tool: tool.to_string(),
+            check: "not run",
+            outcome: Outcome::Skip("needs a live service".to_string()),
+        });
+    }
+    for decl in plugins::declarations() {
+        results.push(CheckResult {
+            tool: decl["name"].as_str().unwrap_or("").to_string(),
+            check: "plugin loaded",
+            outcome: Outcome::Pass,
+        });
+    }
+    for decl in external_tools::declarations() {
+        results.push(CheckResult {
+            tool: decl["name"].as_str().unwrap_or("").to_string(),
+            check: "external tool loaded",
+            outcome: Outcome::Pass,
+        });
+    }
+
+    snapshot::discard().ok();
+    backups::discard().ok();
+    env::set_current_dir(env::temp_dir()).ok();
+    fs::remove_dir_all(sandbox).ok();
+    Ok(results)
+}
+
+fn check(tool: &'static str, check: &'static str, run: impl FnOnce() -> Result<(), String>) -> CheckResult {
+    let outcome = match run() {
+        Ok(()) => Outcome::Pass,
+        Err(reason) => Outcome::Fail(reason),
+    };
+    CheckResult { tool: tool.to_string(), check, outcome }
+}
+
+/// Like check, for a check that runs commands: skipped when commands can't run because the
+/// configured sandbox isn't available.
+fn command_check(tool: &'static str, what: &'static str, run: impl FnOnce() -> Result<(), String>) -> CheckResult {
+    match command::backend() {
+        Ok(_) => check(tool, what, run),
+        Err(e) => CheckResult { tool: tool.to_string(), check: what, outcome: Outcome::Skip(e) },
+    }
+}
+
+/// A symbolic link from the sandbox to its parent directory must not give access to it.
+#[cfg(unix)]
+fn escaping_link_is_refused() -> Result<(), String> {
+    std::os::unix::fs::symlink("..", "escape").map_err(|e| format!("Error creating a symbolic link: {}", e))?;
+    let read = file_editor("read", "escape/outside.txt", None, None);
+    let write = file_editor("write", "escape/outside.txt", Some("x"), None);
+    std::os::unix::fs::symlink("../gemini-selftest-missing", "dangling").map_err(|e| e.to_string())?;
+    let dangling = file_editor("write", "dangling", Some("x"), None);
+    fs::remove_file("escape").ok();
+    fs::remove_file("dangling").ok();
+    expect_contains(&read, "through a symbolic link")?;
+    expect_contains(&write, "through a symbolic link")?;
+    expect_contains(&dangling, "can't be resolved")
+}
+
+// Creating symbolic links needs extra privileges on Windows
+#[cfg(not(unix))]
+fn escaping_link_is_refused() -> Result<(), String> {
+    Ok(())
+}
+
+fn expect_contains(output: &str, expected: &str) -> Result<(), String> {
+    if output.contains(expected) {
+        Ok(())
+    } else {
+        Err(format!("expected {:?} in: {}", expected, output.trim()))
+    }
+}
+
+fn expect_file(name: &str, expected: &str) -> Result<(), String> {
+    let content = fs::read_to_string(PathBuf::from(name)).map_err(|e| format!("Error reading '{}': {}", name, e))?;
+    if content.trim_end() == expected {
+        Ok(())
+    } else {
+        Err(format!("expected {:?}, found {:?}", expected, content))
+    }
+}
+
+/// Serves the mock search APIs on /search (Google) and /searxng/search and a web page on
+/// every other path.
+fn serve_http() -> Result<u16, String> {
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(|e| format!("Error starting the mock web server: {}", e))?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || respond_http(stream, port));
+        }
+    });
+    Ok(port)
+}
+
+fn respond_http(mut stream: TcpStream, port: u16) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok();
+    // Skip the headers
+    let mut line = String::new();
+    while reader.read_line(&mut line).map(|n| n > 2).unwrap_or(false) {
+        line.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (content_type, body) = if path.starts_with("/searxng/search") {
+        let results = serde_json::json!({"results": [
+            {"title": "Selftest page", "url": format!("http://127.0.0.1:{}/page", port), "content": "Selftest"},
+        ]});
+        ("application/json", results.to_string())
+    } else if path.starts_with("/search") {
+        let items = serde_json::json!({"items": [
+            {"title": "Selftest page", "link": format!("http://127.0.0.1:{}/page", port)},
+            {"title": "Unrelated page", "link": format!("http://127.0.0.1:{}/other", port)},
+        ]});
+        ("application/json", items.to_string())
+    } else if path.starts_with("/alphavantage") && path.contains("CURRENCY_EXCHANGE_RATE") {
+        let response = serde_json::json!({"Realtime Currency Exchange Rate": {
+            "1. From_Currency Code": "EUR",
+            "3. To_Currency Code": "USD",
+            "5. Exchange Rate": "1.08500000",
+        }});
+        ("application/json", response.to_string())
+    } else if path.starts_with("/alphavantage") {
+        let series: serde_json::Map<String, serde_json::Value> = (1..=12)
+            .map(|day| {
+                let price = format!("{}.00", 100 + day);
+                (format!("2024-01-{:02}", day), serde_json::json!({"1. open": price, "4. close": price}))
+            })
+            .collect();
+        let response = serde_json::json!({
+            "Meta Data": {"1. Information": "Daily Prices", "2. Symbol": "IBM"},
+            "Time Series (Daily)": series,
+        });
+        ("application/json", response.to_string())
+    } else if path.starts_with("/api") {
+        ("application/json", r#"{"ok": true, "token": "s3lftest-t0ken-value"}"#.to_string())
+    } else if path.starts_with("/robots.txt") {
+        ("text/plain", "User-agent: *\nDisallow: /private/\n".to_string())
+    } else if path.starts_with("/manual.pdf") {
+        ("application/pdf", sample_pdf("Selftest manual about sandboxed tool checks"))
+    } else if path.starts_with("/other") {
+        ("text/html", "<html><body><p>Weather forecast: mild and sunny over the coast.</p></body></html>".to_string())
+    } else {
+        ("text/html", PAGE.to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).ok();
+}
+
+/// A one-page PDF with the given line of text in a standard font.
+fn sample_pdf(text: &str) -> String {
+    let content = format!("BT /F1 12 Tf 72 720 Td ({}) Tj ET", text);
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Resources << /Font << /F1 4 0 R >> >> /Contents 5 0 R >>"
+            .to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        format!("<< /Length {} >>\nstream\n{}\nendstream", content.len(), content),
+    ];
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", index + 1, object));
+    }
+    let xref = pdf.len();
+    pdf.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+    for offset in offsets {
+        pdf.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    pdf.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    ));
+    pdf
+}
+
+/// A minimal SMTP server that accepts every message and keeps the last one.
+fn serve_smtp() -> Result<(u16, Arc<Mutex<String>>), String> {
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(|e| format!("Error starting the mock SMTP server: {}", e))?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    let mailbox = Arc::new(Mutex::new(String::new()));
+    let received = Arc::clone(&mailbox);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            respond_smtp(stream, &received);
+        }
+    });
+    Ok((port, mailbox))
+}
+
+fn respond_smtp(mut stream: TcpStream, mailbox: &Mutex<String>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    stream.write_all(b"220 localhost selftest\r\n").ok();
+    let mut line = String::new();
+    while reader.read_line(&mut line).map(|n| n > 0).unwrap_or(false) {
+        let command = line.trim_end().to_uppercase();
+        let reply: &[u8] = if command.starts_with("EHLO") || command.starts_with("HELO") {
+            b"250 localhost\r\n"
+        } else if command == "DATA" {
+            stream.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n").ok();
+            let mut message = String::new();
+            let mut data_line = String::new();
+            while reader.read_line(&mut data_line).map(|n| n > 0).unwrap_or(false) {
+                if data_line == ".\r\n" {
+                    break;
+                }
+                message.push_str(&data_line);
+                data_line.clear();
+            }
+            *mailbox.lock().unwrap() = message;
+            b"250 OK\r\n"
+        } else if command == "QUIT" {
+            stream.write_all(b"221 Bye\r\n").ok();
+            return;
+        } else {
+            b"250 OK\r\n"
+        };
+        stream.write_all(reply).ok();
+        line.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the whole selftest, so `cargo test` covers every tool. Commands run without a
+    /// sandbox, since CI machines seldom have one, and HOME points into the sandbox so the
+    /// audit log and backups stay out of the real one.
+    #[test]
+    fn every_tool_passes_its_checks() {
+        let sandbox = env::temp_dir().join(format!("gemini-selftest-test-{}", std::process::id()));
+        let home = sandbox.join("home");
+        let home = home.to_string_lossy();
+        // Commands must not wait for input from the terminal
+        ui::reserve_stdout();
+        let results = run_all(&sandbox, &[("GEMINI_SANDBOX", "none"), ("HOME", &home)]).unwrap();
+        let failures: Vec<String> = results
+            .iter()
+            .filter_map(|result| match &result.outcome {
+                Outcome::Fail(reason) => Some(format!("{} {}: {}", result.tool, result.check, reason)),
+                _ => None,
+            })
+            .collect();
+        assert!(failures.is_empty(), "failed checks:\n{}", failures.join("\n"));
+        assert!(results.iter().any(|result| matches!(result.outcome, Outcome::Pass)));
+    }
+}
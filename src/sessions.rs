@@ -0,0 +1,174 @@
+use chrono::{DateTime, Local};
+use clap::Subcommand;
+use colored::{Color, Colorize};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::export;
+use crate::locale;
+use crate::{HistoryEntry, SANDBOX_ROOT};
+
+#[derive(Subcommand)]
+pub enum SessionsAction {
+    /// List saved sessions, newest first
+    List,
+    /// Print a saved session as Markdown
+    Show { name: String },
+    /// Delete a saved session
+    Delete { name: String },
+}
+
+/// A saved chat conversation.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub created: DateTime<Local>,
+    pub sandbox: String,
+    pub turn: usize,
+    pub history: Vec<HistoryEntry>,
+}
+
+/// Directory where chat sessions are stored.
+pub fn sessions_dir() -> PathBuf {
+    dirs::home_dir()
+        .expect("Could not determine home directory")
+        .join(".gemini_sessions")
+}
+
+/// Handles `gemini-cli-rs sessions ...`.
+pub fn run(action: Option<SessionsAction>) -> Result<(), String> {
+    match action.unwrap_or(SessionsAction::List) {
+        SessionsAction::List => print_list(),
+        SessionsAction::Show { name } => {
+            let session = load(&name)?;
+            println!("{}", export::to_markdown(&session.history));
+            Ok(())
+        }
+        SessionsAction::Delete { name } => {
+            let path = session_path(&name)?;
+            fs::remove_file(&path).map_err(|e| format!("Error deleting '{}': {}", path.display(), e))?;
+            println!("{}", format!("Session {} deleted", name).color(Color::Cyan));
+            Ok(())
+        }
+    }
+}
+
+/// Prints the saved sessions, newest first.
+pub fn print_list() -> Result<(), String> {
+    let sessions = list()?;
+    if sessions.is_empty() {
+        println!("{}", "No saved sessions yet.".color(Color::Cyan));
+    }
+    for (name, session) in sessions {
+        println!(
+            "{}  {}  {} turns  {}",
+            name.bold(),
+            format!(
+                "{} {}",
+                locale::format_date(&session.created),
+                locale::format_time(&session.created)
+            )
+            .dimmed(),
+            session.turn,
+            preview(&session)
+        );
+    }
+    Ok(())
+}
+
+/// Whether chat sessions are saved on exit; GEMINI_AUTOSAVE=0 turns it off.
+pub fn autosave_enabled() -> bool {
+    !env::var("GEMINI_AUTOSAVE")
+        .map(|v| v == "0" || v == "false")
+        .unwrap_or(false)
+}
+
+/// Saves a conversation under `name`, or a timestamped name when none is given, and returns
+/// the name used. An existing session of that name is replaced.
+pub fn save(history: &[HistoryEntry], turn: usize, name: Option<&str>) -> Result<String, String> {
+    let dir = sessions_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Error creating '{}': {}", dir.display(), e))?;
+
+    let created = history.first().map(|e| e.timestamp).unwrap_or_else(Local::now);
+    let name = match name {
+        Some(name) => check_name(name)?,
+        None => created.format("%Y%m%d-%H%M%S").to_string(),
+    };
+    let session = Session {
+        created,
+        sandbox: SANDBOX_ROOT.clone(),
+        turn,
+        history: history.to_vec(),
+    };
+    let json = serde_json::to_string_pretty(&session).map_err(|e| e.to_string())?;
+    let path = dir.join(format!("{}.json", name));
+    fs::write(&path, json).map_err(|e| format!("Error writing to '{}': {}", path.display(), e))?;
+    Ok(name)
+}
+
+/// Reads a saved session by name.
+pub fn load(name: &str) -> Result<Session, String> {
+    let path = session_path(name)?;
+    let json = fs::read_to_string(&path).map_err(|e| format!("Error reading '{}': {}", path.display(), e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Invalid session file '{}': {}", path.display(), e))
+}
+
+/// The most recently started session, for resuming without a name.
+pub fn latest() -> Result<(String, Session), String> {
+    list()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "No saved sessions yet.".to_string())
+}
+
+/// All readable sessions, newest first.
+pub fn list() -> Result<Vec<(String, Session)>, String> {
+    let entries = match fs::read_dir(sessions_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()), // Nothing saved yet
+    };
+    let mut sessions: Vec<(String, Session)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().strip_suffix(".json")?.to_string();
+            load(&name).ok().map(|session| (name, session))
+        })
+        .collect();
+    sessions.sort_by_key(|(_, session)| std::cmp::Reverse(session.created));
+    Ok(sessions)
+}
+
+fn session_path(name: &str) -> Result<PathBuf, String> {
+    let name = check_name(name)?;
+    let path = sessions_dir().join(format!("{}.json", name));
+    if !path.exists() {
+        return Err(format!("No session named '{}' (see `sessions list`)", name));
+    }
+    Ok(path)
+}
+
+/// Session names become file names, so they can't leave the sessions directory.
+fn check_name(name: &str) -> Result<String, String> {
+    let name = name.trim().trim_end_matches(".json");
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(format!("Invalid session name '{}'", name));
+    }
+    Ok(name.to_string())
+}
+
+/// The start of the first user message, to recognize a session in the list.
+fn preview(session: &Session) -> String {
+    let text = session
+        .history
+        .iter()
+        .filter(|e| e.content["role"] == "user")
+        .find_map(|e| e.content["parts"][0]["text"].as_str())
+        .unwrap_or("")
+        .replace('\n', " ");
+    if text.chars().count() > 60 {
+        format!("{}…", text.chars().take(60).collect::<String>())
+    } else {
+        text
+    }
+}
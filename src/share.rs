@@ -0,0 +1,39 @@
+use chrono::Local;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::export;
+use crate::github;
+use crate::redact;
+use crate::HistoryEntry;
+
+/// Packages the conversation for someone else: a single Markdown or HTML file with secrets
+/// redacted, written locally or uploaded as a secret gist. Returns where it went.
+pub fn share(history: &[HistoryEntry], format: &str, upload: bool) -> Result<String, String> {
+    if history.is_empty() {
+        return Err("Nothing to share yet".to_string());
+    }
+    let redacted: Vec<HistoryEntry> = history
+        .iter()
+        .map(|entry| {
+            let mut entry = entry.clone();
+            redact::redact_json(&mut entry.content);
+            entry
+        })
+        .collect();
+
+    let (content, extension) = match format {
+        "md" | "markdown" => (export::to_markdown(&redacted), "md"),
+        "html" => (export::to_html(&redacted), "html"),
+        _ => return Err(format!("Unknown share format '{}' (use md or html)", format)),
+    };
+    let filename = format!("transcript-{}.{}", Local::now().format("%Y%m%d-%H%M%S"), extension);
+
+    if upload {
+        let url = github::create_gist("Gemini CLI transcript", &filename, &content)?;
+        return Ok(format!("Transcript shared as a secret gist: {}", url));
+    }
+    let path = PathBuf::from(&filename);
+    fs::write(&path, content).map_err(|e| format!("Error writing to '{}': {}", path.display(), e))?;
+    Ok(format!("Redacted transcript written to {}", path.display()))
+}
@@ -0,0 +1,211 @@
+use chrono::{Local, TimeZone};
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::locale;
+use crate::ui;
+use crate::SANDBOX_ROOT;
+
+// Whether a snapshot was already taken during the current turn
+static TAKEN_THIS_TURN: AtomicBool = AtomicBool::new(false);
+// The user turn in progress
+static CURRENT_TURN: AtomicUsize = AtomicUsize::new(0);
+// The snapshot taken during each turn of this session, for /undo
+static TURN_SNAPSHOTS: Lazy<Mutex<HashMap<usize, usize>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Build output and dependencies would make every snapshot huge
+const DEFAULT_EXCLUDES: &str = "target/\nnode_modules/\n.venv/\n__pycache__/\n";
+
+/// A saved state of the sandbox, oldest first numbered from 1.
+pub struct Snapshot {
+    pub number: usize,
+    pub commit: String,
+    pub time: chrono::DateTime<Local>,
+    pub label: String,
+}
+
+/// Called when a new user turn starts, so the next edit takes a fresh snapshot.
+pub fn new_turn(turn: usize) {
+    TAKEN_THIS_TURN.store(false, Ordering::SeqCst);
+    CURRENT_TURN.store(turn, Ordering::SeqCst);
+}
+
+/// Takes a snapshot before the first file modification of a turn.
+pub fn before_edit(filename: &str) {
+    if TAKEN_THIS_TURN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    match take(&format!("before editing {}", filename)) {
+        Ok(number) => {
+            TURN_SNAPSHOTS.lock().unwrap().insert(CURRENT_TURN.load(Ordering::SeqCst), number);
+            ui::status(
+                &format!("Snapshot {} saved (use /restore {} to roll back)", number, number)
+                    .dimmed()
+                    .to_string(),
+            )
+        }
+        Err(e) => ui::status(&format!("Could not save a snapshot: {}", e).color(Color::Red).to_string()),
+    }
+}
+
+/// Records the current state of the sandbox and returns its number.
+pub fn take(label: &str) -> Result<usize, String> {
+    ensure_repo()?;
+    shadow_git(&["add", "--all", "."])?;
+    shadow_git(&[
+        "-c",
+        "user.name=gemini-cli",
+        "-c",
+        "user.email=gemini-cli@localhost",
+        "commit",
+        "--quiet",
+        "--allow-empty",
+        "--no-verify",
+        "-m",
+        label,
+    ])?;
+    Ok(list()?.len())
+}
+
+/// All snapshots of this sandbox, oldest first.
+pub fn list() -> Result<Vec<Snapshot>, String> {
+    if !shadow_dir().exists() {
+        return Ok(Vec::new());
+    }
+    let log = match shadow_git(&["log", "--reverse", "--format=%H %ct %s"]) {
+        Ok(log) => log,
+        Err(_) => return Ok(Vec::new()), // No commits yet
+    };
+    Ok(log
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let mut fields = line.splitn(3, ' ');
+            let commit = fields.next()?.to_string();
+            let seconds: i64 = fields.next()?.parse().ok()?;
+            let label = fields.next().unwrap_or("").to_string();
+            Some(Snapshot {
+                number: index + 1,
+                commit,
+                time: Local.timestamp_opt(seconds, 0).single()?,
+                label,
+            })
+        })
+        .collect())
+}
+
+/// Rolls the sandbox back to a snapshot: changed and deleted files are restored and
+/// files created since are removed. The current state is saved first, so it can be undone.
+pub fn restore(number: usize) -> Result<String, String> {
+    let snapshots = list()?;
+    let target = snapshots
+        .iter()
+        .find(|s| s.number == number)
+        .ok_or_else(|| format!("No snapshot {} (use /restore to list them)", number))?;
+
+    let safety = take(&format!("before restoring snapshot {}", number))?;
+    let current = shadow_git(&["rev-parse", "HEAD"])?;
+
+    // Files that exist now but didn't in the snapshot
+    let added = shadow_git(&["diff", "--name-only", "--no-renames", "--diff-filter=A", &target.commit, current.trim()])?;
+    for file in added.lines().filter(|l| !l.is_empty()) {
+        fs::remove_file(PathBuf::from(&*SANDBOX_ROOT).join(file)).ok();
+    }
+    shadow_git(&["checkout", &target.commit, "--", "."])?;
+
+    Ok(format!(
+        "Restored snapshot {} ({}). The previous state was saved as snapshot {}.",
+        number, target.label, safety
+    ))
+}
+
+/// Reverts the file edits of a turn by restoring the snapshot taken before its first edit.
+/// Returns None when the turn didn't edit any files.
+pub fn undo_turn(turn: usize) -> Result<Option<String>, String> {
+    let number = TURN_SNAPSHOTS.lock().unwrap().remove(&turn);
+    match number {
+        Some(number) => restore(number).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Deletes every snapshot of this sandbox, for sandboxes that are themselves temporary.
+pub fn discard() -> Result<(), String> {
+    let dir = shadow_dir();
+    if !dir.exists() {
+        return Ok(());
+    }
+    fs::remove_dir_all(&dir).map_err(|e| format!("Error removing '{}': {}", dir.display(), e))
+}
+
+/// Prints the snapshots for `/restore` without an argument.
+pub fn print_list() {
+    match list() {
+        Ok(snapshots) if snapshots.is_empty() => {
+            println!("{}", "No snapshots yet. One is saved before the first file edit of each turn.".color(Color::Cyan))
+        }
+        Ok(snapshots) => {
+            for snapshot in snapshots {
+                println!(
+                    "{:>3}  {}  {}",
+                    snapshot.number,
+                    format!("{} {}", locale::format_date(&snapshot.time), locale::format_time(&snapshot.time)).dimmed(),
+                    snapshot.label
+                );
+            }
+            println!("{}", "Use /restore <number> to roll the working tree back.".color(Color::Cyan));
+        }
+        Err(e) => println!("{}", e.color(Color::Red)),
+    }
+}
+
+/// Snapshots live in a separate git directory outside the sandbox, so the project's
+/// own repository (if any) is never touched.
+fn shadow_dir() -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    SANDBOX_ROOT.hash(&mut hasher);
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("gemini-cli")
+        .join("snapshots")
+        .join(format!("{:016x}.git", hasher.finish()))
+}
+
+fn ensure_repo() -> Result<(), String> {
+    let dir = shadow_dir();
+    if dir.join("HEAD").exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(&dir).map_err(|e| format!("Error creating '{}': {}", dir.display(), e))?;
+    shadow_git(&["init", "--quiet"])?;
+    fs::create_dir_all(dir.join("info")).ok();
+    fs::write(dir.join("info").join("exclude"), DEFAULT_EXCLUDES)
+        .map_err(|e| format!("Error writing snapshot excludes: {}", e))
+}
+
+fn shadow_git(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(shadow_dir())
+        .arg("--work-tree")
+        .arg(&*SANDBOX_ROOT)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
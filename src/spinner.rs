@@ -1,11 +1,25 @@
-use std::io::{self, Write};
-use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+use crate::tui;
 
 pub struct Spinner {
     handle: Option<thread::JoinHandle<()>>,
     running: Arc<AtomicBool>,
+    label: SpinnerLabel,
+}
+
+/// Shared handle to the spinner's phase label, so worker threads can report progress.
+#[derive(Clone)]
+pub struct SpinnerLabel(Arc<Mutex<String>>);
+
+impl SpinnerLabel {
+    pub fn set(&self, label: &str) {
+        *self.0.lock().unwrap() = label.to_string();
+        tui::set_progress(label);
+    }
 }
 
 impl Spinner {
@@ -13,34 +27,60 @@ impl Spinner {
         Spinner {
             handle: None,
             running: Arc::new(AtomicBool::new(false)),
+            label: SpinnerLabel(Arc::new(Mutex::new(String::new()))),
         }
     }
 
-    pub fn start(&mut self) { // Removed message parameter
-        if self.running.load(Ordering::SeqCst) {
-            return; // Already running
+    /// Starts spinning on stderr with a phase label such as "calling model".
+    /// Does nothing when stderr isn't a terminal, so piped output stays clean.
+    pub fn start(&mut self, label: &str) {
+        self.label.set(label);
+        if self.running.load(Ordering::SeqCst) || !io::stderr().is_terminal() {
+            return; // Already running, or nobody to show it to
         }
 
         self.running.store(true, Ordering::SeqCst);
         let running_flag = Arc::clone(&self.running);
+        let label = self.label.clone();
 
         self.handle = Some(thread::spawn(move || {
             let chars = ['-', '\\', '|', '/'];
+            let started = Instant::now();
             let mut i = 0;
+            let mut last_width: usize = 0;
             while running_flag.load(Ordering::SeqCst) {
-                print!("\r{}", chars[i]); // Only print the character
-                io::stdout().flush().unwrap();
+                let frame = format!(
+                    "{} {} ({}s)",
+                    chars[i],
+                    label.0.lock().unwrap(),
+                    started.elapsed().as_secs()
+                );
+                // Pad with spaces in case the previous frame was longer
+                let width = frame.chars().count();
+                eprint!("\r{}{}", frame, " ".repeat(last_width.saturating_sub(width)));
+                io::stderr().flush().ok();
+                last_width = width;
                 i = (i + 1) % chars.len();
                 thread::sleep(Duration::from_millis(100)); // Adjust speed here
             }
             // Clear the spinner line after stopping
-            print!("\r{}", " ".repeat(80)); // Clear the entire line with spaces
-            print!("\r"); // Move cursor back to the beginning of the line
-            io::stdout().flush().unwrap();
+            eprint!("\r{}\r", " ".repeat(last_width));
+            io::stderr().flush().ok();
         }));
     }
 
+    /// Changes the phase label while the spinner is running.
+    pub fn set_label(&self, label: &str) {
+        self.label.set(label);
+    }
+
+    /// Returns a handle that other threads can use to update the label.
+    pub fn label(&self) -> SpinnerLabel {
+        self.label.clone()
+    }
+
     pub fn stop(&mut self) {
+        tui::set_progress("");
         if self.running.load(Ordering::SeqCst) {
             self.running.store(false, Ordering::SeqCst);
             if let Some(handle) = self.handle.take() {
@@ -55,4 +95,4 @@ impl Drop for Spinner {
     fn drop(&mut self) {
         self.stop();
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,149 @@
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::locale;
+use crate::usage;
+
+#[derive(Default)]
+struct ToolStats {
+    calls: u64,
+    failures: u64,
+    total: Duration,
+}
+
+#[derive(Default)]
+struct SessionStats {
+    tools: BTreeMap<String, ToolStats>,
+    api_latencies: Vec<Duration>,
+    files_changed: Vec<String>,
+    commands: Vec<String>,
+    emails: Vec<String>,
+}
+
+static STATS: Lazy<Mutex<SessionStats>> = Lazy::new(|| Mutex::new(SessionStats::default()));
+
+/// Records one model request and how long it took.
+pub fn record_api_call(latency: Duration) {
+    STATS.lock().unwrap().api_latencies.push(latency);
+}
+
+/// Records one tool invocation.
+pub fn record_tool(name: &str, duration: Duration, ok: bool) {
+    let mut stats = STATS.lock().unwrap();
+    let tool = stats.tools.entry(name.to_string()).or_default();
+    tool.calls += 1;
+    tool.total += duration;
+    if !ok {
+        tool.failures += 1;
+    }
+}
+
+/// Records a file written by the file editor.
+pub fn record_file_change(filename: &str) {
+    let mut stats = STATS.lock().unwrap();
+    if !stats.files_changed.iter().any(|f| f == filename) {
+        stats.files_changed.push(filename.to_string());
+    }
+}
+
+/// Records a shell command that was run.
+pub fn record_command(command: &str) {
+    STATS.lock().unwrap().commands.push(command.to_string());
+}
+
+/// Records an email that was sent.
+pub fn record_email(description: &str) {
+    STATS.lock().unwrap().emails.push(description.to_string());
+}
+
+/// What this session changed, printed on exit. None when nothing happened or
+/// GEMINI_EXIT_SUMMARY=0.
+pub fn activity_summary() -> Option<String> {
+    let disabled = env::var("GEMINI_EXIT_SUMMARY")
+        .map(|v| v == "0" || v == "false")
+        .unwrap_or(false);
+    let stats = STATS.lock().unwrap();
+    if disabled || (stats.files_changed.is_empty() && stats.commands.is_empty() && stats.emails.is_empty()) {
+        return None;
+    }
+
+    let mut lines = vec!["This session".color(Color::Cyan).bold().to_string()];
+    let sections = [
+        ("Files changed", &stats.files_changed),
+        ("Commands run", &stats.commands),
+        ("Emails sent", &stats.emails),
+    ];
+    for (title, items) in sections {
+        if items.is_empty() {
+            continue;
+        }
+        lines.push(format!("  {} ({}):", title, items.len()));
+        for item in items.iter() {
+            lines.push(format!("    {}", item));
+        }
+    }
+    Some(lines.join("\n"))
+}
+
+/// The report printed by /stats.
+pub fn report(turns: usize) -> String {
+    let stats = STATS.lock().unwrap();
+    let usage = usage::session();
+    let mut lines = vec![
+        "Session statistics".color(Color::Cyan).bold().to_string(),
+        format!("  Turns:          {}", locale::format_number(turns)),
+        format!("  Model requests: {}", locale::format_number(usage.requests as usize)),
+        format!(
+            "  Tokens:         {} in / {} out",
+            locale::format_number(usage.input_tokens as usize),
+            locale::format_number(usage.output_tokens as usize)
+        ),
+        format!("  Estimated cost: ${:.4}", usage.cost),
+    ];
+
+    if !stats.api_latencies.is_empty() {
+        let mut latencies = stats.api_latencies.clone();
+        latencies.sort();
+        lines.push(format!(
+            "  API latency:    p50 {}  p90 {}  p99 {}  max {}",
+            seconds(percentile(&latencies, 50)),
+            seconds(percentile(&latencies, 90)),
+            seconds(percentile(&latencies, 99)),
+            seconds(*latencies.last().unwrap())
+        ));
+    }
+
+    lines.push(String::new());
+    if stats.tools.is_empty() {
+        lines.push("  No tool calls yet.".to_string());
+    } else {
+        lines.push(format!("  {:<22} {:>6} {:>8} {:>10} {:>9}", "Tool", "Calls", "Failed", "Total", "Average").bold().to_string());
+        let mut tools: Vec<(&String, &ToolStats)> = stats.tools.iter().collect();
+        tools.sort_by_key(|(_, tool)| std::cmp::Reverse(tool.total));
+        for (name, tool) in tools {
+            lines.push(format!(
+                "  {:<22} {:>6} {:>8} {:>10} {:>9}",
+                name,
+                tool.calls,
+                tool.failures,
+                seconds(tool.total),
+                seconds(tool.total / tool.calls as u32)
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Nearest-rank percentile of a sorted, non-empty list.
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.2}s", duration.as_secs_f64())
+}
@@ -0,0 +1,94 @@
+use reqwest::Response;
+use serde_json::{json, Value};
+use std::env;
+
+use crate::thoughts;
+
+/// Whether chat replies are streamed as they are generated; GEMINI_STREAM=0 waits for the
+/// whole reply instead.
+pub fn enabled() -> bool {
+    env::var("GEMINI_STREAM")
+        .map(|v| v != "0" && v != "false")
+        .unwrap_or(true)
+}
+
+/// Reads a streamGenerateContent response (server-sent events, one JSON chunk per `data:`
+/// line), passing each piece of reply text to `on_text` as it arrives, with whether it is a
+/// thought summary. Returns the chunks merged into the shape of a generateContent response.
+pub async fn read(mut response: Response, mut on_text: impl FnMut(&str, bool)) -> Result<Value, String> {
+    let mut merged = json!({"candidates": [{"content": {"role": "model", "parts": []}}]});
+    // Bytes of a line that hasn't ended yet; a chunk may stop in the middle of a character
+    let mut pending: Vec<u8> = Vec::new();
+    loop {
+        let chunk = response
+            .chunk()
+            .await
+            .map_err(|e| format!("Failed to read streamed response: {}", e))?;
+        let Some(chunk) = chunk else {
+            handle_line(&String::from_utf8_lossy(&pending), &mut merged, &mut on_text)?;
+            break;
+        };
+        pending.extend_from_slice(&chunk);
+        while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            handle_line(&String::from_utf8_lossy(&line), &mut merged, &mut on_text)?;
+        }
+    }
+    // Like generateContent, a reply without parts has no content (and stays out of the history)
+    if merged["candidates"][0]["content"]["parts"].as_array().map(|p| p.is_empty()).unwrap_or(true) {
+        if let Some(candidate) = merged["candidates"][0].as_object_mut() {
+            candidate.remove("content");
+        }
+    }
+    Ok(merged)
+}
+
+/// Passes the text of one `data:` line to `on_text` and merges its chunk.
+fn handle_line(line: &str, merged: &mut Value, on_text: &mut impl FnMut(&str, bool)) -> Result<(), String> {
+    let data = match line.strip_prefix("data:") {
+        Some(data) => data.trim(),
+        None => return Ok(()),
+    };
+    let chunk: Value = serde_json::from_str(data).map_err(|e| format!("Failed to parse streamed response: {}", e))?;
+    if let Some(error) = chunk.get("error") {
+        return Err(format!("API request failed: {}", error["message"].as_str().unwrap_or("stream error")));
+    }
+    for part in chunk["candidates"][0]["content"]["parts"].as_array().into_iter().flatten() {
+        if let Some(text) = part["text"].as_str() {
+            on_text(text, thoughts::is_thought(part));
+        }
+    }
+    merge(merged, &chunk);
+    Ok(())
+}
+
+/// Adds a chunk to the merged response: text continues the previous text part, other parts
+/// (function calls, thoughts) are appended, and the latest metadata wins.
+fn merge(merged: &mut Value, chunk: &Value) {
+    for key in ["usageMetadata", "promptFeedback", "modelVersion"] {
+        if let Some(value) = chunk.get(key) {
+            merged[key] = value.clone();
+        }
+    }
+    let candidate = &chunk["candidates"][0];
+    for key in ["finishReason", "safetyRatings"] {
+        if let Some(value) = candidate.get(key) {
+            merged["candidates"][0][key] = value.clone();
+        }
+    }
+
+    let parts = merged["candidates"][0]["content"]["parts"].as_array_mut().unwrap();
+    for part in candidate["content"]["parts"].as_array().into_iter().flatten() {
+        let plain_text = part.as_object().map(|o| o.len() == 1 && o.contains_key("text")).unwrap_or(false);
+        match parts.last_mut() {
+            Some(last) if plain_text && last.as_object().map(|o| o.len() == 1).unwrap_or(false) => {
+                if let Some(previous) = last["text"].as_str() {
+                    last["text"] = json!(format!("{}{}", previous, part["text"].as_str().unwrap_or("")));
+                    continue;
+                }
+                parts.push(part.clone());
+            }
+            _ => parts.push(part.clone()),
+        }
+    }
+}
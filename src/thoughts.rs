@@ -0,0 +1,65 @@
+use colored::Colorize;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::sync::Mutex;
+
+use crate::ui;
+
+// Lines of the model's thinking shown before its answer; /thoughts prints all of it
+const PREVIEW_LINES: usize = 3;
+
+// Thought summaries of the latest reply
+static LAST: Lazy<Mutex<String>> = Lazy::new(|| Mutex::new(String::new()));
+
+/// Whether a part is a summary of the model's thinking rather than part of its answer.
+pub fn is_thought(part: &Value) -> bool {
+    part["thought"].as_bool().unwrap_or(false)
+}
+
+/// The thought summaries of a reply's first candidate, one paragraph each.
+pub fn of(response: &Value) -> String {
+    response["candidates"][0]["content"]["parts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|part| is_thought(part))
+        .filter_map(|part| part["text"].as_str())
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// A reply's content as it is kept in the history: without its thoughts, which the model
+/// doesn't need again and which would take up the context window.
+pub fn without_thoughts(content: &Value) -> Value {
+    let mut content = content.clone();
+    if let Some(parts) = content["parts"].as_array_mut() {
+        parts.retain(|part| !is_thought(part));
+    }
+    content
+}
+
+/// Prints the start of the model's thinking, dimmed, and keeps all of it for /thoughts.
+/// Called for every reply, so /thoughts never shows the thinking behind an older one.
+pub fn show(text: &str) {
+    let text = text.trim();
+    *LAST.lock().unwrap() = text.to_string();
+    if text.is_empty() {
+        return;
+    }
+    let wrapped = ui::wrap_text(text, ui::terminal_width().saturating_sub(2));
+    let lines: Vec<&str> = wrapped.lines().filter(|line| !line.trim().is_empty()).collect();
+    ui::status(&"Thinking:".dimmed().italic().to_string());
+    for line in lines.iter().take(PREVIEW_LINES) {
+        ui::status(&format!("  {}", line).dimmed().italic().to_string());
+    }
+    if lines.len() > PREVIEW_LINES {
+        let more = format!("  … {} more lines (/thoughts shows all)", lines.len() - PREVIEW_LINES);
+        ui::status(&more.dimmed().to_string());
+    }
+}
+
+/// The thinking behind the latest reply, in full, when the model shared it.
+pub fn last() -> Option<String> {
+    Some(LAST.lock().unwrap().clone()).filter(|text| !text.is_empty())
+}
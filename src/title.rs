@@ -0,0 +1,72 @@
+use once_cell::sync::OnceCell;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// The window name tmux showed before we changed it, restored on exit
+static TMUX_WINDOW_NAME: OnceCell<Option<String>> = OnceCell::new();
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Starts managing the terminal title, saving the current one so it can be restored.
+/// Disabled when stderr isn't a terminal or GEMINI_TERMINAL_TITLE=0.
+pub fn init() {
+    let disabled = env::var("GEMINI_TERMINAL_TITLE")
+        .map(|v| v == "0" || v == "false")
+        .unwrap_or(false);
+    if disabled || !io::stderr().is_terminal() {
+        return;
+    }
+
+    TMUX_WINDOW_NAME.get_or_init(|| {
+        env::var("TMUX").ok()?;
+        let output = Command::new("tmux")
+            .args(["display-message", "-p", "#W"])
+            .output()
+            .ok()?;
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    });
+    // Push the current title on the terminal's title stack (xterm and most emulators)
+    write_escape("\x1b[22;0t");
+    ACTIVE.store(true, Ordering::SeqCst);
+    set("idle");
+}
+
+/// Shows the agent's state, e.g. "idle", "thinking" or "running cargo test".
+pub fn set(state: &str) {
+    if !ACTIVE.load(Ordering::SeqCst) {
+        return;
+    }
+    let state: String = state
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(60)
+        .collect();
+    let title = format!("gemini: {}", state);
+    write_escape(&format!("\x1b]0;{}\x07", title));
+    if in_tmux() {
+        // tmux shows its own window name rather than the terminal title
+        write_escape(&format!("\x1bk{}\x1b\\", title));
+    }
+}
+
+/// Puts back the title and tmux window name from before `init`.
+pub fn restore() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    write_escape("\x1b[23;0t");
+    if let Some(Some(name)) = TMUX_WINDOW_NAME.get() {
+        write_escape(&format!("\x1bk{}\x1b\\", name));
+    }
+}
+
+fn in_tmux() -> bool {
+    matches!(TMUX_WINDOW_NAME.get(), Some(Some(_)))
+}
+
+fn write_escape(sequence: &str) {
+    let mut stderr = io::stderr();
+    stderr.write_all(sequence.as_bytes()).ok();
+    stderr.flush().ok();
+}
@@ -0,0 +1,157 @@
+use serde_json::Value;
+use std::env;
+use std::time::{Duration, Instant};
+
+use crate::locale;
+use crate::usage;
+
+// The third identical call in a row is not run
+const REPEAT_LIMIT: usize = 3;
+// Tool rounds per turn before the model is asked to sum up
+const DEFAULT_MAX_ROUNDS: usize = 25;
+// Longest argument summary shown in a repetition report
+const MAX_CALL_WIDTH: usize = 80;
+
+/// Watches the tool calls of one turn for a model that is going in circles: the same call
+/// with the same arguments over and over, or two rounds of calls alternating (A, B, A, B).
+#[derive(Default)]
+pub struct LoopGuard {
+    /// The calls of each round so far, as "name(args)" signatures
+    rounds: Vec<Vec<String>>,
+}
+
+impl LoopGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a round of calls. Returns a description of the repetition when the round
+    /// shouldn't be run.
+    pub fn check(&mut self, calls: &[(String, Value)]) -> Option<String> {
+        let round: Vec<String> = calls.iter().map(|(name, args)| signature(name, args)).collect();
+
+        // Identical calls only count as a loop in consecutive rounds; running a build
+        // again after an edit is fine
+        let recent = &self.rounds[self.rounds.len().saturating_sub(REPEAT_LIMIT - 1)..];
+        for call in &round {
+            if recent.len() == REPEAT_LIMIT - 1 && recent.iter().all(|previous| previous.contains(call)) {
+                return Some(format!(
+                    "{} was requested {} times in a row with the same arguments (tool rounds {} to {})",
+                    shorten(call),
+                    REPEAT_LIMIT,
+                    self.rounds.len() + 2 - REPEAT_LIMIT,
+                    self.rounds.len() + 1
+                ));
+            }
+        }
+
+        let count = self.rounds.len();
+        if count >= 3
+            && round == self.rounds[count - 2]
+            && self.rounds[count - 1] == self.rounds[count - 3]
+            && round != self.rounds[count - 1]
+        {
+            return Some(format!(
+                "the calls keep alternating between {} and {} (tool rounds {} to {})",
+                round.iter().map(|c| shorten(c)).collect::<Vec<_>>().join(", "),
+                self.rounds[count - 1].iter().map(|c| shorten(c)).collect::<Vec<_>>().join(", "),
+                count - 2,
+                count + 1
+            ));
+        }
+
+        self.rounds.push(round);
+        None
+    }
+}
+
+/// Tool rounds allowed per turn, from GEMINI_MAX_TOOL_ROUNDS; 0 means no limit.
+pub fn max_rounds() -> usize {
+    env::var("GEMINI_MAX_TOOL_ROUNDS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_ROUNDS)
+}
+
+/// The time and tokens the tool calls of one turn may use, from GEMINI_TOOL_TIME_BUDGET
+/// (seconds) and GEMINI_TOOL_TOKEN_BUDGET; unset or 0 means no limit. Tokens are counted over
+/// every request made while the tools run, condensing included.
+pub struct TurnBudget {
+    started: Instant,
+    start_tokens: u64,
+    max_time: Option<Duration>,
+    max_tokens: Option<u64>,
+    rounds: usize,
+    calls: usize,
+}
+
+impl TurnBudget {
+    pub fn start() -> Self {
+        let setting = |key: &str| {
+            env::var(key)
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .filter(|limit| *limit > 0)
+        };
+        TurnBudget {
+            started: Instant::now(),
+            start_tokens: usage::session().total_tokens(),
+            max_time: setting("GEMINI_TOOL_TIME_BUDGET").map(Duration::from_secs),
+            max_tokens: setting("GEMINI_TOOL_TOKEN_BUDGET"),
+            rounds: 0,
+            calls: 0,
+        }
+    }
+
+    /// Counts a round of tool calls that was run.
+    pub fn record_round(&mut self, calls: usize) {
+        self.rounds += 1;
+        self.calls += calls;
+    }
+
+    /// Describes the limit that was reached, if any.
+    pub fn exhausted(&self) -> Option<String> {
+        if let Some(max_time) = self.max_time.filter(|max| self.started.elapsed() >= *max) {
+            return Some(format!("the time budget of {} for this turn is used up", minutes(max_time)));
+        }
+        self.max_tokens
+            .filter(|max| self.tokens() >= *max)
+            .map(|max| format!("the budget of {} tokens for this turn is used up", locale::format_number(max as usize)))
+    }
+
+    /// What the turn's tool calls did so far, e.g. "12 tool calls in 5 rounds, 3m 20s, 84,000 tokens".
+    pub fn summary(&self) -> String {
+        format!(
+            "{} tool calls in {} rounds, {}, {} tokens",
+            self.calls,
+            self.rounds,
+            minutes(self.started.elapsed()),
+            locale::format_number(self.tokens() as usize)
+        )
+    }
+
+    fn tokens(&self) -> u64 {
+        usage::session().total_tokens().saturating_sub(self.start_tokens)
+    }
+}
+
+fn minutes(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else {
+        format!("{}m {}s", seconds / 60, seconds % 60)
+    }
+}
+
+fn signature(name: &str, args: &Value) -> String {
+    format!("{}({})", name, serde_json::to_string(args).unwrap_or_default())
+}
+
+fn shorten(call: &str) -> String {
+    if call.chars().count() > MAX_CALL_WIDTH {
+        call.chars().take(MAX_CALL_WIDTH - 4).collect::<String>() + "...)"
+    } else {
+        call.to_string()
+    }
+}
@@ -0,0 +1,481 @@
+use clap::Subcommand;
+use colored::{Color, Colorize};
+use serde_json::{json, Value};
+use std::env;
+
+use crate::config;
+use crate::persona;
+use crate::project;
+use crate::plugins;
+use crate::external_tools;
+
+// Comma-separated list of tools hidden from the model
+const DISABLED_KEY: &str = "GEMINI_DISABLED_TOOLS";
+
+#[derive(Subcommand)]
+pub enum ToolsAction {
+    /// List the tools and whether they are enabled
+    List,
+    /// Offer a tool to the model again
+    Enable { name: String },
+    /// Stop offering a tool to the model
+    Disable { name: String },
+}
+
+/// Handles `gemini-cli-rs tools ...`.
+pub fn run(action: Option<ToolsAction>) -> Result<(), String> {
+    match action.unwrap_or(ToolsAction::List) {
+        ToolsAction::List => {
+            for decl in function_declarations() {
+                let name = decl["name"].as_str().unwrap_or("");
+                let status = if is_enabled(name) {
+                    "enabled ".color(Color::Green)
+                } else {
+                    "disabled".color(Color::Red)
+                };
+                println!(
+                    "{} {:<20} {}",
+                    status,
+                    name.bold(),
+                    decl["description"].as_str().unwrap_or("").dimmed()
+                );
+            }
+            Ok(())
+        }
+        ToolsAction::Enable { name } => set_enabled(&name, true),
+        ToolsAction::Disable { name } => set_enabled(&name, false),
+    }
+}
+
+fn disabled_tools() -> Vec<String> {
+    env::var(DISABLED_KEY)
+        .unwrap_or_default()
+        .split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// Whether a tool is offered: not disabled in the config, and allowed by the project file
+/// and the active persona.
+pub fn is_enabled(name: &str) -> bool {
+    !disabled_tools().iter().any(|t| t == name) && project::allows_tool(name) && persona::allows_tool(name)
+}
+
+/// Declarations for the tools that haven't been disabled, as sent to the model.
+pub fn enabled_declarations() -> Vec<Value> {
+    function_declarations()
+        .into_iter()
+        .filter(|decl| decl["name"].as_str().map(is_enabled).unwrap_or(false))
+        .collect()
+}
+
+fn set_enabled(name: &str, enabled: bool) -> Result<(), String> {
+    if !function_declarations().iter().any(|decl| decl["name"] == name) {
+        return Err(format!("Unknown tool: {} (see `tools list`)", name));
+    }
+    let mut disabled: Vec<String> = disabled_tools().into_iter().filter(|t| t != name).collect();
+    if !enabled {
+        disabled.push(name.to_string());
+    }
+    if disabled.is_empty() {
+        config::unset(DISABLED_KEY)?;
+    } else {
+        config::set(DISABLED_KEY, &disabled.join(","))?;
+    }
+    println!(
+        "{}",
+        format!("{} {}", name, if enabled { "enabled" } else { "disabled" }).color(Color::Cyan)
+    );
+    Ok(())
+}
+
+/// JSON schema declarations for every tool the model can call, plugins and external tools included.
+pub fn function_declarations() -> Vec<Value> {
+    let mut declarations = builtin_declarations();
+    declarations.extend(plugins::declarations());
+    declarations.extend(external_tools::declarations());
+    declarations
+}
+
+/// Declarations of the tools built into the CLI.
+pub fn builtin_declarations() -> Vec<Value> {
+    vec![
+        json!({
+            "name": "search_online",
+            "description": "Searches the web for a given query. Use it to retrieve up to date information.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "The search query",
+                    }
+                },
+                "required": ["query"]
+            }
+        }),
+        json!({
+            "name": "execute_command",
+            "description": "Execute a system command. Use this for any shell task. The working directory and exported variables carry over to the next command, as in a terminal.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "command": {"type": "string"}
+                },
+                "required": ["command"]
+            }
+        }),
+        json!({
+            "name": "send_email",
+            "description": "Sends an email using SMTP, optionally with an HTML version and files from the sandbox attached. Without recipients it goes to the user's configured address; other recipients must be on the user's allowlist.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "subject": {"type": "string", "description": "Email subject line"},
+                    "body": {"type": "string", "description": "Email message body as plain text"},
+                    "html": {"type": "string", "description": "HTML version of the body, shown by mail clients that support it; body remains the plain text version"},
+                    "attachments": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Paths of files in the sandbox to attach (15 MB in total at most)"
+                    },
+                    "to": {"type": "array", "items": {"type": "string"}, "description": "Recipient addresses; the user's own address when left out"},
+                    "cc": {"type": "array", "items": {"type": "string"}, "description": "Carbon copy addresses"},
+                    "bcc": {"type": "array", "items": {"type": "string"}, "description": "Blind carbon copy addresses"}
+                },
+                "required": ["subject", "body"]
+            }
+        }),
+        json!({
+            "name": "alpha_vantage_query",
+            "description": "Query the Alpha Vantage API for stock/financial data. Returns the scalar fields and a table of the latest data points of a time series, not the raw response",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "function": {
+                        "type": "string",
+                        "description": "The Alpha Vantage function (e.g., TIME_SERIES_DAILY, TIME_SERIES_INTRADAY, GLOBAL_QUOTE)"
+                    },
+                    "symbol": {
+                        "type": "string",
+                        "description": "The stock symbol (e.g., IBM)"
+                    },
+                    "interval": {
+                        "type": "string",
+                        "description": "Time between data points for intraday and technical indicator functions (e.g., 5min, 60min, daily)"
+                    },
+                    "outputsize": {
+                        "type": "string",
+                        "enum": ["compact", "full"],
+                        "description": "compact for the latest 100 data points (default), full for the whole history"
+                    },
+                    "month": {
+                        "type": "string",
+                        "description": "Month of intraday history to return, as YYYY-MM"
+                    }
+                },
+                "required": ["function"]
+            }
+        }),
+        json!({
+            "name": "fx_rate",
+            "description": "Current exchange rate between two currencies, e.g. USD to EUR. Also works for a cryptocurrency against a currency (BTC to USD)",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "from_currency": {"type": "string", "description": "Currency code to convert from (e.g., USD, EUR, BTC)"},
+                    "to_currency": {"type": "string", "description": "Currency code to convert to (e.g., JPY, USD)"}
+                },
+                "required": ["from_currency", "to_currency"]
+            }
+        }),
+        json!({
+            "name": "crypto_price",
+            "description": "Current price of a cryptocurrency, and optionally its daily open, high, low, close and volume for the latest days",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "symbol": {"type": "string", "description": "The cryptocurrency symbol (e.g., BTC, ETH)"},
+                    "market": {"type": "string", "description": "Currency to price it in (default USD)"},
+                    "history": {"type": "boolean", "description": "Also return the daily prices of the latest days"}
+                },
+                "required": ["symbol"]
+            }
+        }),
+        json!({
+            "name": "company_overview",
+            "description": "Profile and key figures of a listed company: description, sector, market capitalization, P/E ratio, EPS, dividend yield, 52-week high and low, analyst target price",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "symbol": {"type": "string", "description": "The stock symbol (e.g., IBM)"}
+                },
+                "required": ["symbol"]
+            }
+        }),
+        json!({
+            "name": "company_earnings",
+            "description": "Earnings per share of a listed company by year and by quarter, with the quarterly estimates and surprises",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "symbol": {"type": "string", "description": "The stock symbol (e.g., IBM)"}
+                },
+                "required": ["symbol"]
+            }
+        }),
+        json!({
+            "name": "scrape_url",
+            "description": "Reads a single URL: the main content of a web page as Markdown (without navigation, footers or comments), or the text of a PDF, JSON or plain text document. Long documents come back summarized part by part",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "url": {
+                        "type": "string",
+                        "description": "The URL to scrape",
+                    }
+                },
+                "required": ["url"]
+            }
+        }),
+        json!({
+            "name": "http_request",
+            "description": "Sends an HTTP request to a REST API, webhook or local dev server and returns the status, response headers and body as JSON (JSON bodies parsed). Use it instead of curl. Long bodies are cut and credentials in the response are redacted.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "method": {
+                        "type": "string",
+                        "enum": ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"],
+                    },
+                    "url": {
+                        "type": "string",
+                        "description": "Full http:// or https:// URL, including any query string",
+                    },
+                    "headers": {
+                        "type": "string",
+                        "description": "Request headers, one 'Name: value' per line, e.g. 'Accept: application/json'",
+                    },
+                    "body": {
+                        "type": "string",
+                        "description": "Request body; sent as application/json when it is JSON and no Content-Type is given",
+                    }
+                },
+                "required": ["method", "url"]
+            }
+        }),
+        json!({
+            "name": "file_editor",
+            "description": "Edit files in the sandbox with sub-commands: read, write, search, search_and_replace, apply_diff, mkdir, delete, move, copy. Use these instead of shell commands such as mkdir, rm, mv and cp. apply_diff takes a unified diff whose hunks are located by their context lines, so line numbers may be approximate; a diff with ---/+++ headers for several files changes all of them, and nothing is changed if any hunk doesn't match. Large files are read a page of numbered lines at a time; use start_line and end_line to read a part of a file.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "subcommand": {
+                        "type": "string",
+                        "description": "The sub-command to execute: read, write, search, search_and_replace, apply_diff, mkdir (create a directory and its parents), delete (a file, or a directory with everything in it), move (rename), copy",
+                        "enum": ["read", "write", "search", "search_and_replace", "apply_diff", "mkdir", "delete", "move", "copy"]
+                    },
+                    "filename": {
+                        "type": "string",
+                        "description": "The path of the file or directory in the sandbox to operate on"
+                    },
+                    "data": {
+                        "type": "string",
+                        "description": "Content to write (for write), regex pattern (for search/search_and_replace), diff content (for apply_diff), or destination path (for move/copy)"
+                    },
+                    "replacement": {
+                        "type": "string",
+                        "description": "Replacement text for search_and_replace"
+                    },
+                    "start_line": {
+                        "type": "integer",
+                        "description": "First line to read, counting from 1 (for read)"
+                    },
+                    "end_line": {
+                        "type": "integer",
+                        "description": "Last line to read (for read)"
+                    }
+                },
+                "required": ["subcommand", "filename"]
+            }
+        }),
+        json!({
+            "name": "apply_changeset",
+            "description": "Applies unified diffs to several files as one change, for refactors that span files. Every diff is checked against its file first; if any hunk doesn't match, or a file can't be written, no file is changed.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "changes": {
+                        "type": "array",
+                        "description": "One entry per file",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "filename": {"type": "string", "description": "The path of the file in the sandbox"},
+                                "diff": {"type": "string", "description": "Unified diff for this file (hunks starting with @@)"}
+                            },
+                            "required": ["filename", "diff"]
+                        }
+                    }
+                },
+                "required": ["changes"]
+            }
+        }),
+        json!({
+            "name": "undo_last_edit",
+            "description": "Reverts the most recent file_editor write, search_and_replace or apply_diff, restoring the file as it was before (or removing it if the edit created it). Call it again to undo earlier edits.",
+            "parameters": {
+                "type": "object",
+                "properties": {}
+            }
+        }),
+        json!({
+            "name": "run_in_background",
+            "description": "Starts a shell command in the background and returns at once, for dev servers, file watchers and other processes that keep running. It runs in the sandbox and the current working directory like execute_command. Check on it with list_jobs and stop it with kill_job; running jobs are stopped when the CLI exits.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "command": {"type": "string"}
+                },
+                "required": ["command"]
+            }
+        }),
+        json!({
+            "name": "list_jobs",
+            "description": "Shows the background jobs with their status (running, or how they exited) and the output each printed since the last list_jobs call.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "id": {
+                        "type": "integer",
+                        "description": "Only show this job"
+                    }
+                }
+            }
+        }),
+        json!({
+            "name": "kill_job",
+            "description": "Stops a background job and the processes it started.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "id": {
+                        "type": "integer",
+                        "description": "Job number from run_in_background or list_jobs"
+                    }
+                },
+                "required": ["id"]
+            }
+        }),
+        json!({
+            "name": "list_files",
+            "description": "Lists files in the sandbox with sizes and modification times, skipping hidden and .gitignored files. Without a pattern it shows a directory tree; with a glob pattern (e.g. **/*.rs) it lists the matching files. Use it to explore the project structure instead of running ls or find.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Directory in the sandbox to list (default: the sandbox root)"
+                    },
+                    "pattern": {
+                        "type": "string",
+                        "description": "Glob pattern relative to the directory, e.g. *.md or src/**/*.rs"
+                    },
+                    "depth": {
+                        "type": "integer",
+                        "description": "How many directory levels to descend (default 3 for a tree, unlimited with a pattern)"
+                    }
+                }
+            }
+        }),
+        json!({
+            "name": "search_codebase",
+            "description": "Searches all text files of the sandbox for a string or regular expression and returns the matching lines with line numbers and a few lines around them, grouped by file. Hidden, .gitignored and binary files are skipped, and the search ignores case unless the query has capitals. Use it to find where a symbol is defined or used instead of running grep.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "Text to search for, or a regular expression when regex is true"
+                    },
+                    "regex": {
+                        "type": "boolean",
+                        "description": "Treat the query as a regular expression (default false: a literal string)"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Directory in the sandbox to search (default: the sandbox root)"
+                    },
+                    "glob": {
+                        "type": "string",
+                        "description": "Only search files matching this glob pattern, relative to the directory, e.g. **/*.rs"
+                    },
+                    "context": {
+                        "type": "integer",
+                        "description": "Lines shown before and after each match (default 2, at most 10)"
+                    },
+                    "max_per_file": {
+                        "type": "integer",
+                        "description": "Matching lines shown per file (default 5)"
+                    }
+                },
+                "required": ["query"]
+            }
+        }),
+        json!({
+            "name": "github",
+            "description": "Work with GitHub issues and pull requests: list_issues, view_issue, create_issue, view_pr_diff, comment_pr. The repository defaults to the origin remote of the current directory.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "description": "The operation to perform",
+                        "enum": ["list_issues", "view_issue", "create_issue", "view_pr_diff", "comment_pr"]
+                    },
+                    "repo": {
+                        "type": "string",
+                        "description": "Repository as owner/name (optional, defaults to the origin remote)"
+                    },
+                    "number": {
+                        "type": "integer",
+                        "description": "Issue or pull request number (for view_issue, view_pr_diff, comment_pr)"
+                    },
+                    "title": {"type": "string", "description": "Issue title (for create_issue)"},
+                    "body": {"type": "string", "description": "Issue description or comment text (for create_issue, comment_pr)"},
+                    "labels": {"type": "string", "description": "Comma-separated labels (filter for list_issues, labels to set for create_issue)"},
+                    "state": {
+                        "type": "string",
+                        "description": "Issue state filter for list_issues (default open)",
+                        "enum": ["open", "closed", "all"]
+                    }
+                },
+                "required": ["operation"]
+            }
+        }),
+        json!({
+            "name": "tracker",
+            "description": "Work with the project's issue tracker (Jira or Linear): get_issue fetches an issue by key, my_issues lists open issues assigned to the user, add_comment posts a comment on an issue.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "description": "The operation to perform",
+                        "enum": ["get_issue", "my_issues", "add_comment"]
+                    },
+                    "key": {
+                        "type": "string",
+                        "description": "Issue key such as PROJ-123 (for get_issue and add_comment)"
+                    },
+                    "body": {
+                        "type": "string",
+                        "description": "Comment text (for add_comment)"
+                    }
+                },
+                "required": ["operation"]
+            }
+        }),
+    ]
+}
@@ -0,0 +1,281 @@
+use colored::{Color, Colorize};
+use reqwest::blocking::RequestBuilder;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+
+use crate::config;
+use crate::http;
+use crate::ui;
+
+const LINEAR_API: &str = "https://api.linear.app/graphql";
+
+// Per-project overrides, read from the working directory so each checkout can
+// point at its own tracker, project key or team.
+const PROJECT_FILE: &str = ".gemini-tracker";
+
+/// Tracker settings: the project file takes precedence over ~/.gemini.conf.
+struct TrackerConfig {
+    values: HashMap<String, String>,
+}
+
+impl TrackerConfig {
+    fn load() -> Self {
+        // Same KEY=VALUE format as ~/.gemini.conf, without touching the process environment
+        let values = fs::read_to_string(PROJECT_FILE)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(config::parse_line)
+            .collect();
+        TrackerConfig { values }
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.values
+            .get(key)
+            .cloned()
+            .or_else(|| env::var(key).ok())
+            .filter(|v| !v.trim().is_empty())
+    }
+
+    fn require(&self, key: &str) -> Result<String, String> {
+        self.get(key)
+            .ok_or_else(|| format!("{} not found in {} or ~/.gemini.conf", key, PROJECT_FILE))
+    }
+}
+
+/// Runs an issue tracker operation: get_issue, my_issues or add_comment.
+/// TRACKER selects the backend ("jira" or "linear").
+pub fn tracker_query(operation: &str, key: Option<&str>, body: Option<&str>) -> Result<String, String> {
+    let config = TrackerConfig::load();
+    let backend = config
+        .get("TRACKER")
+        .map(|t| t.to_lowercase())
+        .ok_or_else(|| format!("TRACKER not set (jira or linear) in {} or ~/.gemini.conf", PROJECT_FILE))?;
+
+    ui::status(&format!(
+        "{} {} {}",
+        format!("Gemini is querying {}:", backend).color(Color::Cyan).bold(),
+        operation,
+        key.unwrap_or("")
+    ));
+
+    let key = || key.ok_or_else(|| format!("Missing 'key' parameter for {}", operation));
+    match (backend.as_str(), operation) {
+        ("jira", "get_issue") => jira_get_issue(&config, key()?),
+        ("jira", "my_issues") => jira_my_issues(&config),
+        ("jira", "add_comment") => jira_add_comment(&config, key()?, body.ok_or("Missing 'body' parameter for add_comment")?),
+        ("linear", "get_issue") => linear_get_issue(&config, key()?),
+        ("linear", "my_issues") => linear_my_issues(&config),
+        ("linear", "add_comment") => linear_add_comment(&config, key()?, body.ok_or("Missing 'body' parameter for add_comment")?),
+        ("jira", _) | ("linear", _) => Err(format!("Unknown tracker operation: {}", operation)),
+        _ => Err(format!("Unsupported tracker '{}' (expected jira or linear)", backend)),
+    }
+}
+
+fn send(builder: RequestBuilder, service: &str) -> Result<Value, String> {
+    let response = builder
+        .header("Accept", "application/json")
+        .send()
+        .map_err(|e| format!("{} request failed: {}", service, e))?;
+    let status = response.status();
+    let text = response
+        .text()
+        .map_err(|e| format!("Failed to read {} response: {}", service, e))?;
+    if !status.is_success() {
+        return Err(format!("{} API error ({}): {}", service, status, text.trim()));
+    }
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse {} response: {}", service, e))
+}
+
+// --- Jira (REST API v2, which returns plain-text descriptions) ---
+
+fn jira_request(config: &TrackerConfig, builder: RequestBuilder) -> Result<RequestBuilder, String> {
+    let token = config.require("JIRA_API_TOKEN")?;
+    // Jira Cloud uses email + API token; Server/Data Center uses a personal access token
+    Ok(match config.get("JIRA_EMAIL") {
+        Some(email) => builder.basic_auth(email, Some(token)),
+        None => builder.bearer_auth(token),
+    })
+}
+
+fn jira_url(config: &TrackerConfig, path: &str) -> Result<String, String> {
+    let base = config.require("JIRA_URL")?;
+    Ok(format!("{}/rest/api/2/{}", base.trim_end_matches('/'), path))
+}
+
+fn jira_get_issue(config: &TrackerConfig, key: &str) -> Result<String, String> {
+    let url = jira_url(config, &format!("issue/{}?fields=summary,status,assignee,reporter,description,comment", key))?;
+    let issue = send(jira_request(config, http::client().get(url))?, "Jira")?;
+    let fields = &issue["fields"];
+
+    let mut output = format!(
+        "{} {} [{}]\nAssignee: {}\nReporter: {}\n\n{}",
+        issue["key"].as_str().unwrap_or(key),
+        fields["summary"].as_str().unwrap_or(""),
+        fields["status"]["name"].as_str().unwrap_or(""),
+        fields["assignee"]["displayName"].as_str().unwrap_or("unassigned"),
+        fields["reporter"]["displayName"].as_str().unwrap_or("unknown"),
+        fields["description"].as_str().unwrap_or("(no description)")
+    );
+    for comment in fields["comment"]["comments"].as_array().into_iter().flatten() {
+        output.push_str(&format!(
+            "\n\n--- Comment by {} ({}) ---\n{}",
+            comment["author"]["displayName"].as_str().unwrap_or("unknown"),
+            comment["created"].as_str().unwrap_or(""),
+            comment["body"].as_str().unwrap_or("")
+        ));
+    }
+    Ok(output)
+}
+
+fn jira_my_issues(config: &TrackerConfig) -> Result<String, String> {
+    let mut jql = "assignee = currentUser() AND resolution = Unresolved".to_string();
+    if let Some(project) = config.get("JIRA_PROJECT") {
+        jql.push_str(&format!(" AND project = \"{}\"", project));
+    }
+    jql.push_str(" ORDER BY updated DESC");
+    let url = jira_url(
+        config,
+        &format!("search?jql={}&fields=summary,status&maxResults=50", urlencoding::encode(&jql)),
+    )?;
+    let result = send(jira_request(config, http::client().get(url))?, "Jira")?;
+
+    let issues: Vec<String> = result["issues"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|issue| {
+            format!(
+                "{} [{}] {}",
+                issue["key"].as_str().unwrap_or(""),
+                issue["fields"]["status"]["name"].as_str().unwrap_or(""),
+                issue["fields"]["summary"].as_str().unwrap_or("")
+            )
+        })
+        .collect();
+    if issues.is_empty() {
+        Ok("No open issues assigned to you.".to_string())
+    } else {
+        Ok(issues.join("\n"))
+    }
+}
+
+fn jira_add_comment(config: &TrackerConfig, key: &str, body: &str) -> Result<String, String> {
+    let url = jira_url(config, &format!("issue/{}/comment", key))?;
+    send(
+        jira_request(config, http::client().post(url).json(&json!({"body": body})))?,
+        "Jira",
+    )?;
+    Ok(format!("Added comment to {}", key))
+}
+
+// --- Linear (GraphQL API) ---
+
+fn linear_graphql(config: &TrackerConfig, query: &str, variables: Value) -> Result<Value, String> {
+    let api_key = config.require("LINEAR_API_KEY")?;
+    let result = send(
+        http::client()
+            .post(LINEAR_API)
+            .header("Authorization", api_key)
+            .json(&json!({"query": query, "variables": variables})),
+        "Linear",
+    )?;
+    if let Some(errors) = result.get("errors").and_then(|e| e.as_array()) {
+        let messages: Vec<&str> = errors.iter().filter_map(|e| e["message"].as_str()).collect();
+        return Err(format!("Linear API error: {}", messages.join("; ")));
+    }
+    Ok(result["data"].clone())
+}
+
+fn linear_get_issue(config: &TrackerConfig, key: &str) -> Result<String, String> {
+    let data = linear_graphql(
+        config,
+        "query($id: String!) { issue(id: $id) { identifier title description url state { name } \
+         assignee { name } comments { nodes { body createdAt user { name } } } } }",
+        json!({"id": key}),
+    )?;
+    let issue = &data["issue"];
+    if issue.is_null() {
+        return Err(format!("Issue {} not found", key));
+    }
+
+    let mut output = format!(
+        "{} {} [{}]\nAssignee: {}\nURL: {}\n\n{}",
+        issue["identifier"].as_str().unwrap_or(key),
+        issue["title"].as_str().unwrap_or(""),
+        issue["state"]["name"].as_str().unwrap_or(""),
+        issue["assignee"]["name"].as_str().unwrap_or("unassigned"),
+        issue["url"].as_str().unwrap_or(""),
+        issue["description"].as_str().unwrap_or("(no description)")
+    );
+    for comment in issue["comments"]["nodes"].as_array().into_iter().flatten() {
+        output.push_str(&format!(
+            "\n\n--- Comment by {} ({}) ---\n{}",
+            comment["user"]["name"].as_str().unwrap_or("unknown"),
+            comment["createdAt"].as_str().unwrap_or(""),
+            comment["body"].as_str().unwrap_or("")
+        ));
+    }
+    Ok(output)
+}
+
+fn linear_my_issues(config: &TrackerConfig) -> Result<String, String> {
+    let mut filter = json!({"state": {"type": {"nin": ["completed", "canceled"]}}});
+    if let Some(team) = config.get("LINEAR_TEAM") {
+        filter["team"] = json!({"key": {"eq": team}});
+    }
+    let data = linear_graphql(
+        config,
+        "query($filter: IssueFilter) { viewer { assignedIssues(filter: $filter, first: 50) { \
+         nodes { identifier title state { name } } } } }",
+        json!({"filter": filter}),
+    )?;
+
+    let issues: Vec<String> = data["viewer"]["assignedIssues"]["nodes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|issue| {
+            format!(
+                "{} [{}] {}",
+                issue["identifier"].as_str().unwrap_or(""),
+                issue["state"]["name"].as_str().unwrap_or(""),
+                issue["title"].as_str().unwrap_or("")
+            )
+        })
+        .collect();
+    if issues.is_empty() {
+        Ok("No open issues assigned to you.".to_string())
+    } else {
+        Ok(issues.join("\n"))
+    }
+}
+
+fn linear_add_comment(config: &TrackerConfig, key: &str, body: &str) -> Result<String, String> {
+    // commentCreate needs the issue's UUID, not its identifier
+    let data = linear_graphql(
+        config,
+        "query($id: String!) { issue(id: $id) { id } }",
+        json!({"id": key}),
+    )?;
+    let issue_id = data["issue"]["id"]
+        .as_str()
+        .ok_or_else(|| format!("Issue {} not found", key))?
+        .to_string();
+
+    let data = linear_graphql(
+        config,
+        "mutation($input: CommentCreateInput!) { commentCreate(input: $input) { success comment { url } } }",
+        json!({"input": {"issueId": issue_id, "body": body}}),
+    )?;
+    if data["commentCreate"]["success"].as_bool() != Some(true) {
+        return Err(format!("Linear did not accept the comment on {}", key));
+    }
+    Ok(format!(
+        "Added comment to {}: {}",
+        key,
+        data["commentCreate"]["comment"]["url"].as_str().unwrap_or("")
+    ))
+}
@@ -0,0 +1,753 @@
+use once_cell::sync::Lazy;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::cursor::Show;
+use ratatui::crossterm::event::{
+    self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Direction, Layout, Position, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+use rustyline::history::{DefaultHistory, History};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::{handle_input, history, i18n, persona, print_banner, prompt_status, ChatManager};
+
+// Below this width the panes are stacked instead of side by side
+const SIDE_BY_SIDE_WIDTH: u16 = 100;
+// Lines the input box grows to before it scrolls
+const MAX_INPUT_LINES: usize = 5;
+// Lines kept in the activity pane; older ones are dropped
+const MAX_ACTIVITY_LINES: usize = 5000;
+// How often the screen is redrawn while nothing happens, e.g. for the progress label
+const TICK: Duration = Duration::from_millis(100);
+
+/// What the chat and its output tell the interface.
+enum Update {
+    /// Text the CLI printed, with its colors
+    Output(String),
+    /// A model answer for the conversation pane
+    Answer(String),
+    /// The chat handled an input: the prompt status for the next one, or None when the user quit
+    Ready(Option<String>),
+}
+
+// Set while the interface runs; answers go there instead of to stdout
+static UPDATES: Lazy<Mutex<Option<Sender<Update>>>> = Lazy::new(|| Mutex::new(None));
+// What the spinner would show, e.g. "calling model"
+static PROGRESS: Lazy<Mutex<String>> = Lazy::new(|| Mutex::new(String::new()));
+// The terminal and the redirected streams, to put back on exit
+static SESSION: Lazy<Mutex<Option<Session>>> = Lazy::new(|| Mutex::new(None));
+
+/// Whether the chat runs in the full-screen interface.
+pub fn active() -> bool {
+    UPDATES.lock().unwrap().is_some()
+}
+
+/// Shows a model answer in the conversation pane.
+pub fn answer(text: &str) {
+    if let Some(updates) = UPDATES.lock().unwrap().as_ref() {
+        updates.send(Update::Answer(text.to_string())).ok();
+    }
+}
+
+/// Sets the progress label shown above the input box; called by the spinner.
+pub fn set_progress(label: &str) {
+    *PROGRESS.lock().unwrap() = label.to_string();
+}
+
+/// Puts the terminal back the way it was: called on exit, from the Ctrl-C handler and when
+/// the CLI panics. Does nothing when the interface isn't running.
+pub fn leave() {
+    UPDATES.lock().unwrap().take();
+    if let Some(session) = SESSION.lock().unwrap().take() {
+        session.close();
+    }
+}
+
+/// Runs the chat in a full-screen interface: the conversation on one side, everything the
+/// tools and commands print on the other, and an input box below. Input is handled as in
+/// the plain chat; the CLI's stdout and stderr are read from a pipe for the activity pane,
+/// and what is typed while a request runs answers its questions (e.g. tool confirmations).
+pub fn run(chat_manager: &Arc<Mutex<ChatManager>>, debug: bool) -> Result<(), String> {
+    let colors = colored::control::SHOULD_COLORIZE.should_colorize();
+    let tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|e| format!("The TUI needs a terminal: {}", e))?;
+    let (updates, received) = mpsc::channel();
+    let streams = redirect::Streams::start()?;
+    // Output now goes to a pipe; keep the colors the terminal would have shown
+    colored::control::set_override(colors);
+    let output = streams.output.try_clone().map_err(|e| format!("Cannot read the output: {}", e))?;
+    let mut answers = streams.input.try_clone().map_err(|e| format!("Cannot forward input: {}", e))?;
+    let mut session = Session { tty, streams, screen: false };
+    if let Err(e) = session.enter() {
+        session.close();
+        return Err(format!("Cannot start the TUI: {}", e));
+    }
+    let backend = CrosstermBackend::new(session.tty.try_clone().map_err(|e| e.to_string())?);
+    *SESSION.lock().unwrap() = Some(session);
+    *UPDATES.lock().unwrap() = Some(updates.clone());
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        leave();
+        default_hook(info);
+    }));
+
+    read_output(output, updates.clone());
+    let inputs = start_chat(chat_manager, debug, updates);
+
+    let result = Terminal::new(backend)
+        .map_err(|e| e.to_string())
+        .and_then(|mut terminal| App::new().run(&mut terminal, &received, &inputs, &mut answers));
+    leave();
+    chat_manager.lock().unwrap().cleanup();
+    result
+}
+
+/// Reads what the CLI prints and passes it on, keeping a character that was cut in half
+/// for the next read.
+fn read_output(mut output: File, updates: Sender<Update>) {
+    thread::spawn(move || {
+        let mut pending: Vec<u8> = Vec::new();
+        let mut buffer = [0u8; 4096];
+        while let Ok(count) = output.read(&mut buffer) {
+            if count == 0 {
+                break;
+            }
+            pending.extend_from_slice(&buffer[..count]);
+            let complete = match std::str::from_utf8(&pending) {
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                _ => pending.len(),
+            };
+            let text = String::from_utf8_lossy(&pending[..complete]).into_owned();
+            pending.drain(..complete);
+            if updates.send(Update::Output(text)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Runs the chat on its own thread, one input at a time, so the screen stays responsive
+/// while a request or tool is running. Returns where to send the inputs.
+fn start_chat(chat_manager: &Arc<Mutex<ChatManager>>, debug: bool, updates: Sender<Update>) -> Sender<String> {
+    let (inputs, received) = mpsc::channel::<String>();
+    let chat_manager = Arc::clone(chat_manager);
+    thread::spawn(move || {
+        print_banner();
+        updates.send(Update::Ready(Some(prompt_status(&chat_manager)))).ok();
+        for input in received {
+            let more = handle_input(input.trim(), &chat_manager, debug);
+            io::stdout().flush().ok();
+            updates.send(Update::Ready(more.then(|| prompt_status(&chat_manager)))).ok();
+            if !more {
+                break;
+            }
+        }
+    });
+    inputs
+}
+
+/// The terminal the interface draws on and the streams it replaced.
+struct Session {
+    tty: File,
+    streams: redirect::Streams,
+    /// Whether raw mode and the alternate screen are on
+    screen: bool,
+}
+
+impl Session {
+    fn enter(&mut self) -> io::Result<()> {
+        enable_raw_mode()?;
+        self.screen = true;
+        let mut tty = io::BufWriter::new(&self.tty);
+        execute!(tty, EnterAlternateScreen, EnableBracketedPaste)
+    }
+
+    fn close(mut self) {
+        if self.screen {
+            disable_raw_mode().ok();
+            let mut tty = io::BufWriter::new(&self.tty);
+            execute!(tty, LeaveAlternateScreen, DisableBracketedPaste, Show).ok();
+        }
+        self.streams.restore();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Focus {
+    Conversation,
+    Activity,
+}
+
+enum Speaker {
+    User,
+    Model,
+}
+
+struct App {
+    conversation: Vec<(Speaker, String)>,
+    activity: Activity,
+    input: String,
+    /// Cursor position in the input, in characters
+    cursor: usize,
+    history: DefaultHistory,
+    /// Position while browsing the history with Up and Down
+    browsing: Option<usize>,
+    /// The prompt status while idle; None while the chat handles an input
+    ready: Option<String>,
+    focus: Focus,
+    /// Lines scrolled back from the end, per pane; 0 follows new output
+    scrolled: [usize; 2],
+    /// Rows of each pane at the last draw, for paging
+    heights: [usize; 2],
+    /// Set by Ctrl-C at an idle prompt; a second one quits
+    interrupted: bool,
+}
+
+impl App {
+    fn new() -> App {
+        App {
+            conversation: Vec::new(),
+            activity: Activity::default(),
+            input: String::new(),
+            cursor: 0,
+            history: history::load_entries(),
+            browsing: None,
+            ready: None,
+            focus: Focus::Conversation,
+            scrolled: [0, 0],
+            heights: [0, 0],
+            interrupted: false,
+        }
+    }
+
+    fn run(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<File>>,
+        received: &mpsc::Receiver<Update>,
+        inputs: &Sender<String>,
+        answers: &mut File,
+    ) -> Result<(), String> {
+        loop {
+            while let Ok(update) = received.try_recv() {
+                match update {
+                    Update::Output(text) => self.activity.push(&text),
+                    Update::Answer(text) => self.conversation.push((Speaker::Model, text)),
+                    Update::Ready(None) => return Ok(()),
+                    Update::Ready(status) => self.ready = status,
+                }
+            }
+            terminal.draw(|frame| self.draw(frame)).map_err(|e| e.to_string())?;
+            if !event::poll(TICK).map_err(|e| e.to_string())? {
+                continue;
+            }
+            match event::read().map_err(|e| e.to_string())? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => {
+                    if let Some(line) = self.key(key) {
+                        self.submit(line, inputs, answers);
+                    }
+                }
+                Event::Paste(text) => self.insert(&text.replace("\r\n", "\n").replace('\r', "\n")),
+                _ => {}
+            }
+        }
+    }
+
+    /// Handles a key; returns the input when it was submitted.
+    fn key(&mut self, key: KeyEvent) -> Option<String> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        if !(ctrl && key.code == KeyCode::Char('c')) {
+            self.interrupted = false;
+        }
+        match key.code {
+            KeyCode::Char('c') if ctrl => {
+                if self.ready.is_none() {
+                    redirect::interrupt();
+                } else if !self.input.is_empty() {
+                    self.clear_input();
+                } else if self.interrupted {
+                    return Some("exit".to_string());
+                } else {
+                    self.interrupted = true;
+                    self.activity.push(&format!("{}\n", i18n::t("chat.interrupt_again")));
+                }
+            }
+            KeyCode::Char('d') if ctrl && self.input.is_empty() && self.ready.is_some() => {
+                return Some("exit".to_string());
+            }
+            KeyCode::Enter if alt => self.insert("\n"),
+            KeyCode::Char('j') if ctrl => self.insert("\n"),
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut self.input);
+                self.cursor = 0;
+                self.browsing = None;
+                return Some(line);
+            }
+            KeyCode::Char('a') if ctrl => self.cursor = self.line_start(),
+            KeyCode::Char('e') if ctrl => self.cursor = self.line_end(),
+            KeyCode::Char('u') if ctrl => {
+                let start = self.line_start();
+                self.remove(start, self.cursor);
+            }
+            KeyCode::Char('k') if ctrl => {
+                let end = self.line_end();
+                self.remove(self.cursor, end);
+            }
+            KeyCode::Char('w') if ctrl => {
+                let chars: Vec<char> = self.input.chars().collect();
+                let mut start = self.cursor;
+                while start > 0 && chars[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                while start > 0 && !chars[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                self.remove(start, self.cursor);
+            }
+            KeyCode::Char(c) if !ctrl => self.insert(&c.to_string()),
+            KeyCode::Backspace if self.cursor > 0 => self.remove(self.cursor - 1, self.cursor),
+            KeyCode::Delete if self.cursor < self.input.chars().count() => self.remove(self.cursor, self.cursor + 1),
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.input.chars().count()),
+            KeyCode::Home => self.cursor = self.line_start(),
+            KeyCode::End => self.cursor = self.line_end(),
+            KeyCode::Up => self.browse(true),
+            KeyCode::Down => self.browse(false),
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::Conversation => Focus::Activity,
+                    Focus::Activity => Focus::Conversation,
+                }
+            }
+            KeyCode::PageUp => self.scroll(self.page() as isize),
+            KeyCode::PageDown => self.scroll(-(self.page() as isize)),
+            _ => {}
+        }
+        None
+    }
+
+    /// Sends an input to the chat, or, while it is busy, to whatever it asked (a tool
+    /// confirmation or the interactive shell).
+    fn submit(&mut self, line: String, inputs: &Sender<String>, answers: &mut File) {
+        if self.ready.is_none() {
+            self.activity.push(&format!("{}\n", line));
+            answers.write_all(format!("{}\n", line.replace('\n', " ")).as_bytes()).ok();
+            return;
+        }
+        let input = line.trim();
+        if input.is_empty() {
+            return;
+        }
+        history::record(&mut self.history, &line);
+        let command = input.starts_with('/') || input.starts_with('!') && !input.starts_with("!{");
+        match input.to_lowercase().as_str() {
+            "clear" => {
+                self.conversation.clear();
+                self.activity.push(&format!("> {}\n", input));
+            }
+            "exit" => {}
+            _ if command => self.activity.push(&format!("> {}\n", input)),
+            _ => self.conversation.push((Speaker::User, input.to_string())),
+        }
+        self.scrolled = [0, 0];
+        self.ready = None;
+        inputs.send(line).ok();
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let input_lines = self.input.split('\n').count().clamp(1, MAX_INPUT_LINES);
+        let [panes, input] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(input_lines as u16 + 2)])
+            .areas(frame.area());
+        let direction = if frame.area().width >= SIDE_BY_SIDE_WIDTH {
+            Direction::Horizontal
+        } else {
+            Direction::Vertical
+        };
+        let [conversation, activity] = Layout::default()
+            .direction(direction)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .areas(panes);
+
+        let text = self.conversation_text();
+        self.draw_pane(frame, conversation, Focus::Conversation, &i18n::t("tui.conversation"), text);
+        let text = self.activity.text();
+        self.draw_pane(frame, activity, Focus::Activity, &i18n::t("tui.activity"), text);
+        self.draw_input(frame, input);
+    }
+
+    fn draw_pane(&mut self, frame: &mut Frame, area: Rect, pane: Focus, title: &str, text: Text<'static>) {
+        let border = if self.focus == pane { Color::Cyan } else { Color::DarkGray };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(border))
+            .title(format!(" {} ", title));
+        let inner = block.inner(area);
+        let paragraph = Paragraph::new(text).wrap(Wrap { trim: false });
+        let index = pane as usize;
+        let height = inner.height as usize;
+        let last_top = paragraph.line_count(inner.width).saturating_sub(height);
+        self.heights[index] = height;
+        self.scrolled[index] = self.scrolled[index].min(last_top);
+        let top = (last_top - self.scrolled[index]).min(u16::MAX as usize) as u16;
+        frame.render_widget(paragraph.scroll((top, 0)).block(block), area);
+    }
+
+    fn draw_input(&self, frame: &mut Frame, area: Rect) {
+        let title = match &self.ready {
+            Some(status) => match persona::current() {
+                Some(preset) => format!(" ({}) [{}] ", preset.name, status),
+                None => format!(" [{}] ", status),
+            },
+            None => {
+                let progress = PROGRESS.lock().unwrap().clone();
+                let progress = if progress.is_empty() { i18n::t("tui.working") } else { progress };
+                format!(" {} — {} ", progress, i18n::t("tui.answer_hint"))
+            }
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(if self.ready.is_some() { Color::Cyan } else { Color::Yellow }))
+            .title(title)
+            .title_bottom(Line::from(format!(" {} ", i18n::t("tui.keys"))).style(Style::default().fg(Color::DarkGray)));
+        let inner = block.inner(area);
+
+        // Scroll so the cursor stays in view
+        let before: String = self.input.chars().take(self.cursor).collect();
+        let row = before.matches('\n').count();
+        let column = Line::from(before.rsplit('\n').next().unwrap_or("").to_string()).width();
+        let top = (row + 1).saturating_sub(inner.height as usize);
+        let left = (column + 1).saturating_sub(inner.width as usize);
+        let paragraph = Paragraph::new(self.input.clone()).scroll((top as u16, left as u16)).block(block);
+        frame.render_widget(paragraph, area);
+        frame.set_cursor_position(Position::new(
+            inner.x + (column - left) as u16,
+            inner.y + (row - top) as u16,
+        ));
+    }
+
+    fn conversation_text(&self) -> Text<'static> {
+        let mut lines = Vec::new();
+        for (speaker, text) in &self.conversation {
+            let (name, color) = match speaker {
+                Speaker::User => (i18n::t("tui.you"), Color::Cyan),
+                Speaker::Model => (i18n::t("tui.model"), Color::Yellow),
+            };
+            lines.push(Line::from(Span::styled(name, Style::default().fg(color).add_modifier(Modifier::BOLD))));
+            lines.extend(text.lines().map(|line| Line::from(line.to_string())));
+            lines.push(Line::default());
+        }
+        Text::from(lines)
+    }
+
+    fn page(&self) -> usize {
+        (self.heights[self.focus as usize] / 2).max(1)
+    }
+
+    fn scroll(&mut self, lines: isize) {
+        let scrolled = &mut self.scrolled[self.focus as usize];
+        *scrolled = scrolled.saturating_add_signed(lines);
+    }
+
+    /// Steps through earlier inputs; past the newest one the input box is empty again.
+    fn browse(&mut self, older: bool) {
+        let count = self.history.len();
+        let position = match (self.browsing, older) {
+            (None, true) if count > 0 => count - 1,
+            (None, _) => return,
+            (Some(position), true) => position.saturating_sub(1),
+            (Some(position), false) if position + 1 < count => position + 1,
+            (Some(_), false) => {
+                self.browsing = None;
+                self.clear_input();
+                return;
+            }
+        };
+        if let Some(entry) = self.history.iter().nth(position) {
+            self.input = entry.clone();
+            self.cursor = self.input.chars().count();
+            self.browsing = Some(position);
+        }
+    }
+
+    fn insert(&mut self, text: &str) {
+        let at = self.byte_index(self.cursor);
+        self.input.insert_str(at, text);
+        self.cursor += text.chars().count();
+    }
+
+    fn remove(&mut self, start: usize, end: usize) {
+        let range = self.byte_index(start)..self.byte_index(end);
+        self.input.replace_range(range, "");
+        self.cursor = start;
+    }
+
+    fn clear_input(&mut self) {
+        self.input.clear();
+        self.cursor = 0;
+    }
+
+    fn byte_index(&self, chars: usize) -> usize {
+        self.input.char_indices().nth(chars).map(|(index, _)| index).unwrap_or(self.input.len())
+    }
+
+    fn line_start(&self) -> usize {
+        let before: Vec<char> = self.input.chars().take(self.cursor).collect();
+        before.iter().rposition(|c| *c == '\n').map(|index| index + 1).unwrap_or(0)
+    }
+
+    fn line_end(&self) -> usize {
+        let after = self.input.chars().skip(self.cursor).position(|c| c == '\n');
+        after.map(|count| self.cursor + count).unwrap_or(self.input.chars().count())
+    }
+}
+
+/// The activity pane: the CLI's output as it would have appeared in the terminal, with its
+/// colors. Colors may run over several lines, so each line starts with the style the
+/// previous one ended with.
+#[derive(Default)]
+struct Activity {
+    lines: Vec<Line<'static>>,
+    /// The line still being written, with the style it started with
+    current: String,
+    current_style: Style,
+}
+
+impl Activity {
+    fn push(&mut self, text: &str) {
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\n' => {
+                    let (line, style) = ansi_line(&self.current, self.current_style);
+                    self.lines.push(line);
+                    self.current.clear();
+                    self.current_style = style;
+                }
+                // A carriage return on its own redraws the line, as progress output does
+                '\r' if chars.peek() != Some(&'\n') => self.current.clear(),
+                '\r' => {}
+                c => self.current.push(c),
+            }
+        }
+        if self.lines.len() > MAX_ACTIVITY_LINES {
+            self.lines.drain(..self.lines.len() - MAX_ACTIVITY_LINES);
+        }
+    }
+
+    fn text(&self) -> Text<'static> {
+        let mut lines = self.lines.clone();
+        if !self.current.is_empty() {
+            lines.push(ansi_line(&self.current, self.current_style).0);
+        }
+        Text::from(lines)
+    }
+}
+
+/// Turns a line with ANSI color codes into styled spans, starting from `style`. Returns the
+/// line and the style in effect at its end. Other escape sequences (e.g. the window title)
+/// are left out.
+fn ansi_line(text: &str, mut style: Style) -> (Line<'static>, Style) {
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' {
+            plain.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                let mut sequence = String::new();
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() || c == '~' {
+                        if c == 'm' {
+                            if !plain.is_empty() {
+                                spans.push(Span::styled(std::mem::take(&mut plain), style));
+                            }
+                            style = sgr(&sequence, style);
+                        }
+                        break;
+                    }
+                    sequence.push(c);
+                }
+            }
+            // Strings such as the window title (OSC) or tmux's window name end with BEL or ESC \
+            Some(']' | 'k' | 'P' | '_' | '^' | 'X') => {
+                while let Some(c) = chars.next() {
+                    if c == '\u{7}' || (c == '\u{1b}' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if !plain.is_empty() {
+        spans.push(Span::styled(plain, style));
+    }
+    (Line::from(spans), style)
+}
+
+/// Applies a Select Graphic Rendition sequence such as "1;33" to a style.
+fn sgr(sequence: &str, style: Style) -> Style {
+    let codes: Vec<u16> = sequence.split(';').map(|code| code.parse().unwrap_or(0)).collect();
+    let mut style = style;
+    let mut index = 0;
+    while index < codes.len() {
+        let code = codes[index];
+        style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            7 => style.add_modifier(Modifier::REVERSED),
+            9 => style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            27 => style.remove_modifier(Modifier::REVERSED),
+            29 => style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg(Color::Indexed((code - 30) as u8)),
+            90..=97 => style.fg(Color::Indexed((code - 90 + 8) as u8)),
+            39 => style.fg(Color::Reset),
+            40..=47 => style.bg(Color::Indexed((code - 40) as u8)),
+            100..=107 => style.bg(Color::Indexed((code - 100 + 8) as u8)),
+            49 => style.bg(Color::Reset),
+            38 | 48 => {
+                let color = match codes.get(index + 1) {
+                    Some(5) => codes.get(index + 2).map(|n| (Color::Indexed(*n as u8), 2)),
+                    Some(2) if codes.len() > index + 4 => Some((
+                        Color::Rgb(codes[index + 2] as u8, codes[index + 3] as u8, codes[index + 4] as u8),
+                        4,
+                    )),
+                    _ => None,
+                };
+                match color {
+                    Some((color, used)) => {
+                        index += used;
+                        if code == 38 {
+                            style.fg(color)
+                        } else {
+                            style.bg(color)
+                        }
+                    }
+                    None => style,
+                }
+            }
+            _ => style,
+        };
+        index += 1;
+    }
+    style
+}
+
+/// Replacing stdin, stdout and stderr with pipes, so everything the CLI and its commands
+/// print ends up in the activity pane and answers typed in the input box reach the prompts
+/// that read stdin.
+#[cfg(unix)]
+mod redirect {
+    use std::fs::File;
+    use std::os::fd::{FromRawFd, RawFd};
+
+    pub struct Streams {
+        /// What the CLI prints
+        pub output: File,
+        /// Where input for the CLI's own prompts is written
+        pub input: File,
+        /// The original stdin, stdout and stderr; None once restored
+        saved: Option<[RawFd; 3]>,
+    }
+
+    impl Streams {
+        pub fn start() -> Result<Streams, String> {
+            let error = |e: std::io::Error| format!("Cannot redirect the output: {}", e);
+            let (output_read, output_write) = pipe().map_err(error)?;
+            let (input_read, input_write) = pipe().map_err(error)?;
+            // SAFETY: the descriptors are open and owned here; the copies keep the
+            // originals alive until restore
+            unsafe {
+                let saved = [libc::dup(0), libc::dup(1), libc::dup(2)];
+                std::io::Write::flush(&mut std::io::stdout()).ok();
+                libc::dup2(input_read, 0);
+                libc::dup2(output_write, 1);
+                libc::dup2(output_write, 2);
+                libc::close(input_read);
+                libc::close(output_write);
+                Ok(Streams {
+                    output: File::from_raw_fd(output_read),
+                    input: File::from_raw_fd(input_write),
+                    saved: Some(saved),
+                })
+            }
+        }
+
+        pub fn restore(&mut self) {
+            std::io::Write::flush(&mut std::io::stdout()).ok();
+            if let Some(saved) = self.saved.take() {
+                for (fd, original) in saved.into_iter().enumerate() {
+                    // SAFETY: the originals were duplicated in start and are closed only here
+                    unsafe {
+                        libc::dup2(original, fd as RawFd);
+                        libc::close(original);
+                    }
+                }
+            }
+        }
+    }
+
+    fn pipe() -> std::io::Result<(RawFd, RawFd)> {
+        let mut fds = [0 as RawFd; 2];
+        // SAFETY: fds has room for the two descriptors pipe returns
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok((fds[0], fds[1]))
+    }
+
+    /// Ctrl-C as the terminal would have sent it, so the Ctrl-C handler cancels the running
+    /// request (and quits on a second press) as in the plain chat.
+    pub fn interrupt() {
+        // SAFETY: signals this process only; the Ctrl-C handler is installed at startup
+        unsafe {
+            libc::kill(libc::getpid(), libc::SIGINT);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod redirect {
+    use std::fs::File;
+
+    pub struct Streams {
+        pub output: File,
+        pub input: File,
+    }
+
+    impl Streams {
+        pub fn start() -> Result<Streams, String> {
+            Err("The TUI needs a Unix-like system; use the plain chat instead.".to_string())
+        }
+
+        pub fn restore(&mut self) {}
+    }
+
+    pub fn interrupt() {
+        crate::runtime::interrupt();
+    }
+}
@@ -0,0 +1,360 @@
+use chrono::{DateTime, Local};
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+use std::env;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use terminal_size::{terminal_size, Width};
+
+use crate::locale;
+
+// Maximum number of result lines shown inside a tool block
+const PREVIEW_LINES: usize = 6;
+// Maximum number of characters per line shown inside a tool block
+const PREVIEW_WIDTH: usize = 100;
+// Width used when the terminal size can't be detected (e.g. output is piped)
+const DEFAULT_WIDTH: usize = 80;
+
+static SHOW_TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+
+// Matches the prefix of list items and block quotes: indentation plus "- ", "* ", "1. ", "> "
+static LIST_PREFIX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\s*)([-*+]|\d+[.)]|>)\s+").unwrap());
+
+/// A visually distinct block in the scrollback for a single tool invocation.
+pub struct ToolBlock {
+    started: Instant,
+}
+
+impl ToolBlock {
+    /// Prints the block header with the tool name and its most relevant arguments.
+    pub fn start(name: &str, args: &Value) -> Self {
+        let summary = summarize_args(name, args);
+        if summary.is_empty() {
+            status(&format!("{} {}", "┌─".color(Color::Blue), name.color(Color::Blue).bold()));
+        } else {
+            status(&format!(
+                "{} {} {} {}",
+                "┌─".color(Color::Blue),
+                name.color(Color::Blue).bold(),
+                "·".color(Color::Blue),
+                truncate_line(&summary, PREVIEW_WIDTH)
+            ));
+        }
+        ToolBlock {
+            started: Instant::now(),
+        }
+    }
+
+    /// Prints the indented, truncated result and the status footer. The preview is skipped
+    /// for tools that already streamed their output to the terminal. Returns how long the tool ran.
+    pub fn finish(self, result: &Result<String, String>, show_preview: bool) -> Duration {
+        let (text, ok) = match result {
+            Ok(text) => (text.as_str(), !looks_like_failure(text)),
+            Err(e) => (e.as_str(), false),
+        };
+
+        if show_preview || !ok {
+            let lines: Vec<&str> = text.lines().collect();
+            for line in lines.iter().take(PREVIEW_LINES) {
+                status(&format!(
+                    "{} {}",
+                    "│".color(Color::Blue),
+                    truncate_line(line, PREVIEW_WIDTH).dimmed()
+                ));
+            }
+            if lines.len() > PREVIEW_LINES {
+                status(&format!(
+                    "{} {}",
+                    "│".color(Color::Blue),
+                    format!("… {} more lines", lines.len() - PREVIEW_LINES).dimmed()
+                ));
+            }
+        }
+
+        let duration = self.started.elapsed();
+        let elapsed = format!("{:.1}s", duration.as_secs_f32());
+        if ok {
+            status(&format!(
+                "{} {} {}",
+                "└─".color(Color::Blue),
+                "✓".color(Color::Green).bold(),
+                elapsed.dimmed()
+            ));
+        } else {
+            status(&format!(
+                "{} {} {}",
+                "└─".color(Color::Blue),
+                "✗".color(Color::Red).bold(),
+                elapsed.dimmed()
+            ));
+        }
+        duration
+    }
+}
+
+/// Picks the arguments worth showing in a tool block header.
+pub fn summarize_args(name: &str, args: &Value) -> String {
+    let get = |key: &str| args.get(key).and_then(|v| v.as_str()).unwrap_or("");
+    let summary = match name {
+        "execute_command" => get("command").to_string(),
+        "search_online" => get("query").to_string(),
+        "scrape_url" => get("url").to_string(),
+        "http_request" => format!("{} {}", get("method"), get("url")).trim().to_string(),
+        "send_email" => get("subject").to_string(),
+        "alpha_vantage_query" => format!("{} {}", get("function"), get("symbol")),
+        "fx_rate" => format!("{}/{}", get("from_currency"), get("to_currency")),
+        "crypto_price" | "company_overview" | "company_earnings" => get("symbol").to_string(),
+        "file_editor" => match get("subcommand") {
+            "move" | "copy" => format!("{} {} -> {}", get("subcommand"), get("filename"), get("data")),
+            subcommand => format!("{} {}", subcommand, get("filename")),
+        },
+        "apply_changeset" => args["changes"]
+            .as_array()
+            .map(|changes| {
+                changes
+                    .iter()
+                    .filter_map(|change| change["filename"].as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .unwrap_or_default(),
+        "run_in_background" => get("command").to_string(),
+        "list_jobs" | "kill_job" => args.get("id").map(|id| format!("job {}", id)).unwrap_or_default(),
+        "list_files" => format!("{} {}", get("path"), get("pattern")).trim().to_string(),
+        "search_codebase" => format!("{} {} {}", get("query"), get("path"), get("glob")).trim().to_string(),
+        "github" => match args.get("number") {
+            Some(n) => format!("{} #{}", get("operation"), n.as_str().map(String::from).unwrap_or_else(|| n.to_string())),
+            None => get("operation").to_string(),
+        },
+        "tracker" => format!("{} {}", get("operation"), get("key")),
+        _ => args
+            .as_object()
+            .and_then(|obj| obj.values().find_map(|v| v.as_str()))
+            .unwrap_or("")
+            .to_string(),
+    };
+    summary.trim().replace('\n', " ")
+}
+
+/// Tools report most failures as plain text, so sniff the usual prefixes.
+pub fn looks_like_failure(text: &str) -> bool {
+    let text = text.trim_start();
+    text.starts_with("Error")
+        || text.starts_with("Failed")
+        || text.starts_with("Skipped")
+        || text.starts_with("Search failed")
+}
+
+fn truncate_line(line: &str, max: usize) -> String {
+    if line.chars().count() > max {
+        let truncated: String = line.chars().take(max).collect();
+        format!("{}…", truncated)
+    } else {
+        line.to_string()
+    }
+}
+
+/// Returns the current terminal width, falling back to $COLUMNS and then 80 columns.
+pub fn terminal_width() -> usize {
+    if let Some((Width(w), _)) = terminal_size() {
+        return w as usize;
+    }
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Re-wraps Markdown-ish model output to the given width. Code blocks and tables are left
+/// untouched; list items and quotes get a hanging indent so continuation lines stay aligned.
+pub fn wrap_text(text: &str, width: usize) -> String {
+    let mut output = Vec::new();
+    let mut in_code_block = false;
+
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            output.push(line.to_string());
+            continue;
+        }
+        if in_code_block || line.trim_start().starts_with('|') || line.chars().count() <= width {
+            output.push(line.to_string());
+            continue;
+        }
+
+        let (first_prefix, hanging) = match LIST_PREFIX.find(line) {
+            Some(m) => {
+                let prefix = m.as_str();
+                // Quotes repeat their marker; list items continue under the item text
+                let hanging = if prefix.trim() == ">" {
+                    prefix.to_string()
+                } else {
+                    " ".repeat(prefix.chars().count())
+                };
+                (prefix.to_string(), hanging)
+            }
+            None => {
+                let indent: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+                (indent.clone(), indent)
+            }
+        };
+
+        let body = &line[first_prefix.len()..];
+        let mut current = first_prefix;
+        let mut current_len = current.chars().count();
+        let mut line_has_words = false;
+        for word in body.split_whitespace() {
+            let word_len = word.chars().count();
+            if line_has_words && current_len + 1 + word_len > width {
+                output.push(current);
+                current = hanging.clone();
+                current_len = current.chars().count();
+                line_has_words = false;
+            }
+            if line_has_words {
+                current.push(' ');
+                current_len += 1;
+            }
+            current.push_str(word);
+            current_len += word_len;
+            line_has_words = true;
+        }
+        output.push(current);
+    }
+
+    output.join("\n")
+}
+
+/// Prints reply text that arrives in pieces, wrapping words at the terminal width like
+/// `wrap_text` (code blocks and tables are left alone).
+pub struct StreamWriter {
+    width: usize,
+    column: usize,
+    line: String,
+    word: String,
+    in_code_block: bool,
+}
+
+impl StreamWriter {
+    pub fn new() -> Self {
+        StreamWriter {
+            width: terminal_width(),
+            column: 0,
+            line: String::new(),
+            word: String::new(),
+            in_code_block: false,
+        }
+    }
+
+    pub fn write(&mut self, text: &str) {
+        for c in text.chars() {
+            match c {
+                '\n' => {
+                    self.flush_word();
+                    println!();
+                    if self.line.trim_start().starts_with("```") {
+                        self.in_code_block = !self.in_code_block;
+                    }
+                    self.line.clear();
+                    self.column = 0;
+                }
+                c if c.is_whitespace() => {
+                    self.flush_word();
+                    // Spaces at a wrap point are dropped
+                    if self.column > 0 || self.line.is_empty() {
+                        print!("{}", c);
+                        self.column += 1;
+                    }
+                    self.line.push(c);
+                }
+                c => self.word.push(c),
+            }
+        }
+        io::stdout().flush().ok();
+    }
+
+    /// Prints what is left and ends the line.
+    pub fn finish(&mut self) {
+        self.flush_word();
+        if !self.line.is_empty() {
+            println!();
+        }
+        self.line.clear();
+        self.column = 0;
+    }
+
+    fn flush_word(&mut self) {
+        if self.word.is_empty() {
+            return;
+        }
+        let length = self.word.chars().count();
+        let wrappable = !self.in_code_block && !self.line.trim_start().starts_with('|');
+        if wrappable && self.column > 0 && self.column + length > self.width {
+            println!();
+            self.column = 0;
+        }
+        print!("{}", self.word.color(Color::Yellow));
+        self.column += length;
+        self.line.push_str(&self.word);
+        self.word.clear();
+    }
+}
+
+pub fn set_timestamps_enabled(enabled: bool) {
+    SHOW_TIMESTAMPS.store(enabled, Ordering::SeqCst);
+}
+
+pub fn timestamps_enabled() -> bool {
+    SHOW_TIMESTAMPS.load(Ordering::SeqCst)
+}
+
+/// Formats the prefix shown before a turn, e.g. "#3 14:02:11 gemini". The date is included
+/// when the turn didn't happen today.
+pub fn turn_label(turn: usize, timestamp: &DateTime<Local>, speaker: &str) -> String {
+    if timestamp.date_naive() == Local::now().date_naive() {
+        format!("#{} {} {}", turn, locale::format_time(timestamp), speaker)
+    } else {
+        format!(
+            "#{} {} {} {}",
+            turn,
+            locale::format_date(timestamp),
+            locale::format_time(timestamp),
+            speaker
+        )
+    }
+}
+
+/// Prepares the console for colored output. On Windows this turns on ANSI escape handling
+/// (virtual terminal processing), and turns colors off on consoles too old to support it.
+pub fn init_console() {
+    #[cfg(target_os = "windows")]
+    {
+        if colored::control::set_virtual_terminal(true).is_err() {
+            colored::control::set_override(false);
+        }
+    }
+}
+
+/// Keeps stdout free for machine-readable output (e.g. the MCP server); status messages and
+/// command output are sent to stderr instead.
+pub fn reserve_stdout() {
+    STDOUT_RESERVED.store(true, Ordering::SeqCst);
+}
+
+pub fn stdout_reserved() -> bool {
+    STDOUT_RESERVED.load(Ordering::SeqCst)
+}
+
+/// Prints a status line for the user, on stderr when stdout is reserved.
+pub fn status(line: &str) {
+    if stdout_reserved() {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
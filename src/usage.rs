@@ -0,0 +1,272 @@
+use chrono::Local;
+use colored::{Color, Colorize};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::locale;
+
+/// Token counts and estimated cost, for a session or a day.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Usage {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost: f64,
+}
+
+impl Usage {
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    fn add(&mut self, other: &Usage) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost += other.cost;
+    }
+}
+
+static SESSION: Lazy<Mutex<Usage>> = Lazy::new(|| Mutex::new(Usage::default()));
+// This session's usage by model, for /usage
+static BY_MODEL: Lazy<Mutex<BTreeMap<String, Usage>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+// Set by /budget override or --ignore-budget
+static OVERRIDDEN: AtomicBool = AtomicBool::new(false);
+
+/// USD per million (input, output) tokens, from the public price list.
+fn prices(model: &str) -> (f64, f64) {
+    match model {
+        m if m.contains("flash-lite") => (0.10, 0.40),
+        m if m.contains("flash") => (0.30, 2.50),
+        m if m.contains("pro") => (1.25, 10.00),
+        _ => (0.30, 2.50),
+    }
+}
+
+/// Records the usageMetadata of a generateContent response. Returns what the request used.
+pub fn record(model: &str, response: &Value) -> Usage {
+    let metadata = &response["usageMetadata"];
+    let input_tokens = metadata["promptTokenCount"].as_u64().unwrap_or(0);
+    // Thinking tokens are billed as output
+    let output_tokens = metadata["candidatesTokenCount"].as_u64().unwrap_or(0)
+        + metadata["thoughtsTokenCount"].as_u64().unwrap_or(0);
+    let (input_price, output_price) = prices(model);
+    let usage = Usage {
+        requests: 1,
+        input_tokens,
+        output_tokens,
+        cost: (input_tokens as f64 * input_price + output_tokens as f64 * output_price) / 1_000_000.0,
+    };
+
+    SESSION.lock().unwrap().add(&usage);
+    BY_MODEL.lock().unwrap().entry(model.to_string()).or_default().add(&usage);
+    log_csv(model, &usage);
+    let mut days = load_days();
+    days.entry(today()).or_default().add(&usage);
+    save_days(&days);
+    usage
+}
+
+pub fn session() -> Usage {
+    SESSION.lock().unwrap().clone()
+}
+
+pub fn today_usage() -> Usage {
+    load_days().remove(&today()).unwrap_or_default()
+}
+
+/// Lifts the budget limits for the rest of this session.
+pub fn override_budget() {
+    OVERRIDDEN.store(true, Ordering::SeqCst);
+}
+
+/// Fails with an explanation when a configured budget is used up.
+pub fn check_budget() -> Result<(), String> {
+    if OVERRIDDEN.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let session = session();
+    let today = today_usage();
+    let checks = [
+        ("GEMINI_SESSION_BUDGET_USD", "session", session.cost, true),
+        ("GEMINI_DAILY_BUDGET_USD", "daily", today.cost, true),
+        ("GEMINI_SESSION_TOKEN_BUDGET", "session", session.total_tokens() as f64, false),
+        ("GEMINI_DAILY_TOKEN_BUDGET", "daily", today.total_tokens() as f64, false),
+    ];
+    for (key, scope, used, is_money) in checks {
+        let limit = match limit(key) {
+            Some(limit) => limit,
+            None => continue,
+        };
+        if used >= limit {
+            let (used, limit) = if is_money {
+                (format!("${:.4}", used), format!("${:.2}", limit))
+            } else {
+                (format!("{} tokens", used as u64), format!("{} tokens", limit as u64))
+            };
+            return Err(format!(
+                "The {} budget of {} is exhausted ({} used, set by {}). \
+                 Use /budget override (or --ignore-budget) to continue anyway.",
+                scope, limit, used, key
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Summary shown by /budget.
+pub fn budget_status() -> String {
+    let session = session();
+    let today = today_usage();
+    let mut lines = vec![
+        format!(
+            "Session: {} requests, {} tokens in / {} out, ${:.4}",
+            session.requests, session.input_tokens, session.output_tokens, session.cost
+        ),
+        format!(
+            "Today:   {} requests, {} tokens in / {} out, ${:.4}",
+            today.requests, today.input_tokens, today.output_tokens, today.cost
+        ),
+    ];
+    let limits: Vec<String> = [
+        ("GEMINI_SESSION_BUDGET_USD", "session $"),
+        ("GEMINI_DAILY_BUDGET_USD", "daily $"),
+        ("GEMINI_SESSION_TOKEN_BUDGET", "session tokens "),
+        ("GEMINI_DAILY_TOKEN_BUDGET", "daily tokens "),
+    ]
+    .iter()
+    .filter_map(|(key, label)| limit(key).map(|l| format!("{}{}", label, l)))
+    .collect();
+    lines.push(if limits.is_empty() {
+        "No budget configured.".to_string()
+    } else {
+        format!("Budget: {}", limits.join(", "))
+    });
+    if OVERRIDDEN.load(Ordering::SeqCst) {
+        lines.push("Budget limits are overridden for this session.".color(Color::Yellow).to_string());
+    }
+    lines.join("\n")
+}
+
+/// Report printed by /usage: this session's usage per model, and today's and this month's totals.
+pub fn report() -> String {
+    let by_model = BY_MODEL.lock().unwrap().clone();
+    let line = |label: &str, usage: &Usage| {
+        format!(
+            "  {:<24} {:>5} requests  {:>10} in  {:>9} out  ${:.4}",
+            label,
+            usage.requests,
+            locale::format_number(usage.input_tokens as usize),
+            locale::format_number(usage.output_tokens as usize),
+            usage.cost
+        )
+    };
+
+    let mut lines = vec!["Token usage and estimated cost".color(Color::Cyan).bold().to_string()];
+    if by_model.is_empty() {
+        lines.push("  No model requests in this session yet.".to_string());
+    }
+    for (model, usage) in &by_model {
+        lines.push(line(model, usage));
+    }
+    if by_model.len() > 1 {
+        lines.push(line("session", &session()));
+    }
+
+    let days = load_days();
+    let month_prefix = Local::now().format("%Y-%m").to_string();
+    let mut month = Usage::default();
+    for (_, usage) in days.iter().filter(|(day, _)| day.starts_with(&month_prefix)) {
+        month.add(usage);
+    }
+    lines.push(line("today", &days.get(&today()).cloned().unwrap_or_default()));
+    lines.push(line(&format!("month ({})", month_prefix), &month));
+    if let Some(path) = csv_path() {
+        lines.push(format!("  Each request is logged to {}", path.display()));
+    }
+    lines.join("\n")
+}
+
+/// Session cost for the prompt, when GEMINI_PROMPT_COST is on.
+pub fn prompt_cost() -> Option<String> {
+    let enabled = env::var("GEMINI_PROMPT_COST").is_ok_and(|v| matches!(v.trim(), "1" | "true" | "yes" | "on"));
+    enabled.then(|| format!("${:.4}", session().cost))
+}
+
+/// Appends a request to the CSV file GEMINI_USAGE_CSV names, writing the header first when
+/// the file is new.
+fn log_csv(model: &str, usage: &Usage) {
+    let Some(path) = csv_path() else { return };
+    let is_new = fs::metadata(&path).map(|m| m.len() == 0).unwrap_or(true);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&path) else { return };
+    if is_new {
+        writeln!(file, "timestamp,model,requests,input_tokens,output_tokens,cost_usd").ok();
+    }
+    writeln!(
+        file,
+        "{},{},{},{},{},{:.6}",
+        Local::now().format("%Y-%m-%dT%H:%M:%S%:z"),
+        model.replace(',', " "),
+        usage.requests,
+        usage.input_tokens,
+        usage.output_tokens,
+        usage.cost
+    )
+    .ok();
+}
+
+fn csv_path() -> Option<PathBuf> {
+    let path = env::var("GEMINI_USAGE_CSV").ok()?;
+    let path = path.trim();
+    if path.is_empty() {
+        return None;
+    }
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().map(|home| home.join(rest)),
+        None => Some(PathBuf::from(path)),
+    }
+}
+
+fn limit(key: &str) -> Option<f64> {
+    env::var(key).ok().and_then(|v| v.trim().parse().ok()).filter(|l: &f64| *l > 0.0)
+}
+
+fn today() -> String {
+    Local::now().format("%Y-%m-%d").to_string()
+}
+
+/// Daily totals are kept across sessions, so the daily budget covers every run.
+fn usage_path() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("gemini-cli")
+        .join("usage.json")
+}
+
+fn load_days() -> BTreeMap<String, Usage> {
+    fs::read_to_string(usage_path())
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save_days(days: &BTreeMap<String, Usage>) {
+    let path = usage_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    if let Ok(json) = serde_json::to_string_pretty(days) {
+        fs::write(path, json).ok();
+    }
+}
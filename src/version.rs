@@ -0,0 +1,83 @@
+use colored::{Color, Colorize};
+use std::env;
+use std::path::Path;
+
+use crate::command;
+use crate::config;
+use crate::i18n;
+use crate::keys;
+use crate::models::{self, Task};
+use crate::persona;
+use crate::plugins;
+use crate::external_tools;
+use crate::project;
+use crate::providers;
+use crate::search_providers;
+use crate::sessions;
+use crate::tools;
+use crate::COMPILE_TIME;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const GIT_COMMIT: &str = env!("GIT_COMMIT");
+
+/// One-line version, shown in the chat banner.
+pub fn short() -> String {
+    format!("{} ({}, built {})", VERSION, GIT_COMMIT, COMPILE_TIME)
+}
+
+/// Everything `--version` and `/version` report: build, models, files in use, and which
+/// optional integrations are configured.
+pub fn report() -> String {
+    let mut lines = vec![
+        format!("gemini-cli-rs {}", short()).color(Color::Cyan).bold().to_string(),
+        String::new(),
+        format!("Model:        {} ({})", models::model_for(Task::Main), providers::current().title()),
+        format!("Auxiliary:    {}", models::model_for(Task::Condense)),
+        format!("Fallback:     {}", models::fallback_model().unwrap_or_else(|| "none".to_string())),
+        format!("API keys:     {}", keys::configured().len()),
+        format!("Interface:    {} (translations in {})", i18n::language(), i18n::locales_dir().display()),
+        format!("Sandbox:      {}", command::backend().map(|b| b.describe().to_string()).unwrap_or_else(|e| e)),
+        format!("Web search:   {}", search_providers::current().map(|p| p.name().to_string()).unwrap_or_else(|e| e)),
+        String::new(),
+        format!("Config file:  {}", describe(&config::config_path())),
+        format!("Project file: {}", describe(&project::project_path())),
+        format!("Tracker file: {}", describe(Path::new(".gemini-tracker"))),
+        format!("Personas:     {}", describe(&persona::personas_dir())),
+        format!("Sessions:     {}", describe(&sessions::sessions_dir())),
+        format!("Plugins:      {}", describe(&plugins::plugins_dir())),
+        format!("Tools:        {}", describe(&external_tools::tools_dir())),
+        String::new(),
+    ];
+
+    let integrations = [
+        ("alpha vantage", configured("ALPHA_VANTAGE_API_KEY")),
+        ("email", configured("SMTP_SERVER_IP") || configured("DESTINATION_EMAIL")),
+        ("github", configured("GITHUB_TOKEN")),
+        ("tracker", configured("TRACKER") || Path::new(".gemini-tracker").exists()),
+        ("editor bridge", configured("GEMINI_EDITOR_PORT")),
+        ("proxy", configured("GEMINI_PROXY") || configured("HTTPS_PROXY") || configured("https_proxy")),
+    ];
+    let enabled: Vec<&str> = integrations.iter().filter(|(_, on)| *on).map(|(name, _)| *name).collect();
+    lines.push(format!(
+        "Integrations: {}",
+        if enabled.is_empty() { "none".to_string() } else { enabled.join(", ") }
+    ));
+    let tools: Vec<String> = tools::enabled_declarations()
+        .iter()
+        .filter_map(|decl| decl["name"].as_str().map(String::from))
+        .collect();
+    lines.push(format!("Tools:        {}", tools.join(", ")));
+    lines.join("\n")
+}
+
+fn describe(path: &Path) -> String {
+    if path.exists() {
+        path.display().to_string()
+    } else {
+        format!("{} {}", path.display(), "(not found)".dimmed())
+    }
+}
+
+fn configured(key: &str) -> bool {
+    env::var(key).map(|v| !v.trim().is_empty()).unwrap_or(false)
+}